@@ -0,0 +1,42 @@
+CREATE TABLE users (
+    mail TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL
+);
+
+-- devices followed by users, in the order they are followed
+CREATE TABLE user_devices (
+    seq BIGSERIAL PRIMARY KEY,
+    mail TEXT NOT NULL REFERENCES users (mail) ON DELETE CASCADE,
+    device_id TEXT NOT NULL
+);
+CREATE INDEX user_devices_mail ON user_devices (mail);
+
+CREATE TABLE login_records (
+    seq BIGSERIAL PRIMARY KEY,
+    login_token TEXT NOT NULL,
+    -- milliseconds since epoch
+    login_time BIGINT NOT NULL
+);
+CREATE INDEX login_records_token ON login_records (login_token);
+
+CREATE TABLE devices (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    info TEXT NOT NULL
+);
+
+CREATE TABLE messages (
+    seq BIGSERIAL PRIMARY KEY,
+    device_id TEXT NOT NULL,
+    info TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    alert BOOLEAN NOT NULL,
+    lng DOUBLE PRECISION NOT NULL,
+    lat DOUBLE PRECISION NOT NULL,
+    -- milliseconds since epoch
+    timestamp BIGINT NOT NULL,
+    -- '' if the device doesn't set a message id, NULLs would not be unique
+    msg_id TEXT NOT NULL DEFAULT '',
+    UNIQUE (device_id, timestamp, msg_id)
+);
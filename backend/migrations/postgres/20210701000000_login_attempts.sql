@@ -0,0 +1,4 @@
+-- failed login attempts are recorded too, with an empty login token, to lock accounts out
+ALTER TABLE login_records ADD COLUMN success BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE login_records ADD COLUMN ip TEXT NOT NULL DEFAULT '';
+CREATE INDEX login_records_mail_time ON login_records (mail, login_time);
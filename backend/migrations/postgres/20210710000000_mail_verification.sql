@@ -0,0 +1,11 @@
+-- users registered before mail verification count as verified
+ALTER TABLE users ADD COLUMN verified BOOLEAN NOT NULL DEFAULT TRUE;
+-- pending mail verifications, only the hash of the token is stored
+CREATE TABLE mail_verifications (
+    hashed_token TEXT PRIMARY KEY NOT NULL,
+    mail TEXT NOT NULL REFERENCES users (mail) ON DELETE CASCADE,
+    -- milliseconds since epoch
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+CREATE INDEX mail_verifications_mail ON mail_verifications (mail, created_at);
@@ -0,0 +1,4 @@
+-- user or admin
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+-- disabled by an administrator
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,13 @@
+-- append-only log of security-relevant and device-changing actions
+CREATE TABLE audit_log (
+    seq BIGSERIAL PRIMARY KEY,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    -- json array of {field, before, after}
+    changes TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    -- milliseconds since epoch
+    time BIGINT NOT NULL
+);
+CREATE INDEX audit_log_time ON audit_log (time);
@@ -0,0 +1,2 @@
+-- milliseconds since epoch, when the account is deleted unless the deletion is cancelled
+ALTER TABLE users ADD COLUMN deletion_due_at BIGINT;
@@ -0,0 +1,5 @@
+-- preferences of users, empty if not set
+ALTER TABLE users ADD COLUMN language TEXT NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN timezone TEXT NOT NULL DEFAULT '';
+-- the new address of a mail change, NULL for verifying the mail of a new user
+ALTER TABLE mail_verifications ADD COLUMN new_mail TEXT;
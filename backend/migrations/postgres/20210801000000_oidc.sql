@@ -0,0 +1,3 @@
+-- account at the OpenID Connect provider linked to the user for single sign-on
+ALTER TABLE users ADD COLUMN oidc_subject TEXT;
+CREATE UNIQUE INDEX users_oidc_subject ON users (oidc_subject);
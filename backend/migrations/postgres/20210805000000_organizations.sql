@@ -0,0 +1,31 @@
+-- users belong to organizations, devices and messages to one of them, device ids are unique
+-- within an organization
+CREATE TABLE organizations (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    -- milliseconds since epoch
+    created_at BIGINT NOT NULL
+);
+-- everything of before organizations belongs to the default one
+INSERT INTO organizations (id, name, created_at) VALUES ('default', 'Default', 0);
+
+CREATE TABLE org_members (
+    mail TEXT NOT NULL REFERENCES users (mail) ON DELETE CASCADE,
+    org_id TEXT NOT NULL REFERENCES organizations (id),
+    PRIMARY KEY (mail, org_id)
+);
+INSERT INTO org_members (mail, org_id) SELECT mail, 'default' FROM users;
+
+-- the organization the user works in
+ALTER TABLE users ADD COLUMN org TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE user_devices ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+-- the organization the key works in
+ALTER TABLE api_keys ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE devices ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE devices DROP CONSTRAINT devices_pkey;
+ALTER TABLE devices ADD PRIMARY KEY (org_id, id);
+
+ALTER TABLE messages ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE messages DROP CONSTRAINT messages_device_id_timestamp_msg_id_key;
+ALTER TABLE messages ADD UNIQUE (org_id, device_id, timestamp, msg_id);
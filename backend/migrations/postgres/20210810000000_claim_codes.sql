@@ -0,0 +1,12 @@
+-- hash of the one-time code needed to claim a provisioned device, and the user who claimed it
+ALTER TABLE devices ADD COLUMN claim_code TEXT;
+ALTER TABLE devices ADD COLUMN owner TEXT;
+
+-- devices of before claim codes belong to the user who followed them first, those without
+-- followers have to be provisioned again
+UPDATE devices SET owner = (
+    SELECT mail FROM user_devices
+    WHERE user_devices.org_id = devices.org_id AND user_devices.device_id = devices.id
+    ORDER BY seq
+    LIMIT 1
+);
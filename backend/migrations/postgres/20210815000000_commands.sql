@@ -0,0 +1,18 @@
+-- commands sent to devices over MQTT and the replies of the devices
+CREATE TABLE commands (
+    seq BIGSERIAL PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    org_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    params TEXT NOT NULL,
+    -- pending, sent, acked, failed or timeout
+    status TEXT NOT NULL,
+    -- mail of the user who sent it
+    issued_by TEXT NOT NULL,
+    -- milliseconds since epoch
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    result TEXT NOT NULL
+);
+CREATE INDEX commands_device ON commands (org_id, device_id, created_at);
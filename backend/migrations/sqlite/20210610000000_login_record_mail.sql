@@ -0,0 +1,2 @@
+-- sessions are resolved to users by the bearer token of the v2 API
+ALTER TABLE login_records ADD COLUMN mail TEXT NOT NULL DEFAULT '';
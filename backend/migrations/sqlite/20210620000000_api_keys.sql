@@ -0,0 +1,15 @@
+-- personal API keys, only the hash of the secret is stored
+CREATE TABLE api_keys (
+    id TEXT PRIMARY KEY NOT NULL,
+    mail TEXT NOT NULL REFERENCES users (mail) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- read_only, device_write or admin
+    scope TEXT NOT NULL,
+    hashed_key TEXT NOT NULL UNIQUE,
+    -- milliseconds since epoch, so are the other times
+    created_at BIGINT NOT NULL,
+    -- NULL if the key never expires
+    expires_at BIGINT,
+    last_used_at BIGINT
+);
+CREATE INDEX api_keys_mail ON api_keys (mail);
@@ -0,0 +1,4 @@
+-- sessions expire some time after the latest request instead of after logging in, 0 for
+-- records written before this
+ALTER TABLE login_records ADD COLUMN last_active BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE login_records ADD COLUMN remember BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,15 @@
+-- TOTP secrets, two-factor authentication is enabled once confirmed
+CREATE TABLE totps (
+    mail TEXT PRIMARY KEY NOT NULL REFERENCES users (mail) ON DELETE CASCADE,
+    -- base32 encoded
+    secret TEXT NOT NULL,
+    confirmed BOOLEAN NOT NULL,
+    -- time step of the latest accepted code, so that no code is accepted twice
+    last_step BIGINT NOT NULL
+);
+-- hashes of the unused recovery codes
+CREATE TABLE totp_recovery_codes (
+    mail TEXT NOT NULL REFERENCES totps (mail) ON DELETE CASCADE,
+    hashed_code TEXT NOT NULL,
+    PRIMARY KEY (mail, hashed_code)
+);
@@ -0,0 +1,56 @@
+-- users belong to organizations, devices and messages to one of them, device ids are unique
+-- within an organization
+CREATE TABLE organizations (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    -- milliseconds since epoch
+    created_at BIGINT NOT NULL
+);
+-- everything of before organizations belongs to the default one
+INSERT INTO organizations (id, name, created_at) VALUES ('default', 'Default', 0);
+
+CREATE TABLE org_members (
+    mail TEXT NOT NULL REFERENCES users (mail) ON DELETE CASCADE,
+    org_id TEXT NOT NULL REFERENCES organizations (id),
+    PRIMARY KEY (mail, org_id)
+);
+INSERT INTO org_members (mail, org_id) SELECT mail, 'default' FROM users;
+
+-- the organization the user works in
+ALTER TABLE users ADD COLUMN org TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE user_devices ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+-- the organization the key works in
+ALTER TABLE api_keys ADD COLUMN org_id TEXT NOT NULL DEFAULT 'default';
+
+-- SQLite can't change primary keys and constraints, so the tables are made again
+CREATE TABLE devices_new (
+    org_id TEXT NOT NULL,
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    info TEXT NOT NULL,
+    PRIMARY KEY (org_id, id)
+);
+INSERT INTO devices_new (org_id, id, name, info) SELECT 'default', id, name, info FROM devices;
+DROP TABLE devices;
+ALTER TABLE devices_new RENAME TO devices;
+
+CREATE TABLE messages_new (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    org_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    info TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    alert BOOLEAN NOT NULL,
+    lng DOUBLE PRECISION NOT NULL,
+    lat DOUBLE PRECISION NOT NULL,
+    -- milliseconds since epoch
+    timestamp BIGINT NOT NULL,
+    -- '' if the device doesn't set a message id, NULLs would not be unique
+    msg_id TEXT NOT NULL DEFAULT '',
+    UNIQUE (org_id, device_id, timestamp, msg_id)
+);
+INSERT INTO messages_new (seq, org_id, device_id, info, value, alert, lng, lat, timestamp, msg_id)
+    SELECT seq, 'default', device_id, info, value, alert, lng, lat, timestamp, msg_id
+    FROM messages;
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
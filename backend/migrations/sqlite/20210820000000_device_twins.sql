@@ -0,0 +1,8 @@
+-- device twins: the configuration a device should have as a JSON object, edited by users, and
+-- the state the device reported in its messages, each with a version increased by every change
+ALTER TABLE devices ADD COLUMN desired TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE devices ADD COLUMN desired_version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN reported TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE devices ADD COLUMN reported_version BIGINT NOT NULL DEFAULT 0;
+-- milliseconds since epoch, 0 if the device hasn't reported anything
+ALTER TABLE devices ADD COLUMN reported_at BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,16 @@
+-- logins with a password get a table of their own, so that ending sessions doesn't delete the
+-- successful ones and merge the failures around them. login_records keeps only the sessions, its
+-- success column is no longer used.
+CREATE TABLE login_attempts (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    mail TEXT NOT NULL,
+    -- milliseconds since epoch
+    time BIGINT NOT NULL,
+    success BOOLEAN NOT NULL,
+    ip TEXT NOT NULL
+);
+CREATE INDEX login_attempts_mail_time ON login_attempts (mail, time);
+CREATE INDEX login_attempts_time ON login_attempts (time);
+INSERT INTO login_attempts (mail, time, success, ip)
+    SELECT mail, login_time, success, ip FROM login_records WHERE success = FALSE;
+DELETE FROM login_records WHERE success = FALSE;
@@ -0,0 +1,241 @@
+//! Checking the passwords of logins, against the users of the store or an LDAP directory
+
+use crate::{
+    config::{AuthProviderKind, ServerConfig},
+    database::blake2_str,
+    store::Store,
+};
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use common::{
+    error::ApiError,
+    request::{LoginRequest, Role},
+};
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+
+const LDAP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Result code of a bind with a wrong password or an unknown DN (RFC 4511)
+const LDAP_INVALID_CREDENTIALS: u32 = 49;
+
+/// The password of a login
+pub struct Credentials {
+    pub mail: String,
+    /// hashed_password - SHA-256 of the password in hex, as the frontend sends it
+    pub hashed_password: String,
+    /// plain_password - the password as entered, only sent if a provider needs it
+    pub plain_password: Option<String>,
+}
+
+impl Credentials {
+    pub fn new(info: &LoginRequest) -> Self {
+        Self::with_password(&info.mail, &info.password, info.plain_password.as_deref())
+    }
+
+    /// `password` is the hashed password, which is ignored if `plain_password` is sent
+    pub fn with_password(mail: &str, password: &str, plain_password: Option<&str>) -> Self {
+        let hashed_password = match plain_password {
+            Some(password) => format!("{:x}", Sha256::digest(password.as_bytes())),
+            None => password.to_string(),
+        };
+        Self {
+            mail: mail.to_string(),
+            hashed_password,
+            plain_password: plain_password.map(str::to_string),
+        }
+    }
+}
+
+pub enum Authentication {
+    /// Unknown - the provider doesn't know the mail address, so the next one is asked
+    Unknown,
+    WrongPassword,
+    /// Authenticated - the password is right. Directories may tell the name a new user gets and
+    /// the role the user has.
+    Authenticated {
+        name: Option<String>,
+        role: Option<Role>,
+    },
+}
+
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    async fn authenticate(
+        &self,
+        store: &dyn Store,
+        credentials: &Credentials,
+    ) -> anyhow::Result<Authentication>;
+
+    /// The password has to be sent as entered, because the provider can't check its hash
+    fn needs_plain_password(&self) -> bool {
+        false
+    }
+}
+
+/// Checks the passwords of the users in the store
+pub struct LocalAuth;
+
+#[async_trait]
+impl AuthProvider for LocalAuth {
+    async fn authenticate(
+        &self,
+        store: &dyn Store,
+        credentials: &Credentials,
+    ) -> anyhow::Result<Authentication> {
+        Ok(match store.find_user_by_mail(&credentials.mail).await? {
+            Some(user) if user.password == blake2_str(credentials.hashed_password.as_bytes()) => {
+                Authentication::Authenticated {
+                    name: None,
+                    role: None,
+                }
+            }
+            Some(_) => Authentication::WrongPassword,
+            None => Authentication::Unknown,
+        })
+    }
+}
+
+/// Finds the entry of the user by a search, then binds as it with the password. A connection is
+/// made for every login, as logins are rare and directories close idle connections.
+pub struct LdapAuth {
+    url: String,
+    starttls: bool,
+    /// bind_dn - the search is anonymous if it is empty
+    bind_dn: String,
+    bind_password: String,
+    base_dn: String,
+    user_filter: String,
+    name_attribute: String,
+    group_attribute: String,
+    /// admin_group - members are administrators and the others users, roles aren't touched if
+    /// it is `None`
+    admin_group: Option<String>,
+}
+
+impl LdapAuth {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            url: config.ldap_url().to_string(),
+            starttls: config.ldap_starttls(),
+            bind_dn: config.ldap_bind_dn().to_string(),
+            bind_password: config.ldap_bind_password().to_string(),
+            base_dn: config.ldap_base_dn().to_string(),
+            user_filter: config.ldap_user_filter().to_string(),
+            name_attribute: config.ldap_name_attribute().to_string(),
+            group_attribute: config.ldap_group_attribute().to_string(),
+            admin_group: config.ldap_admin_group().map(str::to_string),
+        }
+    }
+
+    async fn connect(&self) -> anyhow::Result<Ldap> {
+        let settings = LdapConnSettings::new()
+            .set_starttls(self.starttls)
+            .set_conn_timeout(LDAP_CONNECT_TIMEOUT);
+        let (conn, ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .with_context(|| format!("Failed to connect to {}", self.url))
+            .context(ApiError::Net)?;
+        ldap3::drive!(conn);
+        Ok(ldap)
+    }
+
+    /// The entry of the user with the mail address, `None` if there is none
+    async fn find_user(&self, ldap: &mut Ldap, mail: &str) -> anyhow::Result<Option<SearchEntry>> {
+        if !self.bind_dn.is_empty() {
+            ldap.simple_bind(&self.bind_dn, &self.bind_password)
+                .await
+                .and_then(|result| result.success())
+                .with_context(|| format!("Failed to bind to {} as {}", self.url, self.bind_dn))
+                .context(ApiError::Net)?;
+        }
+        let filter = self.user_filter.replace("{mail}", &ldap_escape(mail));
+        let (entries, _) = ldap
+            .search(
+                &self.base_dn,
+                Scope::Subtree,
+                &filter,
+                vec![self.name_attribute.as_str(), self.group_attribute.as_str()],
+            )
+            .await
+            .and_then(|result| result.success())
+            .with_context(|| format!("Failed to search {} by {}", self.base_dn, filter))
+            .context(ApiError::Net)?;
+        if entries.len() > 1 {
+            bail!(
+                "{} entries of {} match {}",
+                entries.len(),
+                self.base_dn,
+                filter
+            );
+        }
+        Ok(entries.into_iter().next().map(SearchEntry::construct))
+    }
+
+    fn role(&self, entry: &SearchEntry) -> Option<Role> {
+        let admin_group = self.admin_group.as_ref()?;
+        let is_member = entry
+            .attrs
+            .get(&self.group_attribute)
+            .map_or(false, |groups| {
+                groups
+                    .iter()
+                    .any(|group| group.eq_ignore_ascii_case(admin_group))
+            });
+        Some(if is_member { Role::Admin } else { Role::User })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for LdapAuth {
+    async fn authenticate(
+        &self,
+        _store: &dyn Store,
+        credentials: &Credentials,
+    ) -> anyhow::Result<Authentication> {
+        let password = match &credentials.plain_password {
+            Some(password) => password,
+            None => bail!(ApiError::InvalidRequest),
+        };
+        let mut ldap = self.connect().await?;
+        let entry = match self.find_user(&mut ldap, &credentials.mail).await? {
+            Some(entry) => entry,
+            None => return Ok(Authentication::Unknown),
+        };
+        // an empty password would make an unauthenticated bind, which always succeeds
+        if password.is_empty() {
+            return Ok(Authentication::WrongPassword);
+        }
+        let result = ldap
+            .simple_bind(&entry.dn, password)
+            .await
+            .with_context(|| format!("Failed to bind to {} as {}", self.url, entry.dn))
+            .context(ApiError::Net)?;
+        let _ = ldap.unbind().await;
+        match result.rc {
+            0 => {}
+            LDAP_INVALID_CREDENTIALS => return Ok(Authentication::WrongPassword),
+            _ => bail!("Failed to bind to {} as {}: {}", self.url, entry.dn, result),
+        }
+        Ok(Authentication::Authenticated {
+            name: entry
+                .attrs
+                .get(&self.name_attribute)
+                .and_then(|values| values.first())
+                .cloned(),
+            role: self.role(&entry),
+        })
+    }
+
+    fn needs_plain_password(&self) -> bool {
+        true
+    }
+}
+
+/// The providers selected by `auth_provider` of the config, asked in order
+pub fn connect(config: &ServerConfig) -> Vec<Arc<dyn AuthProvider>> {
+    match config.auth_provider() {
+        AuthProviderKind::Local => vec![Arc::new(LocalAuth)],
+        AuthProviderKind::Ldap => vec![Arc::new(LdapAuth::new(config)), Arc::new(LocalAuth)],
+    }
+}
@@ -1,34 +1,810 @@
+use anyhow::{bail, Context};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::{net::IpAddr, path::Path};
+
+/// Kind of database the server stores data in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbKind {
+    Mongodb,
+    Sqlite,
+    Postgres,
+}
+
+impl Default for DbKind {
+    fn default() -> Self {
+        DbKind::Mongodb
+    }
+}
+
+impl std::str::FromStr for DbKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mongodb" => Ok(DbKind::Mongodb),
+            "sqlite" => Ok(DbKind::Sqlite),
+            "postgres" | "postgresql" => Ok(DbKind::Postgres),
+            _ => bail!("'{}' is not one of mongodb, sqlite and postgres", s),
+        }
+    }
+}
+
+/// How mails (e.g. verification links) are sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailSenderKind {
+    /// File - written to `mail_dir` instead of being sent, for local testing
+    File,
+    Smtp,
+}
+
+impl Default for MailSenderKind {
+    fn default() -> Self {
+        MailSenderKind::File
+    }
+}
+
+impl std::str::FromStr for MailSenderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "file" => Ok(MailSenderKind::File),
+            "smtp" => Ok(MailSenderKind::Smtp),
+            _ => bail!("'{}' is not one of file and smtp", s),
+        }
+    }
+}
+
+/// What passwords of logins are checked against
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthProviderKind {
+    /// Local - the passwords of the users in the database
+    Local,
+    /// Ldap - an LDAP directory, users it doesn't know are checked locally
+    Ldap,
+}
+
+impl Default for AuthProviderKind {
+    fn default() -> Self {
+        AuthProviderKind::Local
+    }
+}
+
+impl std::str::FromStr for AuthProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(AuthProviderKind::Local),
+            "ldap" => Ok(AuthProviderKind::Ldap),
+            _ => bail!("'{}' is not one of local and ldap", s),
+        }
+    }
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct ServerConfig {
+    #[serde(default = "default_addr_ip")]
     addr_ip: String,
+    #[serde(default = "default_addr_port")]
     addr_port: String,
+    /// db_kind - "mongodb", "sqlite" or "postgres", SQL databases are only configured by db_uri
+    #[serde(default)]
+    db_kind: DbKind,
+    /// db_uri - full connection string, the structured db fields below are ignored if this is
+    /// set
+    #[serde(default)]
+    db_uri: Option<String>,
+    #[serde(default)]
     db_username: String,
+    #[serde(default)]
     db_password: String,
+    /// db_url - host(s) of the database, e.g. "localhost:27017" or "xxx.mongodb.net"
+    #[serde(default)]
     db_url: String,
+    /// db_srv - use "mongodb+srv://" instead of "mongodb://"
+    #[serde(default = "default_db_srv")]
+    db_srv: bool,
+    /// db_options - connection string options, e.g. "retryWrites=true&w=majority"
+    #[serde(default)]
+    db_options: String,
+    #[serde(default = "default_db_name")]
+    db_name: String,
+    /// db_time_series - store messages in a MongoDB time-series collection (MongoDB 5.0+)
+    #[serde(default)]
+    db_time_series: bool,
+    /// mqtt_host - MQTT broker messages are received from and commands are published to, the
+    /// embedded broker must listen on it unless it is disabled by `--no-broker`
+    #[serde(default = "default_mqtt_host")]
+    mqtt_host: String,
+    #[serde(default = "default_mqtt_port")]
+    mqtt_port: i64,
+    /// session_idle_secs - sessions expire after this long without any request
+    #[serde(default = "default_session_idle_secs")]
+    session_idle_secs: i64,
+    /// remember_me_secs - idle timeout of sessions logged in with "remember me"
+    #[serde(default = "default_remember_me_secs")]
+    remember_me_secs: i64,
+    /// account_deletion_grace_secs - accounts are deleted this long after the user asks for it,
+    /// and can be kept by cancelling the deletion before that
+    #[serde(default = "default_account_deletion_grace_secs")]
+    account_deletion_grace_secs: i64,
+    /// command_timeout_secs - commands sent to devices time out if they are not acknowledged
+    /// within this long
+    #[serde(default = "default_command_timeout_secs")]
+    command_timeout_secs: i64,
+    /// public_url - URL the frontend is reached at, used in links of mails, defaults to
+    /// "http://<addr>"
+    #[serde(default)]
+    public_url: Option<String>,
+    /// mail_sender - "file" or "smtp"
+    #[serde(default)]
+    mail_sender: MailSenderKind,
+    /// mail_dir - directory mails are written to by the file sender
+    #[serde(default = "default_mail_dir")]
+    mail_dir: String,
+    /// mail_from - sender address, e.g. "BS App <noreply@example.com>"
+    #[serde(default = "default_mail_from")]
+    mail_from: String,
+    #[serde(default)]
+    smtp_host: String,
+    /// smtp_port - the connection is upgraded with STARTTLS
+    #[serde(default = "default_smtp_port")]
+    smtp_port: i64,
+    #[serde(default)]
+    smtp_username: String,
+    #[serde(default)]
+    smtp_password: String,
+    /// oidc_issuer - issuer URL of the OpenID Connect provider, e.g.
+    /// "https://login.example.com/realms/main", single sign-on is enabled if this is set
+    #[serde(default)]
+    oidc_issuer: Option<String>,
+    #[serde(default)]
+    oidc_client_id: String,
+    #[serde(default)]
+    oidc_client_secret: String,
+    /// oidc_scopes - space separated scopes asked for, must include "openid"
+    #[serde(default = "default_oidc_scopes")]
+    oidc_scopes: String,
+    /// oidc_provider_name - shown on the single sign-on button of the login page
+    #[serde(default = "default_oidc_provider_name")]
+    oidc_provider_name: String,
+    /// auth_provider - "local" or "ldap"
+    #[serde(default)]
+    auth_provider: AuthProviderKind,
+    /// ldap_url - "ldap://host:389" or "ldaps://host:636"
+    #[serde(default)]
+    ldap_url: String,
+    /// ldap_starttls - upgrade "ldap://" connections with StartTLS
+    #[serde(default)]
+    ldap_starttls: bool,
+    /// ldap_bind_dn - account users are searched by, anonymous if empty
+    #[serde(default)]
+    ldap_bind_dn: String,
+    #[serde(default)]
+    ldap_bind_password: String,
+    /// ldap_base_dn - where users are searched, e.g. "ou=people,dc=example,dc=com"
+    #[serde(default)]
+    ldap_base_dn: String,
+    /// ldap_user_filter - finds the entry of a user, "{mail}" is replaced by the mail address
+    #[serde(default = "default_ldap_user_filter")]
+    ldap_user_filter: String,
+    /// ldap_name_attribute - user name of the users created by their first login
+    #[serde(default = "default_ldap_name_attribute")]
+    ldap_name_attribute: String,
+    /// ldap_group_attribute - attribute of user entries listing the DNs of their groups
+    #[serde(default = "default_ldap_group_attribute")]
+    ldap_group_attribute: String,
+    /// ldap_admin_group - DN of the group whose members are administrators, the others are
+    /// users; roles of directory users are managed in the console if this is unset
+    #[serde(default)]
+    ldap_admin_group: Option<String>,
+}
+
+fn default_addr_ip() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_addr_port() -> String {
+    "9000".to_string()
+}
+
+fn default_db_srv() -> bool {
+    true
+}
+
+fn default_db_name() -> String {
+    "bs_proj".to_string()
+}
+
+fn default_mqtt_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_mqtt_port() -> i64 {
+    1883
+}
+
+fn default_session_idle_secs() -> i64 {
+    3600
+}
+
+fn default_remember_me_secs() -> i64 {
+    30 * 24 * 3600
+}
+
+fn default_account_deletion_grace_secs() -> i64 {
+    7 * 24 * 3600
+}
+
+fn default_command_timeout_secs() -> i64 {
+    60
+}
+
+fn default_mail_dir() -> String {
+    "./mails".to_string()
+}
+
+fn default_mail_from() -> String {
+    "BS App <noreply@localhost>".to_string()
+}
+
+fn default_smtp_port() -> i64 {
+    587
+}
+
+fn default_oidc_scopes() -> String {
+    "openid email profile".to_string()
+}
+
+fn default_oidc_provider_name() -> String {
+    "SSO".to_string()
+}
+
+fn default_ldap_user_filter() -> String {
+    "(&(objectClass=inetOrgPerson)(mail={mail}))".to_string()
+}
+
+fn default_ldap_name_attribute() -> String {
+    "uid".to_string()
+}
+
+fn default_ldap_group_attribute() -> String {
+    "memberOf".to_string()
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr_ip: default_addr_ip(),
+            addr_port: default_addr_port(),
+            db_kind: DbKind::default(),
+            db_uri: None,
+            db_username: String::default(),
+            db_password: String::default(),
+            db_url: String::default(),
+            db_srv: default_db_srv(),
+            db_options: String::default(),
+            db_name: default_db_name(),
+            db_time_series: false,
+            mqtt_host: default_mqtt_host(),
+            mqtt_port: default_mqtt_port(),
+            session_idle_secs: default_session_idle_secs(),
+            remember_me_secs: default_remember_me_secs(),
+            account_deletion_grace_secs: default_account_deletion_grace_secs(),
+            command_timeout_secs: default_command_timeout_secs(),
+            public_url: None,
+            mail_sender: MailSenderKind::default(),
+            mail_dir: default_mail_dir(),
+            mail_from: default_mail_from(),
+            smtp_host: String::default(),
+            smtp_port: default_smtp_port(),
+            smtp_username: String::default(),
+            smtp_password: String::default(),
+            oidc_issuer: None,
+            oidc_client_id: String::default(),
+            oidc_client_secret: String::default(),
+            oidc_scopes: default_oidc_scopes(),
+            oidc_provider_name: default_oidc_provider_name(),
+            auth_provider: AuthProviderKind::default(),
+            ldap_url: String::default(),
+            ldap_starttls: false,
+            ldap_bind_dn: String::default(),
+            ldap_bind_password: String::default(),
+            ldap_base_dn: String::default(),
+            ldap_user_filter: default_ldap_user_filter(),
+            ldap_name_attribute: default_ldap_name_attribute(),
+            ldap_group_attribute: default_ldap_group_attribute(),
+            ldap_admin_group: None,
+        }
+    }
 }
 
 lazy_static! {
-    static ref ADDR_IP_RE: Regex = Regex::new(r"^(25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.(25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.(25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.(25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$").unwrap();
+    static ref ADDR_HOST_RE: Regex = Regex::new(r"^([0-9a-zA-Z]([0-9a-zA-Z-]{0,61}[0-9a-zA-Z])?)(\.[0-9a-zA-Z]([0-9a-zA-Z-]{0,61}[0-9a-zA-Z])?)*$").unwrap();
     static ref ADDR_PORT_RE: Regex = Regex::new(r"^(6553[0-5]|655[0-2][0-9]|65[0-4][0-9]{2}|6[0-4][0-9]{3}|[1-5][0-9]{4}|[1-9][0-9]{1,3})$").unwrap();
+    static ref DB_URI_RE: Regex = Regex::new(r"^mongodb(\+srv)?://.+$").unwrap();
+    static ref SQLITE_URI_RE: Regex = Regex::new(r"^sqlite:.+$").unwrap();
+    static ref POSTGRES_URI_RE: Regex = Regex::new(r"^postgres(ql)?://.+$").unwrap();
+    static ref DB_NAME_RE: Regex = Regex::new(r#"^[^/\\. "$*<>:|?]{1,63}$"#).unwrap();
+}
+
+/// Prefix of environment variables that override fields of `ServerConfig`, e.g. `BS_DB_PASSWORD`
+pub const ENV_PREFIX: &str = "BS_";
+
+trait FromEnv: Sized {
+    fn from_env(value: String) -> anyhow::Result<Self>;
+}
+
+impl FromEnv for String {
+    fn from_env(value: String) -> anyhow::Result<Self> {
+        Ok(value)
+    }
+}
+
+impl FromEnv for Option<String> {
+    fn from_env(value: String) -> anyhow::Result<Self> {
+        Ok(if value.is_empty() { None } else { Some(value) })
+    }
+}
+
+impl FromEnv for DbKind {
+    fn from_env(value: String) -> anyhow::Result<Self> {
+        value.parse()
+    }
+}
+
+impl FromEnv for MailSenderKind {
+    fn from_env(value: String) -> anyhow::Result<Self> {
+        value.parse()
+    }
+}
+
+impl FromEnv for AuthProviderKind {
+    fn from_env(value: String) -> anyhow::Result<Self> {
+        value.parse()
+    }
+}
+
+impl FromEnv for i64 {
+    fn from_env(value: String) -> anyhow::Result<Self> {
+        value
+            .parse()
+            .with_context(|| format!("'{}' is not an integer", value))
+    }
+}
+
+impl FromEnv for bool {
+    fn from_env(value: String) -> anyhow::Result<Self> {
+        match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" => Ok(false),
+            _ => bail!("'{}' is not a boolean", value),
+        }
+    }
+}
+
+macro_rules! env_override {
+    ( $self:ident, $( $field:ident ),+ $(,)? ) => {
+        $(
+            let key = format!("{}{}", ENV_PREFIX, stringify!($field).to_uppercase());
+            if let Ok(value) = std::env::var(&key) {
+                $self.$field = FromEnv::from_env(value)
+                    .with_context(|| format!("Invalid environment variable {}", key))?;
+            }
+        )+
+    };
 }
 
 impl ServerConfig {
-    pub fn validate(&self) -> bool {
-        ADDR_IP_RE.is_match(&self.addr_ip) && ADDR_PORT_RE.is_match(&self.addr_port)
+    /// Load config from a json file. If `required` is false, a missing file is not an error and
+    /// the default config is used instead.
+    pub fn load(path: &Path, required: bool) -> anyhow::Result<Self> {
+        if !required && !path.exists() {
+            return Ok(Self::default());
+        }
+        let config_json = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open server config {}", path.display()))?;
+        serde_json::from_reader(&config_json)
+            .with_context(|| format!("Invalid server config json {}", path.display()))
+    }
+
+    /// Override fields with `BS_<FIELD>` environment variables, so that secrets don't have to
+    /// be stored in the config file
+    pub fn apply_env(&mut self) -> anyhow::Result<()> {
+        env_override!(
+            self,
+            addr_ip,
+            addr_port,
+            db_kind,
+            db_uri,
+            db_username,
+            db_password,
+            db_url,
+            db_srv,
+            db_options,
+            db_name,
+            db_time_series,
+            mqtt_host,
+            mqtt_port,
+            session_idle_secs,
+            remember_me_secs,
+            account_deletion_grace_secs,
+            command_timeout_secs,
+            public_url,
+            mail_sender,
+            mail_dir,
+            mail_from,
+            smtp_host,
+            smtp_port,
+            smtp_username,
+            smtp_password,
+            oidc_issuer,
+            oidc_client_id,
+            oidc_client_secret,
+            oidc_scopes,
+            oidc_provider_name,
+            auth_provider,
+            ldap_url,
+            ldap_starttls,
+            ldap_bind_dn,
+            ldap_bind_password,
+            ldap_base_dn,
+            ldap_user_filter,
+            ldap_name_attribute,
+            ldap_group_attribute,
+            ldap_admin_group,
+        );
+        Ok(())
+    }
+
+    /// Override address with "host:port", "host" or "[ipv6]:port"
+    pub fn set_bind(&mut self, bind: &str) {
+        let (ip, port) = if let Some(rest) = bind.strip_prefix('[') {
+            match rest.split_once(']') {
+                Some((ip, port)) => (ip, port.strip_prefix(':')),
+                None => (rest, None),
+            }
+        } else if bind.matches(':').count() == 1 {
+            let (ip, port) = bind.split_once(':').unwrap();
+            (ip, Some(port))
+        } else {
+            (bind, None)
+        };
+        self.addr_ip = ip.to_string();
+        if let Some(port) = port {
+            self.addr_port = port.to_string();
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut errors = vec![];
+        if self.addr_ip.parse::<IpAddr>().is_err() && !ADDR_HOST_RE.is_match(&self.addr_ip) {
+            errors.push(format!(
+                "addr_ip '{}' is neither an IP address nor a host name",
+                self.addr_ip
+            ));
+        }
+        if !ADDR_PORT_RE.is_match(&self.addr_port) {
+            errors.push(format!(
+                "addr_port '{}' is not a port number in 1-65535",
+                self.addr_port
+            ));
+        }
+        match (self.db_kind, &self.db_uri) {
+            (DbKind::Mongodb, Some(db_uri)) => {
+                if !DB_URI_RE.is_match(db_uri) {
+                    errors.push(
+                        "db_uri must start with 'mongodb://' or 'mongodb+srv://'".to_string(),
+                    );
+                }
+            }
+            (DbKind::Mongodb, None) => {
+                if self.db_url.is_empty() {
+                    errors.push("Either db_uri or db_url must be set".to_string());
+                }
+                if self.db_username.is_empty() && !self.db_password.is_empty() {
+                    errors.push("db_password is set but db_username is empty".to_string());
+                }
+            }
+            (DbKind::Sqlite, Some(db_uri)) => {
+                if !SQLITE_URI_RE.is_match(db_uri) {
+                    errors.push(
+                        "db_uri must start with 'sqlite:' when db_kind is sqlite".to_string(),
+                    );
+                }
+            }
+            (DbKind::Postgres, Some(db_uri)) => {
+                if !POSTGRES_URI_RE.is_match(db_uri) {
+                    errors.push(
+                        "db_uri must start with 'postgres://' when db_kind is postgres".to_string(),
+                    );
+                }
+            }
+            (_, None) => {
+                errors.push("db_uri must be set when db_kind is sqlite or postgres".to_string());
+            }
+        }
+        if self.db_kind == DbKind::Mongodb && !DB_NAME_RE.is_match(&self.db_name) {
+            errors.push(format!(
+                "db_name '{}' is not a valid MongoDB database name",
+                self.db_name
+            ));
+        }
+
+        if self.db_time_series && self.db_kind != DbKind::Mongodb {
+            errors.push("db_time_series is only supported by MongoDB".to_string());
+        }
+
+        if self.mqtt_host.parse::<IpAddr>().is_err() && !ADDR_HOST_RE.is_match(&self.mqtt_host) {
+            errors.push(format!(
+                "mqtt_host '{}' is neither an IP address nor a host name",
+                self.mqtt_host
+            ));
+        }
+        if !ADDR_PORT_RE.is_match(&self.mqtt_port.to_string()) {
+            errors.push(format!(
+                "mqtt_port '{}' is not a port number in 1-65535",
+                self.mqtt_port
+            ));
+        }
+
+        if self.session_idle_secs <= 0 {
+            errors.push("session_idle_secs must be positive".to_string());
+        }
+        if self.remember_me_secs < self.session_idle_secs {
+            errors.push("remember_me_secs must not be less than session_idle_secs".to_string());
+        }
+        if self.account_deletion_grace_secs < 0 {
+            errors.push("account_deletion_grace_secs must not be negative".to_string());
+        }
+        if self.command_timeout_secs <= 0 {
+            errors.push("command_timeout_secs must be positive".to_string());
+        }
+
+        if let Some(public_url) = &self.public_url {
+            if !public_url.starts_with("http://") && !public_url.starts_with("https://") {
+                errors.push("public_url must start with 'http://' or 'https://'".to_string());
+            }
+        }
+        if self.mail_sender == MailSenderKind::Smtp && self.smtp_host.is_empty() {
+            errors.push("smtp_host must be set when mail_sender is smtp".to_string());
+        }
+        if !ADDR_PORT_RE.is_match(&self.smtp_port.to_string()) {
+            errors.push(format!(
+                "smtp_port '{}' is not a port number in 1-65535",
+                self.smtp_port
+            ));
+        }
+        if self.smtp_username.is_empty() && !self.smtp_password.is_empty() {
+            errors.push("smtp_password is set but smtp_username is empty".to_string());
+        }
+
+        if let Some(oidc_issuer) = &self.oidc_issuer {
+            if !oidc_issuer.starts_with("http://") && !oidc_issuer.starts_with("https://") {
+                errors.push("oidc_issuer must start with 'http://' or 'https://'".to_string());
+            }
+            if self.oidc_client_id.is_empty() {
+                errors.push("oidc_client_id must be set when oidc_issuer is set".to_string());
+            }
+            if !self
+                .oidc_scopes
+                .split_whitespace()
+                .any(|scope| scope == "openid")
+            {
+                errors.push("oidc_scopes must include 'openid'".to_string());
+            }
+        }
+
+        if self.auth_provider == AuthProviderKind::Ldap {
+            if !self.ldap_url.starts_with("ldap://") && !self.ldap_url.starts_with("ldaps://") {
+                errors.push("ldap_url must start with 'ldap://' or 'ldaps://'".to_string());
+            }
+            if self.ldap_starttls && self.ldap_url.starts_with("ldaps://") {
+                errors.push("ldap_starttls can't be used with 'ldaps://'".to_string());
+            }
+            if self.ldap_base_dn.is_empty() {
+                errors.push("ldap_base_dn must be set when auth_provider is ldap".to_string());
+            }
+            if !self.ldap_user_filter.contains("{mail}") {
+                errors.push("ldap_user_filter must contain '{mail}'".to_string());
+            }
+            if self.ldap_bind_dn.is_empty() && !self.ldap_bind_password.is_empty() {
+                errors.push("ldap_bind_password is set but ldap_bind_dn is empty".to_string());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            bail!("Invalid server config:\n  {}", errors.join("\n  "))
+        }
     }
 
     pub fn addr(&self) -> String {
-        format!("{}:{}", self.addr_ip, self.addr_port)
+        if self.addr_ip.contains(':') {
+            format!("[{}]:{}", self.addr_ip, self.addr_port)
+        } else {
+            format!("{}:{}", self.addr_ip, self.addr_port)
+        }
+    }
+
+    pub fn db_kind(&self) -> DbKind {
+        self.db_kind
     }
 
     pub fn db_url(&self) -> String {
-        format!(
-            "mongodb+srv://{}:{}@{}?retryWrites=true&w=majority",
-            self.db_username, self.db_password, self.db_url
-        )
+        if let Some(db_uri) = &self.db_uri {
+            return db_uri.clone();
+        }
+
+        let scheme = if self.db_srv {
+            "mongodb+srv"
+        } else {
+            "mongodb"
+        };
+        let credentials = if self.db_username.is_empty() {
+            "".to_string()
+        } else {
+            format!(
+                "{}:{}@",
+                percent_encode(&self.db_username),
+                percent_encode(&self.db_password)
+            )
+        };
+        let options = if self.db_options.is_empty() {
+            "".to_string()
+        } else if self.db_url.contains('/') {
+            format!("?{}", self.db_options)
+        } else {
+            format!("/?{}", self.db_options)
+        };
+        format!("{}://{}{}{}", scheme, credentials, self.db_url, options)
+    }
+
+    pub fn db_name(&self) -> &str {
+        &self.db_name
+    }
+
+    pub fn db_time_series(&self) -> bool {
+        self.db_time_series
+    }
+
+    pub fn mqtt_host(&self) -> &str {
+        &self.mqtt_host
+    }
+
+    pub fn mqtt_port(&self) -> u16 {
+        self.mqtt_port as u16
+    }
+
+    pub fn session_idle_secs(&self) -> i64 {
+        self.session_idle_secs
+    }
+
+    pub fn remember_me_secs(&self) -> i64 {
+        self.remember_me_secs
+    }
+
+    pub fn account_deletion_grace_secs(&self) -> i64 {
+        self.account_deletion_grace_secs
+    }
+
+    pub fn command_timeout_secs(&self) -> i64 {
+        self.command_timeout_secs
+    }
+
+    pub fn public_url(&self) -> String {
+        match &self.public_url {
+            Some(public_url) => public_url.trim_end_matches('/').to_string(),
+            None => format!("http://{}", self.addr()),
+        }
+    }
+
+    pub fn mail_sender(&self) -> MailSenderKind {
+        self.mail_sender
+    }
+
+    pub fn mail_dir(&self) -> &str {
+        &self.mail_dir
+    }
+
+    pub fn mail_from(&self) -> &str {
+        &self.mail_from
+    }
+
+    pub fn smtp_host(&self) -> &str {
+        &self.smtp_host
+    }
+
+    pub fn smtp_port(&self) -> u16 {
+        self.smtp_port as u16
+    }
+
+    pub fn smtp_username(&self) -> &str {
+        &self.smtp_username
+    }
+
+    pub fn smtp_password(&self) -> &str {
+        &self.smtp_password
     }
+
+    pub fn oidc_issuer(&self) -> Option<&str> {
+        self.oidc_issuer.as_deref()
+    }
+
+    pub fn oidc_client_id(&self) -> &str {
+        &self.oidc_client_id
+    }
+
+    pub fn oidc_client_secret(&self) -> &str {
+        &self.oidc_client_secret
+    }
+
+    pub fn oidc_scopes(&self) -> &str {
+        &self.oidc_scopes
+    }
+
+    pub fn oidc_provider_name(&self) -> &str {
+        &self.oidc_provider_name
+    }
+
+    pub fn auth_provider(&self) -> AuthProviderKind {
+        self.auth_provider
+    }
+
+    pub fn ldap_url(&self) -> &str {
+        &self.ldap_url
+    }
+
+    pub fn ldap_starttls(&self) -> bool {
+        self.ldap_starttls
+    }
+
+    pub fn ldap_bind_dn(&self) -> &str {
+        &self.ldap_bind_dn
+    }
+
+    pub fn ldap_bind_password(&self) -> &str {
+        &self.ldap_bind_password
+    }
+
+    pub fn ldap_base_dn(&self) -> &str {
+        &self.ldap_base_dn
+    }
+
+    pub fn ldap_user_filter(&self) -> &str {
+        &self.ldap_user_filter
+    }
+
+    pub fn ldap_name_attribute(&self) -> &str {
+        &self.ldap_name_attribute
+    }
+
+    pub fn ldap_group_attribute(&self) -> &str {
+        &self.ldap_group_attribute
+    }
+
+    pub fn ldap_admin_group(&self) -> Option<&str> {
+        self.ldap_admin_group.as_deref()
+    }
+}
+
+/// Username and password may contain characters with special meanings in a connection string,
+/// so may the parameters of URLs
+pub(crate) fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
 }
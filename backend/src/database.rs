@@ -1,550 +1,2433 @@
+use crate::{
+    auth::{AuthProvider, Authentication, Credentials, LocalAuth},
+    error::RetryAfter,
+    export,
+    import::{self, ImportFormat, ImportReport},
+    mail::{Mail, MailSender},
+    mqtt::{self, CommandMqtt, CommandPublisher, DeltaMqtt},
+    oidc::{Identity, OidcClient},
+    store::{
+        ApiKey, AuditEntry, AuditFilter, Command, Device, LoginAttempt, LoginRecord,
+        MailVerification, MessageKey, Organization, Store, Totp, User, DEFAULT_ORG,
+    },
+    throttle::Throttle,
+    totp,
+};
 use anyhow::{bail, Context};
-use bson::doc;
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
 use common::{
+    error::ApiError,
     request::{
-        CreateDeviceRequest, FetchDeviceListRequest, FetchDeviceProfileRequest, FetchDeviceRequest,
-        FetchMessageListRequest, LoginRequest, ModifyDeviceRequest, RegisterRequest,
-        RemoveDeviceRequest,
+        AdminUserRequest, ApiKeyScope, AuditAction, CancelAccountDeletionRequest,
+        ChangeMailRequest, CommandStatus, ConfirmTotpRequest, CreateApiKeyRequest,
+        CreateDeviceRequest, CreateOrgRequest, DeleteAccountRequest, DisableTotpRequest,
+        EnrollTotpRequest, ExportAccountRequest, FetchAllDevicesRequest, FetchApiKeyListRequest,
+        FetchAuditLogRequest, FetchCommandListRequest, FetchDeviceProfileRequest,
+        FetchDeviceTwinRequest, FetchOrgListRequest, FetchProfileRequest, FetchTotpStatusRequest,
+        FetchUserListRequest, ImportMessagesRequest, LoginRequest, OrgMemberRequest,
+        ProvisionDeviceRequest, RegisterRequest, RemoveDeviceRequest, ResendVerificationRequest,
+        RevokeApiKeyRequest, Role, SendCommandRequest, SwitchOrgRequest, UpdateDeviceTwinRequest,
+        UpdateProfileRequest, UpdateUserRequest, LANGUAGES,
+    },
+    response::{
+        ApiKeyInfo, AuditChange, AuditEntryInfo, CommandInfo, DeviceInfo, DeviceTwin, MessageInfo,
+        OrgInfo, ProfileInfo, UserInfo,
     },
-    response::{DeviceInfo, MessageInfo},
 };
-use futures::StreamExt;
-use mongodb::{
-    options::{ClientOptions, FindOneOptions, FindOptions, ResolverConfig},
-    Client, Collection,
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time,
 };
-use serde::{Deserialize, Serialize};
 
-pub struct Database {
-    users: Collection,
-    devices: Collection,
-    messages: Collection,
-    login_records: Collection,
+pub use crate::store::Message;
+
+/// A logged in user, or a user authenticated by an API key
+#[derive(Clone)]
+pub struct Session {
+    /// login_token - the login token or the API key
+    pub login_token: String,
+    pub mail: String,
+    /// scope - `Admin` for password logins
+    pub scope: ApiKeyScope,
+    /// org - the organization the session works in, devices and messages are looked up in it
+    pub org: String,
+}
+
+impl Session {
+    /// Fails with `Forbidden` if the scope of the session doesn't include `scope`
+    pub fn require(&self, scope: ApiKeyScope) -> anyhow::Result<()> {
+        if self.scope < scope {
+            bail!(ApiError::Forbidden);
+        }
+        Ok(())
+    }
+}
+
+/// API keys start with this, so that they can be told apart from login tokens
+const API_KEY_PREFIX: &str = "bsk_";
+const API_KEY_SECRET_LEN: usize = 40;
+const API_KEY_ID_LEN: usize = 12;
+const MAX_API_KEY_NAME_LEN: usize = 64;
+
+const MAX_ORG_ID_LEN: usize = 32;
+const MAX_ORG_NAME_LEN: usize = 64;
+
+const LOGIN_TOKEN_LEN: usize = 64;
+
+/// Issuer shown by authenticator apps
+const TOTP_ISSUER: &str = "bs-app";
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Recovery codes are shown as two groups of this many characters, "xxxxx-xxxxx"
+const RECOVERY_CODE_GROUP_LEN: usize = 5;
+const CLAIM_CODE_GROUP_LEN: usize = 4;
+const COMMAND_ID_LEN: usize = 16;
+/// Reports of a device are merged into its reported state at most this many times when others
+/// change it at the same time
+const MAX_REPORT_ATTEMPTS: usize = 5;
+/// Sessions are renewed at most once in this interval (or a quarter of the idle timeout if it is
+/// shorter), so that not every request writes the login record
+const SESSION_RENEW_INTERVAL_SECS: i64 = 60;
+
+/// Names of users made by single sign-on or a directory are cut to this long, so that a suffix
+/// still fits in 32 characters
+const MAX_EXTERNAL_NAME_BASE_LEN: usize = 25;
+
+const MAIL_VERIFICATION_TOKEN_LEN: usize = 32;
+/// Verification links expire after this long
+const MAIL_VERIFICATION_HOURS: i64 = 24;
+/// Verification mails of a user are sent at most once in this interval
+const MAIL_RESEND_INTERVAL_SECS: i64 = 60;
+
+/// Accounts are locked for `LOCKOUT_SECS` after this many failed logins in a row within that time
+const MAX_FAILED_LOGINS: usize = 5;
+const LOCKOUT_SECS: i64 = 15 * 60;
+/// Accounts of single sign-on have no password, so they confirm changes of the account by having
+/// logged in within this long instead
+const REAUTH_SECS: i64 = 5 * 60;
+/// Failed logins and registrations an IP address may make before being throttled, see `Throttle`
+const IP_FREE_ATTEMPTS: u32 = 10;
+const IP_BASE_DELAY: time::Duration = time::Duration::from_secs(1);
+const IP_MAX_DELAY: time::Duration = time::Duration::from_secs(15 * 60);
+
+/// A new session of a user
+pub struct LoginInfo {
+    pub login_token: String,
+    pub mail: String,
+    pub name: String,
+    /// expires_at - milliseconds since epoch, when the session expires if no request is made
+    pub expires_at: i64,
+    pub role: Role,
+    pub language: String,
+    pub timezone: String,
 }
 
-#[derive(Deserialize, Serialize)]
-struct User {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    _id: Option<bson::oid::ObjectId>,
-    mail: String,
-    name: String,
-    password: String,
-    devices: Vec<String>,
+pub struct Database {
+    store: Arc<dyn Store>,
+    duplicated_message_count: AtomicU64,
+    session_idle_timeout: Duration,
+    remember_me_timeout: Duration,
+    /// deletion_grace - accounts are deleted this long after the user asks for it
+    deletion_grace: Duration,
+    ip_throttle: Throttle,
+    /// mail_sender - verification mails are sent by this, users are verified on registration if
+    /// it is `None`
+    mail_sender: Option<Arc<dyn MailSender>>,
+    /// public_url - where the frontend is reached, links in mails point to it
+    public_url: String,
+    /// oidc - users can log in by single sign-on with this provider if it is set
+    oidc: Option<OidcClient>,
+    /// auth_providers - passwords of logins are checked by the first of these that knows the user
+    auth_providers: Vec<Arc<dyn AuthProvider>>,
+    /// command_publisher - commands and twin deltas are published to devices by this, commands
+    /// fail if it is `None`
+    command_publisher: Option<Arc<dyn CommandPublisher>>,
+    /// command_timeout - commands time out if devices don't reply within this long
+    command_timeout: Duration,
 }
 
-#[derive(Deserialize, Serialize)]
-struct Device {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    _id: Option<bson::oid::ObjectId>,
-    id: String,
-    name: String,
-    info: String,
-}
+impl Database {
+    pub fn new(store: Arc<dyn Store>) -> Self {
+        Self {
+            store,
+            duplicated_message_count: AtomicU64::new(0),
+            session_idle_timeout: Duration::hours(1),
+            remember_me_timeout: Duration::days(30),
+            deletion_grace: Duration::days(7),
+            ip_throttle: Throttle::new(IP_FREE_ATTEMPTS, IP_BASE_DELAY, IP_MAX_DELAY),
+            mail_sender: None,
+            public_url: String::new(),
+            oidc: None,
+            auth_providers: vec![Arc::new(LocalAuth)],
+            command_publisher: None,
+            command_timeout: Duration::seconds(60),
+        }
+    }
+
+    /// Require new users to verify their mail addresses by links sent by `sender`, which point
+    /// to the frontend at `public_url`
+    pub fn with_mail_sender(mut self, sender: Arc<dyn MailSender>, public_url: String) -> Self {
+        self.mail_sender = Some(sender);
+        self.public_url = public_url;
+        self
+    }
+
+    /// Sessions expire `idle_secs` after the latest request, or `remember_me_secs` if logged in
+    /// with "remember me"
+    pub fn with_session_timeouts(mut self, idle_secs: i64, remember_me_secs: i64) -> Self {
+        self.session_idle_timeout = Duration::seconds(idle_secs);
+        self.remember_me_timeout = Duration::seconds(remember_me_secs);
+        self
+    }
+
+    /// Allow logging in by single sign-on with the OpenID Connect provider of `client`
+    pub fn with_oidc(mut self, client: OidcClient) -> Self {
+        self.oidc = Some(client);
+        self
+    }
+
+    /// Check the passwords of logins by `providers` in order instead of only locally
+    pub fn with_auth_providers(mut self, providers: Vec<Arc<dyn AuthProvider>>) -> Self {
+        self.auth_providers = providers;
+        self
+    }
+
+    /// Accounts are deleted `grace_secs` after the user asks for it, see `request_deletion`
+    pub fn with_deletion_grace(mut self, grace_secs: i64) -> Self {
+        self.deletion_grace = Duration::seconds(grace_secs);
+        self
+    }
+
+    /// Send commands to devices by `publisher`
+    pub fn with_command_publisher(mut self, publisher: Arc<dyn CommandPublisher>) -> Self {
+        self.command_publisher = Some(publisher);
+        self
+    }
+
+    /// Commands time out if devices don't reply within `timeout_secs`, see `expire_commands`
+    pub fn with_command_timeout(mut self, timeout_secs: i64) -> Self {
+        self.command_timeout = Duration::seconds(timeout_secs);
+        self
+    }
+
+    /// Fails with `RetryAfter` if the account is locked because of too many failed logins, every
+    /// attempt is recorded with the IP address it came from. Users with two-factor
+    /// authentication also need `totp_code`, logging in without it fails with `TotpRequired`.
+    pub async fn login(&self, info: LoginRequest, ip: &str) -> anyhow::Result<LoginInfo> {
+        if let Some(secs) = self.account_locked_for(&info.mail).await? {
+            bail!(RetryAfter(secs));
+        }
+        let err = match self.authenticate(&info, ip).await? {
+            Ok(user) => {
+                if user.disabled {
+                    self.audit(&user.mail, AuditAction::LoginFailed, &user.mail, vec![], ip)
+                        .await?;
+                    bail!(ApiError::AccountDisabled);
+                }
+                if self
+                    .check_totp(&user.mail, info.totp_code.as_deref())
+                    .await?
+                {
+                    let mail = user.mail.clone();
+                    self.record_login_attempt(&mail, true, ip).await?;
+                    let info = self
+                        .new_session(user, info.remember, Utc::now(), ip)
+                        .await?;
+                    self.audit(&mail, AuditAction::Login, &mail, vec![], ip)
+                        .await?;
+                    return Ok(info);
+                }
+                ApiError::WrongTotpCode
+            }
+            Err(err) => err,
+        };
+        self.audit(&info.mail, AuditAction::LoginFailed, &info.mail, vec![], ip)
+            .await?;
+        self.record_login_attempt(&info.mail, false, ip).await?;
+        bail!(err)
+    }
+
+    async fn record_login_attempt(
+        &self,
+        mail: &str,
+        success: bool,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let attempt = LoginAttempt {
+            mail: mail.to_string(),
+            time: Utc::now(),
+            success,
+            ip: ip.to_string(),
+        };
+        self.store.insert_login_attempt(attempt).await
+    }
+
+    /// Delete the login attempts too old to lock accounts, returns how many there were
+    pub async fn prune_login_attempts(&self) -> anyhow::Result<u32> {
+        let before = Utc::now() - Duration::seconds(LOCKOUT_SECS);
+        self.store.delete_login_attempts(before).await
+    }
+
+    /// Passwords must be sent as entered instead of hashed, because a provider checks them
+    pub fn plain_password_required(&self) -> bool {
+        self.auth_providers
+            .iter()
+            .any(|provider| provider.needs_plain_password())
+    }
+
+    /// The user if the first provider that knows the mail address accepts the password,
+    /// `WrongPassword` or `NoUser` otherwise. Users of a directory get a local user by their first
+    /// login, and their role follows the directory if it tells one.
+    async fn authenticate(
+        &self,
+        info: &LoginRequest,
+        ip: &str,
+    ) -> anyhow::Result<Result<User, ApiError>> {
+        let credentials = Credentials::new(info);
+        for provider in &self.auth_providers {
+            let (name, role) = match provider.authenticate(&*self.store, &credentials).await? {
+                Authentication::Unknown => continue,
+                Authentication::WrongPassword => return Ok(Err(ApiError::WrongPassword)),
+                Authentication::Authenticated { name, role } => (name, role),
+            };
+            let mut user = match self.store.find_user_by_mail(&info.mail).await? {
+                Some(user) => user,
+                None => {
+                    let role = role.unwrap_or(Role::User);
+                    let user = self
+                        .insert_external_user(&info.mail, name.as_deref(), role, None, ip)
+                        .await?;
+                    return Ok(Ok(user));
+                }
+            };
+            if let Some(role) = role.filter(|role| *role != user.role) {
+                self.store.set_user_role(&user.mail, role).await?;
+                let changes = vec![change("role", user.role.as_str(), role.as_str())];
+                self.audit(&user.mail, AuditAction::UpdateUser, &user.mail, changes, ip)
+                    .await?;
+                user.role = role;
+            }
+            return Ok(Ok(user));
+        }
+        Ok(Err(ApiError::NoUser))
+    }
+
+    /// Seconds until the account may log in again, `None` if it isn't locked
+    async fn account_locked_for(&self, mail: &str) -> anyhow::Result<Option<u64>> {
+        let now = Utc::now();
+        let lockout = Duration::seconds(LOCKOUT_SECS);
+        let attempts = self.store.find_login_attempts(mail, now - lockout).await?;
+        let failures = attempts
+            .iter()
+            .take_while(|attempt| !attempt.success)
+            .collect::<Vec<_>>();
+        if failures.len() < MAX_FAILED_LOGINS {
+            return Ok(None);
+        }
+        // locked since the `MAX_FAILED_LOGINS`th latest failure
+        let unlock_time = failures[MAX_FAILED_LOGINS - 1].time + lockout;
+        let remaining_ms = (unlock_time - now).num_milliseconds();
+        Ok(Some(((remaining_ms + 999) / 1000).max(1) as u64))
+    }
+
+    /// Fails with `RetryAfter` if the IP address made too many failed attempts recently
+    pub fn check_ip(&self, ip: &str) -> anyhow::Result<()> {
+        match self.ip_throttle.blocked_for(ip) {
+            Some(secs) => bail!(RetryAfter(secs)),
+            None => Ok(()),
+        }
+    }
+
+    /// Record a login or registration from the IP address, failures slow it down exponentially
+    pub fn record_ip_attempt(&self, ip: &str, failed: bool) {
+        if failed {
+            self.ip_throttle.record_failure(ip);
+        } else {
+            self.ip_throttle.record_success(ip);
+        }
+    }
+
+    pub async fn register(&self, info: RegisterRequest, ip: &str) -> anyhow::Result<()> {
+        if self.store.find_user_by_mail(&info.mail).await?.is_some() {
+            bail!(ApiError::DupEmail);
+        }
+
+        if self.store.find_user_by_name(&info.name).await?.is_some() {
+            bail!(ApiError::DupUsername);
+        }
+
+        let hashed_password = blake2_str(info.password.as_bytes());
+        let user = User {
+            mail: info.mail.clone(),
+            name: info.name,
+            password: hashed_password,
+            devices: vec![],
+            verified: self.mail_sender.is_none(),
+            role: Role::User,
+            disabled: false,
+            deletion_due_at: None,
+            language: String::new(),
+            timezone: String::new(),
+            oidc_subject: None,
+            orgs: vec![DEFAULT_ORG.to_string()],
+            org: DEFAULT_ORG.to_string(),
+        };
+        let verified = user.verified;
+        self.store.insert_user(user).await?;
+        self.audit(&info.mail, AuditAction::Register, &info.mail, vec![], ip)
+            .await?;
+        if !verified {
+            // the user is registered anyway and can ask for another mail
+            if let Err(err) = self.send_verification(&info.mail, None).await {
+                eprintln!(
+                    "Failed to send verification mail to {}, err = {:#}",
+                    info.mail, err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a new verification link, fails with `RetryAfter` if the previous one was sent just
+    /// now and with `InvalidRequest` if the user has been verified
+    pub async fn resend_verification(&self, info: ResendVerificationRequest) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.resend_verification_mail(&session.mail).await
+    }
+
+    pub async fn resend_verification_mail(&self, mail: &str) -> anyhow::Result<()> {
+        match self.store.find_user_by_mail(mail).await? {
+            Some(user) if user.verified => bail!(ApiError::InvalidRequest),
+            Some(_) => {}
+            None => bail!(ApiError::NoUser),
+        }
+        self.check_mail_interval(mail).await?;
+        self.send_verification(mail, None).await
+    }
+
+    /// Fails with `RetryAfter` if a verification mail of the user was sent just now
+    async fn check_mail_interval(&self, mail: &str) -> anyhow::Result<()> {
+        if let Some(latest) = self.store.find_latest_mail_verification(mail).await? {
+            let next_ms = latest.created_at + MAIL_RESEND_INTERVAL_SECS * 1000;
+            let remaining_ms = next_ms - Utc::now().timestamp_millis();
+            if remaining_ms > 0 {
+                bail!(RetryAfter(((remaining_ms + 999) / 1000) as u64));
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a verification link of the mail of a user, or of `new_mail` to change it to. The link
+    /// of a change is sent to the new address.
+    async fn send_verification(&self, mail: &str, new_mail: Option<&str>) -> anyhow::Result<()> {
+        let sender = match &self.mail_sender {
+            Some(sender) => sender,
+            None => bail!(ApiError::InvalidRequest),
+        };
+        let token = random_string(MAIL_VERIFICATION_TOKEN_LEN);
+        let now = Utc::now();
+        let verification = MailVerification {
+            hashed_token: blake2_str(token.as_bytes()),
+            mail: mail.to_string(),
+            created_at: now.timestamp_millis(),
+            expires_at: (now + Duration::hours(MAIL_VERIFICATION_HOURS)).timestamp_millis(),
+            new_mail: new_mail.map(str::to_string),
+        };
+        self.store.insert_mail_verification(verification).await?;
+        let link = format!("{}/#verify_mail/{}", self.public_url, token);
+        let (to, body) = match new_mail {
+            Some(new_mail) => (
+                new_mail,
+                format!(
+                    "Please confirm the change of the e-mail address of your account to this one \
+                     by opening this link:\n\n{}\n\nThe link expires in {} hours. If you didn't \
+                     ask for it, just ignore this mail.\n",
+                    link, MAIL_VERIFICATION_HOURS
+                ),
+            ),
+            None => (
+                mail,
+                format!(
+                    "Please verify your e-mail address by opening this link:\n\n{}\n\n\
+                     The link expires in {} hours. If you didn't register, just ignore this mail.\n",
+                    link, MAIL_VERIFICATION_HOURS
+                ),
+            ),
+        };
+        sender
+            .send(Mail {
+                to: to.to_string(),
+                subject: "Verify your e-mail address".to_string(),
+                body,
+            })
+            .await
+            .context(ApiError::Net)
+    }
+
+    /// Verify the mail address of a link, every link works only once. Fails with
+    /// `InvalidVerification` if the token is unknown, used or expired. Links of a mail change
+    /// change the mail of the user to the new address.
+    pub async fn verify_mail(&self, token: &str, ip: &str) -> anyhow::Result<()> {
+        let hashed_token = blake2_str(token.trim().as_bytes());
+        let verification = match self.store.take_mail_verification(&hashed_token).await? {
+            Some(verification) => verification,
+            None => bail!(ApiError::InvalidVerification),
+        };
+        if verification.expires_at < Utc::now().timestamp_millis() {
+            bail!(ApiError::InvalidVerification);
+        }
+        if let Some(new_mail) = &verification.new_mail {
+            // the address may have been taken since the link was sent
+            if self.store.find_user_by_mail(new_mail).await?.is_some() {
+                bail!(ApiError::DupEmail);
+            }
+            return self
+                .change_user_mail(&verification.mail, new_mail, ip)
+                .await;
+        }
+        self.store.set_user_verified(&verification.mail).await?;
+        self.store
+            .delete_mail_verifications(&verification.mail)
+            .await
+    }
+
+    /// Name of the single sign-on provider, `None` if single sign-on isn't enabled
+    pub fn sso_provider_name(&self) -> Option<&str> {
+        self.oidc.as_ref().map(OidcClient::provider_name)
+    }
+
+    /// Start a single sign-on, returns the URL of the provider to send the browser to. Fails with
+    /// `InvalidRequest` if single sign-on isn't enabled.
+    pub async fn sso_authorize(&self) -> anyhow::Result<String> {
+        match &self.oidc {
+            Some(oidc) => oidc.authorize_url().await,
+            None => bail!(ApiError::InvalidRequest),
+        }
+    }
+
+    /// Finish a single sign-on with the code the provider redirected back with. The account at the
+    /// provider is linked to the user with the same mail address by the first login, or to a new
+    /// user if there is none, which needs the provider to have verified the mail
+    /// (`MailNotVerified` otherwise). Two-factor authentication is left to the provider.
+    pub async fn sso_login(&self, state: &str, code: &str, ip: &str) -> anyhow::Result<LoginInfo> {
+        let identity = match &self.oidc {
+            Some(oidc) => oidc.identify(state, code).await?,
+            None => bail!(ApiError::InvalidRequest),
+        };
+        let user = match self
+            .store
+            .find_user_by_oidc_subject(&identity.subject)
+            .await?
+        {
+            Some(user) => user,
+            None => self.link_sso_user(identity, ip).await?,
+        };
+        if user.disabled {
+            self.audit(&user.mail, AuditAction::LoginFailed, &user.mail, vec![], ip)
+                .await?;
+            bail!(ApiError::AccountDisabled);
+        }
+        let mail = user.mail.clone();
+        let info = self.new_session(user, false, Utc::now(), ip).await?;
+        self.audit(&mail, AuditAction::Login, &mail, vec![], ip)
+            .await?;
+        Ok(info)
+    }
+
+    async fn link_sso_user(&self, identity: Identity, ip: &str) -> anyhow::Result<User> {
+        let mail = match identity.mail {
+            Some(mail) if identity.mail_verified => mail,
+            _ => bail!(ApiError::MailNotVerified),
+        };
+        if let Some(mut user) = self.store.find_user_by_mail(&mail).await? {
+            // linked to another account of the provider, which may have had this address before
+            if user.oidc_subject.is_some() {
+                bail!(ApiError::SsoFailed);
+            }
+            self.store
+                .set_user_oidc_subject(&mail, &identity.subject)
+                .await?;
+            let provider_name = self.sso_provider_name().unwrap_or_default();
+            self.audit(
+                &mail,
+                AuditAction::UpdateUser,
+                &mail,
+                vec![change("sso", "", provider_name)],
+                ip,
+            )
+            .await?;
+            user.oidc_subject = Some(identity.subject);
+            user.verified = true;
+            return Ok(user);
+        }
+        self.insert_external_user(
+            &mail,
+            identity.name.as_deref(),
+            Role::User,
+            Some(identity.subject),
+            ip,
+        )
+        .await
+    }
+
+    /// Register a user vouched for by single sign-on or a directory, whose mail address counts as
+    /// verified
+    async fn insert_external_user(
+        &self,
+        mail: &str,
+        name: Option<&str>,
+        role: Role,
+        oidc_subject: Option<String>,
+        ip: &str,
+    ) -> anyhow::Result<User> {
+        let user = User {
+            mail: mail.to_string(),
+            name: self.free_user_name(name).await?,
+            // nobody knows the password, the user logs in by the provider only
+            password: blake2_str(random_string(LOGIN_TOKEN_LEN).as_bytes()),
+            devices: vec![],
+            verified: true,
+            role,
+            disabled: false,
+            deletion_due_at: None,
+            language: String::new(),
+            timezone: String::new(),
+            oidc_subject,
+            orgs: vec![DEFAULT_ORG.to_string()],
+            org: DEFAULT_ORG.to_string(),
+        };
+        self.store.insert_user(user.clone()).await?;
+        self.audit(mail, AuditAction::Register, mail, vec![], ip)
+            .await?;
+        Ok(user)
+    }
+
+    /// A user name that isn't taken, made from the name at the provider so that it has 4 to 32
+    /// letters, digits or underscores like the names entered on registration
+    async fn free_user_name(&self, name: Option<&str>) -> anyhow::Result<String> {
+        let mut base = name
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .take(MAX_EXTERNAL_NAME_BASE_LEN)
+            .collect::<String>();
+        if base.trim_matches('_').len() < 4 {
+            base = "user".to_string();
+        }
+        if self.store.find_user_by_name(&base).await?.is_none() {
+            return Ok(base);
+        }
+        loop {
+            let suffix: u32 = rand::thread_rng().gen_range(0..1_000_000);
+            let name = format!("{}_{:06}", base, suffix);
+            if self.store.find_user_by_name(&name).await?.is_none() {
+                return Ok(name);
+            }
+        }
+    }
+
+    pub async fn logout(&self, login_token: &str) -> anyhow::Result<()> {
+        if self.store.find_login_record(login_token).await?.is_some() {
+            self.store.delete_login_records(login_token).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns `false` if the message has already been inserted
+    pub async fn insert_message(&self, msg: Message) -> anyhow::Result<bool> {
+        let inserted = self.store.insert_message(msg).await?;
+        if !inserted {
+            self.duplicated_message_count
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(inserted)
+    }
+
+    /// Returns the number of messages that have already been inserted
+    pub async fn insert_messages(&self, msgs: Vec<Message>) -> anyhow::Result<u32> {
+        let duplicated = self.store.insert_messages(msgs).await?;
+        self.duplicated_message_count
+            .fetch_add(duplicated as u64, Ordering::Relaxed);
+        Ok(duplicated)
+    }
+
+    /// Number of duplicated messages dropped since the server started
+    pub fn duplicated_message_count(&self) -> u64 {
+        self.duplicated_message_count.load(Ordering::Relaxed)
+    }
+
+    pub async fn fetch_message_keys(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<HashSet<MessageKey>> {
+        self.store
+            .find_message_keys(org, id, start_timestamp, end_timestamp)
+            .await
+    }
+
+    pub async fn import_messages(
+        &self,
+        info: ImportMessagesRequest,
+    ) -> anyhow::Result<ImportReport> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::DeviceWrite)
+            .await?;
+        self.ensure_device_access(&session.mail, &session.org, &info.id)
+            .await?;
+
+        let format: ImportFormat = info.format.parse()?;
+        import::import_messages(
+            self,
+            &session.org,
+            &info.id,
+            format,
+            &info.content,
+            info.dry_run,
+        )
+        .await
+    }
+
+    pub async fn create_device(&self, info: CreateDeviceRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::DeviceWrite)
+            .await?;
+        self.follow_device(
+            &session.mail,
+            &session.mail,
+            &session.org,
+            &info.id,
+            &info.claim_code,
+            ip,
+        )
+        .await
+    }
+
+    pub async fn provision_device(
+        &self,
+        info: ProvisionDeviceRequest,
+        ip: &str,
+    ) -> anyhow::Result<(String, String)> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        let code = self
+            .register_device(&session.mail, &session.org, &info.id, &info.name, ip)
+            .await?;
+        Ok((info.id.trim().to_string(), code))
+    }
+
+    pub async fn remove_device(&self, info: RemoveDeviceRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::DeviceWrite)
+            .await?;
+        self.unfollow_device(&session.mail, &session.mail, &session.org, &info.id, ip)
+            .await
+    }
+
+    pub async fn fetch_device_profile(
+        &self,
+        info: FetchDeviceProfileRequest,
+    ) -> anyhow::Result<DeviceInfo> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::ReadOnly)
+            .await?;
+        self.ensure_device_access(&session.mail, &session.org, &info.id)
+            .await?;
+        self.device_profile(&session.org, &info.id).await
+    }
+
+    pub async fn fetch_api_key_list(
+        &self,
+        info: FetchApiKeyListRequest,
+    ) -> anyhow::Result<Vec<ApiKeyInfo>> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.api_keys(&session.mail).await
+    }
+
+    pub async fn create_api_key(
+        &self,
+        info: CreateApiKeyRequest,
+    ) -> anyhow::Result<(String, ApiKeyInfo)> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.new_api_key(
+            &session.mail,
+            &session.org,
+            &info.name,
+            info.scope,
+            info.expires_at,
+        )
+        .await
+    }
+
+    pub async fn revoke_api_key(&self, info: RevokeApiKeyRequest) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.delete_api_key(&session.mail, &info.id).await
+    }
+
+    pub async fn fetch_totp_status(
+        &self,
+        info: FetchTotpStatusRequest,
+    ) -> anyhow::Result<(bool, u32)> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.totp_status(&session.mail).await
+    }
+
+    pub async fn enroll_totp(&self, info: EnrollTotpRequest) -> anyhow::Result<(String, String)> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.new_totp(&session.mail).await
+    }
+
+    pub async fn confirm_totp(&self, info: ConfirmTotpRequest) -> anyhow::Result<Vec<String>> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.enable_totp(&session.mail, &info.code).await
+    }
+
+    pub async fn disable_totp(&self, info: DisableTotpRequest) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.remove_totp(&session.mail, &info.code).await
+    }
+
+    pub async fn fetch_profile(&self, info: FetchProfileRequest) -> anyhow::Result<ProfileInfo> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.profile(&session.mail).await
+    }
+
+    pub async fn delete_account(&self, info: DeleteAccountRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.request_deletion(&session, &info.password, info.plain_password.as_deref(), ip)
+            .await
+    }
+
+    pub async fn cancel_account_deletion(
+        &self,
+        info: CancelAccountDeletionRequest,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.cancel_deletion(&session.mail, ip).await
+    }
+
+    pub async fn update_profile(&self, info: UpdateProfileRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.set_profile(&session.mail, info.name, info.language, info.timezone, ip)
+            .await
+    }
+
+    pub async fn change_mail(&self, info: ChangeMailRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.request_mail_change(
+            &session,
+            &info.mail,
+            &info.password,
+            info.plain_password.as_deref(),
+            ip,
+        )
+        .await
+    }
+
+    pub async fn export_account(&self, info: ExportAccountRequest) -> anyhow::Result<Vec<u8>> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        export::export_account(self, &session.mail).await
+    }
+
+    pub async fn fetch_user_list(
+        &self,
+        info: FetchUserListRequest,
+    ) -> anyhow::Result<(u32, Vec<UserInfo>)> {
+        self.ensure_admin(&info.login_token).await?;
+        self.users(&info.query, info.first_index, info.limit).await
+    }
+
+    pub async fn modify_user(&self, info: UpdateUserRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        self.update_user(&session.mail, &info.mail, info.role, info.disabled, ip)
+            .await
+    }
+
+    pub async fn remove_user(&self, info: AdminUserRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        self.delete_user(&session.mail, &info.mail, ip).await
+    }
+
+    pub async fn impersonate_user(
+        &self,
+        info: AdminUserRequest,
+        ip: &str,
+    ) -> anyhow::Result<LoginInfo> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        self.impersonate(&session.mail, &info.mail, ip).await
+    }
+
+    pub async fn reset_user_totp(&self, info: AdminUserRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        self.admin_reset_totp(&session.mail, &info.mail, ip).await
+    }
+
+    pub async fn fetch_all_devices(
+        &self,
+        info: FetchAllDevicesRequest,
+    ) -> anyhow::Result<(u32, Vec<DeviceInfo>)> {
+        self.ensure_admin(&info.login_token).await?;
+        self.all_devices(info.first_index, info.limit).await
+    }
+
+    pub async fn fetch_org_list(
+        &self,
+        info: FetchOrgListRequest,
+    ) -> anyhow::Result<(String, Vec<OrgInfo>)> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::ReadOnly)
+            .await?;
+        self.user_orgs(&session).await
+    }
+
+    pub async fn switch_org(&self, info: SwitchOrgRequest) -> anyhow::Result<()> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::Admin)
+            .await?;
+        self.set_current_org(&session.mail, &info.org).await
+    }
+
+    pub async fn fetch_all_orgs(&self, info: FetchOrgListRequest) -> anyhow::Result<Vec<OrgInfo>> {
+        self.ensure_admin(&info.login_token).await?;
+        self.all_orgs().await
+    }
+
+    pub async fn create_org(&self, info: CreateOrgRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        self.new_org(&session.mail, &info.id, &info.name, ip).await
+    }
+
+    pub async fn add_org_member(&self, info: OrgMemberRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        self.add_member(&session.mail, &info.org, &info.mail, ip)
+            .await
+    }
+
+    pub async fn remove_org_member(&self, info: OrgMemberRequest, ip: &str) -> anyhow::Result<()> {
+        let session = self.ensure_admin(&info.login_token).await?;
+        self.remove_member(&session.mail, &info.org, &info.mail, ip)
+            .await
+    }
+
+    pub async fn fetch_audit_log(
+        &self,
+        info: FetchAuditLogRequest,
+    ) -> anyhow::Result<(u32, Vec<AuditEntryInfo>)> {
+        self.ensure_admin(&info.login_token).await?;
+        let filter = AuditFilter {
+            actor: Some(info.actor).filter(|actor| !actor.is_empty()),
+            action: info.action,
+            target: Some(info.target).filter(|target| !target.is_empty()),
+            from: info.from.unwrap_or(0),
+            to: info.to.unwrap_or(i64::MAX),
+        };
+        self.audit_log(&filter, info.first_index, info.limit).await
+    }
+
+    pub async fn send_command(
+        &self,
+        info: SendCommandRequest,
+        ip: &str,
+    ) -> anyhow::Result<CommandInfo> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::DeviceWrite)
+            .await?;
+        self.issue_command(
+            &session.mail,
+            &session.org,
+            &info.id,
+            &info.name,
+            &info.params,
+            ip,
+        )
+        .await
+    }
+
+    pub async fn fetch_command_list(
+        &self,
+        info: FetchCommandListRequest,
+    ) -> anyhow::Result<(u32, Vec<CommandInfo>)> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::ReadOnly)
+            .await?;
+        self.ensure_device_access(&session.mail, &session.org, &info.id)
+            .await?;
+        self.commands(&session.org, &info.id, info.first_index, info.limit)
+            .await
+    }
+
+    pub async fn fetch_device_twin(
+        &self,
+        info: FetchDeviceTwinRequest,
+    ) -> anyhow::Result<DeviceTwin> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::ReadOnly)
+            .await?;
+        self.ensure_device_access(&session.mail, &session.org, &info.id)
+            .await?;
+        self.device_twin(&session.org, &info.id).await
+    }
+
+    pub async fn update_device_twin(
+        &self,
+        info: UpdateDeviceTwinRequest,
+        ip: &str,
+    ) -> anyhow::Result<DeviceTwin> {
+        let session = self
+            .ensure_login(&info.login_token, ApiKeyScope::DeviceWrite)
+            .await?;
+        self.update_desired(
+            &session.mail,
+            &session.org,
+            &info.id,
+            &info.desired,
+            info.version,
+            ip,
+        )
+        .await
+    }
+
+    /// Follow a provisioned device of an organization. The first user to follow it claims it with
+    /// the claim code and becomes the owner, after that only the owner can follow it again, which
+    /// needs no code. Fails with `MailNotVerified` if the user hasn't verified the mail address,
+    /// `Forbidden` if the user isn't a member of the organization, `InvalidClaimCode` if the code
+    /// doesn't match and `DeviceClaimed` if someone else owns the device. Recorded in the audit log
+    /// as done by `actor`.
+    pub async fn follow_device(
+        &self,
+        actor: &str,
+        mail: &str,
+        org: &str,
+        id: &str,
+        claim_code: &str,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        match self.store.find_user_by_mail(mail).await? {
+            Some(user) if !user.verified => bail!(ApiError::MailNotVerified),
+            Some(user) if !user.orgs.iter().any(|o| o == org) => bail!(ApiError::Forbidden),
+            Some(_) => {}
+            None => bail!(ApiError::NoUser),
+        }
+
+        let device = match self.store.find_device(org, id).await? {
+            Some(device) => device,
+            None => bail!(ApiError::NoDevice),
+        };
+        let mut changes = vec![];
+        match device.owner.as_deref() {
+            Some(owner) if owner == mail => {}
+            Some(_) => bail!(ApiError::DeviceClaimed),
+            None => {
+                let hashed_code = hash_code(claim_code);
+                if device.claim_code.as_deref() != Some(hashed_code.as_str()) {
+                    bail!(ApiError::InvalidClaimCode);
+                }
+                // somebody else may have claimed it in the meantime
+                if !self.store.claim_device(org, id, mail, &hashed_code).await? {
+                    bail!(ApiError::DeviceClaimed);
+                }
+                changes.push(change("owner", "", mail));
+            }
+        }
+
+        self.store.add_user_device(mail, org, id).await?;
+        self.audit(actor, AuditAction::CreateDevice, id, changes, ip)
+            .await
+    }
+
+    /// Register a device in an organization before anyone follows it, and return the claim code a
+    /// user needs to claim it. Provisioning an unclaimed device again replaces its claim code.
+    pub async fn register_device(
+        &self,
+        actor: &str,
+        org: &str,
+        id: &str,
+        name: &str,
+        ip: &str,
+    ) -> anyhow::Result<String> {
+        let id = id.trim();
+        let name = name.trim();
+        if id.is_empty() {
+            bail!(ApiError::InvalidRequest);
+        }
+
+        let claim_code = new_claim_code();
+        let hashed_code = hash_code(&claim_code);
+        let mut changes = vec![];
+        match self.store.find_device(org, id).await? {
+            Some(device) if device.owner.is_some() => bail!(ApiError::DeviceClaimed),
+            Some(_) => {
+                self.store
+                    .set_device_claim_code(org, id, &hashed_code)
+                    .await?
+            }
+            None => {
+                let name = if name.is_empty() { id } else { name };
+                let dev = Device {
+                    org: org.to_string(),
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    info: "".to_string(),
+                    claim_code: Some(hashed_code),
+                    owner: None,
+                    desired: "{}".to_string(),
+                    desired_version: 0,
+                    reported: "{}".to_string(),
+                    reported_version: 0,
+                    reported_at: 0,
+                };
+                self.store.insert_device(dev).await?;
+                changes.push(change("name", "", name));
+            }
+        }
+
+        self.audit(actor, AuditAction::ProvisionDevice, id, changes, ip)
+            .await?;
+        Ok(claim_code)
+    }
+
+    /// Stop following a device, recorded in the audit log as done by `actor`
+    pub async fn unfollow_device(
+        &self,
+        actor: &str,
+        mail: &str,
+        org: &str,
+        id: &str,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let user = self.store.find_user_by_mail(mail).await?;
+        if user.is_none() {
+            bail!(ApiError::NoUser);
+        }
+
+        if !follows(&user.unwrap(), org, id) {
+            bail!(ApiError::NoDevice);
+        }
+
+        self.store.remove_user_device(mail, org, id).await?;
+        self.audit(actor, AuditAction::RemoveDevice, id, vec![], ip)
+            .await
+    }
+
+    /// Fails with `Forbidden` if the device exists but is not followed by the user
+    pub async fn ensure_device_access(
+        &self,
+        mail: &str,
+        org: &str,
+        id: &str,
+    ) -> anyhow::Result<()> {
+        let follows = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => follows(&user, org, id),
+            None => bail!(ApiError::NoUser),
+        };
+        if follows {
+            Ok(())
+        } else if self.store.find_device(org, id).await?.is_some() {
+            bail!(ApiError::Forbidden)
+        } else {
+            bail!(ApiError::NoDevice)
+        }
+    }
+
+    /// A device of the organization of the session, fails with `Forbidden` if the user doesn't
+    /// follow it
+    pub async fn session_device(&self, session: &Session, id: &str) -> anyhow::Result<Device> {
+        self.ensure_device_access(&session.mail, &session.org, id)
+            .await?;
+        self.device(&session.org, id).await
+    }
+
+    /// Rename a device the user of the session follows or change its description, `None` keeps
+    /// them. The session needs the `DeviceWrite` scope.
+    pub async fn modify_device(
+        &self,
+        session: &Session,
+        id: &str,
+        name: Option<String>,
+        info: Option<String>,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        session.require(ApiKeyScope::DeviceWrite)?;
+        let device = self.session_device(session, id).await?;
+        let name = name.unwrap_or(device.name);
+        let info = info.unwrap_or(device.info);
+        self.update_device(&session.mail, &session.org, id, &name, &info, ip)
+            .await
+    }
+
+    /// Rename a device or change its description, recorded in the audit log as done by `actor`
+    pub async fn update_device(
+        &self,
+        actor: &str,
+        org: &str,
+        id: &str,
+        name: &str,
+        info: &str,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let device = match self.store.find_device(org, id).await? {
+            Some(device) => device,
+            None => bail!(ApiError::NoDevice),
+        };
+
+        self.store.update_device(org, id, name, info).await?;
+        let changes = [
+            ("name", device.name.as_str(), name),
+            ("info", device.info.as_str(), info),
+        ]
+        .iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| change(field, before, after))
+        .collect();
+        self.audit(actor, AuditAction::ModifyDevice, id, changes, ip)
+            .await
+    }
+
+    /// Send a command to a device followed by the user. The command is returned as it is after
+    /// publishing, `sent`, or `failed` with the reason as the result if it couldn't be published.
+    pub async fn issue_command(
+        &self,
+        mail: &str,
+        org: &str,
+        device_id: &str,
+        name: &str,
+        params: &str,
+        ip: &str,
+    ) -> anyhow::Result<CommandInfo> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!(ApiError::InvalidRequest);
+        }
+        self.ensure_device_access(mail, org, device_id).await?;
+
+        let now = Utc::now().timestamp_millis();
+        let mut command = Command {
+            id: random_string(COMMAND_ID_LEN),
+            org: org.to_string(),
+            device_id: device_id.to_string(),
+            name: name.to_string(),
+            params: params.to_string(),
+            status: CommandStatus::Pending,
+            issued_by: mail.to_string(),
+            created_at: now,
+            updated_at: now,
+            result: "".to_string(),
+        };
+        self.store.insert_command(command.clone()).await?;
+        self.audit(
+            mail,
+            AuditAction::SendCommand,
+            device_id,
+            vec![change("name", "", name)],
+            ip,
+        )
+        .await?;
+
+        let (status, result) = match self.publish_command(&command) {
+            Ok(()) => (CommandStatus::Sent, "".to_string()),
+            Err(err) => (CommandStatus::Failed, format!("{:#}", err)),
+        };
+        let updated_at = Utc::now().timestamp_millis();
+        // the device may have replied already
+        if self
+            .store
+            .update_command_status(&command.id, status, &result, updated_at)
+            .await?
+        {
+            command.status = status;
+            command.result = result;
+            command.updated_at = updated_at;
+        } else if let Some(stored) = self.store.find_command(&command.id).await? {
+            command = stored;
+        }
+        Ok(command_info(command))
+    }
+
+    fn publish_command(&self, command: &Command) -> anyhow::Result<()> {
+        let publisher = match &self.command_publisher {
+            Some(publisher) => publisher,
+            None => bail!("MQTT is not connected"),
+        };
+        let payload = serde_json::to_vec(&CommandMqtt {
+            id: command.id.clone(),
+            name: command.name.clone(),
+            params: command.params.clone(),
+            timestamp: command.created_at,
+        })
+        .context(ApiError::Unknown)?;
+        publisher.publish(
+            &mqtt::command_topic(&command.org, &command.device_id),
+            payload,
+        )
+    }
+
+    /// Record the reply of device `client_id` to a command. Returns `false` if the command isn't
+    /// one sent to that device or it is already done, e.g. timed out.
+    pub async fn acknowledge_command(
+        &self,
+        org: &str,
+        client_id: &str,
+        command_id: &str,
+        success: bool,
+        result: &str,
+    ) -> anyhow::Result<bool> {
+        match self.store.find_command(command_id).await? {
+            Some(command) if command.org == org && command.device_id == client_id => {}
+            _ => return Ok(false),
+        }
+        let status = if success {
+            CommandStatus::Acked
+        } else {
+            CommandStatus::Failed
+        };
+        self.store
+            .update_command_status(command_id, status, result, Utc::now().timestamp_millis())
+            .await
+    }
+
+    /// Time out the commands that haven't been replied within the command timeout, returns how
+    /// many there were
+    pub async fn expire_commands(&self) -> anyhow::Result<u32> {
+        let now = Utc::now();
+        self.store
+            .expire_commands(
+                (now - self.command_timeout).timestamp_millis(),
+                now.timestamp_millis(),
+            )
+            .await
+    }
+
+    /// Count of commands sent to a device and a page of them, the latest first
+    pub async fn commands(
+        &self,
+        org: &str,
+        device_id: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<(u32, Vec<CommandInfo>)> {
+        let count = self.store.count_commands(org, device_id).await?;
+        let commands = self
+            .store
+            .find_commands(org, device_id, skip, limit)
+            .await?
+            .into_iter()
+            .map(command_info)
+            .collect();
+        Ok((count, commands))
+    }
+
+    /// Desired configuration and reported state of a device, and the delta between them
+    pub async fn device_twin(&self, org: &str, id: &str) -> anyhow::Result<DeviceTwin> {
+        let device = self.device(org, id).await?;
+        twin_info(&device)
+    }
+
+    /// Replace the desired configuration of a device followed by the user with the JSON object
+    /// `desired`, and publish the delta to the device. Fails with `VersionConflict` if the
+    /// desired configuration isn't at `version` any more, i.e. someone else has changed it since.
+    pub async fn update_desired(
+        &self,
+        mail: &str,
+        org: &str,
+        id: &str,
+        desired: &str,
+        version: i64,
+        ip: &str,
+    ) -> anyhow::Result<DeviceTwin> {
+        let desired = parse_object(desired).context(ApiError::InvalidRequest)?;
+        self.ensure_device_access(mail, org, id).await?;
+
+        let device = self.device(org, id).await?;
+        if device.desired_version != version {
+            bail!(ApiError::VersionConflict);
+        }
+        let desired = Value::Object(desired).to_string();
+        if !self
+            .store
+            .set_device_desired(org, id, &desired, version)
+            .await?
+        {
+            bail!(ApiError::VersionConflict);
+        }
+        self.audit(
+            mail,
+            AuditAction::UpdateDesired,
+            id,
+            vec![change("desired", &device.desired, &desired)],
+            ip,
+        )
+        .await?;
+
+        let device = Device {
+            desired,
+            desired_version: version + 1,
+            ..device
+        };
+        // the device gets the delta again when it reports its state
+        if let Err(err) = self.publish_delta(&device) {
+            eprintln!("Failed to publish the delta of {}, err = {:#}", id, err);
+        }
+        twin_info(&device)
+    }
+
+    /// Merge a report of a device into its reported state, where `null` members remove what is
+    /// there, and publish what still differs from the desired configuration. Devices report
+    /// their state when they (re)connect, so that they get the changes they have missed.
+    pub async fn report_state(
+        &self,
+        org: &str,
+        id: &str,
+        report: Map<String, Value>,
+    ) -> anyhow::Result<()> {
+        for _ in 0..MAX_REPORT_ATTEMPTS {
+            let device = self.device(org, id).await?;
+            let mut reported = parse_object(&device.reported).context(ApiError::Unknown)?;
+            merge_reported(&mut reported, report.clone());
+            let reported = Value::Object(reported).to_string();
+            let now = Utc::now().timestamp_millis();
+            if self
+                .store
+                .set_device_reported(org, id, &reported, device.reported_version, now)
+                .await?
+            {
+                let device = Device {
+                    reported,
+                    reported_version: device.reported_version + 1,
+                    reported_at: now,
+                    ..device
+                };
+                return self.publish_delta(&device);
+            }
+        }
+        bail!(ApiError::VersionConflict)
+    }
+
+    /// Publish the part of the desired configuration a device doesn't report yet, if there is
+    /// any and there is a publisher
+    fn publish_delta(&self, device: &Device) -> anyhow::Result<()> {
+        let publisher = match &self.command_publisher {
+            Some(publisher) => publisher,
+            None => return Ok(()),
+        };
+        let delta = twin_delta(
+            &parse_object(&device.desired).context(ApiError::Unknown)?,
+            &parse_object(&device.reported).context(ApiError::Unknown)?,
+        );
+        if delta.is_empty() {
+            return Ok(());
+        }
+        let payload = serde_json::to_vec(&DeltaMqtt {
+            version: device.desired_version,
+            state: Value::Object(delta),
+            timestamp: Utc::now().timestamp_millis(),
+        })
+        .context(ApiError::Unknown)?;
+        publisher.publish(&mqtt::twin_topic(&device.org, &device.id), payload)
+    }
+
+    pub async fn device(&self, org: &str, id: &str) -> anyhow::Result<Device> {
+        if let Some(device) = self.store.find_device(org, id).await? {
+            Ok(device)
+        } else {
+            bail!(ApiError::NoDevice)
+        }
+    }
+
+    pub async fn device_profile(&self, org: &str, id: &str) -> anyhow::Result<DeviceInfo> {
+        let dev = self.device(org, id).await?;
+        self.device_info(dev).await
+    }
+
+    /// Count of the messages of a device the user of the session follows in a time range, and a
+    /// page of them
+    pub async fn device_messages(
+        &self,
+        session: &Session,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<(u32, Vec<MessageInfo>)> {
+        self.ensure_device_access(&session.mail, &session.org, id)
+            .await?;
+        self.messages(
+            &session.org,
+            id,
+            start_timestamp,
+            end_timestamp,
+            skip,
+            limit,
+        )
+        .await
+    }
+
+    /// Devices of an organization followed by a user
+    pub async fn user_devices(&self, mail: &str, org: &str) -> anyhow::Result<Vec<DeviceInfo>> {
+        self.followed_devices(mail, Some(org)).await
+    }
+
+    /// Devices of every organization followed by a user
+    pub async fn all_user_devices(&self, mail: &str) -> anyhow::Result<Vec<DeviceInfo>> {
+        self.followed_devices(mail, None).await
+    }
+
+    async fn followed_devices(
+        &self,
+        mail: &str,
+        org: Option<&str>,
+    ) -> anyhow::Result<Vec<DeviceInfo>> {
+        let user = self.store.find_user_by_mail(mail).await?;
+        if user.is_none() {
+            bail!(ApiError::NoUser);
+        }
+        let user = user.unwrap();
+
+        let mut devices = Vec::with_capacity(user.devices.len());
+        for followed in &user.devices {
+            if org.map_or(false, |org| followed.org != org) {
+                continue;
+            }
+            if let Some(dev) = self.store.find_device(&followed.org, &followed.id).await? {
+                devices.push(self.device_info(dev).await?);
+            } else {
+                bail!(ApiError::NoDevice);
+            }
+        }
+
+        Ok(devices)
+    }
+
+    /// Count of messages in the time range and a page of them, the latest first
+    pub async fn messages(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<(u32, Vec<MessageInfo>)> {
+        let count = self
+            .store
+            .count_messages_in_range(org, id, start_timestamp, end_timestamp)
+            .await?;
+        let messages = self
+            .store
+            .find_messages(org, id, start_timestamp, end_timestamp, skip, limit)
+            .await?
+            .into_iter()
+            .map(|msg| MessageInfo {
+                id: msg.id,
+                info: msg.info,
+                value: msg.value as u32,
+                alert: msg.alert,
+                lng: msg.lng,
+                lat: msg.lat,
+                timestamp: msg.timestamp,
+            })
+            .collect();
+
+        Ok((count, messages))
+    }
+
+    /// API keys of a user, the oldest first
+    pub async fn api_keys(&self, mail: &str) -> anyhow::Result<Vec<ApiKeyInfo>> {
+        let keys = self.store.find_api_keys(mail).await?;
+        Ok(keys.into_iter().map(api_key_info).collect())
+    }
+
+    /// Create an API key, returns the key itself which can't be fetched again
+    pub async fn new_api_key(
+        &self,
+        mail: &str,
+        org: &str,
+        name: &str,
+        scope: ApiKeyScope,
+        expires_at: Option<i64>,
+    ) -> anyhow::Result<(String, ApiKeyInfo)> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_API_KEY_NAME_LEN {
+            bail!(ApiError::InvalidRequest);
+        }
+        let now = Utc::now().timestamp_millis();
+        if expires_at.map_or(false, |expires_at| expires_at <= now) {
+            bail!(ApiError::InvalidRequest);
+        }
+
+        let key = format!("{}{}", API_KEY_PREFIX, random_string(API_KEY_SECRET_LEN));
+        let api_key = ApiKey {
+            id: random_string(API_KEY_ID_LEN),
+            mail: mail.to_string(),
+            org: org.to_string(),
+            name: name.to_string(),
+            scope,
+            hashed_key: blake2_str(key.as_bytes()),
+            created_at: now,
+            expires_at,
+            last_used_at: None,
+        };
+        self.store.insert_api_key(api_key.clone()).await?;
+        Ok((key, api_key_info(api_key)))
+    }
 
-#[derive(Deserialize, Serialize)]
-pub struct Message {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    _id: Option<bson::oid::ObjectId>,
-    id: String,
-    info: String,
-    value: i32,
-    alert: bool,
-    lng: f64,
-    lat: f64,
-    timestamp: i64,
-}
+    pub async fn delete_api_key(&self, mail: &str, id: &str) -> anyhow::Result<()> {
+        if !self.store.delete_api_key(mail, id).await? {
+            bail!(ApiError::NoApiKey);
+        }
+        Ok(())
+    }
 
-impl Database {
-    pub async fn new(db_url: String) -> anyhow::Result<Self> {
-        let options =
-            ClientOptions::parse_with_resolver_config(&db_url, ResolverConfig::cloudflare())
-                .await?;
-        let client = Client::with_options(options)?;
-        let database = client.database("bs_proj");
-        let users = database.collection("users");
-        let devices = database.collection("devices");
-        let messages = database.collection("messages");
-        let login_records = database.collection("login_records");
-
-        Ok(Self {
-            users,
-            devices,
-            messages,
-            login_records,
+    /// Whether two-factor authentication is enabled, and the number of unused recovery codes
+    pub async fn totp_status(&self, mail: &str) -> anyhow::Result<(bool, u32)> {
+        Ok(match self.store.find_totp(mail).await? {
+            Some(totp) if totp.confirmed => (true, totp.recovery_codes.len() as u32),
+            _ => (false, 0),
         })
     }
 
-    pub async fn login(&self, info: LoginRequest) -> anyhow::Result<(String, String, String)> {
-        let filter = doc! {
-            "mail": info.mail
+    /// Generate a TOTP secret, returns it and its provisioning URI. Two-factor authentication is
+    /// enabled once a code of it is confirmed by `enable_totp`. Fails with `InvalidRequest` if it
+    /// is already enabled.
+    pub async fn new_totp(&self, mail: &str) -> anyhow::Result<(String, String)> {
+        if self.totp_status(mail).await?.0 {
+            bail!(ApiError::InvalidRequest);
+        }
+        let secret = totp::generate_secret();
+        let uri = totp::provisioning_uri(TOTP_ISSUER, mail, &secret);
+        let totp = Totp {
+            mail: mail.to_string(),
+            secret: secret.clone(),
+            confirmed: false,
+            last_step: 0,
+            recovery_codes: vec![],
         };
+        self.store.set_totp(totp).await?;
+        Ok((secret, uri))
+    }
 
-        if let Some(doc) = self
-            .users
-            .find_one(filter, None)
-            .await
-            .context("error-net")?
-        {
-            let user =
-                bson::from_bson::<User>(bson::Bson::Document(doc)).context("error-unknown")?;
-            let hashed_password = blake2_str(info.password.as_bytes());
-            return if user.password == hashed_password {
-                let login_token = blake2_str(user.mail.as_bytes());
-
-                let new_record = doc! {
-                    "login_token": login_token.clone(),
-                    "login_time": Utc::now(),
-                };
-                self.login_records
-                    .insert_one(new_record, None)
-                    .await
-                    .context("error-net")?;
-                Ok((login_token, user.mail, user.name))
-            } else {
-                bail!("error-wrong-password")
-            };
+    /// Enable two-factor authentication with a code of the secret from `new_totp`, returns the
+    /// recovery codes which can't be fetched again
+    pub async fn enable_totp(&self, mail: &str, code: &str) -> anyhow::Result<Vec<String>> {
+        let mut totp = match self.store.find_totp(mail).await? {
+            Some(totp) if !totp.confirmed => totp,
+            _ => bail!(ApiError::InvalidRequest),
+        };
+        let step = match totp::verify(&totp.secret, code, Utc::now().timestamp())? {
+            Some(step) => step,
+            None => bail!(ApiError::WrongTotpCode),
+        };
+        if !self.store.use_totp_step(mail, step).await? {
+            bail!(ApiError::WrongTotpCode);
         }
-        bail!("error-no-user")
+        let recovery_codes = (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let code = random_string(RECOVERY_CODE_GROUP_LEN * 2).to_lowercase();
+                format!(
+                    "{}-{}",
+                    &code[..RECOVERY_CODE_GROUP_LEN],
+                    &code[RECOVERY_CODE_GROUP_LEN..]
+                )
+            })
+            .collect::<Vec<_>>();
+        totp.confirmed = true;
+        totp.last_step = step;
+        totp.recovery_codes = recovery_codes.iter().map(|code| hash_code(code)).collect();
+        self.store.set_totp(totp).await?;
+        Ok(recovery_codes)
     }
 
-    pub async fn register(&self, info: RegisterRequest) -> anyhow::Result<()> {
-        let filter = doc! {
-            "mail": &info.mail,
+    /// Disable two-factor authentication, which needs a code or a recovery code
+    pub async fn remove_totp(&self, mail: &str, code: &str) -> anyhow::Result<()> {
+        let totp = match self.store.find_totp(mail).await? {
+            Some(totp) if totp.confirmed => totp,
+            _ => bail!(ApiError::InvalidRequest),
         };
-        if let Some(_) = self
-            .users
-            .find_one(filter, None)
-            .await
-            .context("error-net")?
-        {
-            bail!("error-dup-email");
+        if !self.use_totp_code(&totp, code).await? {
+            bail!(ApiError::WrongTotpCode);
         }
+        self.store.delete_totp(mail).await
+    }
 
-        let filter = doc! {
-            "name": &info.name,
-        };
-        if let Some(_) = self
-            .users
-            .find_one(filter, None)
-            .await
-            .context("error-net")?
-        {
-            bail!("error-dup-username");
+    /// Disable two-factor authentication of a user without a code, for users who have lost both
+    /// their authenticator and recovery codes
+    pub async fn reset_totp(&self, mail: &str) -> anyhow::Result<()> {
+        if self.store.find_user_by_mail(mail).await?.is_none() {
+            bail!(ApiError::NoUser);
         }
+        self.store.delete_totp(mail).await
+    }
 
-        let hashed_password = blake2_str(info.password.as_bytes());
-        let user = User {
-            _id: None,
-            mail: info.mail,
-            name: info.name,
-            password: hashed_password,
-            devices: vec![],
+    pub async fn profile(&self, mail: &str) -> anyhow::Result<ProfileInfo> {
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        let serialized_user = bson::to_bson(&user).context("error-unknown")?;
-        let doc = serialized_user.as_document().context("error-unknown")?;
-        self.users
-            .insert_one(doc.to_owned(), None)
-            .await
-            .context("error-net")?;
-        Ok(())
+        let now = Utc::now().timestamp_millis();
+        let pending_mail = self
+            .store
+            .find_latest_mail_verification(mail)
+            .await?
+            .filter(|verification| verification.expires_at >= now)
+            .and_then(|verification| verification.new_mail);
+        Ok(ProfileInfo {
+            mail: user.mail,
+            name: user.name,
+            role: user.role,
+            verified: user.verified,
+            deletion_due_at: user.deletion_due_at,
+            pending_mail,
+            language: user.language,
+            timezone: user.timezone,
+            sso: user.oidc_subject.is_some(),
+        })
     }
 
-    pub async fn logout(&self, login_token: &str) -> anyhow::Result<()> {
-        let filter = doc! {
-            "login_token": login_token,
+    /// Change the name and preferences of a user, `None` leaves them unchanged. Fails with
+    /// `DupUsername` if the name is taken by another user and with `InvalidRequest` for an
+    /// unknown language or timezone.
+    pub async fn set_profile(
+        &self,
+        mail: &str,
+        name: Option<String>,
+        language: Option<String>,
+        timezone: Option<String>,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        if let Some(_) = self
-            .login_records
-            .find_one(filter.clone(), None)
-            .await
-            .context("error-net")?
+        let name = name.unwrap_or_else(|| user.name.clone());
+        let language = language.unwrap_or_else(|| user.language.clone());
+        let timezone = timezone.unwrap_or_else(|| user.timezone.clone());
+        // names from single sign-on or a directory are kept until the user changes them
+        if !(name == user.name || valid_user_name(&name))
+            || !(language.is_empty() || LANGUAGES.contains(&language.as_str()))
+            || !(timezone.is_empty() || timezone.parse::<Tz>().is_ok())
         {
-            self.login_records
-                .delete_many(filter, None)
-                .await
-                .context("error-net")?;
+            bail!(ApiError::InvalidRequest);
         }
-        Ok(())
-    }
 
-    pub async fn insert_message(&self, msg: Message) -> anyhow::Result<()> {
-        let serialized_msg = bson::to_bson(&msg).context("error-unknown")?;
-        let doc = serialized_msg.as_document().context("error-unknown")?;
-        self.messages
-            .insert_one(doc.to_owned(), None)
+        let mut changes = vec![];
+        if name != user.name {
+            if self.store.find_user_by_name(&name).await?.is_some() {
+                bail!(ApiError::DupUsername);
+            }
+            self.store.set_user_name(mail, &name).await?;
+            changes.push(change("name", &user.name, &name));
+        }
+        if language != user.language || timezone != user.timezone {
+            self.store
+                .set_user_preferences(mail, &language, &timezone)
+                .await?;
+            if language != user.language {
+                changes.push(change("language", &user.language, &language));
+            }
+            if timezone != user.timezone {
+                changes.push(change("timezone", &user.timezone, &timezone));
+            }
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.audit(mail, AuditAction::UpdateUser, mail, changes, ip)
             .await
-            .context("error-net")?;
-        Ok(())
     }
 
-    pub async fn create_device(&self, info: CreateDeviceRequest) -> anyhow::Result<()> {
-        if !self.check_login(&info.login_token).await? {
-            bail!("Login has expired");
+    /// Change the mail of a user to `new_mail` once a link sent to it is opened, or at once if
+    /// mails aren't verified. The user has to authenticate again, see `reauthenticate`.
+    pub async fn request_mail_change(
+        &self,
+        session: &Session,
+        new_mail: &str,
+        password: &str,
+        plain_password: Option<&str>,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let mail = session.mail.as_str();
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
+        };
+        self.reauthenticate(session, &user, password, plain_password)
+            .await?;
+        let new_mail = new_mail.trim();
+        if new_mail.is_empty() || new_mail == mail {
+            bail!(ApiError::InvalidRequest);
+        }
+        if self.store.find_user_by_mail(new_mail).await?.is_some() {
+            bail!(ApiError::DupEmail);
+        }
+        if self.mail_sender.is_none() {
+            return self.change_user_mail(mail, new_mail, ip).await;
         }
+        self.check_mail_interval(mail).await?;
+        self.send_verification(mail, Some(new_mail)).await
+    }
+
+    async fn change_user_mail(&self, mail: &str, new_mail: &str, ip: &str) -> anyhow::Result<()> {
+        self.store.change_user_mail(mail, new_mail).await?;
+        self.audit(
+            new_mail,
+            AuditAction::UpdateUser,
+            new_mail,
+            vec![change("mail", mail, new_mail)],
+            ip,
+        )
+        .await
+    }
 
-        let filter = doc! {
-            "mail": info.mail.clone(),
+    /// Schedule the account to be deleted after the grace period. Every session ends and API keys
+    /// stop working, but the user can still log in and cancel it until then. The user has to
+    /// authenticate again, see `reauthenticate`.
+    pub async fn request_deletion(
+        &self,
+        session: &Session,
+        password: &str,
+        plain_password: Option<&str>,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let mail = session.mail.as_str();
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        if let None = self
-            .users
-            .find_one(filter, None)
+        self.reauthenticate(session, &user, password, plain_password)
+            .await?;
+        if user.deletion_due_at.is_some() {
+            bail!(ApiError::InvalidRequest);
+        }
+        let deletion_due_at = (Utc::now() + self.deletion_grace).timestamp_millis();
+        self.store
+            .set_user_deletion_due(mail, Some(deletion_due_at))
+            .await?;
+        self.store.delete_user_login_records(mail).await?;
+        self.audit(mail, AuditAction::RequestDeletion, mail, vec![], ip)
             .await
-            .context("error-net")?
-        {
-            bail!("error-no-user");
+    }
+
+    /// Fails with `WrongPassword` unless the password of the user is checked again by the provider
+    /// that knows the user, as for logging in, so that a stolen session isn't enough to change
+    /// the account. Single sign-on accounts may send no password, as they may have none, their
+    /// session must have logged in within `REAUTH_SECS` then, and it fails with `LoginExpired`
+    /// otherwise.
+    async fn reauthenticate(
+        &self,
+        session: &Session,
+        user: &User,
+        password: &str,
+        plain_password: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if user.oidc_subject.is_some() && password.is_empty() && plain_password.is_none() {
+            let record = self.store.find_login_record(&session.login_token).await?;
+            let reauth = Duration::seconds(REAUTH_SECS);
+            match record {
+                Some(record) if Utc::now() - record.login_time < reauth => return Ok(()),
+                _ => bail!(ApiError::LoginExpired),
+            }
+        }
+        let credentials = Credentials::with_password(&user.mail, password, plain_password);
+        for provider in &self.auth_providers {
+            match provider.authenticate(&*self.store, &credentials).await? {
+                Authentication::Unknown => continue,
+                Authentication::WrongPassword => break,
+                Authentication::Authenticated { .. } => return Ok(()),
+            }
         }
+        bail!(ApiError::WrongPassword)
+    }
 
-        let filter = doc! {
-            "id": info.id.clone()
-        };
-        if let None = self
-            .devices
-            .find_one(filter, None)
+    /// Fails with `InvalidRequest` if the account isn't going to be deleted
+    pub async fn cancel_deletion(&self, mail: &str, ip: &str) -> anyhow::Result<()> {
+        match self.store.find_user_by_mail(mail).await? {
+            Some(user) if user.deletion_due_at.is_some() => {}
+            Some(_) => bail!(ApiError::InvalidRequest),
+            None => bail!(ApiError::NoUser),
+        }
+        self.store.set_user_deletion_due(mail, None).await?;
+        self.audit(mail, AuditAction::CancelDeletion, mail, vec![], ip)
             .await
-            .context("error-net")?
-        {
-            let dev = Device {
-                _id: None,
-                id: info.id.clone(),
-                name: info.id.clone(),
-                info: "".to_string(),
-            };
-            let serialized_dev = bson::to_bson(&dev).context("error-unknown")?;
-            let doc = serialized_dev.as_document().context("error-unknown")?;
-            self.devices
-                .insert_one(doc.to_owned(), None)
-                .await
-                .context("error-net")?;
+    }
+
+    /// Delete the accounts whose grace period has ended with their sessions, follow lists,
+    /// API keys and two-factor authentication, returns how many are deleted. The audit log and
+    /// the devices are kept.
+    pub async fn delete_due_accounts(&self) -> anyhow::Result<u32> {
+        let now = Utc::now().timestamp_millis();
+        let users = self.store.find_users_due_for_deletion(now).await?;
+        for user in &users {
+            self.store.delete_user(&user.mail).await?;
+            self.audit(
+                &user.mail,
+                AuditAction::DeleteAccount,
+                &user.mail,
+                vec![],
+                "",
+            )
+            .await?;
+        }
+        Ok(users.len() as u32)
+    }
+
+    /// Fails with `Forbidden` if the session isn't of an administrator, API keys need the
+    /// `Admin` scope too
+    pub async fn require_admin(&self, session: &Session) -> anyhow::Result<()> {
+        session.require(ApiKeyScope::Admin)?;
+        match self.store.find_user_by_mail(&session.mail).await? {
+            Some(user) if user.role == Role::Admin => Ok(()),
+            _ => bail!(ApiError::Forbidden),
         }
+    }
+
+    async fn ensure_admin(&self, login_token: &str) -> anyhow::Result<Session> {
+        let session = self.ensure_login(login_token, ApiKeyScope::Admin).await?;
+        self.require_admin(&session).await?;
+        Ok(session)
+    }
+
+    /// Count of users matching the query and a page of them
+    pub async fn users(
+        &self,
+        query: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<(u32, Vec<UserInfo>)> {
+        let query = query.trim();
+        let count = self.store.count_users(query).await?;
+        let users = self
+            .store
+            .find_users(query, skip, limit)
+            .await?
+            .into_iter()
+            .map(user_info)
+            .collect();
+        Ok((count, users))
+    }
 
-        let query = doc! {
-            "mail": info.mail
+    /// Change the role of a user or disable it, disabling also ends every session of it.
+    /// Administrators can't change themselves or other administrators, so that none of them
+    /// can be locked out by another one.
+    pub async fn update_user(
+        &self,
+        admin_mail: &str,
+        mail: &str,
+        role: Option<Role>,
+        disabled: Option<bool>,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        if admin_mail == mail {
+            bail!(ApiError::InvalidRequest);
+        }
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        let update = doc! {
-            "$push": {
-                "devices": info.id.clone(),
+        if user.role == Role::Admin {
+            bail!(ApiError::Forbidden);
+        }
+        let mut changes = vec![];
+        if let Some(role) = role {
+            self.store.set_user_role(mail, role).await?;
+            changes.push(change("role", user.role.as_str(), role.as_str()));
+        }
+        if let Some(disabled) = disabled {
+            self.store.set_user_disabled(mail, disabled).await?;
+            if disabled {
+                self.store.delete_user_login_records(mail).await?;
             }
-        };
-        self.users
-            .update_one(query, update, None)
+            changes.push(change(
+                "disabled",
+                &user.disabled.to_string(),
+                &disabled.to_string(),
+            ));
+        }
+        self.audit(admin_mail, AuditAction::UpdateUser, mail, changes, ip)
             .await
-            .context("error-net")?;
-        Ok(())
     }
 
-    pub async fn remove_device(&self, info: RemoveDeviceRequest) -> anyhow::Result<()> {
-        if !self.check_login(&info.login_token).await? {
-            bail!("Login has expired");
+    /// Delete another user, devices followed by it are kept. Other administrators can't be
+    /// deleted.
+    pub async fn delete_user(&self, admin_mail: &str, mail: &str, ip: &str) -> anyhow::Result<()> {
+        if admin_mail == mail {
+            bail!(ApiError::InvalidRequest);
+        }
+        match self.store.find_user_by_mail(mail).await? {
+            Some(user) if user.role == Role::Admin => bail!(ApiError::Forbidden),
+            Some(_) => {}
+            None => bail!(ApiError::NoUser),
         }
+        self.store.delete_user(mail).await?;
+        self.audit(admin_mail, AuditAction::DeleteUser, mail, vec![], ip)
+            .await
+    }
 
-        let filter = doc! {
-            "mail": info.mail.clone(),
+    /// A new session of another user, so that support can see what the user sees. Other
+    /// administrators can't be impersonated.
+    pub async fn impersonate(
+        &self,
+        admin_mail: &str,
+        mail: &str,
+        ip: &str,
+    ) -> anyhow::Result<LoginInfo> {
+        if admin_mail == mail {
+            bail!(ApiError::InvalidRequest);
+        }
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        if let None = self
-            .users
-            .find_one(filter, None)
-            .await
-            .context("error-net")?
-        {
-            bail!("error-no-user");
+        if user.role == Role::Admin {
+            bail!(ApiError::Forbidden);
         }
+        if user.disabled {
+            bail!(ApiError::AccountDisabled);
+        }
+        let info = self.new_session(user, false, Utc::now(), ip).await?;
+        self.audit(admin_mail, AuditAction::Impersonate, mail, vec![], ip)
+            .await?;
+        Ok(info)
+    }
 
-        let filter = doc! {
-            "mail": info.mail.clone(),
-            "devices": {
-                "$elemMatch": {
-                    "$eq": info.id.clone()
-                }
+    /// Disable two-factor authentication of a user who has lost both the authenticator and the
+    /// recovery codes. Other administrators have to be reset by the command line.
+    pub async fn admin_reset_totp(
+        &self,
+        admin_mail: &str,
+        mail: &str,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        if admin_mail == mail {
+            bail!(ApiError::InvalidRequest);
+        }
+        if let Some(user) = self.store.find_user_by_mail(mail).await? {
+            if user.role == Role::Admin {
+                bail!(ApiError::Forbidden);
             }
-        };
-        if let None = self
-            .users
-            .find_one(filter, None)
+        }
+        self.reset_totp(mail).await?;
+        self.audit(admin_mail, AuditAction::ResetTotp, mail, vec![], ip)
             .await
-            .context("error-net")?
-        {
-            bail!("error-no-device");
+    }
+
+    /// Count of every device and a page of them, ordered by organization and id
+    pub async fn all_devices(
+        &self,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<(u32, Vec<DeviceInfo>)> {
+        let count = self.store.count_devices().await?;
+        let mut devices = vec![];
+        for dev in self.store.find_devices(skip, limit).await? {
+            devices.push(self.device_info(dev).await?);
         }
+        Ok((count, devices))
+    }
+
+    /// Count of entries of the audit log matching the filter and a page of them, the latest first
+    pub async fn audit_log(
+        &self,
+        filter: &AuditFilter,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<(u32, Vec<AuditEntryInfo>)> {
+        let count = self.store.count_audit_entries(filter).await?;
+        let entries = self
+            .store
+            .find_audit_entries(filter, skip, limit)
+            .await?
+            .into_iter()
+            .map(audit_entry_info)
+            .collect();
+        Ok((count, entries))
+    }
 
-        let query = doc! {
-            "mail": info.mail
+    /// The organization the session works in and those the user is a member of, ordered by id
+    pub async fn user_orgs(&self, session: &Session) -> anyhow::Result<(String, Vec<OrgInfo>)> {
+        let user = match self.store.find_user_by_mail(&session.mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        let update = doc! {
-            "$pull": {
-                "devices": info.id.clone(),
+        let mut orgs = Vec::with_capacity(user.orgs.len());
+        for id in &user.orgs {
+            if let Some(org) = self.store.find_org(id).await? {
+                orgs.push(org_info(org));
             }
-        };
-        self.users
-            .update_one(query, update, None)
-            .await
-            .context("error-net")?;
-        Ok(())
+        }
+        orgs.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok((session.org.clone(), orgs))
     }
 
-    pub async fn modify_device(&self, info: ModifyDeviceRequest) -> anyhow::Result<()> {
-        if !self.check_login(&info.login_token).await? {
-            bail!("Login has expired");
+    /// Work in another organization, which sessions of the user see from their next request.
+    /// API keys stay in the organization they were created in.
+    pub async fn set_current_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        match self.store.find_user_by_mail(mail).await? {
+            Some(user) if user.orgs.iter().any(|o| o == org) => {}
+            Some(_) => bail!(ApiError::Forbidden),
+            None => bail!(ApiError::NoUser),
         }
+        self.store.set_user_org(mail, org).await
+    }
 
-        let filter = doc! {
-            "id": info.id.clone(),
-        };
-        let device = self
-            .devices
-            .find_one(filter, None)
-            .await
-            .context("error-net")?;
-        if device.is_none() {
-            bail!("error-no-device");
-        }
+    pub async fn all_orgs(&self) -> anyhow::Result<Vec<OrgInfo>> {
+        let orgs = self.store.find_orgs().await?;
+        Ok(orgs.into_iter().map(org_info).collect())
+    }
 
-        let query = doc! {
-            "id": info.id,
-        };
-        let update = doc! {
-            "$set": {
-                "name": info.name,
-                "info": info.info,
-            }
+    /// Create an organization, the administrator creating it becomes a member of it
+    pub async fn new_org(
+        &self,
+        admin_mail: &str,
+        id: &str,
+        name: &str,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let name = name.trim();
+        if !valid_org_id(id) || name.is_empty() || name.chars().count() > MAX_ORG_NAME_LEN {
+            bail!(ApiError::InvalidRequest);
+        }
+        if self.store.find_org(id).await?.is_some() {
+            bail!(ApiError::DupOrg);
+        }
+        let org = Organization {
+            id: id.to_string(),
+            name: name.to_string(),
+            created_at: Utc::now().timestamp_millis(),
         };
-        self.devices
-            .update_one(query, update, None)
-            .await
-            .context("error-net")?;
-        Ok(())
+        self.store.insert_org(org).await?;
+        self.store.add_user_org(admin_mail, id).await?;
+        self.audit(
+            admin_mail,
+            AuditAction::CreateOrg,
+            id,
+            vec![change("name", "", name)],
+            ip,
+        )
+        .await
     }
 
-    pub async fn fetch_device(
+    /// Make a user a member of an organization, nothing is done if it already is one
+    pub async fn add_member(
         &self,
-        info: FetchDeviceRequest,
-    ) -> anyhow::Result<(String, String, String)> {
-        if !self.check_login(&info.login_token).await? {
-            bail!("Login has expired".to_string());
+        admin_mail: &str,
+        org: &str,
+        mail: &str,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        if self.store.find_org(org).await?.is_none() {
+            bail!(ApiError::NoOrg);
         }
-
-        let filter = doc! {
-            "id": info.id
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        let device = self
-            .devices
-            .find_one(filter, None)
-            .await
-            .context("error-net")?;
-        if device.is_none() {
-            bail!("error-no-device");
+        if user.orgs.iter().any(|o| o == org) {
+            return Ok(());
         }
-        let device: Device =
-            bson::from_bson(bson::Bson::Document(device.context("error-unknown")?))
-                .context("error-unknown")?;
-        Ok((device.id, device.name, device.info))
+        self.store.add_user_org(mail, org).await?;
+        self.audit(
+            admin_mail,
+            AuditAction::AddOrgMember,
+            mail,
+            vec![change("org", "", org)],
+            ip,
+        )
+        .await
     }
 
-    pub async fn fetch_device_profile(
+    /// Remove a user from an organization, which also unfollows the devices of it. Users are
+    /// always left in some organization, and move to another one if they were working in it.
+    pub async fn remove_member(
         &self,
-        info: FetchDeviceProfileRequest,
-    ) -> anyhow::Result<DeviceInfo> {
-        if !self.check_login(&info.login_token).await? {
-            bail!("Login has expired");
+        admin_mail: &str,
+        org: &str,
+        mail: &str,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        if self.store.find_org(org).await?.is_none() {
+            bail!(ApiError::NoOrg);
         }
-
-        let filter = doc! {
-            "id": info.id.clone()
+        let user = match self.store.find_user_by_mail(mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
         };
-        if let Some(dev) = self
-            .devices
-            .find_one(filter, None)
-            .await
-            .context("error-net")?
-        {
-            let dev: Device =
-                bson::from_bson(bson::Bson::Document(dev)).context("error-unknown")?;
-
-            let count_filter = doc! {
-                "id": dev.id.clone(),
-            };
-            let message_count = self
-                .messages
-                .count_documents(count_filter, None)
-                .await
-                .context("error-net")? as u32;
-            let count_filter = doc! {
-                "id": dev.id.clone(),
-                "alert": true,
-            };
-            let alert_message_count = self
-                .messages
-                .count_documents(count_filter, None)
-                .await
-                .context("error-net")? as u32;
-
-            Ok(DeviceInfo {
-                id: dev.id,
-                name: dev.name,
-                message_count,
-                alert_message_count,
-            })
-        } else {
-            bail!("error-no-device")
+        let other_org = match user.orgs.iter().find(|o| *o != org) {
+            Some(other_org) if user.orgs.iter().any(|o| o == org) => other_org,
+            _ => bail!(ApiError::InvalidRequest),
+        };
+        if user.org == org {
+            self.store.set_user_org(mail, other_org).await?;
         }
+        self.store.remove_user_org(mail, org).await?;
+        self.audit(
+            admin_mail,
+            AuditAction::RemoveOrgMember,
+            mail,
+            vec![change("org", org, "")],
+            ip,
+        )
+        .await
     }
 
-    pub async fn fetch_device_list(
+    async fn audit(
         &self,
-        info: FetchDeviceListRequest,
-    ) -> anyhow::Result<Vec<DeviceInfo>> {
-        if !self.check_login(&info.login_token).await? {
-            bail!("Login has expired".to_string());
+        actor: &str,
+        action: AuditAction,
+        target: &str,
+        changes: Vec<AuditChange>,
+        ip: &str,
+    ) -> anyhow::Result<()> {
+        let entry = AuditEntry {
+            actor: actor.to_string(),
+            action,
+            target: target.to_string(),
+            changes,
+            ip: ip.to_string(),
+            time: Utc::now().timestamp_millis(),
+        };
+        self.store.insert_audit_entry(entry).await
+    }
+
+    /// Used by the command line to make the first administrator
+    pub async fn set_role(&self, mail: &str, role: Role) -> anyhow::Result<()> {
+        if self.store.find_user_by_mail(mail).await?.is_none() {
+            bail!(ApiError::NoUser);
         }
+        self.store.set_user_role(mail, role).await
+    }
 
-        let filter = doc! {
-            "mail": info.mail.clone(),
+    /// `true` if the user doesn't have two-factor authentication or the code is accepted, fails
+    /// with `TotpRequired` if the user has it but there is no code
+    async fn check_totp(&self, mail: &str, code: Option<&str>) -> anyhow::Result<bool> {
+        let totp = match self.store.find_totp(mail).await? {
+            Some(totp) if totp.confirmed => totp,
+            _ => return Ok(true),
         };
-        let user = self
-            .users
-            .find_one(filter, None)
-            .await
-            .context("error-net")?;
-        if user.is_none() {
-            bail!("error-no-user");
+        match code.map(str::trim) {
+            Some(code) if !code.is_empty() => self.use_totp_code(&totp, code).await,
+            _ => bail!(ApiError::TotpRequired),
         }
-        let user: User = bson::from_bson(bson::Bson::Document(user.context("error-unknown")?))
-            .context("error-unknown")?;
-
-        let mut devices = Vec::with_capacity(user.devices.len());
-        for id in &user.devices {
-            let filter = doc! {
-                "id": id.clone()
-            };
-            if let Some(dev) = self
-                .devices
-                .find_one(filter, None)
-                .await
-                .context("error-net")?
-            {
-                let dev: Device =
-                    bson::from_bson(bson::Bson::Document(dev)).context("error-unknown")?;
+    }
 
-                let count_filter = doc! {
-                    "id": dev.id.clone(),
-                };
-                let message_count = self
-                    .messages
-                    .count_documents(count_filter, None)
-                    .await
-                    .context("error-net")? as u32;
-                let count_filter = doc! {
-                    "id": dev.id.clone(),
-                    "alert": true,
-                };
-                let alert_message_count = self
-                    .messages
-                    .count_documents(count_filter, None)
-                    .await
-                    .context("error-net")? as u32;
-
-                let dev = DeviceInfo {
-                    id: dev.id,
-                    name: dev.name,
-                    message_count,
-                    alert_message_count,
-                };
-                devices.push(dev);
-            } else {
-                bail!("error-no-device");
-            }
+    /// Accept a code of the secret or a recovery code, neither can be used again
+    async fn use_totp_code(&self, totp: &Totp, code: &str) -> anyhow::Result<bool> {
+        if let Some(step) = totp::verify(&totp.secret, code, Utc::now().timestamp())? {
+            return self.store.use_totp_step(&totp.mail, step).await;
         }
+        self.store
+            .use_totp_recovery_code(&totp.mail, &hash_code(code))
+            .await
+    }
 
-        Ok(devices)
+    /// Replace a session with a new one with a new login token, so that clients can keep logged in
+    /// without the password
+    pub async fn refresh_login(&self, login_token: &str) -> anyhow::Result<LoginInfo> {
+        if login_token.starts_with(API_KEY_PREFIX) {
+            bail!(ApiError::InvalidRequest);
+        }
+        let session = match self.session(login_token).await? {
+            Some(session) => session,
+            None => bail!(ApiError::LoginExpired),
+        };
+        let record = match self.store.find_login_record(login_token).await? {
+            Some(record) => record,
+            None => bail!(ApiError::LoginExpired),
+        };
+        let user = match self.store.find_user_by_mail(&session.mail).await? {
+            Some(user) => user,
+            None => bail!(ApiError::NoUser),
+        };
+        if user.disabled {
+            bail!(ApiError::AccountDisabled);
+        }
+        // the session is still the same login
+        let info = self
+            .new_session(user, record.remember, record.login_time, &record.ip)
+            .await?;
+        self.store.delete_login_records(login_token).await?;
+        Ok(info)
     }
 
-    pub async fn fetch_message_list(
+    /// login_time - when the user logged in, refreshed sessions keep the time of the first one
+    async fn new_session(
         &self,
-        info: FetchMessageListRequest,
-    ) -> anyhow::Result<(u32, Vec<MessageInfo>)> {
-        if !self.check_login(&info.login_token).await? {
-            bail!("Login has expired");
+        user: User,
+        remember: bool,
+        login_time: DateTime<Utc>,
+        ip: &str,
+    ) -> anyhow::Result<LoginInfo> {
+        let login_token = random_string(LOGIN_TOKEN_LEN);
+        let now = Utc::now();
+        let new_record = LoginRecord {
+            login_token: login_token.clone(),
+            mail: user.mail.clone(),
+            login_time,
+            last_active: now,
+            remember,
+            ip: ip.to_string(),
+        };
+        self.store.insert_login_record(new_record).await?;
+        Ok(LoginInfo {
+            login_token,
+            mail: user.mail,
+            name: user.name,
+            expires_at: (now + self.idle_timeout(remember)).timestamp_millis(),
+            role: user.role,
+            language: user.language,
+            timezone: user.timezone,
+        })
+    }
+
+    fn idle_timeout(&self, remember: bool) -> Duration {
+        if remember {
+            self.remember_me_timeout
+        } else {
+            self.session_idle_timeout
         }
+    }
 
-        let filter = doc! {
-            "id": info.id.clone(),
-            "timestamp": {
-                "$gte": info.start_timestamp,
-                "$lte": info.end_timestamp,
-            }
-        };
-        let count = self
-            .messages
-            .count_documents(filter.clone(), None)
-            .await
-            .context("error-net")? as u32;
-        let find_options = FindOptions::builder()
-            .sort(doc! { "timestamp": -1 })
-            .build();
-        let mut cursor = self
-            .messages
-            .find(filter, find_options)
-            .await
-            .context("error-net")?
-            .skip(info.first_index);
-        let mut messages = vec![];
-        while let Some(msg) = cursor.next().await {
-            let msg: Message = bson::from_bson(bson::Bson::Document(msg.context("error-unknown")?))
-                .context("error-unknown")?;
-            let msg = MessageInfo {
-                id: msg.id,
-                info: msg.info,
-                value: msg.value as u32,
-                alert: msg.alert,
-                lng: msg.lng,
-                lat: msg.lat,
-                timestamp: msg.timestamp,
-            };
-            messages.push(msg);
-            if messages.len() == info.limit {
-                break;
+    /// The session of a login token or an API key, `None` if it doesn't exist or has expired.
+    /// Sessions of login tokens are renewed, so they only expire after being idle for a while.
+    pub async fn session(&self, login_token: &str) -> anyhow::Result<Option<Session>> {
+        if login_token.starts_with(API_KEY_PREFIX) {
+            return self.api_key_session(login_token).await;
+        }
+
+        if let Some(record) = self.store.find_login_record(login_token).await? {
+            let now = Utc::now();
+            let timeout = self.idle_timeout(record.remember);
+            let idle = now.signed_duration_since(record.last_active);
+            if idle > timeout {
+                self.store.delete_login_records(login_token).await?;
+            } else {
+                let user = match self.store.find_user_by_mail(&record.mail).await? {
+                    Some(user) => user,
+                    None => return Ok(None),
+                };
+                if idle >= Duration::seconds(SESSION_RENEW_INTERVAL_SECS).min(timeout / 4) {
+                    self.store.touch_login_record(login_token, now).await?;
+                }
+                return Ok(Some(Session {
+                    login_token: record.login_token,
+                    mail: record.mail,
+                    scope: ApiKeyScope::Admin,
+                    org: user.org,
+                }));
             }
         }
 
-        Ok((count, messages))
+        Ok(None)
     }
 
-    const MAX_LOGIN_TIME_SECS: i64 = 3600;
+    async fn api_key_session(&self, key: &str) -> anyhow::Result<Option<Session>> {
+        let api_key = match self
+            .store
+            .find_api_key_by_hash(&blake2_str(key.as_bytes()))
+            .await?
+        {
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+        let now = Utc::now().timestamp_millis();
+        if api_key
+            .expires_at
+            .map_or(false, |expires_at| expires_at <= now)
+        {
+            return Ok(None);
+        }
+        // keys stop working when the owner leaves the organization of them
+        match self.store.find_user_by_mail(&api_key.mail).await? {
+            Some(user)
+                if !user.disabled
+                    && user.deletion_due_at.is_none()
+                    && user.orgs.contains(&api_key.org) => {}
+            _ => return Ok(None),
+        }
+        self.store
+            .update_api_key_last_used(&api_key.id, now)
+            .await?;
+        Ok(Some(Session {
+            login_token: key.to_string(),
+            mail: api_key.mail,
+            scope: api_key.scope,
+            org: api_key.org,
+        }))
+    }
 
     pub async fn check_login(&self, login_token: &str) -> anyhow::Result<bool> {
-        let filter = doc! {
-            "login_token": login_token
-        };
-        let find_options = FindOneOptions::builder()
-            .sort(doc! { "login_time": -1 })
-            .build();
-        if let Some(record) = self
-            .login_records
-            .find_one(filter.clone(), find_options)
-            .await
-            .context("error-net")?
-        {
-            let login_time = record.get_datetime("login_time").context("error-unknown")?;
-            let now_time = Utc::now();
-            let diff = now_time
-                .naive_utc()
-                .signed_duration_since(login_time.naive_utc());
-            if diff.num_seconds() > Self::MAX_LOGIN_TIME_SECS {
-                self.login_records
-                    .delete_one(filter, None)
-                    .await
-                    .context("error-net")?;
-            } else {
-                return Ok(true);
-            }
+        Ok(self.session(login_token).await?.is_some())
+    }
+
+    /// Fails with `LoginExpired` if there is no such session, or `Forbidden` if it is an API key
+    /// without `scope`
+    /// The session of a login token or an API key, fails with `LoginExpired` if there is none
+    pub async fn login_session(&self, login_token: &str) -> anyhow::Result<Session> {
+        match self.session(login_token).await? {
+            Some(session) => Ok(session),
+            None => bail!(ApiError::LoginExpired),
         }
+    }
 
-        Ok(false)
+    async fn ensure_login(&self, login_token: &str, scope: ApiKeyScope) -> anyhow::Result<Session> {
+        let session = self.login_session(login_token).await?;
+        session.require(scope)?;
+        Ok(session)
+    }
+
+    async fn device_info(&self, dev: Device) -> anyhow::Result<DeviceInfo> {
+        let message_count = self.store.count_messages(&dev.org, &dev.id, false).await?;
+        let alert_message_count = self.store.count_messages(&dev.org, &dev.id, true).await?;
+        Ok(DeviceInfo {
+            org: dev.org,
+            id: dev.id,
+            name: dev.name,
+            owner: dev.owner.unwrap_or_default(),
+            message_count,
+            alert_message_count,
+        })
     }
 }
 
 impl Message {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        org: String,
         id: String,
         info: String,
         value: i32,
@@ -552,9 +2435,10 @@ impl Message {
         lng: f64,
         lat: f64,
         timestamp: i64,
+        msg_id: Option<String>,
     ) -> Self {
         Self {
-            _id: None,
+            org,
             id,
             info,
             value,
@@ -562,11 +2446,186 @@ impl Message {
             lng,
             lat,
             timestamp,
+            msg_id,
+        }
+    }
+}
+
+fn change(field: &str, before: &str, after: &str) -> AuditChange {
+    AuditChange {
+        field: field.to_string(),
+        before: before.to_string(),
+        after: after.to_string(),
+    }
+}
+
+fn follows(user: &User, org: &str, id: &str) -> bool {
+    user.devices
+        .iter()
+        .any(|dev| dev.org == org && dev.id == id)
+}
+
+/// User names are 4 to 32 letters, digits and underscores, as the registration page asks for
+fn valid_user_name(name: &str) -> bool {
+    (4..=32).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Ids of organizations are lowercase letters, digits and dashes, so that they can be a level of
+/// MQTT topics, but not one of the levels the backend uses itself
+fn valid_org_id(id: &str) -> bool {
+    !id.is_empty()
+        && !mqtt::RESERVED_ORG_IDS.contains(&id)
+        && id.len() <= MAX_ORG_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+fn org_info(org: Organization) -> OrgInfo {
+    OrgInfo {
+        id: org.id,
+        name: org.name,
+    }
+}
+
+fn audit_entry_info(entry: AuditEntry) -> AuditEntryInfo {
+    AuditEntryInfo {
+        actor: entry.actor,
+        action: entry.action,
+        target: entry.target,
+        changes: entry.changes,
+        ip: entry.ip,
+        time: entry.time,
+    }
+}
+
+fn user_info(user: User) -> UserInfo {
+    UserInfo {
+        device_count: user.devices.len() as u32,
+        mail: user.mail,
+        name: user.name,
+        role: user.role,
+        verified: user.verified,
+        disabled: user.disabled,
+    }
+}
+
+fn api_key_info(key: ApiKey) -> ApiKeyInfo {
+    ApiKeyInfo {
+        id: key.id,
+        name: key.name,
+        scope: key.scope,
+        created_at: key.created_at,
+        expires_at: key.expires_at,
+        last_used_at: key.last_used_at,
+    }
+}
+
+fn command_info(command: Command) -> CommandInfo {
+    CommandInfo {
+        id: command.id,
+        device_id: command.device_id,
+        name: command.name,
+        params: command.params,
+        status: command.status,
+        issued_by: command.issued_by,
+        created_at: command.created_at,
+        updated_at: command.updated_at,
+        result: command.result,
+    }
+}
+
+fn twin_info(device: &Device) -> anyhow::Result<DeviceTwin> {
+    let delta = twin_delta(
+        &parse_object(&device.desired).context(ApiError::Unknown)?,
+        &parse_object(&device.reported).context(ApiError::Unknown)?,
+    );
+    Ok(DeviceTwin {
+        desired: device.desired.clone(),
+        desired_version: device.desired_version,
+        reported: device.reported.clone(),
+        reported_version: device.reported_version,
+        reported_at: device.reported_at,
+        delta: Value::Object(delta).to_string(),
+    })
+}
+
+fn parse_object(json: &str) -> Option<Map<String, Value>> {
+    match serde_json::from_str(json) {
+        Ok(Value::Object(object)) => Some(object),
+        _ => None,
+    }
+}
+
+/// Members of `desired` that `reported` doesn't have or has other values of, nested objects are
+/// compared member by member
+fn twin_delta(desired: &Map<String, Value>, reported: &Map<String, Value>) -> Map<String, Value> {
+    let mut delta = Map::new();
+    for (key, value) in desired {
+        match (value, reported.get(key)) {
+            (Value::Object(desired), Some(Value::Object(reported))) => {
+                let nested = twin_delta(desired, reported);
+                if !nested.is_empty() {
+                    delta.insert(key.clone(), Value::Object(nested));
+                }
+            }
+            (value, Some(reported)) if value == reported => {}
+            (value, _) => {
+                delta.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    delta
+}
+
+fn merge_reported(state: &mut Map<String, Value>, report: Map<String, Value>) {
+    for (key, value) in report {
+        match value {
+            Value::Null => {
+                state.remove(&key);
+            }
+            Value::Object(report) => match state.get_mut(&key) {
+                Some(Value::Object(nested)) => merge_reported(nested, report),
+                _ => {
+                    state.insert(key, Value::Object(report));
+                }
+            },
+            value => {
+                state.insert(key, value);
+            }
         }
     }
 }
 
-fn blake2_str(input: &[u8]) -> String {
+/// Claim codes are printed on devices as three groups of uppercase characters, "XXXX-XXXX-XXXX"
+fn new_claim_code() -> String {
+    let code = random_string(CLAIM_CODE_GROUP_LEN * 3).to_uppercase();
+    code.as_bytes()
+        .chunks(CLAIM_CODE_GROUP_LEN)
+        .map(|group| std::str::from_utf8(group).unwrap())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub(crate) fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// Recovery and claim codes are compared ignoring case, whitespaces and dashes
+fn hash_code(code: &str) -> String {
+    let code = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    blake2_str(code.as_bytes())
+}
+
+pub(crate) fn blake2_str(input: &[u8]) -> String {
     use blake2::{Blake2b, Digest};
     format!("{:x}", Blake2b::digest(input))
 }
@@ -0,0 +1,83 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use common::{
+    error::ApiError,
+    response::{ErrorResponse, SimpleResponse},
+};
+use std::fmt;
+
+/// Error of a request handler, responded with the status code of the `ApiError` and a body that
+/// can be read as any response type
+#[derive(Debug)]
+pub struct ServerError {
+    pub err: ApiError,
+    /// retry_after - seconds to wait before retrying, sent as `Retry-After` too
+    pub retry_after: Option<u64>,
+}
+
+/// Too many failed attempts, the request is refused for some seconds
+#[derive(Debug)]
+pub struct RetryAfter(pub u64);
+
+impl fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        ApiError::TooManyAttempts.fmt(f)
+    }
+}
+
+impl std::error::Error for RetryAfter {}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.err.fmt(f)
+    }
+}
+
+impl From<ApiError> for ServerError {
+    fn from(err: ApiError) -> Self {
+        Self {
+            err,
+            retry_after: None,
+        }
+    }
+}
+
+impl From<RetryAfter> for ServerError {
+    fn from(RetryAfter(secs): RetryAfter) -> Self {
+        Self {
+            err: ApiError::TooManyAttempts,
+            retry_after: Some(secs),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ServerError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(RetryAfter(secs)) = err.downcast_ref::<RetryAfter>() {
+            return RetryAfter(*secs).into();
+        }
+        let api_err = match err.downcast_ref::<ApiError>() {
+            Some(api_err) => *api_err,
+            None => ApiError::from_message_id(&err.to_string()).unwrap_or(ApiError::Unknown),
+        };
+        if api_err.status() >= 500 {
+            eprintln!("Internal error: {:#}", err);
+        }
+        api_err.into()
+    }
+}
+
+impl ResponseError for ServerError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.err.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = SimpleResponse::api_err(self.err);
+        body.retry_after = self.retry_after;
+        let mut res = HttpResponse::build(self.status_code());
+        if let Some(secs) = self.retry_after {
+            res.header("Retry-After", secs.to_string());
+        }
+        res.json(body)
+    }
+}
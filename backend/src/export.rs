@@ -0,0 +1,48 @@
+use crate::{database::Database, store::AuditFilter};
+use anyhow::Context;
+use common::error::ApiError;
+use serde::Serialize;
+use std::io::{Cursor, Write};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+pub const EXPORT_CONTENT_TYPE: &str = "application/zip";
+
+/// Personal data of a user as a zip of json files: `profile.json`, `devices.json` with the
+/// followed devices of every organization and `audit_log.json` with the audit entries done by or
+/// to the user, the latest first
+pub async fn export_account(db: &Database, mail: &str) -> anyhow::Result<Vec<u8>> {
+    let profile = db.profile(mail).await?;
+    let devices = db.all_user_devices(mail).await?;
+
+    let by_user = AuditFilter {
+        actor: Some(mail.to_string()),
+        ..AuditFilter::default()
+    };
+    let (_, mut audit_log) = db.audit_log(&by_user, 0, 0).await?;
+    let to_user = AuditFilter {
+        target: Some(mail.to_string()),
+        ..AuditFilter::default()
+    };
+    let (_, to_user) = db.audit_log(&to_user, 0, 0).await?;
+    audit_log.extend(to_user.into_iter().filter(|entry| entry.actor != mail));
+    audit_log.sort_by(|a, b| b.time.cmp(&a.time));
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    write_json(&mut zip, "profile.json", &profile)?;
+    write_json(&mut zip, "devices.json", &devices)?;
+    write_json(&mut zip, "audit_log.json", &audit_log)?;
+    let zip = zip.finish().context(ApiError::Unknown)?;
+    Ok(zip.into_inner())
+}
+
+fn write_json<T: Serialize>(
+    zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    value: &T,
+) -> anyhow::Result<()> {
+    let json = serde_json::to_vec_pretty(value).context(ApiError::Unknown)?;
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(name, options).context(ApiError::Unknown)?;
+    zip.write_all(&json).context(ApiError::Unknown)?;
+    Ok(())
+}
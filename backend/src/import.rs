@@ -0,0 +1,190 @@
+use crate::database::{Database, Message};
+use anyhow::bail;
+use common::{error::ApiError, response::ImportRowError};
+use serde::Deserialize;
+use std::{collections::HashSet, str::FromStr};
+
+#[derive(Clone, Copy)]
+pub enum ImportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl FromStr for ImportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ImportFormat::Csv),
+            "ndjson" | "jsonl" => Ok(ImportFormat::Ndjson),
+            _ => bail!(ApiError::ImportFormat),
+        }
+    }
+}
+
+/// One row of an imported file. Field names follow the MQTT payload so that logged readings
+/// can be imported without any conversion.
+#[derive(Deserialize)]
+struct ImportRow {
+    #[serde(default)]
+    info: String,
+    value: i32,
+    alert: u8,
+    lng: f64,
+    lat: f64,
+    timestamp: i64,
+    #[serde(rename = "msgId", default)]
+    msg_id: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ImportReport {
+    pub imported: u32,
+    pub duplicated: u32,
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Parse, validate and de-duplicate (on device id, timestamp and message id) the content of a file, and
+/// then insert the remaining messages into the device of the organization unless `dry_run` is set.
+/// Fails with `NoDevice` if the device doesn't exist.
+pub async fn import_messages(
+    db: &Database,
+    org: &str,
+    device_id: &str,
+    format: ImportFormat,
+    content: &str,
+    dry_run: bool,
+) -> anyhow::Result<ImportReport> {
+    db.device(org, device_id).await?;
+    let mut report = ImportReport::default();
+
+    let rows = match format {
+        ImportFormat::Csv => parse_csv(content, &mut report.errors),
+        ImportFormat::Ndjson => parse_ndjson(content, &mut report.errors),
+    };
+    let rows: Vec<_> = rows
+        .into_iter()
+        .filter(|(line, row)| match validate_row(row) {
+            Ok(_) => true,
+            Err(err) => {
+                report.errors.push(ImportRowError {
+                    row: *line,
+                    err: err.to_string(),
+                });
+                false
+            }
+        })
+        .collect();
+
+    let (min_timestamp, max_timestamp) = rows
+        .iter()
+        .fold((i64::MAX, i64::MIN), |(min, max), (_, row)| {
+            (min.min(row.timestamp), max.max(row.timestamp))
+        });
+    let mut seen_keys = if rows.is_empty() {
+        HashSet::new()
+    } else {
+        db.fetch_message_keys(org, device_id, min_timestamp, max_timestamp)
+            .await?
+    };
+
+    let mut messages = Vec::with_capacity(rows.len());
+    for (line, row) in rows {
+        if !seen_keys.insert((row.timestamp, row.msg_id.clone())) {
+            report.duplicated += 1;
+            report.errors.push(ImportRowError {
+                row: line,
+                err: "error-dup-message".to_string(),
+            });
+            continue;
+        }
+        messages.push(Message::new(
+            org.to_string(),
+            device_id.to_string(),
+            row.info,
+            row.value,
+            row.alert != 0,
+            row.lng,
+            row.lat,
+            row.timestamp,
+            row.msg_id,
+        ));
+    }
+
+    report.imported = messages.len() as u32;
+    if !dry_run && !messages.is_empty() {
+        // messages may still be inserted by MQTT in the meantime
+        let duplicated = db.insert_messages(messages).await?;
+        report.imported -= duplicated;
+        report.duplicated += duplicated;
+    }
+    report.errors.sort_by_key(|err| err.row);
+    Ok(report)
+}
+
+fn parse_csv(content: &str, errors: &mut Vec<ImportRowError>) -> Vec<(usize, ImportRow)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(err) => {
+            errors.push(ImportRowError {
+                row: 1,
+                err: format!("error-parse: {}", err),
+            });
+            return vec![];
+        }
+    };
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let result = record.and_then(|record| {
+            let line = record.position().map_or(0, |pos| pos.line() as usize);
+            record
+                .deserialize::<ImportRow>(Some(&headers))
+                .map(|row| (line, row))
+        });
+        match result {
+            Ok(row) => rows.push(row),
+            Err(err) => errors.push(ImportRowError {
+                row: err.position().map_or(0, |pos| pos.line() as usize),
+                err: format!("error-parse: {}", err),
+            }),
+        }
+    }
+    rows
+}
+
+fn parse_ndjson(content: &str, errors: &mut Vec<ImportRowError>) -> Vec<(usize, ImportRow)> {
+    let mut rows = vec![];
+    for (ind, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<ImportRow>(line) {
+            Ok(row) => rows.push((ind + 1, row)),
+            Err(err) => errors.push(ImportRowError {
+                row: ind + 1,
+                err: format!("error-parse: {}", err),
+            }),
+        }
+    }
+    rows
+}
+
+fn validate_row(row: &ImportRow) -> anyhow::Result<()> {
+    if row.timestamp <= 0 {
+        bail!("error-invalid-timestamp");
+    }
+    if row.value < 0 {
+        bail!("error-invalid-value");
+    }
+    if !(-180.0..=180.0).contains(&row.lng) || !(-90.0..=90.0).contains(&row.lat) {
+        bail!("error-invalid-position");
+    }
+    if row.alert > 1 {
+        bail!("error-invalid-alert");
+    }
+    Ok(())
+}
@@ -0,0 +1,13 @@
+pub mod auth;
+pub mod config;
+pub mod database;
+pub mod error;
+pub mod export;
+pub mod import;
+pub mod mail;
+pub mod mqtt;
+pub mod oidc;
+pub mod server;
+pub mod store;
+pub mod throttle;
+pub mod totp;
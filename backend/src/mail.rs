@@ -0,0 +1,143 @@
+//! Sending mails, by SMTP or by writing them to files for local testing
+
+use crate::config::{MailSenderKind, ServerConfig};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncStd1Executor, AsyncTransport, Message,
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// A plain text mail
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait MailSender: Send + Sync {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()>;
+}
+
+/// Sends mails to an SMTP server with STARTTLS
+pub struct SmtpSender {
+    transport: AsyncSmtpTransport<AsyncStd1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpSender {
+    pub fn new(
+        host: &str,
+        port: u16,
+        username: &str,
+        password: &str,
+        from: &str,
+    ) -> anyhow::Result<Self> {
+        let mut builder = AsyncSmtpTransport::<AsyncStd1Executor>::starttls_relay(host)
+            .with_context(|| format!("Invalid SMTP host '{}'", host))?
+            .port(port);
+        if !username.is_empty() {
+            builder =
+                builder.credentials(Credentials::new(username.to_string(), password.to_string()));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from: from
+                .parse()
+                .with_context(|| format!("Invalid mail_from '{}'", from))?,
+        })
+    }
+}
+
+#[async_trait]
+impl MailSender for SmtpSender {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail
+                .to
+                .parse()
+                .with_context(|| format!("Invalid mail address '{}'", mail.to))?)
+            .subject(mail.subject)
+            .body(mail.body)?;
+        self.transport
+            .send(message)
+            .await
+            .with_context(|| format!("Failed to send mail to {}", mail.to))?;
+        Ok(())
+    }
+}
+
+/// Writes every mail to a `.eml` file in a directory instead of sending it, so that links in
+/// them can be followed without a mail server
+pub struct FileSender {
+    dir: PathBuf,
+    from: String,
+    count: AtomicU64,
+}
+
+impl FileSender {
+    pub fn new(dir: PathBuf, from: &str) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create mail directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            from: from.to_string(),
+            count: AtomicU64::new(0),
+        })
+    }
+}
+
+#[async_trait]
+impl MailSender for FileSender {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let to = mail
+            .to
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        let count = self.count.fetch_add(1, Ordering::Relaxed);
+        let path = self
+            .dir
+            .join(format!("{}-{}-{}.eml", now.timestamp_millis(), count, to));
+        let content = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\n\r\n{}",
+            self.from,
+            mail.to,
+            mail.subject,
+            now.to_rfc2822(),
+            mail.body.replace('\n', "\r\n"),
+        );
+        async_std::fs::write(&path, content)
+            .await
+            .with_context(|| format!("Failed to write mail {}", path.display()))
+    }
+}
+
+/// The sender selected by `mail_sender` of the config
+pub fn connect(config: &ServerConfig) -> anyhow::Result<Arc<dyn MailSender>> {
+    let sender: Arc<dyn MailSender> = match config.mail_sender() {
+        MailSenderKind::File => Arc::new(FileSender::new(
+            PathBuf::from(config.mail_dir()),
+            config.mail_from(),
+        )?),
+        MailSenderKind::Smtp => Arc::new(SmtpSender::new(
+            config.smtp_host(),
+            config.smtp_port(),
+            config.smtp_username(),
+            config.smtp_password(),
+            config.mail_from(),
+        )?),
+    };
+    Ok(sender)
+}
@@ -1,37 +1,270 @@
-mod config;
-mod database;
-mod mqtt;
-mod server;
-
 use actix_web::{web, App, HttpServer};
-use config::ServerConfig;
-use database::Database;
+use anyhow::Context;
+use bs_backend::{
+    auth,
+    config::{DbKind, ServerConfig},
+    database::Database,
+    import::{self, ImportFormat},
+    mail, mqtt, oidc, server, store,
+};
+use common::request::Role;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "bs-backend")]
+struct Opt {
+    /// Server config json, a missing default file is ignored so that everything can be given
+    /// by BS_* environment variables
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Config of the embedded MQTT broker
+    #[structopt(long, parse(from_os_str), default_value = "./config/mqtt_broker.toml")]
+    broker_config: PathBuf,
+    /// Address to bind, "host:port", "host" or "[ipv6]:port", overrides the config
+    #[structopt(long)]
+    bind: Option<String>,
+    /// Don't run the embedded MQTT broker, messages are received from the external one at
+    /// mqtt_host:mqtt_port of the config
+    #[structopt(long)]
+    no_broker: bool,
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Import historical messages of a device from a CSV or NDJSON file
+    Import {
+        /// Organization of the device
+        #[structopt(long, default_value = "default")]
+        org: String,
+        /// Id of the device that sent these messages
+        #[structopt(long)]
+        device: String,
+        /// File format, "csv" or "ndjson"
+        #[structopt(long, default_value = "csv")]
+        format: ImportFormat,
+        /// Only validate the file and print the report, nothing will be inserted
+        #[structopt(long)]
+        dry_run: bool,
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Copy messages into a time-series collection, renaming the current `messages` collection
+    /// to the source first if it is a regular one. Messages copied before are skipped, so it can
+    /// be run again after being interrupted.
+    MigrateMessages {
+        /// Collection to copy messages from
+        #[structopt(long, default_value = "messages_old")]
+        from: String,
+    },
+    /// Remove duplicated messages kept before de-duplication, so that the unique index on
+    /// messages can be created
+    DedupeMessages,
+    /// Disable two-factor authentication of a user who has lost both the authenticator and the
+    /// recovery codes
+    ResetTotp {
+        /// E-mail address of the user
+        mail: String,
+    },
+    /// Change the role of a user, "user" or "admin", e.g. to make the first administrator
+    SetRole {
+        /// E-mail address of the user
+        mail: String,
+        role: Role,
+    },
+}
+
+const DEFAULT_CONFIG_PATH: &str = "./config/server_cfg.json";
+
+/// Accounts whose grace period has ended are deleted this often
+const ACCOUNT_DELETION_INTERVAL: Duration = Duration::from_secs(3600);
+/// Login attempts too old to lock accounts are deleted this often
+const LOGIN_ATTEMPT_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Commands not replied in time are timed out this often
+const COMMAND_EXPIRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Newly dropped duplicated messages are reported this often
+const DUPLICATE_REPORT_INTERVAL: Duration = Duration::from_secs(600);
+
+fn load_config(opt: &Opt) -> anyhow::Result<ServerConfig> {
+    let mut config = match &opt.config {
+        Some(path) => ServerConfig::load(path, true)?,
+        None => ServerConfig::load(Path::new(DEFAULT_CONFIG_PATH), false)?,
+    };
+    config.apply_env()?;
+    if let Some(bind) = &opt.bind {
+        config.set_bind(bind);
+    }
+    config.validate()?;
+    Ok(config)
+}
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    println!("Begin");
-    mqtt::run_mqtt_broker();
-    println!("MQTT broker is running");
+async fn main() -> anyhow::Result<()> {
+    let opt = Opt::from_args();
+    let config = load_config(&opt)?;
+
+    if let Some(Command::MigrateMessages { from }) = &opt.cmd {
+        if config.db_kind() != DbKind::Mongodb {
+            anyhow::bail!("Time-series collections are only supported by MongoDB");
+        }
+        let copied =
+            store::mongo::migrate_messages_to_time_series(config.db_url(), config.db_name(), from)
+                .await
+                .context("Failed to migrate messages")?;
+        println!(
+            "Copied {} messages from '{}', it can be dropped once verified",
+            copied, from
+        );
+        return Ok(());
+    }
+
+    if let Some(Command::DedupeMessages) = &opt.cmd {
+        if config.db_kind() != DbKind::Mongodb {
+            anyhow::bail!("Only MongoDB databases may have duplicated messages");
+        }
+        let removed = store::mongo::remove_duplicated_messages(config.db_url(), config.db_name())
+            .await
+            .context("Failed to remove duplicated messages")?;
+        println!("Removed {} duplicated messages", removed);
+        return Ok(());
+    }
+
+    if let Some(Command::ResetTotp { mail }) = &opt.cmd {
+        let database = Database::new(store::connect(&config).await?);
+        database
+            .reset_totp(mail)
+            .await
+            .with_context(|| format!("Failed to reset two-factor authentication of {}", mail))?;
+        println!("Two-factor authentication of {} is disabled", mail);
+        return Ok(());
+    }
 
-    let config_json =
-        std::fs::File::open("./config/server_cfg.json").expect("Server config json not found");
-    let config: ServerConfig =
-        serde_json::from_reader(&config_json).expect("Invalid server config json");
-    assert!(config.validate(), "Invalid server config json");
+    if let Some(Command::SetRole { mail, role }) = &opt.cmd {
+        let database = Database::new(store::connect(&config).await?);
+        database
+            .set_role(mail, *role)
+            .await
+            .with_context(|| format!("Failed to change the role of {}", mail))?;
+        println!("{} is now {}", mail, role.as_str());
+        return Ok(());
+    }
 
-    let database = web::Data::new(Database::new(config.db_url()).await.unwrap());
-    println!("MongoDB is connected");
+    if let Some(Command::Import {
+        org,
+        device,
+        format,
+        dry_run,
+        file,
+    }) = opt.cmd
+    {
+        let content = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let database = Database::new(store::connect(&config).await?);
+        let report = import::import_messages(&database, &org, &device, format, &content, dry_run)
+            .await
+            .context("Failed to import messages")?;
+        for err in &report.errors {
+            eprintln!("Row {}: {}", err.row, err.err);
+        }
+        println!(
+            "{} {} messages, {} duplicated, {} rows rejected",
+            if dry_run { "Would import" } else { "Imported" },
+            report.imported,
+            report.duplicated,
+            report
+                .errors
+                .iter()
+                .filter(|err| err.err != "error-dup-message")
+                .count(),
+        );
+        return Ok(());
+    }
 
-    mqtt::run_mqtt_subscriber(database.clone());
+    println!("Begin");
+    if !opt.no_broker {
+        mqtt::run_mqtt_broker(&opt.broker_config)?;
+        println!("MQTT broker is running");
+    }
+
+    let (publisher, mqtt_conn) = mqtt::connect(config.mqtt_host(), config.mqtt_port())?;
+    let mut database = Database::new(store::connect(&config).await?)
+        .with_session_timeouts(config.session_idle_secs(), config.remember_me_secs())
+        .with_deletion_grace(config.account_deletion_grace_secs())
+        .with_mail_sender(mail::connect(&config)?, config.public_url())
+        .with_auth_providers(auth::connect(&config))
+        .with_command_publisher(Arc::new(publisher))
+        .with_command_timeout(config.command_timeout_secs());
+    if let Some(client) = oidc::connect(&config) {
+        database = database.with_oidc(client);
+    }
+    let database = web::Data::new(database);
+    println!("Database is connected");
+
+    mqtt::run_mqtt_subscriber(database.clone(), mqtt_conn);
     println!("MQTT subscriber is running");
 
+    run_account_deleter(database.clone());
+    run_login_attempt_pruner(database.clone());
+    run_command_expirer(database.clone());
+    run_duplicate_reporter(database.clone());
+
     HttpServer::new(move || {
         App::new()
             .app_data(database.clone())
             .configure(server::config)
     })
     .bind(config.addr())
-    .expect("Failed to bind address")
+    .with_context(|| format!("Failed to bind address {}", config.addr()))?
     .run()
-    .await
+    .await?;
+    Ok(())
+}
+
+fn run_account_deleter(db: web::Data<Database>) {
+    std::thread::spawn(move || loop {
+        match async_std::task::block_on(db.delete_due_accounts()) {
+            Ok(0) => {}
+            Ok(count) => println!("Deleted {} accounts", count),
+            Err(err) => eprintln!("Failed to delete accounts, err = {:#}", err),
+        }
+        std::thread::sleep(ACCOUNT_DELETION_INTERVAL);
+    });
+}
+
+fn run_login_attempt_pruner(db: web::Data<Database>) {
+    std::thread::spawn(move || loop {
+        if let Err(err) = async_std::task::block_on(db.prune_login_attempts()) {
+            eprintln!("Failed to delete login attempts, err = {:#}", err);
+        }
+        std::thread::sleep(LOGIN_ATTEMPT_PRUNE_INTERVAL);
+    });
+}
+
+fn run_command_expirer(db: web::Data<Database>) {
+    std::thread::spawn(move || loop {
+        if let Err(err) = async_std::task::block_on(db.expire_commands()) {
+            eprintln!("Failed to time out commands, err = {:#}", err);
+        }
+        std::thread::sleep(COMMAND_EXPIRY_INTERVAL);
+    });
+}
+
+fn run_duplicate_reporter(db: web::Data<Database>) {
+    std::thread::spawn(move || {
+        let mut reported = 0;
+        loop {
+            std::thread::sleep(DUPLICATE_REPORT_INTERVAL);
+            let count = db.duplicated_message_count();
+            if count > reported {
+                println!("Dropped {} duplicated messages since start", count);
+                reported = count;
+            }
+        }
+    });
 }
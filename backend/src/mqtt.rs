@@ -1,16 +1,67 @@
-use crate::database::{Database, Message};
+use crate::{
+    database::{Database, Message},
+    store::DEFAULT_ORG,
+};
 use actix_web::web;
+use anyhow::Context;
 use librumqttd::Config;
-use rumqttc::{Client, Event, MqttOptions, Packet};
+use rumqttc::{Client, Connection, Event, MqttOptions, Packet, QoS};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{path::Path, sync::Mutex};
 
-pub fn run_mqtt_broker() {
-    let config: Config =
-        confy::load_path("./config/mqtt_broker.toml").expect("Invalid MQTT broker config");
+/// Devices of the default organization publish to this topic, those of other organizations to
+/// `testapp/<organization id>`
+const TOPIC: &str = "testapp";
+/// Commands are published to `testapp/commands/<organization id>/<device id>`
+const COMMAND_TOPIC: &str = "testapp/commands";
+/// Devices reply to commands on `testapp/replies/<organization id>`
+const REPLY_TOPIC: &str = "testapp/replies";
+/// Deltas of device twins are published to `testapp/twin/<organization id>/<device id>`
+const TWIN_TOPIC: &str = "testapp/twin";
+/// Levels of the topics above, which can't be ids of organizations
+pub const RESERVED_ORG_IDS: [&str; 3] = ["commands", "replies", "twin"];
+
+/// Publishes commands and twin deltas to devices
+pub trait CommandPublisher: Send + Sync {
+    fn publish(&self, topic: &str, payload: Vec<u8>) -> anyhow::Result<()>;
+}
+
+/// Publishes with the client of the MQTT subscriber. Publishing never waits: it is called from
+/// HTTP handlers and from the subscriber thread, which is the one driving the connection, so it
+/// fails instead if the requests to the broker are queued up, e.g. while it is down.
+pub struct MqttPublisher {
+    client: Mutex<Client>,
+}
+
+impl CommandPublisher for MqttPublisher {
+    fn publish(&self, topic: &str, payload: Vec<u8>) -> anyhow::Result<()> {
+        self.client
+            .lock()
+            .unwrap()
+            .try_publish(topic, QoS::AtLeastOnce, false, payload)
+            .context("Failed to publish MQTT message")
+    }
+}
+
+/// Topic the commands of a device are published to
+pub fn command_topic(org: &str, device_id: &str) -> String {
+    format!("{}/{}/{}", COMMAND_TOPIC, org, device_id)
+}
+
+/// Topic the twin deltas of a device are published to
+pub fn twin_topic(org: &str, device_id: &str) -> String {
+    format!("{}/{}/{}", TWIN_TOPIC, org, device_id)
+}
+
+pub fn run_mqtt_broker(config_path: &Path) -> anyhow::Result<()> {
+    let config: Config = confy::load_path(config_path)
+        .with_context(|| format!("Invalid MQTT broker config {}", config_path.display()))?;
     let mut broker = librumqttd::Broker::new(config);
     std::thread::spawn(move || {
         broker.start().expect("MQTT broker shut down due to error");
     });
+    Ok(())
 }
 
 #[derive(Deserialize, Serialize)]
@@ -23,23 +74,96 @@ struct MessageMqtt {
     lng: f64,
     lat: f64,
     timestamp: i64,
+    #[serde(rename = "msgId", default)]
+    msg_id: Option<String>,
+    /// reported - state of the device, merged into the reported state of its twin
+    #[serde(default)]
+    reported: Option<Map<String, Value>>,
+}
+
+/// Part of the desired configuration of a device that it doesn't report yet
+#[derive(Deserialize, Serialize)]
+pub struct DeltaMqtt {
+    /// version - the desired version the delta is made from
+    pub version: i64,
+    pub state: Value,
+    pub timestamp: i64,
 }
 
-pub fn run_mqtt_subscriber(db: web::Data<Database>) {
-    let mut options = MqttOptions::new("mqtt_sub", "127.0.0.1", 1883);
+/// Payload of a command published to a device
+#[derive(Deserialize, Serialize)]
+pub struct CommandMqtt {
+    #[serde(rename = "commandId")]
+    pub id: String,
+    pub name: String,
+    pub params: String,
+    pub timestamp: i64,
+}
+
+/// Reply of a device to a command
+#[derive(Deserialize, Serialize)]
+struct ReplyMqtt {
+    #[serde(rename = "commandId")]
+    command_id: String,
+    #[serde(rename = "clientId")]
+    client_id: String,
+    success: bool,
+    #[serde(default)]
+    result: String,
+}
+
+/// Connect to the broker and subscribe to the topics of messages and replies. The publisher shares
+/// the client with the connection, which has to be run by `run_mqtt_subscriber`.
+pub fn connect(host: &str, port: u16) -> anyhow::Result<(MqttPublisher, Connection)> {
+    let mut options = MqttOptions::new("mqtt_sub", host, port);
     options.set_keep_alive(5);
 
-    let (mut client, mut conn) = Client::new(options, 10);
-    client
-        .subscribe("testapp", rumqttc::QoS::AtMostOnce)
-        .unwrap();
+    let (mut client, conn) = Client::new(options, 10);
+    for topic in &[
+        TOPIC.to_string(),
+        format!("{}/+", TOPIC),
+        format!("{}/+", REPLY_TOPIC),
+    ] {
+        client
+            .subscribe(topic, QoS::AtLeastOnce)
+            .context("Failed to subscribe MQTT topic")?;
+    }
+    let publisher = MqttPublisher {
+        client: Mutex::new(client),
+    };
+    Ok((publisher, conn))
+}
 
+pub fn run_mqtt_subscriber(db: web::Data<Database>, mut conn: Connection) {
     std::thread::spawn(move || {
         for msg in conn.iter() {
             if let Ok(Event::Incoming(Packet::Publish(msg))) = msg {
+                let reply_org = msg
+                    .topic
+                    .strip_prefix(REPLY_TOPIC)
+                    .and_then(|org| org.strip_prefix('/'));
+                if let Some(org) = reply_org {
+                    handle_reply(&db, org, &msg.payload);
+                    continue;
+                }
+                let org = match msg.topic.strip_prefix(TOPIC) {
+                    Some("") => DEFAULT_ORG.to_string(),
+                    Some(org) => match org.strip_prefix('/') {
+                        Some(org) => org.to_string(),
+                        None => continue,
+                    },
+                    None => continue,
+                };
                 let payloads = msg.payload;
                 let msg: MessageMqtt = serde_json::from_slice(&payloads).unwrap();
+                if let Some(report) = msg.reported {
+                    let reported = db.report_state(&org, &msg.id, report);
+                    if let Err(err) = async_std::task::block_on(reported) {
+                        eprintln!("Failed to update reported state, err = {}", err);
+                    }
+                }
                 let msg = Message::new(
+                    org,
                     msg.id,
                     msg.info,
                     msg.value,
@@ -47,6 +171,7 @@ pub fn run_mqtt_subscriber(db: web::Data<Database>) {
                     msg.lng,
                     msg.lat,
                     msg.timestamp,
+                    msg.msg_id,
                 );
                 if let Err(err) = async_std::task::block_on(db.insert_message(msg)) {
                     eprintln!("Failed to insert message, err = {}", err);
@@ -55,3 +180,23 @@ pub fn run_mqtt_subscriber(db: web::Data<Database>) {
         }
     });
 }
+
+fn handle_reply(db: &Database, org: &str, payload: &[u8]) {
+    let reply: ReplyMqtt = match serde_json::from_slice(payload) {
+        Ok(reply) => reply,
+        Err(err) => {
+            eprintln!("Invalid command reply, err = {}", err);
+            return;
+        }
+    };
+    let acknowledged = db.acknowledge_command(
+        org,
+        &reply.client_id,
+        &reply.command_id,
+        reply.success,
+        &reply.result,
+    );
+    if let Err(err) = async_std::task::block_on(acknowledged) {
+        eprintln!("Failed to acknowledge command, err = {}", err);
+    }
+}
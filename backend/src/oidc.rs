@@ -0,0 +1,328 @@
+//! Single sign-on with an OpenID Connect provider, by the authorization code flow with PKCE
+//! (RFC 7636)
+
+use crate::{
+    config::{percent_encode, ServerConfig},
+    database::random_string,
+};
+use actix_web::client::Client;
+use anyhow::{anyhow, bail, Context};
+use chrono::Utc;
+use common::error::ApiError;
+use serde::{de::DeserializeOwned, Deserialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Logins have to be finished within this long after being started
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(10 * 60);
+const STATE_LEN: usize = 32;
+const NONCE_LEN: usize = 32;
+/// RFC 7636 allows 43 to 128 characters
+const CODE_VERIFIER_LEN: usize = 64;
+
+/// The account of a user at the provider
+pub struct Identity {
+    /// subject - identifier of the account, which never changes
+    pub subject: String,
+    pub mail: Option<String>,
+    /// mail_verified - the provider has verified that the account owns the mail address
+    pub mail_verified: bool,
+    /// name - preferred username, or the full name if there is none
+    pub name: Option<String>,
+}
+
+/// Endpoints from the discovery document of the provider
+#[derive(Clone, Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    #[serde(default)]
+    userinfo_endpoint: Option<String>,
+}
+
+/// A login started by `authorize_url`, looked up by its state when the provider redirects back
+struct PendingLogin {
+    nonce: String,
+    code_verifier: String,
+    started_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    #[serde(default)]
+    access_token: String,
+}
+
+/// Claims of the ID token, and of the userinfo response without `iss`, `aud`, `exp` and `nonce`
+#[derive(Deserialize)]
+struct Claims {
+    #[serde(default)]
+    iss: String,
+    sub: String,
+    #[serde(default)]
+    aud: Audience,
+    #[serde(default)]
+    exp: i64,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    #[serde(default)]
+    preferred_username: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// `aud` is either a single client id or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Audience {
+    fn default() -> Self {
+        Audience::Many(vec![])
+    }
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+/// Client of the provider. Logins in progress are kept in memory, so they have to be finished
+/// on the server instance they were started on.
+pub struct OidcClient {
+    issuer: String,
+    client_id: String,
+    /// client_secret - empty for public clients, which are only protected by PKCE
+    client_secret: String,
+    scopes: String,
+    provider_name: String,
+    /// redirect_url - `/sso_callback` of the server, registered at the provider
+    redirect_url: String,
+    /// metadata - fetched by the first login, so that the server starts while the provider is down
+    metadata: Mutex<Option<Metadata>>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcClient {
+    pub fn new(
+        issuer: &str,
+        client_id: &str,
+        client_secret: &str,
+        scopes: &str,
+        provider_name: &str,
+        redirect_url: &str,
+    ) -> Self {
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            scopes: scopes.to_string(),
+            provider_name: provider_name.to_string(),
+            redirect_url: redirect_url.to_string(),
+            metadata: Mutex::new(None),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn provider_name(&self) -> &str {
+        &self.provider_name
+    }
+
+    /// Start a login, returns the authorization URL of the provider to send the browser to
+    pub async fn authorize_url(&self) -> anyhow::Result<String> {
+        let metadata = self.metadata().await?;
+        let state = random_string(STATE_LEN);
+        let nonce = random_string(NONCE_LEN);
+        let code_verifier = random_string(CODE_VERIFIER_LEN);
+        let code_challenge = base64::encode_config(
+            Sha256::digest(code_verifier.as_bytes()),
+            base64::URL_SAFE_NO_PAD,
+        );
+        let separator = if metadata.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let url = format!(
+            "{}{}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&nonce={}\
+             &code_challenge={}&code_challenge_method=S256",
+            metadata.authorization_endpoint,
+            separator,
+            percent_encode(&self.client_id),
+            percent_encode(&self.redirect_url),
+            percent_encode(&self.scopes),
+            state,
+            nonce,
+            code_challenge,
+        );
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, login| login.started_at.elapsed() < PENDING_LOGIN_TTL);
+        pending.insert(
+            state,
+            PendingLogin {
+                nonce,
+                code_verifier,
+                started_at: Instant::now(),
+            },
+        );
+        Ok(url)
+    }
+
+    /// Finish the login of `state` with the authorization code given by the provider. Fails with
+    /// `SsoFailed` if the login is unknown or expired, or the provider refuses the code.
+    ///
+    /// The ID token comes straight from the token endpoint of the provider, so its signature isn't
+    /// checked (OpenID Connect Core 3.1.3.7), the issuer, audience, expiry and nonce are.
+    pub async fn identify(&self, state: &str, code: &str) -> anyhow::Result<Identity> {
+        let login = match self.pending.lock().unwrap().remove(state) {
+            Some(login) if login.started_at.elapsed() < PENDING_LOGIN_TTL => login,
+            _ => bail!(ApiError::SsoFailed),
+        };
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_url.as_str()),
+            ("code_verifier", login.code_verifier.as_str()),
+        ];
+        let mut request = Client::default().post(&metadata.token_endpoint);
+        if self.client_secret.is_empty() {
+            form.push(("client_id", self.client_id.as_str()));
+        } else {
+            request = request.basic_auth(
+                percent_encode(&self.client_id),
+                Some(percent_encode(&self.client_secret).as_str()),
+            );
+        }
+        let mut response = request
+            .send_form(&form)
+            .await
+            .map_err(|err| anyhow!("Failed to reach {}: {}", metadata.token_endpoint, err))
+            .context(ApiError::Net)?;
+        if !response.status().is_success() {
+            let body = response.body().await.unwrap_or_default();
+            eprintln!(
+                "The OpenID Connect provider refused the code, status = {}, body = {}",
+                response.status(),
+                String::from_utf8_lossy(&body)
+            );
+            bail!(ApiError::SsoFailed);
+        }
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|err| anyhow!("Invalid token response: {}", err))
+            .context(ApiError::Net)?;
+
+        let claims = decode_id_token(&tokens.id_token).context(ApiError::SsoFailed)?;
+        if claims.iss != metadata.issuer
+            || !claims.aud.contains(&self.client_id)
+            || claims.exp <= Utc::now().timestamp()
+            || claims.nonce.as_deref() != Some(login.nonce.as_str())
+        {
+            bail!(ApiError::SsoFailed);
+        }
+
+        // some providers only tell the mail address by the userinfo endpoint
+        let claims = match &metadata.userinfo_endpoint {
+            Some(endpoint) if claims.email.is_none() && !tokens.access_token.is_empty() => {
+                let request = Client::default()
+                    .get(endpoint)
+                    .bearer_auth(&tokens.access_token);
+                let userinfo: Claims = fetch_json(request, endpoint).await?;
+                if userinfo.sub != claims.sub {
+                    bail!(ApiError::SsoFailed);
+                }
+                userinfo
+            }
+            _ => claims,
+        };
+        Ok(Identity {
+            subject: claims.sub,
+            mail: claims.email,
+            mail_verified: claims.email_verified,
+            name: claims.preferred_username.or(claims.name),
+        })
+    }
+
+    async fn metadata(&self) -> anyhow::Result<Metadata> {
+        if let Some(metadata) = &*self.metadata.lock().unwrap() {
+            return Ok(metadata.clone());
+        }
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+        let metadata: Metadata = fetch_json(Client::default().get(&url), &url).await?;
+        if metadata.issuer.trim_end_matches('/') != self.issuer {
+            bail!(
+                "The OpenID Connect provider is '{}' instead of '{}'",
+                metadata.issuer,
+                self.issuer
+            );
+        }
+        *self.metadata.lock().unwrap() = Some(metadata.clone());
+        Ok(metadata)
+    }
+}
+
+/// The client selected by the `oidc_*` fields of the config, `None` if single sign-on isn't
+/// enabled
+pub fn connect(config: &ServerConfig) -> Option<OidcClient> {
+    config.oidc_issuer().map(|issuer| {
+        OidcClient::new(
+            issuer,
+            config.oidc_client_id(),
+            config.oidc_client_secret(),
+            config.oidc_scopes(),
+            config.oidc_provider_name(),
+            &format!("{}/sso_callback", config.public_url()),
+        )
+    })
+}
+
+async fn fetch_json<T: DeserializeOwned>(
+    request: actix_web::client::ClientRequest,
+    url: &str,
+) -> anyhow::Result<T> {
+    let mut response = request
+        .send()
+        .await
+        .map_err(|err| anyhow!("Failed to reach {}: {}", url, err))
+        .context(ApiError::Net)?;
+    if !response.status().is_success() {
+        return Err(anyhow!("{} responded {}", url, response.status())).context(ApiError::Net);
+    }
+    response
+        .json()
+        .await
+        .map_err(|err| anyhow!("Invalid response of {}: {}", url, err))
+        .context(ApiError::Net)
+}
+
+/// Claims of the payload of a JWT
+fn decode_id_token(id_token: &str) -> anyhow::Result<Claims> {
+    let payload = match id_token.split('.').collect::<Vec<_>>().as_slice() {
+        [_, payload, _] => *payload,
+        _ => bail!("The ID token is not a JWT"),
+    };
+    let payload = base64::decode_config(payload.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+        .context("The ID token is not base64url encoded")?;
+    serde_json::from_slice(&payload).context("Invalid claims of the ID token")
+}
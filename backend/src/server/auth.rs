@@ -0,0 +1,107 @@
+use crate::{
+    database::{Database, Session},
+    error::ServerError,
+};
+use actix_web::{
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    web, FromRequest, HttpMessage, HttpRequest,
+};
+use common::error::ApiError;
+use futures::future::{ok, ready, LocalBoxFuture, Ready};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+/// Middleware resolving `Authorization: Bearer <login token>` to a `Session`, requests without
+/// a valid token are answered with `LoginExpired`
+pub struct BearerAuth;
+
+impl<S, B> Transform<S> for BearerAuth
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = BearerAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(BearerAuthMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct BearerAuthMiddleware<S> {
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service for BearerAuthMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let login_token =
+                bearer_token(&req).ok_or(ServerError::from(ApiError::LoginExpired))?;
+            let db = req
+                .app_data::<web::Data<Database>>()
+                .ok_or(ServerError::from(ApiError::Unknown))?;
+            let session = db
+                .session(&login_token)
+                .await
+                .map_err(ServerError::from)?
+                .ok_or(ServerError::from(ApiError::LoginExpired))?;
+            req.extensions_mut().insert(session);
+            let fut = service.borrow_mut().call(req);
+            fut.await
+        })
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let token = value.strip_prefix("Bearer ")?.trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(token.to_string())
+    }
+}
+
+/// The session put by `BearerAuth`, only available in routes wrapped by it
+impl FromRequest for Session {
+    type Error = ServerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Session>()
+                .cloned()
+                .ok_or(ServerError::from(ApiError::LoginExpired)),
+        )
+    }
+}
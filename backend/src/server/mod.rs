@@ -0,0 +1,871 @@
+mod auth;
+pub mod routes;
+mod throttle;
+pub mod v2;
+
+use self::throttle::{client_ip, LoginThrottle};
+use crate::{
+    config::percent_encode,
+    database::{Database, LoginInfo},
+    error::ServerError,
+    export::EXPORT_CONTENT_TYPE,
+};
+use actix_web::{
+    error::{InternalError, JsonPayloadError, QueryPayloadError},
+    get, post, web, HttpRequest, HttpResponse, ResponseError,
+};
+use common::{
+    error::ApiError,
+    openapi,
+    request::{
+        AdminUserRequest, CancelAccountDeletionRequest, ChangeMailRequest, ConfirmTotpRequest,
+        CreateApiKeyRequest, CreateDeviceRequest, CreateOrgRequest, DeleteAccountRequest,
+        DisableTotpRequest, EnrollTotpRequest, ExportAccountRequest, FetchAllDevicesRequest,
+        FetchApiKeyListRequest, FetchAuditLogRequest, FetchCommandListRequest,
+        FetchDeviceListRequest, FetchDeviceProfileRequest, FetchDeviceRequest,
+        FetchDeviceTwinRequest, FetchMessageListRequest, FetchOrgListRequest, FetchProfileRequest,
+        FetchTotpStatusRequest, FetchUserListRequest, ImportMessagesRequest, LoginRequest,
+        ModifyDeviceRequest, OrgMemberRequest, ProvisionDeviceRequest, RegisterRequest,
+        RemoveDeviceRequest, ResendVerificationRequest, RevokeApiKeyRequest, SendCommandRequest,
+        SsoCallbackQuery, SsoLoginRequest, SwitchOrgRequest, UpdateDeviceTwinRequest,
+        UpdateProfileRequest, UpdateUserRequest, VerifyMailRequest,
+    },
+    response::{
+        ConfirmTotpResponse, CreateApiKeyResponse, EnrollTotpResponse, FetchAllDevicesResponse,
+        FetchApiKeyListResponse, FetchAuditLogResponse, FetchCommandListResponse,
+        FetchDeviceListResponse, FetchDeviceProfileResponse, FetchDeviceResponse,
+        FetchDeviceTwinResponse, FetchLoginOptionsResponse, FetchMessageListResponse,
+        FetchOrgListResponse, FetchProfileResponse, FetchSsoStatusResponse,
+        FetchTotpStatusResponse, FetchUserListResponse, ImportMessagesResponse, LoginResponse,
+        ProfileInfo, ProvisionDeviceResponse, SendCommandResponse, SimpleResponse,
+        SsoAuthorizeResponse,
+    },
+};
+use lazy_static::lazy_static;
+
+async fn login(
+    req: HttpRequest,
+    info: web::Json<LoginRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let info = db.login(info, &client_ip(req.peer_addr())).await?;
+    Ok(HttpResponse::Ok().json(login_response(info)))
+}
+
+async fn register(
+    req: HttpRequest,
+    info: web::Json<RegisterRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.register(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/verify_mail")]
+async fn verify_mail(
+    req: HttpRequest,
+    info: web::Json<VerifyMailRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.verify_mail(&info.token, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[post("/fetch_login_options")]
+async fn fetch_login_options(db: web::Data<Database>) -> HttpResponse {
+    login_options_response(&db)
+}
+
+#[post("/fetch_sso_status")]
+async fn fetch_sso_status(db: web::Data<Database>) -> HttpResponse {
+    sso_status_response(&db)
+}
+
+#[post("/sso_authorize")]
+async fn sso_authorize(db: web::Data<Database>) -> Result<HttpResponse, ServerError> {
+    let url = db.sso_authorize().await?;
+    Ok(sso_authorize_response(url))
+}
+
+/// The provider redirects the browser here, which is sent on to the frontend to finish the login
+/// by `/sso_login`
+#[get("/sso_callback")]
+async fn sso_callback(query: web::Query<SsoCallbackQuery>) -> HttpResponse {
+    let location = match &query.code {
+        Some(code) if query.error.is_none() => format!(
+            "/#sso_login/{}/{}",
+            percent_encode(&query.state),
+            percent_encode(code)
+        ),
+        _ => "/#sso_failed".to_string(),
+    };
+    HttpResponse::Found().header("Location", location).finish()
+}
+
+async fn sso_login(
+    req: HttpRequest,
+    info: web::Json<SsoLoginRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = db
+        .sso_login(&info.state, &info.code, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(HttpResponse::Ok().json(login_response(info)))
+}
+
+#[post("/resend_verification")]
+async fn resend_verification(
+    info: web::Json<ResendVerificationRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.resend_verification(info).await?;
+    Ok(simple_success())
+}
+
+#[post("/logout")]
+async fn logout(
+    info: web::Json<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let login_token = info.into_inner();
+    db.logout(&login_token).await?;
+    Ok(simple_success())
+}
+
+#[post("/refresh_login")]
+async fn refresh_login(
+    info: web::Json<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let login_token = info.into_inner();
+    let info = db.refresh_login(&login_token).await?;
+    Ok(HttpResponse::Ok().json(login_response(info)))
+}
+
+#[post("/check_login")]
+async fn check_login(
+    info: web::Json<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let login_token = info.into_inner();
+    if db.check_login(&login_token).await? {
+        Ok(simple_success())
+    } else {
+        Err(ApiError::LoginExpired.into())
+    }
+}
+
+#[post("/create_device")]
+async fn create_device(
+    req: HttpRequest,
+    info: web::Json<CreateDeviceRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.create_device(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/remove_device")]
+async fn remove_device(
+    req: HttpRequest,
+    info: web::Json<RemoveDeviceRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.remove_device(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/modify_device")]
+async fn modify_device(
+    req: HttpRequest,
+    info: web::Json<ModifyDeviceRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let session = db.login_session(&info.login_token).await?;
+    db.modify_device(
+        &session,
+        &info.id,
+        Some(info.name),
+        Some(info.info),
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[post("/fetch_device")]
+async fn fetch_device(
+    info: web::Json<FetchDeviceRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let session = db.login_session(&info.login_token).await?;
+    let device = db.session_device(&session, &info.id).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceResponse {
+        success: true,
+        id: device.id,
+        name: device.name,
+        info: device.info,
+        ..Default::default()
+    }))
+}
+
+#[post("/fetch_device_profile")]
+async fn fetch_device_profile(
+    info: web::Json<FetchDeviceProfileRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let info = db.fetch_device_profile(info).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceProfileResponse {
+        success: true,
+        name: info.name,
+        message_count: info.message_count,
+        alert_message_count: info.alert_message_count,
+        ..Default::default()
+    }))
+}
+
+#[post("/fetch_device_list")]
+async fn fetch_device_list(
+    info: web::Json<FetchDeviceListRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let session = db.login_session(&info.login_token).await?;
+    let devices = db.user_devices(&session.mail, &session.org).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceListResponse {
+        success: true,
+        devices,
+        ..Default::default()
+    }))
+}
+
+#[post("/fetch_message_list")]
+async fn fetch_message_list(
+    info: web::Json<FetchMessageListRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let session = db.login_session(&info.login_token).await?;
+    let (count, messages) = db
+        .device_messages(
+            &session,
+            &info.id,
+            info.start_timestamp,
+            info.end_timestamp,
+            info.first_index,
+            info.limit,
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchMessageListResponse {
+        success: true,
+        count,
+        messages,
+        ..Default::default()
+    }))
+}
+
+#[post("/send_command")]
+async fn send_command(
+    req: HttpRequest,
+    info: web::Json<SendCommandRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let command = db.send_command(info, &client_ip(req.peer_addr())).await?;
+    Ok(HttpResponse::Ok().json(SendCommandResponse {
+        success: true,
+        command,
+        ..Default::default()
+    }))
+}
+
+#[post("/fetch_command_list")]
+async fn fetch_command_list(
+    info: web::Json<FetchCommandListRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (count, commands) = db.fetch_command_list(info).await?;
+    Ok(HttpResponse::Ok().json(FetchCommandListResponse {
+        success: true,
+        count,
+        commands,
+        ..Default::default()
+    }))
+}
+
+#[post("/fetch_device_twin")]
+async fn fetch_device_twin(
+    info: web::Json<FetchDeviceTwinRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let twin = db.fetch_device_twin(info).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceTwinResponse {
+        success: true,
+        twin,
+        ..Default::default()
+    }))
+}
+
+#[post("/update_device_twin")]
+async fn update_device_twin(
+    req: HttpRequest,
+    info: web::Json<UpdateDeviceTwinRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let twin = db
+        .update_device_twin(info, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceTwinResponse {
+        success: true,
+        twin,
+        ..Default::default()
+    }))
+}
+
+#[post("/fetch_api_key_list")]
+async fn fetch_api_key_list(
+    info: web::Json<FetchApiKeyListRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let keys = db.fetch_api_key_list(info).await?;
+    Ok(HttpResponse::Ok().json(FetchApiKeyListResponse {
+        success: true,
+        keys,
+        ..Default::default()
+    }))
+}
+
+#[post("/create_api_key")]
+async fn create_api_key(
+    info: web::Json<CreateApiKeyRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (key, info) = db.create_api_key(info).await?;
+    Ok(HttpResponse::Ok().json(CreateApiKeyResponse {
+        success: true,
+        key,
+        info,
+        ..Default::default()
+    }))
+}
+
+#[post("/revoke_api_key")]
+async fn revoke_api_key(
+    info: web::Json<RevokeApiKeyRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.revoke_api_key(info).await?;
+    Ok(simple_success())
+}
+
+#[post("/fetch_totp_status")]
+async fn fetch_totp_status(
+    info: web::Json<FetchTotpStatusRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (enabled, recovery_codes_left) = db.fetch_totp_status(info).await?;
+    Ok(HttpResponse::Ok().json(FetchTotpStatusResponse {
+        success: true,
+        enabled,
+        recovery_codes_left,
+        ..Default::default()
+    }))
+}
+
+#[post("/enroll_totp")]
+async fn enroll_totp(
+    info: web::Json<EnrollTotpRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (secret, uri) = db.enroll_totp(info).await?;
+    Ok(HttpResponse::Ok().json(EnrollTotpResponse {
+        success: true,
+        secret,
+        uri,
+        ..Default::default()
+    }))
+}
+
+#[post("/confirm_totp")]
+async fn confirm_totp(
+    info: web::Json<ConfirmTotpRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let recovery_codes = db.confirm_totp(info).await?;
+    Ok(HttpResponse::Ok().json(ConfirmTotpResponse {
+        success: true,
+        recovery_codes,
+        ..Default::default()
+    }))
+}
+
+#[post("/disable_totp")]
+async fn disable_totp(
+    info: web::Json<DisableTotpRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.disable_totp(info).await?;
+    Ok(simple_success())
+}
+
+#[post("/fetch_profile")]
+async fn fetch_profile(
+    info: web::Json<FetchProfileRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let profile = db.fetch_profile(info).await?;
+    Ok(profile_response(profile))
+}
+
+#[post("/update_profile")]
+async fn update_profile(
+    req: HttpRequest,
+    info: web::Json<UpdateProfileRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.update_profile(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/change_mail")]
+async fn change_mail(
+    req: HttpRequest,
+    info: web::Json<ChangeMailRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.change_mail(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/delete_account")]
+async fn delete_account(
+    req: HttpRequest,
+    info: web::Json<DeleteAccountRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.delete_account(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/cancel_account_deletion")]
+async fn cancel_account_deletion(
+    req: HttpRequest,
+    info: web::Json<CancelAccountDeletionRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.cancel_account_deletion(info, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[post("/export_account")]
+async fn export_account(
+    info: web::Json<ExportAccountRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let data = db.export_account(info).await?;
+    Ok(export_response(data))
+}
+
+#[post("/admin/fetch_user_list")]
+async fn fetch_user_list(
+    info: web::Json<FetchUserListRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (count, users) = db.fetch_user_list(info).await?;
+    Ok(HttpResponse::Ok().json(FetchUserListResponse {
+        success: true,
+        count,
+        users,
+        ..Default::default()
+    }))
+}
+
+#[post("/admin/modify_user")]
+async fn modify_user(
+    req: HttpRequest,
+    info: web::Json<UpdateUserRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.modify_user(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/admin/remove_user")]
+async fn remove_user(
+    req: HttpRequest,
+    info: web::Json<AdminUserRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.remove_user(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/admin/impersonate")]
+async fn impersonate(
+    req: HttpRequest,
+    info: web::Json<AdminUserRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let info = db
+        .impersonate_user(info, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(HttpResponse::Ok().json(login_response(info)))
+}
+
+#[post("/admin/reset_totp")]
+async fn reset_totp(
+    req: HttpRequest,
+    info: web::Json<AdminUserRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.reset_user_totp(info, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[post("/admin/fetch_audit_log")]
+async fn fetch_audit_log(
+    info: web::Json<FetchAuditLogRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (count, entries) = db.fetch_audit_log(info).await?;
+    Ok(HttpResponse::Ok().json(FetchAuditLogResponse {
+        success: true,
+        count,
+        entries,
+        ..Default::default()
+    }))
+}
+
+#[post("/admin/fetch_device_list")]
+async fn fetch_all_devices(
+    info: web::Json<FetchAllDevicesRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (count, devices) = db.fetch_all_devices(info).await?;
+    Ok(HttpResponse::Ok().json(FetchAllDevicesResponse {
+        success: true,
+        count,
+        devices,
+        ..Default::default()
+    }))
+}
+
+#[post("/admin/provision_device")]
+async fn provision_device(
+    req: HttpRequest,
+    info: web::Json<ProvisionDeviceRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (id, claim_code) = db
+        .provision_device(info, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(HttpResponse::Ok().json(ProvisionDeviceResponse {
+        success: true,
+        id,
+        claim_code,
+        ..Default::default()
+    }))
+}
+
+#[post("/fetch_org_list")]
+async fn fetch_org_list(
+    info: web::Json<FetchOrgListRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let (org, orgs) = db.fetch_org_list(info).await?;
+    Ok(HttpResponse::Ok().json(FetchOrgListResponse {
+        success: true,
+        org,
+        orgs,
+        ..Default::default()
+    }))
+}
+
+#[post("/switch_org")]
+async fn switch_org(
+    info: web::Json<SwitchOrgRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.switch_org(info).await?;
+    Ok(simple_success())
+}
+
+#[post("/admin/fetch_org_list")]
+async fn fetch_all_orgs(
+    info: web::Json<FetchOrgListRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let orgs = db.fetch_all_orgs(info).await?;
+    Ok(HttpResponse::Ok().json(FetchOrgListResponse {
+        success: true,
+        orgs,
+        ..Default::default()
+    }))
+}
+
+#[post("/admin/create_org")]
+async fn create_org(
+    req: HttpRequest,
+    info: web::Json<CreateOrgRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.create_org(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/admin/add_org_member")]
+async fn add_org_member(
+    req: HttpRequest,
+    info: web::Json<OrgMemberRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.add_org_member(info, &client_ip(req.peer_addr())).await?;
+    Ok(simple_success())
+}
+
+#[post("/admin/remove_org_member")]
+async fn remove_org_member(
+    req: HttpRequest,
+    info: web::Json<OrgMemberRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.remove_org_member(info, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+async fn import_messages(
+    info: web::Json<ImportMessagesRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    let report = db.import_messages(info).await?;
+    Ok(HttpResponse::Ok().json(ImportMessagesResponse {
+        success: true,
+        imported: report.imported,
+        duplicated: report.duplicated,
+        errors: report.errors,
+        ..Default::default()
+    }))
+}
+
+lazy_static! {
+    static ref OPENAPI_SPEC: String = serde_json::to_string(&openapi_spec()).unwrap();
+}
+
+/// OpenAPI 3 document of every route in `routes::routes`
+pub fn openapi_spec() -> serde_json::Value {
+    openapi::spec("bs-app", env!("CARGO_PKG_VERSION"), &routes::routes())
+}
+
+#[get("/openapi.json")]
+async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(OPENAPI_SPEC.as_str())
+}
+
+fn login_response(info: LoginInfo) -> LoginResponse {
+    LoginResponse {
+        success: true,
+        login_token: info.login_token,
+        mail: info.mail,
+        name: info.name,
+        expires_at: info.expires_at,
+        role: info.role,
+        language: info.language,
+        timezone: info.timezone,
+        ..Default::default()
+    }
+}
+
+fn login_options_response(db: &Database) -> HttpResponse {
+    HttpResponse::Ok().json(FetchLoginOptionsResponse {
+        success: true,
+        plain_password: db.plain_password_required(),
+        ..Default::default()
+    })
+}
+
+fn sso_status_response(db: &Database) -> HttpResponse {
+    let provider_name = db.sso_provider_name();
+    HttpResponse::Ok().json(FetchSsoStatusResponse {
+        success: true,
+        enabled: provider_name.is_some(),
+        provider_name: provider_name.unwrap_or_default().to_string(),
+        ..Default::default()
+    })
+}
+
+fn sso_authorize_response(url: String) -> HttpResponse {
+    HttpResponse::Ok().json(SsoAuthorizeResponse {
+        success: true,
+        url,
+        ..Default::default()
+    })
+}
+
+fn profile_response(profile: ProfileInfo) -> HttpResponse {
+    HttpResponse::Ok().json(FetchProfileResponse {
+        success: true,
+        profile,
+        ..Default::default()
+    })
+}
+
+/// The zip of `export::export_account`, downloaded as a file by browsers
+fn export_response(data: Vec<u8>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(EXPORT_CONTENT_TYPE)
+        .header(
+            "Content-Disposition",
+            "attachment; filename=\"bs-app-export.zip\"",
+        )
+        .body(data)
+}
+
+fn simple_success() -> HttpResponse {
+    HttpResponse::Ok().json(SimpleResponse {
+        success: true,
+        ..Default::default()
+    })
+}
+
+/// Malformed json bodies are answered like any other error instead of actix's plain text
+fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    InternalError::from_response(
+        err,
+        ServerError::from(ApiError::InvalidRequest).error_response(),
+    )
+    .into()
+}
+
+fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    InternalError::from_response(
+        err,
+        ServerError::from(ApiError::InvalidRequest).error_response(),
+    )
+    .into()
+}
+
+/// Imported files can be much larger than the default 32KB json payload limit
+const IMPORT_PAYLOAD_LIMIT: usize = 64 * 1024 * 1024;
+
+fn import_json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(IMPORT_PAYLOAD_LIMIT)
+        .error_handler(json_error)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(json_error))
+        .app_data(web::QueryConfig::default().error_handler(query_error))
+        .service(
+            web::resource("/login")
+                .wrap(LoginThrottle)
+                .route(web::post().to(login)),
+        )
+        .service(
+            web::resource("/register")
+                .wrap(LoginThrottle)
+                .route(web::post().to(register)),
+        )
+        .service(verify_mail)
+        .service(fetch_login_options)
+        .service(fetch_sso_status)
+        .service(sso_authorize)
+        .service(sso_callback)
+        .service(
+            web::resource("/sso_login")
+                .wrap(LoginThrottle)
+                .route(web::post().to(sso_login)),
+        )
+        .service(resend_verification)
+        .service(logout)
+        .service(refresh_login)
+        .service(check_login)
+        .service(create_device)
+        .service(remove_device)
+        .service(modify_device)
+        .service(fetch_device)
+        .service(fetch_device_profile)
+        .service(fetch_device_list)
+        .service(fetch_message_list)
+        .service(send_command)
+        .service(fetch_command_list)
+        .service(fetch_device_twin)
+        .service(update_device_twin)
+        .service(fetch_api_key_list)
+        .service(create_api_key)
+        .service(revoke_api_key)
+        .service(fetch_totp_status)
+        .service(enroll_totp)
+        .service(confirm_totp)
+        .service(disable_totp)
+        .service(fetch_profile)
+        .service(update_profile)
+        .service(change_mail)
+        .service(delete_account)
+        .service(cancel_account_deletion)
+        .service(export_account)
+        .service(fetch_user_list)
+        .service(modify_user)
+        .service(remove_user)
+        .service(impersonate)
+        .service(reset_totp)
+        .service(fetch_all_devices)
+        .service(provision_device)
+        .service(fetch_audit_log)
+        .service(fetch_org_list)
+        .service(switch_org)
+        .service(fetch_all_orgs)
+        .service(create_org)
+        .service(add_org_member)
+        .service(remove_org_member)
+        .service(
+            web::resource("/import_messages")
+                .app_data(import_json_config())
+                .route(web::post().to(import_messages)),
+        )
+        .service(openapi_json)
+        .configure(v2::config);
+}
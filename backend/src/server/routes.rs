@@ -0,0 +1,665 @@
+use common::{
+    openapi::{Auth, Route},
+    request::{
+        AdminUserRequest, AuditQuery, CancelAccountDeletionRequest, ChangeMailRequest,
+        ConfirmTotpRequest, CreateApiKeyRequest, CreateDeviceRequest, CreateOrgRequest,
+        DeleteAccountRequest, DesiredRequest, DisableTotpRequest, EnrollTotpRequest,
+        ExportAccountRequest, FetchAllDevicesRequest, FetchApiKeyListRequest, FetchAuditLogRequest,
+        FetchCommandListRequest, FetchDeviceListRequest, FetchDeviceProfileRequest,
+        FetchDeviceRequest, FetchDeviceTwinRequest, FetchMessageListRequest, FetchOrgListRequest,
+        FetchProfileRequest, FetchTotpStatusRequest, FetchUserListRequest, ImportFileRequest,
+        ImportMessagesRequest, LoginRequest, MessageQuery, ModifyDeviceRequest, NewApiKeyRequest,
+        NewCommandRequest, NewDeviceRequest, NewMailRequest, NewOrgRequest, OrgMemberRequest,
+        OrgSwitch, PageQuery, PasswordRequest, ProfilePatch, ProvisionDeviceRequest,
+        ProvisionRequest, RegisterRequest, RemoveDeviceRequest, ResendVerificationRequest,
+        RevokeApiKeyRequest, SendCommandRequest, SsoCallbackQuery, SsoLoginRequest,
+        SwitchOrgRequest, TotpCodeRequest, UpdateDeviceRequest, UpdateDeviceTwinRequest,
+        UpdateProfileRequest, UpdateUserRequest, UserPatch, UserQuery, VerifyMailRequest,
+    },
+    response::{
+        ConfirmTotpResponse, CreateApiKeyResponse, EnrollTotpResponse, FetchAllDevicesResponse,
+        FetchApiKeyListResponse, FetchAuditLogResponse, FetchCommandListResponse,
+        FetchDeviceListResponse, FetchDeviceProfileResponse, FetchDeviceResponse,
+        FetchDeviceTwinResponse, FetchLoginOptionsResponse, FetchMessageListResponse,
+        FetchOrgListResponse, FetchProfileResponse, FetchSsoStatusResponse,
+        FetchTotpStatusResponse, FetchUserListResponse, ImportMessagesResponse, LoginResponse,
+        ProvisionDeviceResponse, SendCommandResponse, SsoAuthorizeResponse,
+    },
+};
+
+/// Every route registered by `server::config`, used to generate the OpenAPI document. The
+/// `openapi` test fails if a route here has no handler or the document is out of date.
+pub fn routes() -> Vec<Route> {
+    vec![
+        Route::new("post", "/login", "Log in")
+            .body::<LoginRequest>()
+            .response::<LoginResponse>(),
+        Route::new("post", "/register", "Register").body::<RegisterRequest>(),
+        Route::new(
+            "post",
+            "/verify_mail",
+            "Verify the mail address with the token of a verification link",
+        )
+        .body::<VerifyMailRequest>(),
+        Route::new(
+            "post",
+            "/fetch_login_options",
+            "How logins have to send the password",
+        )
+        .response::<FetchLoginOptionsResponse>(),
+        Route::new(
+            "post",
+            "/fetch_sso_status",
+            "Whether users can log in by single sign-on",
+        )
+        .response::<FetchSsoStatusResponse>(),
+        Route::new(
+            "post",
+            "/sso_authorize",
+            "Start a single sign-on, returns the URL of the provider to open",
+        )
+        .response::<SsoAuthorizeResponse>(),
+        Route::new(
+            "get",
+            "/sso_callback",
+            "Where the provider redirects the browser to, which is redirected on to the frontend",
+        )
+        .query::<SsoCallbackQuery>(),
+        Route::new(
+            "post",
+            "/sso_login",
+            "Finish a single sign-on with the code the provider redirected back with",
+        )
+        .body::<SsoLoginRequest>()
+        .response::<LoginResponse>(),
+        Route::new(
+            "post",
+            "/resend_verification",
+            "Send the verification mail again",
+        )
+        .auth(Auth::Body)
+        .body::<ResendVerificationRequest>(),
+        Route::new("post", "/logout", "Log out, the body is the login token")
+            .auth(Auth::Body)
+            .body::<String>(),
+        Route::new(
+            "post",
+            "/refresh_login",
+            "Replace the session (the body is the login token) with a new one",
+        )
+        .auth(Auth::Body)
+        .body::<String>()
+        .response::<LoginResponse>(),
+        Route::new(
+            "post",
+            "/check_login",
+            "Check if the login token (the body) is valid",
+        )
+        .auth(Auth::Body)
+        .body::<String>(),
+        Route::new(
+            "post",
+            "/create_device",
+            "Follow a device, claiming it with the claim code if nobody has",
+        )
+        .auth(Auth::Body)
+        .body::<CreateDeviceRequest>(),
+        Route::new("post", "/remove_device", "Unfollow a device")
+            .auth(Auth::Body)
+            .body::<RemoveDeviceRequest>(),
+        Route::new("post", "/modify_device", "Modify name and info of a device")
+            .auth(Auth::Body)
+            .body::<ModifyDeviceRequest>(),
+        Route::new("post", "/fetch_device", "Get a device")
+            .auth(Auth::Body)
+            .body::<FetchDeviceRequest>()
+            .response::<FetchDeviceResponse>(),
+        Route::new(
+            "post",
+            "/fetch_device_profile",
+            "Message counts of a device",
+        )
+        .auth(Auth::Body)
+        .body::<FetchDeviceProfileRequest>()
+        .response::<FetchDeviceProfileResponse>(),
+        Route::new("post", "/fetch_device_list", "Devices followed by a user")
+            .auth(Auth::Body)
+            .body::<FetchDeviceListRequest>()
+            .response::<FetchDeviceListResponse>(),
+        Route::new(
+            "post",
+            "/fetch_message_list",
+            "Messages of a device in a time range",
+        )
+        .auth(Auth::Body)
+        .body::<FetchMessageListRequest>()
+        .response::<FetchMessageListResponse>(),
+        Route::new(
+            "post",
+            "/import_messages",
+            "Import messages from a CSV/NDJSON file into a followed device",
+        )
+        .auth(Auth::Body)
+        .body::<ImportMessagesRequest>()
+        .response::<ImportMessagesResponse>(),
+        Route::new(
+            "post",
+            "/send_command",
+            "Send a command to a followed device over MQTT",
+        )
+        .auth(Auth::Body)
+        .body::<SendCommandRequest>()
+        .response::<SendCommandResponse>(),
+        Route::new(
+            "post",
+            "/fetch_command_list",
+            "Commands sent to a followed device, the latest first",
+        )
+        .auth(Auth::Body)
+        .body::<FetchCommandListRequest>()
+        .response::<FetchCommandListResponse>(),
+        Route::new(
+            "post",
+            "/fetch_device_twin",
+            "Desired configuration and reported state of a followed device",
+        )
+        .auth(Auth::Body)
+        .body::<FetchDeviceTwinRequest>()
+        .response::<FetchDeviceTwinResponse>(),
+        Route::new(
+            "post",
+            "/update_device_twin",
+            "Change the desired configuration of a followed device, the delta is published to it",
+        )
+        .auth(Auth::Body)
+        .body::<UpdateDeviceTwinRequest>()
+        .response::<FetchDeviceTwinResponse>(),
+        Route::new("post", "/fetch_api_key_list", "API keys of the user")
+            .auth(Auth::Body)
+            .body::<FetchApiKeyListRequest>()
+            .response::<FetchApiKeyListResponse>(),
+        Route::new(
+            "post",
+            "/create_api_key",
+            "Create an API key, the key is only returned once",
+        )
+        .auth(Auth::Body)
+        .body::<CreateApiKeyRequest>()
+        .response::<CreateApiKeyResponse>(),
+        Route::new("post", "/revoke_api_key", "Revoke an API key")
+            .auth(Auth::Body)
+            .body::<RevokeApiKeyRequest>(),
+        Route::new(
+            "post",
+            "/fetch_totp_status",
+            "Two-factor authentication status",
+        )
+        .auth(Auth::Body)
+        .body::<FetchTotpStatusRequest>()
+        .response::<FetchTotpStatusResponse>(),
+        Route::new(
+            "post",
+            "/enroll_totp",
+            "Generate a TOTP secret, enabled once confirmed",
+        )
+        .auth(Auth::Body)
+        .body::<EnrollTotpRequest>()
+        .response::<EnrollTotpResponse>(),
+        Route::new(
+            "post",
+            "/confirm_totp",
+            "Enable two-factor authentication with a code, returns the recovery codes",
+        )
+        .auth(Auth::Body)
+        .body::<ConfirmTotpRequest>()
+        .response::<ConfirmTotpResponse>(),
+        Route::new(
+            "post",
+            "/disable_totp",
+            "Disable two-factor authentication with a code or a recovery code",
+        )
+        .auth(Auth::Body)
+        .body::<DisableTotpRequest>(),
+        Route::new("post", "/fetch_profile", "Profile of the user")
+            .auth(Auth::Body)
+            .body::<FetchProfileRequest>()
+            .response::<FetchProfileResponse>(),
+        Route::new(
+            "post",
+            "/update_profile",
+            "Change the name, language or timezone of the user",
+        )
+        .auth(Auth::Body)
+        .body::<UpdateProfileRequest>(),
+        Route::new(
+            "post",
+            "/change_mail",
+            "Send a link to the new mail address, the mail changes once it is opened",
+        )
+        .auth(Auth::Body)
+        .body::<ChangeMailRequest>(),
+        Route::new(
+            "post",
+            "/delete_account",
+            "Delete the account after the grace period, every session ends",
+        )
+        .auth(Auth::Body)
+        .body::<DeleteAccountRequest>(),
+        Route::new(
+            "post",
+            "/cancel_account_deletion",
+            "Keep the account that was going to be deleted",
+        )
+        .auth(Auth::Body)
+        .body::<CancelAccountDeletionRequest>(),
+        Route::new(
+            "post",
+            "/export_account",
+            "Zip of the profile, devices and audit log entries of the user",
+        )
+        .auth(Auth::Body)
+        .body::<ExportAccountRequest>()
+        .file("application/zip"),
+        Route::new(
+            "post",
+            "/admin/fetch_user_list",
+            "Users whose mail or name contains the query, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<FetchUserListRequest>()
+        .response::<FetchUserListResponse>(),
+        Route::new(
+            "post",
+            "/admin/modify_user",
+            "Change the role of a user or disable it, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<UpdateUserRequest>(),
+        Route::new(
+            "post",
+            "/admin/remove_user",
+            "Delete a user, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<AdminUserRequest>(),
+        Route::new(
+            "post",
+            "/admin/impersonate",
+            "Log in as another user for support, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<AdminUserRequest>()
+        .response::<LoginResponse>(),
+        Route::new(
+            "post",
+            "/admin/reset_totp",
+            "Disable two-factor authentication of a user, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<AdminUserRequest>(),
+        Route::new(
+            "post",
+            "/admin/fetch_device_list",
+            "Every device, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<FetchAllDevicesRequest>()
+        .response::<FetchAllDevicesResponse>(),
+        Route::new(
+            "post",
+            "/admin/provision_device",
+            "Register a device in the current organization and get its one-time claim code, \
+             administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<ProvisionDeviceRequest>()
+        .response::<ProvisionDeviceResponse>(),
+        Route::new(
+            "post",
+            "/admin/fetch_audit_log",
+            "Audit log of security-relevant and device-changing actions, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<FetchAuditLogRequest>()
+        .response::<FetchAuditLogResponse>(),
+        Route::new(
+            "post",
+            "/fetch_org_list",
+            "Organizations of the user and the one it works in",
+        )
+        .auth(Auth::Body)
+        .body::<FetchOrgListRequest>()
+        .response::<FetchOrgListResponse>(),
+        Route::new(
+            "post",
+            "/switch_org",
+            "Work in another organization of the user",
+        )
+        .auth(Auth::Body)
+        .body::<SwitchOrgRequest>(),
+        Route::new(
+            "post",
+            "/admin/fetch_org_list",
+            "Every organization, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<FetchOrgListRequest>()
+        .response::<FetchOrgListResponse>(),
+        Route::new(
+            "post",
+            "/admin/create_org",
+            "Create an organization, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<CreateOrgRequest>(),
+        Route::new(
+            "post",
+            "/admin/add_org_member",
+            "Make a user a member of an organization, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<OrgMemberRequest>(),
+        Route::new(
+            "post",
+            "/admin/remove_org_member",
+            "Remove a user from an organization, administrators only",
+        )
+        .auth(Auth::Body)
+        .body::<OrgMemberRequest>(),
+        Route::new("post", "/api/v2/users", "Register").body::<RegisterRequest>(),
+        Route::new(
+            "post",
+            "/api/v2/verify_mail",
+            "Verify the mail address with the token of a verification link",
+        )
+        .body::<VerifyMailRequest>(),
+        Route::new(
+            "post",
+            "/api/v2/verify_mail/resend",
+            "Send the verification mail again",
+        )
+        .auth(Auth::Bearer),
+        Route::new("post", "/api/v2/sessions", "Log in")
+            .body::<LoginRequest>()
+            .response::<LoginResponse>(),
+        Route::new(
+            "get",
+            "/api/v2/login_options",
+            "How logins have to send the password",
+        )
+        .response::<FetchLoginOptionsResponse>(),
+        Route::new(
+            "get",
+            "/api/v2/sso",
+            "Whether users can log in by single sign-on",
+        )
+        .response::<FetchSsoStatusResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/sso/authorize",
+            "Start a single sign-on, returns the URL of the provider to open",
+        )
+        .response::<SsoAuthorizeResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/sso/sessions",
+            "Finish a single sign-on with the code the provider redirected back with",
+        )
+        .body::<SsoLoginRequest>()
+        .response::<LoginResponse>(),
+        Route::new("get", "/api/v2/session", "Check the current session").auth(Auth::Bearer),
+        Route::new("delete", "/api/v2/session", "Log out").auth(Auth::Bearer),
+        Route::new(
+            "post",
+            "/api/v2/session/refresh",
+            "Replace the session with a new one",
+        )
+        .auth(Auth::Bearer)
+        .response::<LoginResponse>(),
+        Route::new("get", "/api/v2/devices", "Devices followed by the user")
+            .auth(Auth::Bearer)
+            .response::<FetchDeviceListResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/devices",
+            "Follow a device, claiming it with the claim code if nobody has",
+        )
+        .auth(Auth::Bearer)
+        .body::<NewDeviceRequest>(),
+        Route::new("get", "/api/v2/devices/{id}", "Get a device")
+            .auth(Auth::Bearer)
+            .response::<FetchDeviceResponse>(),
+        Route::new("patch", "/api/v2/devices/{id}", "Update a device")
+            .auth(Auth::Bearer)
+            .body::<UpdateDeviceRequest>(),
+        Route::new("delete", "/api/v2/devices/{id}", "Unfollow a device").auth(Auth::Bearer),
+        Route::new(
+            "get",
+            "/api/v2/devices/{id}/profile",
+            "Message counts of a device",
+        )
+        .auth(Auth::Bearer)
+        .response::<FetchDeviceProfileResponse>(),
+        Route::new(
+            "get",
+            "/api/v2/devices/{id}/messages",
+            "Messages in a time range, the latest first",
+        )
+        .auth(Auth::Bearer)
+        .query::<MessageQuery>()
+        .response::<FetchMessageListResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/devices/{id}/messages/import",
+            "Import messages from a CSV/NDJSON file into a followed device",
+        )
+        .auth(Auth::Bearer)
+        .body::<ImportFileRequest>()
+        .response::<ImportMessagesResponse>(),
+        Route::new(
+            "get",
+            "/api/v2/devices/{id}/commands",
+            "Commands sent to a followed device, the latest first",
+        )
+        .auth(Auth::Bearer)
+        .query::<PageQuery>()
+        .response::<FetchCommandListResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/devices/{id}/commands",
+            "Send a command to a followed device over MQTT",
+        )
+        .auth(Auth::Bearer)
+        .body::<NewCommandRequest>()
+        .response::<SendCommandResponse>(),
+        Route::new(
+            "get",
+            "/api/v2/devices/{id}/twin",
+            "Desired configuration and reported state of a followed device",
+        )
+        .auth(Auth::Bearer)
+        .response::<FetchDeviceTwinResponse>(),
+        Route::new(
+            "put",
+            "/api/v2/devices/{id}/twin/desired",
+            "Change the desired configuration of a followed device, the delta is published to it",
+        )
+        .auth(Auth::Bearer)
+        .body::<DesiredRequest>()
+        .response::<FetchDeviceTwinResponse>(),
+        Route::new("get", "/api/v2/api_keys", "API keys of the user")
+            .auth(Auth::Bearer)
+            .response::<FetchApiKeyListResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/api_keys",
+            "Create an API key, the key is only returned once",
+        )
+        .auth(Auth::Bearer)
+        .body::<NewApiKeyRequest>()
+        .response::<CreateApiKeyResponse>(),
+        Route::new("delete", "/api/v2/api_keys/{id}", "Revoke an API key").auth(Auth::Bearer),
+        Route::new("get", "/api/v2/totp", "Two-factor authentication status")
+            .auth(Auth::Bearer)
+            .response::<FetchTotpStatusResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/totp",
+            "Generate a TOTP secret, enabled once confirmed",
+        )
+        .auth(Auth::Bearer)
+        .response::<EnrollTotpResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/totp/confirm",
+            "Enable two-factor authentication with a code, returns the recovery codes",
+        )
+        .auth(Auth::Bearer)
+        .body::<TotpCodeRequest>()
+        .response::<ConfirmTotpResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/totp/disable",
+            "Disable two-factor authentication with a code or a recovery code",
+        )
+        .auth(Auth::Bearer)
+        .body::<TotpCodeRequest>(),
+        Route::new("get", "/api/v2/account", "Profile of the user")
+            .auth(Auth::Bearer)
+            .response::<FetchProfileResponse>(),
+        Route::new(
+            "patch",
+            "/api/v2/account",
+            "Change the name, language or timezone of the user",
+        )
+        .auth(Auth::Bearer)
+        .body::<ProfilePatch>(),
+        Route::new(
+            "post",
+            "/api/v2/account/mail",
+            "Send a link to the new mail address, the mail changes once it is opened",
+        )
+        .auth(Auth::Bearer)
+        .body::<NewMailRequest>(),
+        Route::new(
+            "post",
+            "/api/v2/account/deletion",
+            "Delete the account after the grace period, every session ends",
+        )
+        .auth(Auth::Bearer)
+        .body::<PasswordRequest>(),
+        Route::new(
+            "delete",
+            "/api/v2/account/deletion",
+            "Keep the account that was going to be deleted",
+        )
+        .auth(Auth::Bearer),
+        Route::new(
+            "get",
+            "/api/v2/account/export",
+            "Zip of the profile, devices and audit log entries of the user",
+        )
+        .auth(Auth::Bearer)
+        .file("application/zip"),
+        Route::new(
+            "get",
+            "/api/v2/admin/users",
+            "Users whose mail or name contains the query, administrators only",
+        )
+        .auth(Auth::Bearer)
+        .query::<UserQuery>()
+        .response::<FetchUserListResponse>(),
+        Route::new(
+            "patch",
+            "/api/v2/admin/users/{mail}",
+            "Change the role of a user or disable it, administrators only",
+        )
+        .auth(Auth::Bearer)
+        .body::<UserPatch>(),
+        Route::new(
+            "delete",
+            "/api/v2/admin/users/{mail}",
+            "Delete a user, administrators only",
+        )
+        .auth(Auth::Bearer),
+        Route::new(
+            "post",
+            "/api/v2/admin/users/{mail}/impersonate",
+            "Log in as another user for support, administrators only",
+        )
+        .auth(Auth::Bearer)
+        .response::<LoginResponse>(),
+        Route::new(
+            "delete",
+            "/api/v2/admin/users/{mail}/totp",
+            "Disable two-factor authentication of a user, administrators only",
+        )
+        .auth(Auth::Bearer),
+        Route::new(
+            "get",
+            "/api/v2/admin/devices",
+            "Every device, administrators only",
+        )
+        .auth(Auth::Bearer)
+        .query::<PageQuery>()
+        .response::<FetchAllDevicesResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/admin/devices",
+            "Register a device in the current organization and get its one-time claim code, \
+             administrators only",
+        )
+        .auth(Auth::Bearer)
+        .body::<ProvisionRequest>()
+        .response::<ProvisionDeviceResponse>(),
+        Route::new(
+            "get",
+            "/api/v2/admin/audit",
+            "Audit log of security-relevant and device-changing actions, the latest first, \
+             administrators only",
+        )
+        .auth(Auth::Bearer)
+        .query::<AuditQuery>()
+        .response::<FetchAuditLogResponse>(),
+        Route::new(
+            "get",
+            "/api/v2/orgs",
+            "Organizations of the user and the one it works in",
+        )
+        .auth(Auth::Bearer)
+        .response::<FetchOrgListResponse>(),
+        Route::new(
+            "put",
+            "/api/v2/org",
+            "Work in another organization of the user",
+        )
+        .auth(Auth::Bearer)
+        .body::<OrgSwitch>(),
+        Route::new(
+            "get",
+            "/api/v2/admin/orgs",
+            "Every organization, administrators only",
+        )
+        .auth(Auth::Bearer)
+        .response::<FetchOrgListResponse>(),
+        Route::new(
+            "post",
+            "/api/v2/admin/orgs",
+            "Create an organization, administrators only",
+        )
+        .auth(Auth::Bearer)
+        .body::<NewOrgRequest>(),
+        Route::new(
+            "put",
+            "/api/v2/admin/orgs/{org}/members/{mail}",
+            "Make a user a member of an organization, administrators only",
+        )
+        .auth(Auth::Bearer),
+        Route::new(
+            "delete",
+            "/api/v2/admin/orgs/{org}/members/{mail}",
+            "Remove a user from an organization, administrators only",
+        )
+        .auth(Auth::Bearer),
+    ]
+}
@@ -0,0 +1,88 @@
+use crate::{database::Database, error::ServerError};
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode,
+    web,
+};
+use common::error::ApiError;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use std::{
+    cell::RefCell,
+    net::SocketAddr,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+/// Middleware throttling logins and registrations by the IP address of the client, see
+/// `Database::check_ip`. Requests answered with 401/404/409 count as failed attempts.
+pub struct LoginThrottle;
+
+impl<S, B> Transform<S> for LoginThrottle
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = LoginThrottleMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(LoginThrottleMiddleware {
+            service: Rc::new(RefCell::new(service)),
+        })
+    }
+}
+
+pub struct LoginThrottleMiddleware<S> {
+    service: Rc<RefCell<S>>,
+}
+
+impl<S, B> Service for LoginThrottleMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let ip = client_ip(req.peer_addr());
+            let db = req
+                .app_data::<web::Data<Database>>()
+                .cloned()
+                .ok_or(ServerError::from(ApiError::Unknown))?;
+            db.check_ip(&ip).map_err(ServerError::from)?;
+            let fut = service.borrow_mut().call(req);
+            let res = fut.await?;
+            match res.status() {
+                StatusCode::UNAUTHORIZED | StatusCode::NOT_FOUND | StatusCode::CONFLICT => {
+                    db.record_ip_attempt(&ip, true)
+                }
+                status if status.is_success() => db.record_ip_attempt(&ip, false),
+                _ => {}
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// The IP address of the peer. `X-Forwarded-For` is ignored since anyone can send it, so behind
+/// a reverse proxy every client shares the proxy's address.
+pub fn client_ip(peer_addr: Option<SocketAddr>) -> String {
+    peer_addr.map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string())
+}
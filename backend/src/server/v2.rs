@@ -0,0 +1,880 @@
+//! Resource oriented API under `/api/v2`, authenticated by `Authorization: Bearer <token>` where
+//! the token is a login token or an API key
+
+use super::{
+    auth::BearerAuth,
+    export_response, login_options_response, login_response, profile_response, simple_success,
+    sso_authorize_response, sso_status_response,
+    throttle::{client_ip, LoginThrottle},
+};
+use crate::{
+    database::{Database, Session},
+    error::ServerError,
+    export,
+    import::{self, ImportFormat},
+    store::AuditFilter,
+};
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse};
+use common::{
+    request::{
+        ApiKeyScope, AuditQuery, DesiredRequest, ImportFileRequest, LoginRequest, MessageQuery,
+        NewApiKeyRequest, NewCommandRequest, NewDeviceRequest, NewMailRequest, NewOrgRequest,
+        OrgSwitch, PageQuery, PasswordRequest, ProfilePatch, ProvisionRequest, RegisterRequest,
+        SsoLoginRequest, TotpCodeRequest, UpdateDeviceRequest, UserPatch, UserQuery,
+        VerifyMailRequest,
+    },
+    response::{
+        ConfirmTotpResponse, CreateApiKeyResponse, EnrollTotpResponse, FetchAllDevicesResponse,
+        FetchApiKeyListResponse, FetchAuditLogResponse, FetchCommandListResponse,
+        FetchDeviceListResponse, FetchDeviceProfileResponse, FetchDeviceResponse,
+        FetchDeviceTwinResponse, FetchMessageListResponse, FetchOrgListResponse,
+        FetchTotpStatusResponse, FetchUserListResponse, ImportMessagesResponse, LoginResponse,
+        ProvisionDeviceResponse, SendCommandResponse,
+    },
+};
+
+const DEFAULT_MESSAGE_LIMIT: usize = 20;
+const DEFAULT_PAGE_LIMIT: usize = 20;
+
+async fn create_session(
+    req: HttpRequest,
+    info: web::Json<LoginRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = db
+        .login(info.into_inner(), &client_ip(req.peer_addr()))
+        .await?;
+    Ok(HttpResponse::Created().json(login_response(info)))
+}
+
+async fn create_user(
+    req: HttpRequest,
+    info: web::Json<RegisterRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.register(info.into_inner(), &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+async fn verify_mail(
+    req: HttpRequest,
+    info: web::Json<VerifyMailRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.verify_mail(&info.token, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+async fn get_login_options(db: web::Data<Database>) -> HttpResponse {
+    login_options_response(&db)
+}
+
+async fn get_sso(db: web::Data<Database>) -> HttpResponse {
+    sso_status_response(&db)
+}
+
+async fn sso_authorize(db: web::Data<Database>) -> Result<HttpResponse, ServerError> {
+    let url = db.sso_authorize().await?;
+    Ok(sso_authorize_response(url))
+}
+
+async fn create_sso_session(
+    req: HttpRequest,
+    info: web::Json<SsoLoginRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = db
+        .sso_login(&info.state, &info.code, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(HttpResponse::Created().json(login_response(info)))
+}
+
+#[post("/verify_mail/resend")]
+async fn resend_verification(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    db.resend_verification_mail(&session.mail).await?;
+    Ok(simple_success())
+}
+
+#[get("/session")]
+async fn get_session(_session: Session) -> HttpResponse {
+    simple_success()
+}
+
+#[delete("/session")]
+async fn delete_session(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.logout(&session.login_token).await?;
+    Ok(simple_success())
+}
+
+#[post("/session/refresh")]
+async fn refresh_session(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = db.refresh_login(&session.login_token).await?;
+    Ok(HttpResponse::Ok().json(login_response(info)))
+}
+
+#[get("/devices")]
+async fn list_devices(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let devices = db.user_devices(&session.mail, &session.org).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceListResponse {
+        success: true,
+        devices,
+        ..Default::default()
+    }))
+}
+
+#[post("/devices")]
+async fn create_device(
+    req: HttpRequest,
+    session: Session,
+    info: web::Json<NewDeviceRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::DeviceWrite)?;
+    db.follow_device(
+        &session.mail,
+        &session.mail,
+        &session.org,
+        &info.id,
+        &info.claim_code,
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[get("/devices/{id}")]
+async fn get_device(
+    session: Session,
+    id: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let device = db.session_device(&session, &id).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceResponse {
+        success: true,
+        id: device.id,
+        name: device.name,
+        info: device.info,
+        ..Default::default()
+    }))
+}
+
+#[patch("/devices/{id}")]
+async fn update_device(
+    req: HttpRequest,
+    session: Session,
+    id: web::Path<String>,
+    info: web::Json<UpdateDeviceRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let info = info.into_inner();
+    db.modify_device(
+        &session,
+        &id,
+        info.name,
+        info.info,
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[delete("/devices/{id}")]
+async fn delete_device(
+    req: HttpRequest,
+    session: Session,
+    id: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::DeviceWrite)?;
+    db.unfollow_device(
+        &session.mail,
+        &session.mail,
+        &session.org,
+        &id,
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[get("/devices/{id}/profile")]
+async fn get_device_profile(
+    session: Session,
+    id: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.ensure_device_access(&session.mail, &session.org, &id)
+        .await?;
+    let info = db.device_profile(&session.org, &id).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceProfileResponse {
+        success: true,
+        name: info.name,
+        message_count: info.message_count,
+        alert_message_count: info.alert_message_count,
+        ..Default::default()
+    }))
+}
+
+#[get("/devices/{id}/messages")]
+async fn list_messages(
+    session: Session,
+    id: web::Path<String>,
+    query: web::Query<MessageQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    let (count, messages) = db
+        .device_messages(
+            &session,
+            &id,
+            query.from.unwrap_or(0),
+            query.to.unwrap_or(i64::MAX),
+            query.skip.unwrap_or(0),
+            query.limit.unwrap_or(DEFAULT_MESSAGE_LIMIT),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchMessageListResponse {
+        success: true,
+        count,
+        messages,
+        ..Default::default()
+    }))
+}
+
+#[get("/devices/{id}/commands")]
+async fn list_commands(
+    session: Session,
+    id: web::Path<String>,
+    query: web::Query<PageQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.ensure_device_access(&session.mail, &session.org, &id)
+        .await?;
+    let (count, commands) = db
+        .commands(
+            &session.org,
+            &id,
+            query.skip.unwrap_or(0),
+            query.limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchCommandListResponse {
+        success: true,
+        count,
+        commands,
+        ..Default::default()
+    }))
+}
+
+#[post("/devices/{id}/commands")]
+async fn send_command(
+    req: HttpRequest,
+    session: Session,
+    id: web::Path<String>,
+    info: web::Json<NewCommandRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::DeviceWrite)?;
+    let command = db
+        .issue_command(
+            &session.mail,
+            &session.org,
+            &id,
+            &info.name,
+            &info.params,
+            &client_ip(req.peer_addr()),
+        )
+        .await?;
+    Ok(HttpResponse::Created().json(SendCommandResponse {
+        success: true,
+        command,
+        ..Default::default()
+    }))
+}
+
+#[get("/devices/{id}/twin")]
+async fn get_device_twin(
+    session: Session,
+    id: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.ensure_device_access(&session.mail, &session.org, &id)
+        .await?;
+    let twin = db.device_twin(&session.org, &id).await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceTwinResponse {
+        success: true,
+        twin,
+        ..Default::default()
+    }))
+}
+
+#[put("/devices/{id}/twin/desired")]
+async fn update_desired(
+    req: HttpRequest,
+    session: Session,
+    id: web::Path<String>,
+    info: web::Json<DesiredRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::DeviceWrite)?;
+    let twin = db
+        .update_desired(
+            &session.mail,
+            &session.org,
+            &id,
+            &info.desired,
+            info.version,
+            &client_ip(req.peer_addr()),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchDeviceTwinResponse {
+        success: true,
+        twin,
+        ..Default::default()
+    }))
+}
+
+#[get("/api_keys")]
+async fn list_api_keys(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let keys = db.api_keys(&session.mail).await?;
+    Ok(HttpResponse::Ok().json(FetchApiKeyListResponse {
+        success: true,
+        keys,
+        ..Default::default()
+    }))
+}
+
+#[post("/api_keys")]
+async fn create_api_key(
+    session: Session,
+    info: web::Json<NewApiKeyRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let (key, info) = db
+        .new_api_key(
+            &session.mail,
+            &session.org,
+            &info.name,
+            info.scope,
+            info.expires_at,
+        )
+        .await?;
+    Ok(HttpResponse::Created().json(CreateApiKeyResponse {
+        success: true,
+        key,
+        info,
+        ..Default::default()
+    }))
+}
+
+#[delete("/api_keys/{id}")]
+async fn delete_api_key(
+    session: Session,
+    id: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    db.delete_api_key(&session.mail, &id).await?;
+    Ok(simple_success())
+}
+
+#[get("/totp")]
+async fn get_totp(session: Session, db: web::Data<Database>) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let (enabled, recovery_codes_left) = db.totp_status(&session.mail).await?;
+    Ok(HttpResponse::Ok().json(FetchTotpStatusResponse {
+        success: true,
+        enabled,
+        recovery_codes_left,
+        ..Default::default()
+    }))
+}
+
+#[post("/totp")]
+async fn create_totp(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let (secret, uri) = db.new_totp(&session.mail).await?;
+    Ok(HttpResponse::Created().json(EnrollTotpResponse {
+        success: true,
+        secret,
+        uri,
+        ..Default::default()
+    }))
+}
+
+#[post("/totp/confirm")]
+async fn confirm_totp(
+    session: Session,
+    info: web::Json<TotpCodeRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let recovery_codes = db.enable_totp(&session.mail, &info.code).await?;
+    Ok(HttpResponse::Ok().json(ConfirmTotpResponse {
+        success: true,
+        recovery_codes,
+        ..Default::default()
+    }))
+}
+
+#[post("/totp/disable")]
+async fn disable_totp(
+    session: Session,
+    info: web::Json<TotpCodeRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    db.remove_totp(&session.mail, &info.code).await?;
+    Ok(simple_success())
+}
+
+#[get("/account")]
+async fn get_account(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let profile = db.profile(&session.mail).await?;
+    Ok(profile_response(profile))
+}
+
+#[patch("/account")]
+async fn update_account(
+    req: HttpRequest,
+    session: Session,
+    info: web::Json<ProfilePatch>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let info = info.into_inner();
+    db.set_profile(
+        &session.mail,
+        info.name,
+        info.language,
+        info.timezone,
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[post("/account/mail")]
+async fn change_account_mail(
+    req: HttpRequest,
+    session: Session,
+    info: web::Json<NewMailRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    db.request_mail_change(
+        &session,
+        &info.mail,
+        &info.password,
+        info.plain_password.as_deref(),
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[post("/account/deletion")]
+async fn request_account_deletion(
+    req: HttpRequest,
+    session: Session,
+    info: web::Json<PasswordRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    db.request_deletion(
+        &session,
+        &info.password,
+        info.plain_password.as_deref(),
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[delete("/account/deletion")]
+async fn cancel_account_deletion(
+    req: HttpRequest,
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    db.cancel_deletion(&session.mail, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[get("/account/export")]
+async fn export_account(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    let data = export::export_account(&db, &session.mail).await?;
+    Ok(export_response(data))
+}
+
+#[get("/admin/users")]
+async fn list_users(
+    session: Session,
+    query: web::Query<UserQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let (count, users) = db
+        .users(
+            query.query.as_deref().unwrap_or(""),
+            query.skip.unwrap_or(0),
+            query.limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchUserListResponse {
+        success: true,
+        count,
+        users,
+        ..Default::default()
+    }))
+}
+
+#[patch("/admin/users/{mail}")]
+async fn update_user(
+    req: HttpRequest,
+    session: Session,
+    mail: web::Path<String>,
+    info: web::Json<UserPatch>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    db.update_user(
+        &session.mail,
+        &mail,
+        info.role,
+        info.disabled,
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[delete("/admin/users/{mail}")]
+async fn delete_user(
+    req: HttpRequest,
+    session: Session,
+    mail: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    db.delete_user(&session.mail, &mail, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[post("/admin/users/{mail}/impersonate")]
+async fn impersonate_user(
+    req: HttpRequest,
+    session: Session,
+    mail: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let info = db
+        .impersonate(&session.mail, &mail, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(HttpResponse::Created().json(login_response(info)))
+}
+
+#[delete("/admin/users/{mail}/totp")]
+async fn reset_user_totp(
+    req: HttpRequest,
+    session: Session,
+    mail: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    db.admin_reset_totp(&session.mail, &mail, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[get("/admin/devices")]
+async fn list_all_devices(
+    session: Session,
+    query: web::Query<PageQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let (count, devices) = db
+        .all_devices(
+            query.skip.unwrap_or(0),
+            query.limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchAllDevicesResponse {
+        success: true,
+        count,
+        devices,
+        ..Default::default()
+    }))
+}
+
+#[post("/admin/devices")]
+async fn provision_device(
+    req: HttpRequest,
+    session: Session,
+    info: web::Json<ProvisionRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let claim_code = db
+        .register_device(
+            &session.mail,
+            &session.org,
+            &info.id,
+            &info.name,
+            &client_ip(req.peer_addr()),
+        )
+        .await?;
+    Ok(HttpResponse::Created().json(ProvisionDeviceResponse {
+        success: true,
+        id: info.id.trim().to_string(),
+        claim_code,
+        ..Default::default()
+    }))
+}
+
+#[get("/orgs")]
+async fn list_orgs(session: Session, db: web::Data<Database>) -> Result<HttpResponse, ServerError> {
+    let (org, orgs) = db.user_orgs(&session).await?;
+    Ok(HttpResponse::Ok().json(FetchOrgListResponse {
+        success: true,
+        org,
+        orgs,
+        ..Default::default()
+    }))
+}
+
+#[put("/org")]
+async fn switch_org(
+    session: Session,
+    info: web::Json<OrgSwitch>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::Admin)?;
+    db.set_current_org(&session.mail, &info.org).await?;
+    Ok(simple_success())
+}
+
+#[get("/admin/orgs")]
+async fn list_all_orgs(
+    session: Session,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let orgs = db.all_orgs().await?;
+    Ok(HttpResponse::Ok().json(FetchOrgListResponse {
+        success: true,
+        org: session.org,
+        orgs,
+        ..Default::default()
+    }))
+}
+
+#[post("/admin/orgs")]
+async fn create_org(
+    req: HttpRequest,
+    session: Session,
+    info: web::Json<NewOrgRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    db.new_org(
+        &session.mail,
+        &info.id,
+        &info.name,
+        &client_ip(req.peer_addr()),
+    )
+    .await?;
+    Ok(simple_success())
+}
+
+#[put("/admin/orgs/{org}/members/{mail}")]
+async fn add_org_member(
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<(String, String)>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let (org, mail) = path.into_inner();
+    db.add_member(&session.mail, &org, &mail, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[delete("/admin/orgs/{org}/members/{mail}")]
+async fn remove_org_member(
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<(String, String)>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let (org, mail) = path.into_inner();
+    db.remove_member(&session.mail, &org, &mail, &client_ip(req.peer_addr()))
+        .await?;
+    Ok(simple_success())
+}
+
+#[get("/admin/audit")]
+async fn list_audit_log(
+    session: Session,
+    query: web::Query<AuditQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    db.require_admin(&session).await?;
+    let query = query.into_inner();
+    let filter = AuditFilter {
+        actor: query.actor,
+        action: query.action,
+        target: query.target,
+        from: query.from.unwrap_or(0),
+        to: query.to.unwrap_or(i64::MAX),
+    };
+    let (count, entries) = db
+        .audit_log(
+            &filter,
+            query.skip.unwrap_or(0),
+            query.limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(FetchAuditLogResponse {
+        success: true,
+        count,
+        entries,
+        ..Default::default()
+    }))
+}
+
+async fn import_messages(
+    session: Session,
+    id: web::Path<String>,
+    info: web::Json<ImportFileRequest>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, ServerError> {
+    session.require(ApiKeyScope::DeviceWrite)?;
+    db.ensure_device_access(&session.mail, &session.org, &id)
+        .await?;
+    let info = info.into_inner();
+    let format: ImportFormat = info.format.parse()?;
+    let report =
+        import::import_messages(&db, &session.org, &id, format, &info.content, info.dry_run)
+            .await?;
+    Ok(HttpResponse::Ok().json(ImportMessagesResponse {
+        success: true,
+        imported: report.imported,
+        duplicated: report.duplicated,
+        errors: report.errors,
+        ..Default::default()
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/v2")
+            .service(
+                web::resource("/sessions")
+                    .wrap(LoginThrottle)
+                    .route(web::post().to(create_session)),
+            )
+            .service(
+                web::resource("/users")
+                    .wrap(LoginThrottle)
+                    .route(web::post().to(create_user)),
+            )
+            .service(web::resource("/verify_mail").route(web::post().to(verify_mail)))
+            .service(web::resource("/login_options").route(web::get().to(get_login_options)))
+            .service(web::resource("/sso").route(web::get().to(get_sso)))
+            .service(web::resource("/sso/authorize").route(web::post().to(sso_authorize)))
+            .service(
+                web::resource("/sso/sessions")
+                    .wrap(LoginThrottle)
+                    .route(web::post().to(create_sso_session)),
+            )
+            .service(
+                web::scope("")
+                    .wrap(BearerAuth)
+                    .service(get_session)
+                    .service(delete_session)
+                    .service(refresh_session)
+                    .service(resend_verification)
+                    .service(list_devices)
+                    .service(create_device)
+                    .service(get_device)
+                    .service(update_device)
+                    .service(delete_device)
+                    .service(get_device_profile)
+                    .service(list_messages)
+                    .service(list_commands)
+                    .service(send_command)
+                    .service(get_device_twin)
+                    .service(update_desired)
+                    .service(list_api_keys)
+                    .service(create_api_key)
+                    .service(delete_api_key)
+                    .service(get_totp)
+                    .service(create_totp)
+                    .service(confirm_totp)
+                    .service(disable_totp)
+                    .service(get_account)
+                    .service(update_account)
+                    .service(change_account_mail)
+                    .service(request_account_deletion)
+                    .service(cancel_account_deletion)
+                    .service(export_account)
+                    .service(list_users)
+                    .service(update_user)
+                    .service(delete_user)
+                    .service(impersonate_user)
+                    .service(reset_user_totp)
+                    .service(list_all_devices)
+                    .service(provision_device)
+                    .service(list_audit_log)
+                    .service(list_orgs)
+                    .service(switch_org)
+                    .service(list_all_orgs)
+                    .service(create_org)
+                    .service(add_org_member)
+                    .service(remove_org_member)
+                    .service(
+                        web::resource("/devices/{id}/messages/import")
+                            .app_data(super::import_json_config())
+                            .route(web::post().to(import_messages)),
+                    ),
+            ),
+    );
+}
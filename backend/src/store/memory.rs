@@ -0,0 +1,840 @@
+use super::{
+    ApiKey, AuditEntry, AuditFilter, Command, Device, FollowedDevice, LoginAttempt, LoginRecord,
+    MailVerification, Message, MessageKey, Organization, Store, Totp, User, DEFAULT_ORG,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use common::request::{CommandStatus, Role};
+use std::{collections::HashSet, sync::Mutex};
+
+/// A store keeping everything in memory, used by tests so that no MongoDB instance is needed
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<Data>,
+}
+
+struct Data {
+    users: Vec<User>,
+    orgs: Vec<Organization>,
+    devices: Vec<Device>,
+    messages: Vec<Message>,
+    login_records: Vec<LoginRecord>,
+    login_attempts: Vec<LoginAttempt>,
+    api_keys: Vec<ApiKey>,
+    totps: Vec<Totp>,
+    mail_verifications: Vec<MailVerification>,
+    audit_log: Vec<AuditEntry>,
+    commands: Vec<Command>,
+}
+
+impl Default for Data {
+    /// Empty but for the default organization
+    fn default() -> Self {
+        Self {
+            users: vec![],
+            orgs: vec![Organization {
+                id: DEFAULT_ORG.to_string(),
+                name: "Default".to_string(),
+                created_at: 0,
+            }],
+            devices: vec![],
+            messages: vec![],
+            login_records: vec![],
+            login_attempts: vec![],
+            api_keys: vec![],
+            totps: vec![],
+            mail_verifications: vec![],
+            audit_log: vec![],
+            commands: vec![],
+        }
+    }
+}
+
+impl Data {
+    fn users_matching<'a>(&'a self, query: &'a str) -> impl Iterator<Item = &'a User> {
+        let query = query.to_lowercase();
+        self.users.iter().filter(move |user| {
+            user.mail.to_lowercase().contains(&query) || user.name.to_lowercase().contains(&query)
+        })
+    }
+
+    fn audit_entries_matching<'a>(
+        &'a self,
+        filter: &'a AuditFilter,
+    ) -> impl Iterator<Item = &'a AuditEntry> {
+        self.audit_log.iter().filter(move |entry| {
+            filter
+                .actor
+                .as_ref()
+                .map_or(true, |actor| entry.actor == *actor)
+                && filter.action.map_or(true, |action| entry.action == action)
+                && filter
+                    .target
+                    .as_ref()
+                    .map_or(true, |target| entry.target == *target)
+                && entry.time >= filter.from
+                && entry.time <= filter.to
+        })
+    }
+
+    fn message_exists(&self, msg: &Message) -> bool {
+        self.messages.iter().any(|m| {
+            m.org == msg.org
+                && m.id == msg.id
+                && m.timestamp == msg.timestamp
+                && m.msg_id == msg.msg_id
+        })
+    }
+
+    fn messages_in_range<'a>(
+        &'a self,
+        org: &'a str,
+        id: &'a str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> impl Iterator<Item = &'a Message> {
+        self.messages.iter().filter(move |msg| {
+            msg.org == org
+                && msg.id == id
+                && msg.timestamp >= start_timestamp
+                && msg.timestamp <= end_timestamp
+        })
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn find_user_by_mail(&self, mail: &str) -> anyhow::Result<Option<User>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.users.iter().find(|user| user.mail == mail).cloned())
+    }
+
+    async fn find_user_by_name(&self, name: &str) -> anyhow::Result<Option<User>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.users.iter().find(|user| user.name == name).cloned())
+    }
+
+    async fn find_user_by_oidc_subject(&self, subject: &str) -> anyhow::Result<Option<User>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .users
+            .iter()
+            .find(|user| user.oidc_subject.as_deref() == Some(subject))
+            .cloned())
+    }
+
+    async fn insert_user(&self, user: User) -> anyhow::Result<()> {
+        self.data.lock().unwrap().users.push(user);
+        Ok(())
+    }
+
+    async fn add_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.devices.push(FollowedDevice {
+                org: org.to_string(),
+                id: id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn remove_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.devices.retain(|dev| dev.org != org || dev.id != id);
+        }
+        Ok(())
+    }
+
+    async fn add_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            if !user.orgs.iter().any(|o| o == org) {
+                user.orgs.push(org.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.orgs.retain(|o| o != org);
+            user.devices.retain(|dev| dev.org != org);
+        }
+        Ok(())
+    }
+
+    async fn set_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.org = org.to_string();
+        }
+        Ok(())
+    }
+
+    async fn set_user_verified(&self, mail: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.verified = true;
+        }
+        Ok(())
+    }
+
+    async fn set_user_role(&self, mail: &str, role: Role) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.role = role;
+        }
+        Ok(())
+    }
+
+    async fn set_user_disabled(&self, mail: &str, disabled: bool) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.disabled = disabled;
+        }
+        Ok(())
+    }
+
+    async fn set_user_deletion_due(
+        &self,
+        mail: &str,
+        deletion_due_at: Option<i64>,
+    ) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.deletion_due_at = deletion_due_at;
+        }
+        Ok(())
+    }
+
+    async fn set_user_name(&self, mail: &str, name: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.name = name.to_string();
+        }
+        Ok(())
+    }
+
+    async fn set_user_preferences(
+        &self,
+        mail: &str,
+        language: &str,
+        timezone: &str,
+    ) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.language = language.to_string();
+            user.timezone = timezone.to_string();
+        }
+        Ok(())
+    }
+
+    async fn set_user_oidc_subject(&self, mail: &str, subject: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.oidc_subject = Some(subject.to_string());
+            user.verified = true;
+        }
+        Ok(())
+    }
+
+    async fn change_user_mail(&self, mail: &str, new_mail: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(user) = data.users.iter_mut().find(|user| user.mail == mail) {
+            user.mail = new_mail.to_string();
+            user.verified = true;
+        }
+        for record in data.login_records.iter_mut().filter(|r| r.mail == mail) {
+            record.mail = new_mail.to_string();
+        }
+        for key in data.api_keys.iter_mut().filter(|key| key.mail == mail) {
+            key.mail = new_mail.to_string();
+        }
+        for totp in data.totps.iter_mut().filter(|totp| totp.mail == mail) {
+            totp.mail = new_mail.to_string();
+        }
+        for dev in data.devices.iter_mut() {
+            if dev.owner.as_deref() == Some(mail) {
+                dev.owner = Some(new_mail.to_string());
+            }
+        }
+        data.mail_verifications.retain(|v| v.mail != mail);
+        Ok(())
+    }
+
+    async fn find_users_due_for_deletion(&self, now: i64) -> anyhow::Result<Vec<User>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .users
+            .iter()
+            .filter(|user| user.deletion_due_at.map_or(false, |due| due <= now))
+            .cloned()
+            .collect())
+    }
+
+    async fn count_users(&self, query: &str) -> anyhow::Result<u32> {
+        let data = self.data.lock().unwrap();
+        Ok(data.users_matching(query).count() as u32)
+    }
+
+    async fn find_users(
+        &self,
+        query: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<User>> {
+        let data = self.data.lock().unwrap();
+        let mut users: Vec<_> = data.users_matching(query).cloned().collect();
+        users.sort_by(|a, b| a.mail.cmp(&b.mail));
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(users.into_iter().skip(skip).take(limit).collect())
+    }
+
+    async fn delete_user(&self, mail: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.users.retain(|user| user.mail != mail);
+        data.login_records.retain(|record| record.mail != mail);
+        data.login_attempts.retain(|attempt| attempt.mail != mail);
+        data.api_keys.retain(|key| key.mail != mail);
+        data.totps.retain(|totp| totp.mail != mail);
+        data.mail_verifications.retain(|v| v.mail != mail);
+        for dev in data.devices.iter_mut() {
+            if dev.owner.as_deref() == Some(mail) {
+                dev.owner = None;
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_login_record(&self, record: LoginRecord) -> anyhow::Result<()> {
+        self.data.lock().unwrap().login_records.push(record);
+        Ok(())
+    }
+
+    async fn find_login_record(&self, login_token: &str) -> anyhow::Result<Option<LoginRecord>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .login_records
+            .iter()
+            .filter(|record| record.login_token == login_token)
+            .max_by_key(|record| record.login_time)
+            .cloned())
+    }
+
+    async fn delete_login_records(&self, login_token: &str) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.login_records
+            .retain(|record| record.login_token != login_token);
+        Ok(())
+    }
+
+    async fn delete_user_login_records(&self, mail: &str) -> anyhow::Result<()> {
+        self.data
+            .lock()
+            .unwrap()
+            .login_records
+            .retain(|record| record.mail != mail);
+        Ok(())
+    }
+
+    async fn touch_login_record(
+        &self,
+        login_token: &str,
+        last_active: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        for record in data
+            .login_records
+            .iter_mut()
+            .filter(|record| record.login_token == login_token)
+        {
+            record.last_active = last_active;
+        }
+        Ok(())
+    }
+
+    async fn insert_login_attempt(&self, attempt: LoginAttempt) -> anyhow::Result<()> {
+        self.data.lock().unwrap().login_attempts.push(attempt);
+        Ok(())
+    }
+
+    async fn find_login_attempts(
+        &self,
+        mail: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<LoginAttempt>> {
+        let data = self.data.lock().unwrap();
+        let mut attempts: Vec<_> = data
+            .login_attempts
+            .iter()
+            .filter(|attempt| attempt.mail == mail && attempt.time >= since)
+            .cloned()
+            .collect();
+        attempts.sort_by(|a, b| b.time.cmp(&a.time));
+        Ok(attempts)
+    }
+
+    async fn delete_login_attempts(&self, before: DateTime<Utc>) -> anyhow::Result<u32> {
+        let mut data = self.data.lock().unwrap();
+        let count = data.login_attempts.len();
+        data.login_attempts.retain(|attempt| attempt.time >= before);
+        Ok((count - data.login_attempts.len()) as u32)
+    }
+
+    async fn insert_api_key(&self, key: ApiKey) -> anyhow::Result<()> {
+        self.data.lock().unwrap().api_keys.push(key);
+        Ok(())
+    }
+
+    async fn find_api_keys(&self, mail: &str) -> anyhow::Result<Vec<ApiKey>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .api_keys
+            .iter()
+            .filter(|key| key.mail == mail)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_api_key_by_hash(&self, hashed_key: &str) -> anyhow::Result<Option<ApiKey>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .api_keys
+            .iter()
+            .find(|key| key.hashed_key == hashed_key)
+            .cloned())
+    }
+
+    async fn delete_api_key(&self, mail: &str, id: &str) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        let len = data.api_keys.len();
+        data.api_keys
+            .retain(|key| !(key.mail == mail && key.id == id));
+        Ok(data.api_keys.len() != len)
+    }
+
+    async fn update_api_key_last_used(&self, id: &str, last_used_at: i64) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(key) = data.api_keys.iter_mut().find(|key| key.id == id) {
+            key.last_used_at = Some(last_used_at);
+        }
+        Ok(())
+    }
+
+    async fn find_totp(&self, mail: &str) -> anyhow::Result<Option<Totp>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.totps.iter().find(|totp| totp.mail == mail).cloned())
+    }
+
+    async fn set_totp(&self, totp: Totp) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.totps.retain(|t| t.mail != totp.mail);
+        data.totps.push(totp);
+        Ok(())
+    }
+
+    async fn delete_totp(&self, mail: &str) -> anyhow::Result<()> {
+        self.data
+            .lock()
+            .unwrap()
+            .totps
+            .retain(|totp| totp.mail != mail);
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, mail: &str, step: i64) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        match data.totps.iter_mut().find(|totp| totp.mail == mail) {
+            Some(totp) if totp.last_step < step => {
+                totp.last_step = step;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn use_totp_recovery_code(&self, mail: &str, hashed_code: &str) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(totp) = data.totps.iter_mut().find(|totp| totp.mail == mail) {
+            let len = totp.recovery_codes.len();
+            totp.recovery_codes.retain(|code| code != hashed_code);
+            return Ok(totp.recovery_codes.len() != len);
+        }
+        Ok(false)
+    }
+
+    async fn insert_mail_verification(&self, verification: MailVerification) -> anyhow::Result<()> {
+        self.data
+            .lock()
+            .unwrap()
+            .mail_verifications
+            .push(verification);
+        Ok(())
+    }
+
+    async fn take_mail_verification(
+        &self,
+        hashed_token: &str,
+    ) -> anyhow::Result<Option<MailVerification>> {
+        let mut data = self.data.lock().unwrap();
+        let index = data
+            .mail_verifications
+            .iter()
+            .position(|v| v.hashed_token == hashed_token);
+        Ok(index.map(|index| data.mail_verifications.remove(index)))
+    }
+
+    async fn find_latest_mail_verification(
+        &self,
+        mail: &str,
+    ) -> anyhow::Result<Option<MailVerification>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .mail_verifications
+            .iter()
+            .filter(|v| v.mail == mail)
+            .max_by_key(|v| v.created_at)
+            .cloned())
+    }
+
+    async fn delete_mail_verifications(&self, mail: &str) -> anyhow::Result<()> {
+        self.data
+            .lock()
+            .unwrap()
+            .mail_verifications
+            .retain(|v| v.mail != mail);
+        Ok(())
+    }
+
+    async fn insert_audit_entry(&self, entry: AuditEntry) -> anyhow::Result<()> {
+        self.data.lock().unwrap().audit_log.push(entry);
+        Ok(())
+    }
+
+    async fn count_audit_entries(&self, filter: &AuditFilter) -> anyhow::Result<u32> {
+        let data = self.data.lock().unwrap();
+        Ok(data.audit_entries_matching(filter).count() as u32)
+    }
+
+    async fn find_audit_entries(
+        &self,
+        filter: &AuditFilter,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        let data = self.data.lock().unwrap();
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        // entries are appended in time order, the stable sort keeps later ones of the same
+        // millisecond first
+        let mut entries: Vec<_> = data.audit_entries_matching(filter).cloned().collect();
+        entries.reverse();
+        entries.sort_by(|a, b| b.time.cmp(&a.time));
+        Ok(entries.into_iter().skip(skip).take(limit).collect())
+    }
+
+    async fn insert_org(&self, org: Organization) -> anyhow::Result<()> {
+        self.data.lock().unwrap().orgs.push(org);
+        Ok(())
+    }
+
+    async fn find_org(&self, id: &str) -> anyhow::Result<Option<Organization>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.orgs.iter().find(|org| org.id == id).cloned())
+    }
+
+    async fn find_orgs(&self) -> anyhow::Result<Vec<Organization>> {
+        let mut orgs = self.data.lock().unwrap().orgs.clone();
+        orgs.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(orgs)
+    }
+
+    async fn find_device(&self, org: &str, id: &str) -> anyhow::Result<Option<Device>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .devices
+            .iter()
+            .find(|dev| dev.org == org && dev.id == id)
+            .cloned())
+    }
+
+    async fn insert_device(&self, device: Device) -> anyhow::Result<()> {
+        self.data.lock().unwrap().devices.push(device);
+        Ok(())
+    }
+
+    async fn update_device(
+        &self,
+        org: &str,
+        id: &str,
+        name: &str,
+        info: &str,
+    ) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(dev) = data
+            .devices
+            .iter_mut()
+            .find(|dev| dev.org == org && dev.id == id)
+        {
+            dev.name = name.to_string();
+            dev.info = info.to_string();
+        }
+        Ok(())
+    }
+
+    async fn set_device_claim_code(
+        &self,
+        org: &str,
+        id: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        if let Some(dev) = data
+            .devices
+            .iter_mut()
+            .find(|dev| dev.org == org && dev.id == id)
+        {
+            dev.claim_code = Some(claim_code.to_string());
+        }
+        Ok(())
+    }
+
+    async fn claim_device(
+        &self,
+        org: &str,
+        id: &str,
+        mail: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        match data.devices.iter_mut().find(|dev| {
+            dev.org == org
+                && dev.id == id
+                && dev.owner.is_none()
+                && dev.claim_code.as_deref() == Some(claim_code)
+        }) {
+            Some(dev) => {
+                dev.owner = Some(mail.to_string());
+                dev.claim_code = None;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn set_device_desired(
+        &self,
+        org: &str,
+        id: &str,
+        desired: &str,
+        version: i64,
+    ) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        match data
+            .devices
+            .iter_mut()
+            .find(|dev| dev.org == org && dev.id == id && dev.desired_version == version)
+        {
+            Some(dev) => {
+                dev.desired = desired.to_string();
+                dev.desired_version += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn set_device_reported(
+        &self,
+        org: &str,
+        id: &str,
+        reported: &str,
+        version: i64,
+        reported_at: i64,
+    ) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        match data
+            .devices
+            .iter_mut()
+            .find(|dev| dev.org == org && dev.id == id && dev.reported_version == version)
+        {
+            Some(dev) => {
+                dev.reported = reported.to_string();
+                dev.reported_version += 1;
+                dev.reported_at = reported_at;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn count_devices(&self) -> anyhow::Result<u32> {
+        Ok(self.data.lock().unwrap().devices.len() as u32)
+    }
+
+    async fn find_devices(&self, skip: usize, limit: usize) -> anyhow::Result<Vec<Device>> {
+        let data = self.data.lock().unwrap();
+        let mut devices = data.devices.clone();
+        devices.sort_by(|a, b| (&a.org, &a.id).cmp(&(&b.org, &b.id)));
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(devices.into_iter().skip(skip).take(limit).collect())
+    }
+
+    async fn insert_message(&self, msg: Message) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        if data.message_exists(&msg) {
+            Ok(false)
+        } else {
+            data.messages.push(msg);
+            Ok(true)
+        }
+    }
+
+    async fn insert_messages(&self, msgs: Vec<Message>) -> anyhow::Result<u32> {
+        let mut data = self.data.lock().unwrap();
+        let mut duplicated = 0;
+        for msg in msgs {
+            if data.message_exists(&msg) {
+                duplicated += 1;
+            } else {
+                data.messages.push(msg);
+            }
+        }
+        Ok(duplicated)
+    }
+
+    async fn count_messages(&self, org: &str, id: &str, alert_only: bool) -> anyhow::Result<u32> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .messages
+            .iter()
+            .filter(|msg| msg.org == org && msg.id == id && (msg.alert || !alert_only))
+            .count() as u32)
+    }
+
+    async fn count_messages_in_range(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<u32> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .messages_in_range(org, id, start_timestamp, end_timestamp)
+            .count() as u32)
+    }
+
+    async fn find_messages(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Message>> {
+        let data = self.data.lock().unwrap();
+        let mut messages: Vec<_> = data
+            .messages_in_range(org, id, start_timestamp, end_timestamp)
+            .cloned()
+            .collect();
+        messages.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(messages.into_iter().skip(skip).take(limit).collect())
+    }
+
+    async fn find_message_keys(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<HashSet<MessageKey>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .messages_in_range(org, id, start_timestamp, end_timestamp)
+            .map(|msg| (msg.timestamp, msg.msg_id.clone()))
+            .collect())
+    }
+
+    async fn insert_command(&self, command: Command) -> anyhow::Result<()> {
+        self.data.lock().unwrap().commands.push(command);
+        Ok(())
+    }
+
+    async fn find_command(&self, id: &str) -> anyhow::Result<Option<Command>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.commands.iter().find(|cmd| cmd.id == id).cloned())
+    }
+
+    async fn update_command_status(
+        &self,
+        id: &str,
+        status: CommandStatus,
+        result: &str,
+        updated_at: i64,
+    ) -> anyhow::Result<bool> {
+        let mut data = self.data.lock().unwrap();
+        match data
+            .commands
+            .iter_mut()
+            .find(|cmd| cmd.id == id && !cmd.status.is_final())
+        {
+            Some(cmd) => {
+                cmd.status = status;
+                cmd.result = result.to_string();
+                cmd.updated_at = updated_at;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn expire_commands(&self, created_before: i64, now: i64) -> anyhow::Result<u32> {
+        let mut data = self.data.lock().unwrap();
+        let mut count = 0;
+        for cmd in data
+            .commands
+            .iter_mut()
+            .filter(|cmd| !cmd.status.is_final() && cmd.created_at < created_before)
+        {
+            cmd.status = CommandStatus::Timeout;
+            cmd.updated_at = now;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn count_commands(&self, org: &str, device_id: &str) -> anyhow::Result<u32> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .commands
+            .iter()
+            .filter(|cmd| cmd.org == org && cmd.device_id == device_id)
+            .count() as u32)
+    }
+
+    async fn find_commands(
+        &self,
+        org: &str,
+        device_id: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Command>> {
+        let data = self.data.lock().unwrap();
+        // commands are pushed in the order they are created
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(data
+            .commands
+            .iter()
+            .rev()
+            .filter(|cmd| cmd.org == org && cmd.device_id == device_id)
+            .skip(skip)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,618 @@
+pub mod memory;
+pub mod mongo;
+pub mod sql;
+
+use crate::config::{DbKind, ServerConfig};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use common::{
+    request::{ApiKeyScope, AuditAction, CommandStatus, Role},
+    response::AuditChange,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashSet, sync::Arc};
+
+pub use memory::MemoryStore;
+pub use mongo::MongoStore;
+pub use sql::SqlStore;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct User {
+    pub mail: String,
+    pub name: String,
+    pub password: String,
+    pub devices: Vec<FollowedDevice>,
+    /// verified - the mail address has been verified, users registered before verification was
+    /// introduced count as verified
+    #[serde(default = "default_verified")]
+    pub verified: bool,
+    #[serde(default)]
+    pub role: Role,
+    /// disabled - disabled by an administrator, the user can't log in
+    #[serde(default)]
+    pub disabled: bool,
+    /// deletion_due_at - milliseconds since epoch, the user asked for the account to be deleted
+    /// and it will be at this time unless the deletion is cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletion_due_at: Option<i64>,
+    /// language - preferred language, empty if there is none
+    #[serde(default)]
+    pub language: String,
+    /// timezone - preferred timezone as an IANA name, empty for UTC
+    #[serde(default)]
+    pub timezone: String,
+    /// oidc_subject - subject of the account at the OpenID Connect provider linked to the user,
+    /// who can log in by single sign-on with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc_subject: Option<String>,
+    /// orgs - ids of the organizations the user is a member of, users registered before
+    /// organizations were introduced are members of the default one
+    #[serde(default = "default_orgs")]
+    pub orgs: Vec<String>,
+    /// org - the organization the user works in, one of `orgs`
+    #[serde(default = "default_org")]
+    pub org: String,
+}
+
+fn default_verified() -> bool {
+    true
+}
+
+/// The organization everything belonged to before organizations were introduced, new users
+/// are members of it
+pub const DEFAULT_ORG: &str = "default";
+
+fn default_org() -> String {
+    DEFAULT_ORG.to_string()
+}
+
+fn default_orgs() -> Vec<String> {
+    vec![default_org()]
+}
+
+fn is_default_org(org: &str) -> bool {
+    org == DEFAULT_ORG
+}
+
+fn empty_object() -> String {
+    "{}".to_string()
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Organization {
+    /// id - lowercase letters, digits and dashes
+    pub id: String,
+    pub name: String,
+    /// created_at - milliseconds since epoch
+    pub created_at: i64,
+}
+
+/// A device followed by a user. Devices of the default organization are stored as the plain
+/// id, as they were before organizations were introduced.
+#[derive(Clone, PartialEq)]
+pub struct FollowedDevice {
+    pub org: String,
+    pub id: String,
+}
+
+impl Serialize for FollowedDevice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Qualified<'a> {
+            org: &'a str,
+            id: &'a str,
+        }
+
+        if is_default_org(&self.org) {
+            serializer.serialize_str(&self.id)
+        } else {
+            Qualified {
+                org: &self.org,
+                id: &self.id,
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FollowedDevice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Id(String),
+            Qualified { org: String, id: String },
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Id(id) => FollowedDevice {
+                org: default_org(),
+                id,
+            },
+            Repr::Qualified { org, id } => FollowedDevice { org, id },
+        })
+    }
+}
+
+/// Devices are identified by the id within their organization. `org` is left out of the stored
+/// documents for the default organization, so that documents of before organizations match.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Device {
+    #[serde(default = "default_org", skip_serializing_if = "is_default_org")]
+    pub org: String,
+    pub id: String,
+    pub name: String,
+    pub info: String,
+    /// claim_code - hash of the one-time code a user needs to claim a provisioned device, cleared
+    /// once it is claimed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claim_code: Option<String>,
+    /// owner - mail of the user who claimed the device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// desired - JSON object of the configuration the device should have, see `DeviceTwin`
+    #[serde(default = "empty_object")]
+    pub desired: String,
+    /// desired_version - increased by every change of `desired`, so that changes made to an
+    /// older version can be rejected; `reported_version` works the same way
+    #[serde(default)]
+    pub desired_version: i64,
+    /// reported - JSON object of the state the device reported in its messages
+    #[serde(default = "empty_object")]
+    pub reported: String,
+    #[serde(default)]
+    pub reported_version: i64,
+    /// reported_at - milliseconds since epoch, 0 if the device hasn't reported anything
+    #[serde(default)]
+    pub reported_at: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Message {
+    /// org - the organization of the device, see `Device`
+    #[serde(default = "default_org", skip_serializing_if = "is_default_org")]
+    pub org: String,
+    pub id: String,
+    pub info: String,
+    pub value: i32,
+    pub alert: bool,
+    pub lng: f64,
+    pub lat: f64,
+    pub timestamp: i64,
+    /// msg_id - optional message id set by the device, used to tell apart messages with the same
+    /// timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<String>,
+}
+
+/// A session, which ends by logging out, expiring or being refreshed
+#[derive(Clone)]
+pub struct LoginRecord {
+    pub login_token: String,
+    /// mail - the user who logged in
+    pub mail: String,
+    pub login_time: DateTime<Utc>,
+    /// last_active - time of the latest request of the session, sessions expire some time after
+    /// this
+    pub last_active: DateTime<Utc>,
+    /// remember - logged in with "remember me", which has a longer idle timeout
+    pub remember: bool,
+    /// ip - address the login came from
+    pub ip: String,
+}
+
+/// A login with a password, successful or not. Attempts are kept apart from the sessions, so
+/// that ending sessions doesn't change how many failures in a row lock the account.
+#[derive(Clone)]
+pub struct LoginAttempt {
+    pub mail: String,
+    pub time: DateTime<Utc>,
+    pub success: bool,
+    /// ip - address the attempt came from
+    pub ip: String,
+}
+
+/// A personal API key, only the hash of the secret is stored
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    /// mail - the owner of the key
+    pub mail: String,
+    /// org - the organization the key works in, the one the owner was in when creating it
+    #[serde(default = "default_org")]
+    pub org: String,
+    pub name: String,
+    pub scope: ApiKeyScope,
+    pub hashed_key: String,
+    /// created_at - milliseconds since epoch, so are the other times
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+}
+
+/// TOTP secret of a user, two-factor authentication is enabled once it is confirmed
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Totp {
+    pub mail: String,
+    /// secret - base32 encoded
+    pub secret: String,
+    /// confirmed - a code of the secret has been entered, so the authenticator app has it
+    pub confirmed: bool,
+    /// last_step - time step of the latest accepted code, codes of it or earlier steps are
+    /// rejected so that a code can't be used twice
+    pub last_step: i64,
+    /// recovery_codes - hashes of the unused recovery codes
+    pub recovery_codes: Vec<String>,
+}
+
+/// A pending mail verification, only the hash of the token sent in the link is stored
+#[derive(Clone, Deserialize, Serialize)]
+pub struct MailVerification {
+    pub hashed_token: String,
+    pub mail: String,
+    /// created_at - milliseconds since epoch, so is `expires_at`
+    pub created_at: i64,
+    pub expires_at: i64,
+    /// new_mail - the user asked to change the mail to this address, which the link is sent to,
+    /// `None` for verifying the mail of a new user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_mail: Option<String>,
+}
+
+/// An entry of the audit log, which is only ever appended to
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AuditEntry {
+    /// actor - mail of the user who did it, or the mail tried for failed logins
+    pub actor: String,
+    pub action: AuditAction,
+    /// target - mail of a user or id of a device
+    pub target: String,
+    /// changes - fields changed by the action, with their values before and after it
+    pub changes: Vec<AuditChange>,
+    pub ip: String,
+    /// time - milliseconds since epoch
+    pub time: i64,
+}
+
+/// Filters of the audit log, `None` matches everything, times are inclusive
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub from: i64,
+    pub to: i64,
+}
+
+impl Default for AuditFilter {
+    /// Matches every entry
+    fn default() -> Self {
+        Self {
+            actor: None,
+            action: None,
+            target: None,
+            from: 0,
+            to: i64::MAX,
+        }
+    }
+}
+
+/// A command sent to a device over MQTT, see `CommandStatus` for how its status changes
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Command {
+    pub id: String,
+    pub org: String,
+    pub device_id: String,
+    pub name: String,
+    pub params: String,
+    pub status: CommandStatus,
+    /// issued_by - mail of the user who sent it
+    pub issued_by: String,
+    /// created_at - milliseconds since epoch, so is `updated_at`
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// result - reply of the device, or why publishing failed
+    pub result: String,
+}
+
+/// Key used to de-duplicate messages, (timestamp, message id) of a device
+pub type MessageKey = (i64, Option<String>);
+
+/// Persistence of users, organizations, sessions (login records), API keys, TOTP secrets, mail
+/// verifications, the audit log, devices, messages and commands. Devices, messages and commands
+/// are looked up within an organization.
+///
+/// Errors carry `ApiError::Net` or `ApiError::Unknown` as context.
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn find_user_by_mail(&self, mail: &str) -> anyhow::Result<Option<User>>;
+
+    async fn find_user_by_name(&self, name: &str) -> anyhow::Result<Option<User>>;
+
+    async fn find_user_by_oidc_subject(&self, subject: &str) -> anyhow::Result<Option<User>>;
+
+    async fn insert_user(&self, user: User) -> anyhow::Result<()>;
+
+    /// Append a device to the device list of a user
+    async fn add_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()>;
+
+    /// Remove a device from the device list of a user
+    async fn remove_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()>;
+
+    /// Make a user a member of an organization, nothing changes if it already is one
+    async fn add_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()>;
+
+    /// Remove a user from an organization, along with the devices of it the user follows
+    async fn remove_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()>;
+
+    /// Set the organization the user works in
+    async fn set_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()>;
+
+    async fn set_user_verified(&self, mail: &str) -> anyhow::Result<()>;
+
+    async fn set_user_role(&self, mail: &str, role: Role) -> anyhow::Result<()>;
+
+    async fn set_user_disabled(&self, mail: &str, disabled: bool) -> anyhow::Result<()>;
+
+    async fn set_user_deletion_due(
+        &self,
+        mail: &str,
+        deletion_due_at: Option<i64>,
+    ) -> anyhow::Result<()>;
+
+    async fn set_user_name(&self, mail: &str, name: &str) -> anyhow::Result<()>;
+
+    async fn set_user_preferences(
+        &self,
+        mail: &str,
+        language: &str,
+        timezone: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Link the account at the OpenID Connect provider to a user, which verifies the mail of it
+    async fn set_user_oidc_subject(&self, mail: &str, subject: &str) -> anyhow::Result<()>;
+
+    /// Change the mail address of a user, which verifies it. Memberships, followed and claimed
+    /// devices, sessions, API keys and the TOTP secret move to the new address, pending mail
+    /// verifications are dropped and the audit log is left as it is.
+    async fn change_user_mail(&self, mail: &str, new_mail: &str) -> anyhow::Result<()>;
+
+    /// Users whose deletion is due at or before `now`
+    async fn find_users_due_for_deletion(&self, now: i64) -> anyhow::Result<Vec<User>>;
+
+    /// Number of users whose mail or name contains `query`, ignoring case
+    async fn count_users(&self, query: &str) -> anyhow::Result<u32>;
+
+    /// Users whose mail or name contains `query`, ignoring case, ordered by mail
+    async fn find_users(&self, query: &str, skip: usize, limit: usize)
+        -> anyhow::Result<Vec<User>>;
+
+    /// Delete a user with the sessions, login attempts, API keys, TOTP secret and mail
+    /// verifications of it. The devices claimed by the user are left without an owner.
+    async fn delete_user(&self, mail: &str) -> anyhow::Result<()>;
+
+    async fn insert_login_record(&self, record: LoginRecord) -> anyhow::Result<()>;
+
+    /// The latest login record with the token
+    async fn find_login_record(&self, login_token: &str) -> anyhow::Result<Option<LoginRecord>>;
+
+    async fn delete_login_records(&self, login_token: &str) -> anyhow::Result<()>;
+
+    /// Delete every session of a user, the login attempts are kept
+    async fn delete_user_login_records(&self, mail: &str) -> anyhow::Result<()>;
+
+    async fn touch_login_record(
+        &self,
+        login_token: &str,
+        last_active: DateTime<Utc>,
+    ) -> anyhow::Result<()>;
+
+    async fn insert_login_attempt(&self, attempt: LoginAttempt) -> anyhow::Result<()>;
+
+    /// Login attempts of a user since some time, the latest first
+    async fn find_login_attempts(
+        &self,
+        mail: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<LoginAttempt>>;
+
+    /// Delete the login attempts made before some time, returns how many there were
+    async fn delete_login_attempts(&self, before: DateTime<Utc>) -> anyhow::Result<u32>;
+
+    async fn insert_api_key(&self, key: ApiKey) -> anyhow::Result<()>;
+
+    /// API keys of a user, the oldest first
+    async fn find_api_keys(&self, mail: &str) -> anyhow::Result<Vec<ApiKey>>;
+
+    async fn find_api_key_by_hash(&self, hashed_key: &str) -> anyhow::Result<Option<ApiKey>>;
+
+    /// Returns `false` if the user has no API key with the id
+    async fn delete_api_key(&self, mail: &str, id: &str) -> anyhow::Result<bool>;
+
+    async fn update_api_key_last_used(&self, id: &str, last_used_at: i64) -> anyhow::Result<()>;
+
+    async fn find_totp(&self, mail: &str) -> anyhow::Result<Option<Totp>>;
+
+    /// Insert the TOTP secret of a user, or replace the existing one
+    async fn set_totp(&self, totp: Totp) -> anyhow::Result<()>;
+
+    async fn delete_totp(&self, mail: &str) -> anyhow::Result<()>;
+
+    /// Set `last_step` if it is later than the stored one, returns `false` if it isn't
+    async fn use_totp_step(&self, mail: &str, step: i64) -> anyhow::Result<bool>;
+
+    /// Remove a recovery code, returns `false` if the user doesn't have it
+    async fn use_totp_recovery_code(&self, mail: &str, hashed_code: &str) -> anyhow::Result<bool>;
+
+    async fn insert_mail_verification(&self, verification: MailVerification) -> anyhow::Result<()>;
+
+    /// Remove a mail verification and return it, so that every token is used at most once
+    async fn take_mail_verification(
+        &self,
+        hashed_token: &str,
+    ) -> anyhow::Result<Option<MailVerification>>;
+
+    /// The latest mail verification sent to a user
+    async fn find_latest_mail_verification(
+        &self,
+        mail: &str,
+    ) -> anyhow::Result<Option<MailVerification>>;
+
+    async fn delete_mail_verifications(&self, mail: &str) -> anyhow::Result<()>;
+
+    async fn insert_audit_entry(&self, entry: AuditEntry) -> anyhow::Result<()>;
+
+    async fn count_audit_entries(&self, filter: &AuditFilter) -> anyhow::Result<u32>;
+
+    /// Entries matching the filter, the latest first
+    async fn find_audit_entries(
+        &self,
+        filter: &AuditFilter,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditEntry>>;
+
+    async fn insert_org(&self, org: Organization) -> anyhow::Result<()>;
+
+    async fn find_org(&self, id: &str) -> anyhow::Result<Option<Organization>>;
+
+    /// Every organization, ordered by id
+    async fn find_orgs(&self) -> anyhow::Result<Vec<Organization>>;
+
+    async fn find_device(&self, org: &str, id: &str) -> anyhow::Result<Option<Device>>;
+
+    async fn insert_device(&self, device: Device) -> anyhow::Result<()>;
+
+    async fn update_device(
+        &self,
+        org: &str,
+        id: &str,
+        name: &str,
+        info: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Set the hashed claim code of a device
+    async fn set_device_claim_code(
+        &self,
+        org: &str,
+        id: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Make a user the owner of an unclaimed device if the hashed claim code matches, which
+    /// uses it up. Returns `false` if it doesn't match or the device has already been claimed.
+    async fn claim_device(
+        &self,
+        org: &str,
+        id: &str,
+        mail: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<bool>;
+
+    /// Replace the desired configuration of a device if it is still at `version`, and increase the
+    /// version. Returns `false` if the version has changed since.
+    async fn set_device_desired(
+        &self,
+        org: &str,
+        id: &str,
+        desired: &str,
+        version: i64,
+    ) -> anyhow::Result<bool>;
+
+    /// Replace the reported state of a device if it is still at `version`, like
+    /// `set_device_desired`
+    async fn set_device_reported(
+        &self,
+        org: &str,
+        id: &str,
+        reported: &str,
+        version: i64,
+        reported_at: i64,
+    ) -> anyhow::Result<bool>;
+
+    async fn count_devices(&self) -> anyhow::Result<u32>;
+
+    /// Devices of every organization, ordered by organization and id
+    async fn find_devices(&self, skip: usize, limit: usize) -> anyhow::Result<Vec<Device>>;
+
+    /// Returns `false` if a message with the same key has already been inserted
+    async fn insert_message(&self, msg: Message) -> anyhow::Result<bool>;
+
+    /// Returns the number of messages skipped because they have already been inserted
+    async fn insert_messages(&self, msgs: Vec<Message>) -> anyhow::Result<u32>;
+
+    async fn count_messages(&self, org: &str, id: &str, alert_only: bool) -> anyhow::Result<u32>;
+
+    async fn count_messages_in_range(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<u32>;
+
+    /// Messages of a device in a time range (inclusive), the latest first
+    async fn find_messages(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Message>>;
+
+    async fn find_message_keys(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<HashSet<MessageKey>>;
+
+    async fn insert_command(&self, command: Command) -> anyhow::Result<()>;
+
+    async fn find_command(&self, id: &str) -> anyhow::Result<Option<Command>>;
+
+    /// Change the status of a command whose status isn't final yet. Returns `false` if there is
+    /// no such command or its status is final.
+    async fn update_command_status(
+        &self,
+        id: &str,
+        status: CommandStatus,
+        result: &str,
+        updated_at: i64,
+    ) -> anyhow::Result<bool>;
+
+    /// Time out the pending and sent commands created before `created_before`, returns how many
+    /// there were
+    async fn expire_commands(&self, created_before: i64, now: i64) -> anyhow::Result<u32>;
+
+    async fn count_commands(&self, org: &str, device_id: &str) -> anyhow::Result<u32>;
+
+    /// Commands sent to a device, the latest first
+    async fn find_commands(
+        &self,
+        org: &str,
+        device_id: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Command>>;
+}
+
+/// Connect to the database selected by `db_kind` of the config
+pub async fn connect(config: &ServerConfig) -> anyhow::Result<Arc<dyn Store>> {
+    let store: Arc<dyn Store> = match config.db_kind() {
+        DbKind::Mongodb => Arc::new(
+            MongoStore::new(config.db_url(), config.db_name(), config.db_time_series())
+                .await
+                .context("Failed to connect to MongoDB")?,
+        ),
+        DbKind::Sqlite | DbKind::Postgres => Arc::new(
+            SqlStore::new(&config.db_url())
+                .await
+                .context("Failed to connect to SQL database")?,
+        ),
+    };
+    Ok(store)
+}
@@ -0,0 +1,1818 @@
+use super::{
+    ApiKey, AuditEntry, AuditFilter, Command, Device, FollowedDevice, LoginAttempt, LoginRecord,
+    MailVerification, Message, MessageKey, Organization, Store, Totp, User, DEFAULT_ORG,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, TimeZone, Utc};
+use common::{
+    error::ApiError,
+    request::{CommandStatus, Role},
+};
+use futures::StreamExt;
+use mongodb::{
+    error::{ErrorKind, WriteFailure},
+    options::{
+        AggregateOptions, ClientOptions, FindOneOptions, FindOptions, InsertManyOptions,
+        ReplaceOptions, UpdateOptions,
+    },
+    Client, Collection, Database,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashSet;
+
+pub struct MongoStore {
+    users: Collection,
+    orgs: Collection,
+    devices: Collection,
+    messages: Collection,
+    login_records: Collection,
+    login_attempts: Collection,
+    api_keys: Collection,
+    totps: Collection,
+    mail_verifications: Collection,
+    audit_log: Collection,
+    commands: Collection,
+    /// time_series - `messages` is a time-series collection, where `timestamp` is stored as a
+    /// BSON date instead of milliseconds
+    time_series: bool,
+}
+
+const DUPLICATE_KEY_ERROR_CODE: i32 = 11000;
+const NAMESPACE_EXISTS_ERROR_CODE: i32 = 48;
+const INDEX_NOT_FOUND_ERROR_CODE: i32 = 27;
+
+const MESSAGES: &str = "messages";
+const MIGRATION_BATCH_SIZE: usize = 1000;
+
+impl MongoStore {
+    pub async fn new(db_url: String, db_name: &str, time_series: bool) -> anyhow::Result<Self> {
+        let database = connect(&db_url, db_name).await?;
+        let users = database.collection("users");
+        let orgs = database.collection("orgs");
+        let devices = database.collection("devices");
+        let messages = database.collection(MESSAGES);
+        let login_records = database.collection("login_records");
+        let login_attempts = database.collection("login_attempts");
+        let api_keys = database.collection("api_keys");
+        let totps = database.collection("totps");
+        let mail_verifications = database.collection("mail_verifications");
+        let audit_log = database.collection("audit_log");
+        let commands = database.collection("commands");
+
+        if time_series {
+            match collection_type(&database, MESSAGES).await? {
+                Some(ty) if ty != "timeseries" => anyhow::bail!(
+                    "Collection '{}' is not a time-series collection, \
+                     run `bs-backend migrate-messages` first",
+                    MESSAGES
+                ),
+                Some(_) => {}
+                None => create_time_series_collection(&database, MESSAGES).await?,
+            }
+        } else {
+            // MQTT redelivery and device retries may send the same message several times, this
+            // unique index makes ingestion idempotent. Messages of the default organization
+            // have no `org`, which is indexed as null.
+            let res = database
+                .run_command(
+                    doc! {
+                        "createIndexes": MESSAGES,
+                        "indexes": [
+                            {
+                                "key": { "org": 1, "id": 1, "timestamp": 1, "msg_id": 1 },
+                                "name": "org_message_key",
+                                "unique": true,
+                            }
+                        ]
+                    },
+                    None,
+                )
+                .await;
+            match res {
+                Ok(_) => {}
+                Err(err) if command_error_code(&err) == Some(DUPLICATE_KEY_ERROR_CODE) => {
+                    anyhow::bail!(
+                        "Collection '{}' has duplicated messages, \
+                         run `bs-backend dedupe-messages` first",
+                        MESSAGES
+                    )
+                }
+                Err(err) => return Err(err).context("Failed to create unique index on messages"),
+            }
+            // the index of before organizations would keep devices of different organizations
+            // from sending messages with the same id and key
+            let res = database
+                .run_command(
+                    doc! {
+                        "dropIndexes": MESSAGES,
+                        "index": "message_key",
+                    },
+                    None,
+                )
+                .await;
+            match res {
+                Ok(_) => {}
+                Err(err) if command_error_code(&err) == Some(INDEX_NOT_FOUND_ERROR_CODE) => {}
+                Err(err) => {
+                    return Err(err).context("Failed to drop the old unique index on messages")
+                }
+            }
+        }
+
+        let default_org = Organization {
+            id: DEFAULT_ORG.to_string(),
+            name: "Default".to_string(),
+            created_at: 0,
+        };
+        let upsert = UpdateOptions::builder().upsert(true).build();
+        orgs.update_one(
+            doc! { "id": DEFAULT_ORG },
+            doc! { "$setOnInsert": to_document(&default_org)? },
+            upsert,
+        )
+        .await
+        .context("Failed to create the default organization")?;
+        assign_device_owners(&devices, &users)
+            .await
+            .context("Failed to assign owners to devices")?;
+        // failed logins were login records before they had a collection of their own, they are
+        // dropped as they would only lock accounts for some more minutes
+        login_records
+            .delete_many(doc! { "success": false }, None)
+            .await
+            .context("Failed to delete failed logins from the login records")?;
+
+        Ok(Self {
+            users,
+            orgs,
+            devices,
+            messages,
+            login_records,
+            login_attempts,
+            api_keys,
+            totps,
+            mail_verifications,
+            audit_log,
+            commands,
+            time_series,
+        })
+    }
+
+    fn message_to_document(&self, msg: &Message) -> anyhow::Result<Document> {
+        let mut doc = to_document(msg)?;
+        if self.time_series {
+            doc.insert("timestamp", timestamp_to_date(msg.timestamp));
+        }
+        Ok(doc)
+    }
+
+    fn timestamp_to_bson(&self, timestamp: i64) -> Bson {
+        if self.time_series {
+            timestamp_to_date(timestamp)
+        } else {
+            Bson::Int64(timestamp)
+        }
+    }
+
+    fn range_filter(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> Document {
+        doc! {
+            "org": org_filter(org),
+            "id": id,
+            "timestamp": {
+                "$gte": self.timestamp_to_bson(start_timestamp),
+                "$lte": self.timestamp_to_bson(end_timestamp),
+            }
+        }
+    }
+
+    /// Users registered before organizations have no `orgs`, which is read as the default
+    /// organization. It is written out before the list is changed, so that the membership of
+    /// the default organization isn't lost.
+    async fn set_default_orgs(&self, mail: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+            "orgs": { "$exists": false },
+        };
+        let update = doc! {
+            "$set": {
+                "orgs": [DEFAULT_ORG],
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    /// Time-series collections can't have unique indices, so messages are looked up before
+    /// being inserted. This is not atomic, but covers redelivery which comes some time later.
+    async fn filter_existing_messages(&self, msgs: Vec<Message>) -> anyhow::Result<Vec<Message>> {
+        let mut msgs_by_device: Vec<((String, String), Vec<Message>)> = vec![];
+        for msg in msgs {
+            match msgs_by_device
+                .iter_mut()
+                .find(|((org, id), _)| *org == msg.org && *id == msg.id)
+            {
+                Some((_, dev_msgs)) => dev_msgs.push(msg),
+                None => msgs_by_device.push(((msg.org.clone(), msg.id.clone()), vec![msg])),
+            }
+        }
+
+        let mut new_msgs = vec![];
+        for ((org, id), dev_msgs) in msgs_by_device {
+            let start_timestamp = dev_msgs.iter().map(|msg| msg.timestamp).min().unwrap();
+            let end_timestamp = dev_msgs.iter().map(|msg| msg.timestamp).max().unwrap();
+            let mut keys = self
+                .find_message_keys(&org, &id, start_timestamp, end_timestamp)
+                .await?;
+            for msg in dev_msgs {
+                if keys.insert((msg.timestamp, msg.msg_id.clone())) {
+                    new_msgs.push(msg);
+                }
+            }
+        }
+        Ok(new_msgs)
+    }
+}
+
+/// Remove duplicated messages, which have the same organization, device id, timestamp and
+/// message id, keeping one of each, so that the unique index on them can be created. Returns
+/// the number of removed messages.
+pub async fn remove_duplicated_messages(db_url: String, db_name: &str) -> anyhow::Result<u64> {
+    let messages = connect(&db_url, db_name).await?.collection(MESSAGES);
+    let pipeline = vec![
+        doc! {
+            "$group": {
+                "_id": {
+                    "org": { "$ifNull": ["$org", Bson::Null] },
+                    "id": "$id",
+                    "timestamp": "$timestamp",
+                    "msg_id": { "$ifNull": ["$msg_id", Bson::Null] },
+                },
+                "ids": { "$push": "$_id" },
+                "count": { "$sum": 1 },
+            }
+        },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+    ];
+    let options = AggregateOptions::builder().allow_disk_use(true).build();
+    let mut cursor = messages
+        .aggregate(pipeline, options)
+        .await
+        .context("Failed to find duplicated messages")?;
+    let mut duplicates = Vec::with_capacity(MIGRATION_BATCH_SIZE);
+    let mut removed = 0;
+    while let Some(group) = cursor.next().await {
+        let group = group.context("Failed to find duplicated messages")?;
+        let ids = group
+            .get_array("ids")
+            .context("Failed to find duplicated messages")?;
+        duplicates.extend(ids.iter().skip(1).cloned());
+        if duplicates.len() >= MIGRATION_BATCH_SIZE {
+            let ids: Vec<_> = duplicates.drain(..).collect();
+            removed += messages
+                .delete_many(doc! { "_id": { "$in": ids } }, None)
+                .await
+                .context("Failed to remove duplicated messages")?
+                .deleted_count as u64;
+        }
+    }
+    if !duplicates.is_empty() {
+        removed += messages
+            .delete_many(doc! { "_id": { "$in": duplicates } }, None)
+            .await
+            .context("Failed to remove duplicated messages")?
+            .deleted_count as u64;
+    }
+    Ok(removed)
+}
+
+/// Copy messages from the `source` collection into a time-series `messages` collection. If
+/// `messages` is still a regular collection, it is renamed to `source` first. Returns the number
+/// of copied messages. If `messages` already has messages, e.g. when a copy was interrupted,
+/// those that are in it already are skipped, so that it can be run again.
+pub async fn migrate_messages_to_time_series(
+    db_url: String,
+    db_name: &str,
+    source: &str,
+) -> anyhow::Result<u64> {
+    let client = Client::with_options(ClientOptions::parse(&db_url).await?)?;
+    let database = client.database(db_name);
+
+    match collection_type(&database, MESSAGES).await? {
+        Some(ty) if ty == "timeseries" => {}
+        Some(_) => {
+            if collection_type(&database, source).await?.is_some() {
+                anyhow::bail!(
+                    "Both '{}' and '{}' exist, drop or rename '{}' first",
+                    MESSAGES,
+                    source,
+                    source
+                );
+            }
+            client
+                .database("admin")
+                .run_command(
+                    doc! {
+                        "renameCollection": format!("{}.{}", db_name, MESSAGES),
+                        "to": format!("{}.{}", db_name, source),
+                    },
+                    None,
+                )
+                .await
+                .with_context(|| format!("Failed to rename '{}' to '{}'", MESSAGES, source))?;
+            create_time_series_collection(&database, MESSAGES).await?;
+        }
+        None => create_time_series_collection(&database, MESSAGES).await?,
+    }
+    if collection_type(&database, source).await?.is_none() {
+        anyhow::bail!("Collection '{}' doesn't exist, nothing to migrate", source);
+    }
+
+    let target = database.collection(MESSAGES);
+    let skip_existing = target
+        .find_one(None, None)
+        .await
+        .context("Failed to read messages")?
+        .is_some();
+    let mut cursor = database
+        .collection(source)
+        .find(None, None)
+        .await
+        .with_context(|| format!("Failed to read '{}'", source))?;
+    let mut batch = Vec::with_capacity(MIGRATION_BATCH_SIZE);
+    let mut copied = 0;
+    while let Some(doc) = cursor.next().await {
+        let mut doc = doc.with_context(|| format!("Failed to read '{}'", source))?;
+        doc.remove("_id");
+        if let Ok(timestamp) = doc.get_i64("timestamp") {
+            doc.insert("timestamp", timestamp_to_date(timestamp));
+        }
+        batch.push(doc);
+        if batch.len() == MIGRATION_BATCH_SIZE {
+            copied += copy_messages(&target, batch.drain(..).collect(), skip_existing).await?;
+        }
+    }
+    if !batch.is_empty() {
+        copied += copy_messages(&target, batch, skip_existing).await?;
+    }
+    Ok(copied)
+}
+
+/// (org, id, timestamp, msg_id) of a message document of a time-series collection
+type MessageDocKey = (Option<String>, String, i64, Option<String>);
+
+fn message_doc_key(doc: &Document) -> MessageDocKey {
+    (
+        doc.get_str("org").ok().map(str::to_string),
+        doc.get_str("id").unwrap_or_default().to_string(),
+        doc.get_datetime("timestamp")
+            .map(|timestamp| timestamp.timestamp_millis())
+            .unwrap_or_default(),
+        doc.get_str("msg_id").ok().map(str::to_string),
+    )
+}
+
+/// Insert a batch of migrated messages, leaving out those already in `target` if
+/// `skip_existing` is set. Returns the number of inserted messages.
+async fn copy_messages(
+    target: &Collection,
+    mut batch: Vec<Document>,
+    skip_existing: bool,
+) -> anyhow::Result<u64> {
+    if skip_existing {
+        let keys: Vec<_> = batch
+            .iter()
+            .map(|doc| {
+                let (org, id, timestamp, msg_id) = message_doc_key(doc);
+                doc! {
+                    "org": org.map_or(Bson::Null, Bson::String),
+                    "id": id,
+                    "timestamp": timestamp_to_date(timestamp),
+                    "msg_id": msg_id.map_or(Bson::Null, Bson::String),
+                }
+            })
+            .collect();
+        let options = FindOptions::builder()
+            .projection(doc! { "org": 1, "id": 1, "timestamp": 1, "msg_id": 1 })
+            .build();
+        let mut cursor = target
+            .find(doc! { "$or": keys }, options)
+            .await
+            .context("Failed to read messages")?;
+        let mut existing = HashSet::new();
+        while let Some(doc) = cursor.next().await {
+            existing.insert(message_doc_key(&doc.context("Failed to read messages")?));
+        }
+        batch.retain(|doc| !existing.contains(&message_doc_key(doc)));
+        if batch.is_empty() {
+            return Ok(0);
+        }
+    }
+    let count = batch.len() as u64;
+    target
+        .insert_many(batch, None)
+        .await
+        .context("Failed to insert messages")?;
+    Ok(count)
+}
+
+/// Devices of before claim codes have neither a code nor an owner, so that nobody could follow
+/// them again. The first registered of their followers becomes the owner, devices without
+/// followers have to be provisioned again.
+async fn assign_device_owners(devices: &Collection, users: &Collection) -> anyhow::Result<()> {
+    let filter = doc! {
+        "owner": { "$exists": false },
+        "claim_code": { "$exists": false },
+    };
+    let unowned: Vec<Device> = find_all(devices, filter, FindOptions::default()).await?;
+    let first_registered = FindOneOptions::builder().sort(doc! { "_id": 1 }).build();
+    for device in unowned {
+        let follower = users
+            .find_one(
+                doc! { "devices": followed_device(&device.org, &device.id)? },
+                first_registered.clone(),
+            )
+            .await?;
+        let mail = match follower.as_ref().and_then(|user| user.get_str("mail").ok()) {
+            Some(mail) => mail,
+            None => continue,
+        };
+        let filter = doc! {
+            "org": org_filter(&device.org),
+            "id": &device.id,
+        };
+        devices
+            .update_one(filter, doc! { "$set": { "owner": mail } }, None)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn connect(db_url: &str, db_name: &str) -> anyhow::Result<Database> {
+    let options = ClientOptions::parse(db_url).await?;
+    let client = Client::with_options(options)?;
+    Ok(client.database(db_name))
+}
+
+/// Type of a collection ("collection", "timeseries", "view"), `None` if it doesn't exist
+async fn collection_type(database: &Database, name: &str) -> anyhow::Result<Option<String>> {
+    let mut cursor = database
+        .list_collections(doc! { "name": name }, None)
+        .await
+        .context("Failed to list collections")?;
+    if let Some(info) = cursor.next().await {
+        let info = info.context("Failed to list collections")?;
+        Ok(Some(
+            info.get_str("type").unwrap_or("collection").to_string(),
+        ))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Requires MongoDB 5.0 or later
+async fn create_time_series_collection(database: &Database, name: &str) -> anyhow::Result<()> {
+    let res = database
+        .run_command(
+            doc! {
+                "create": name,
+                "timeseries": {
+                    "timeField": "timestamp",
+                    "metaField": "id",
+                    "granularity": "seconds",
+                },
+            },
+            None,
+        )
+        .await;
+    match res {
+        Ok(_) => Ok(()),
+        Err(err) if command_error_code(&err) == Some(NAMESPACE_EXISTS_ERROR_CODE) => Ok(()),
+        Err(err) => {
+            Err(err).context("Failed to create time-series collection, MongoDB 5.0+ is required")
+        }
+    }
+}
+
+#[async_trait]
+impl Store for MongoStore {
+    async fn find_user_by_mail(&self, mail: &str) -> anyhow::Result<Option<User>> {
+        let filter = doc! {
+            "mail": mail,
+        };
+        find_one(&self.users, filter).await
+    }
+
+    async fn find_user_by_name(&self, name: &str) -> anyhow::Result<Option<User>> {
+        let filter = doc! {
+            "name": name,
+        };
+        find_one(&self.users, filter).await
+    }
+
+    async fn find_user_by_oidc_subject(&self, subject: &str) -> anyhow::Result<Option<User>> {
+        let filter = doc! {
+            "oidc_subject": subject,
+        };
+        find_one(&self.users, filter).await
+    }
+
+    async fn insert_user(&self, user: User) -> anyhow::Result<()> {
+        self.users
+            .insert_one(to_document(&user)?, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn add_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$push": {
+                "devices": followed_device(org, id)?,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn remove_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$pull": {
+                "devices": followed_device(org, id)?,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn add_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        self.set_default_orgs(mail).await?;
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$addToSet": {
+                "orgs": org,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn remove_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        self.set_default_orgs(mail).await?;
+        let query = doc! {
+            "mail": mail,
+        };
+        // devices of the default organization are followed by the plain id
+        let devices = if org == DEFAULT_ORG {
+            doc! { "$type": "string" }
+        } else {
+            doc! { "org": org }
+        };
+        let update = doc! {
+            "$pull": {
+                "orgs": org,
+                "devices": devices,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "org": org,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_verified(&self, mail: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "verified": true,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_oidc_subject(&self, mail: &str, subject: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "oidc_subject": subject,
+                "verified": true,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_role(&self, mail: &str, role: Role) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "role": role.as_str(),
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_disabled(&self, mail: &str, disabled: bool) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "disabled": disabled,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_deletion_due(
+        &self,
+        mail: &str,
+        deletion_due_at: Option<i64>,
+    ) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = match deletion_due_at {
+            Some(deletion_due_at) => doc! {
+                "$set": {
+                    "deletion_due_at": deletion_due_at,
+                }
+            },
+            None => doc! {
+                "$unset": {
+                    "deletion_due_at": "",
+                }
+            },
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_name(&self, mail: &str, name: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "name": name,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_preferences(
+        &self,
+        mail: &str,
+        language: &str,
+        timezone: &str,
+    ) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "language": language,
+                "timezone": timezone,
+            }
+        };
+        self.users
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn change_user_mail(&self, mail: &str, new_mail: &str) -> anyhow::Result<()> {
+        let filter = doc! {
+            "mail": mail,
+        };
+        self.mail_verifications
+            .delete_many(filter.clone(), None)
+            .await
+            .context(ApiError::Net)?;
+        let update = doc! {
+            "$set": {
+                "mail": new_mail,
+                "verified": true,
+            }
+        };
+        self.users
+            .update_one(filter.clone(), update, None)
+            .await
+            .context(ApiError::Net)?;
+        let update = doc! {
+            "$set": {
+                "mail": new_mail,
+            }
+        };
+        for collection in &[&self.login_records, &self.api_keys, &self.totps] {
+            collection
+                .update_many(filter.clone(), update.clone(), None)
+                .await
+                .context(ApiError::Net)?;
+        }
+        let query = doc! {
+            "owner": mail,
+        };
+        let update = doc! {
+            "$set": {
+                "owner": new_mail,
+            }
+        };
+        self.devices
+            .update_many(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_users_due_for_deletion(&self, now: i64) -> anyhow::Result<Vec<User>> {
+        let filter = doc! {
+            "deletion_due_at": {
+                "$lte": now,
+            }
+        };
+        find_all(&self.users, filter, None).await
+    }
+
+    async fn count_users(&self, query: &str) -> anyhow::Result<u32> {
+        let count = self
+            .users
+            .count_documents(user_query_filter(query), None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(count as u32)
+    }
+
+    async fn find_users(
+        &self,
+        query: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<User>> {
+        let find_options = FindOptions::builder()
+            .sort(doc! { "mail": 1 })
+            .skip(skip as i64)
+            .limit(limit as i64)
+            .build();
+        find_all(&self.users, user_query_filter(query), find_options).await
+    }
+
+    async fn delete_user(&self, mail: &str) -> anyhow::Result<()> {
+        let filter = doc! {
+            "mail": mail,
+        };
+        for collection in &[
+            &self.login_records,
+            &self.login_attempts,
+            &self.api_keys,
+            &self.totps,
+            &self.mail_verifications,
+            &self.users,
+        ] {
+            collection
+                .delete_many(filter.clone(), None)
+                .await
+                .context(ApiError::Net)?;
+        }
+        let query = doc! {
+            "owner": mail,
+        };
+        let update = doc! {
+            "$unset": {
+                "owner": "",
+            }
+        };
+        self.devices
+            .update_many(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn insert_login_record(&self, record: LoginRecord) -> anyhow::Result<()> {
+        let new_record = doc! {
+            "login_token": record.login_token,
+            "mail": record.mail,
+            "login_time": record.login_time,
+            "last_active": record.last_active,
+            "remember": record.remember,
+            "ip": record.ip,
+        };
+        self.login_records
+            .insert_one(new_record, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_login_record(&self, login_token: &str) -> anyhow::Result<Option<LoginRecord>> {
+        let filter = doc! {
+            "login_token": login_token,
+        };
+        let find_options = FindOneOptions::builder()
+            .sort(doc! { "login_time": -1 })
+            .build();
+        self.login_records
+            .find_one(filter, find_options)
+            .await
+            .context(ApiError::Net)?
+            .map(|record| login_record_from_document(&record))
+            .transpose()
+    }
+
+    async fn delete_login_records(&self, login_token: &str) -> anyhow::Result<()> {
+        let filter = doc! {
+            "login_token": login_token,
+        };
+        self.login_records
+            .delete_many(filter, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn delete_user_login_records(&self, mail: &str) -> anyhow::Result<()> {
+        let filter = doc! {
+            "mail": mail,
+        };
+        self.login_records
+            .delete_many(filter, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn touch_login_record(
+        &self,
+        login_token: &str,
+        last_active: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let query = doc! {
+            "login_token": login_token,
+        };
+        let update = doc! {
+            "$set": {
+                "last_active": last_active,
+            }
+        };
+        self.login_records
+            .update_many(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn insert_login_attempt(&self, attempt: LoginAttempt) -> anyhow::Result<()> {
+        let new_attempt = doc! {
+            "mail": attempt.mail,
+            "time": attempt.time,
+            "success": attempt.success,
+            "ip": attempt.ip,
+        };
+        self.login_attempts
+            .insert_one(new_attempt, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_login_attempts(
+        &self,
+        mail: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<LoginAttempt>> {
+        let filter = doc! {
+            "mail": mail,
+            "time": { "$gte": since },
+        };
+        let find_options = FindOptions::builder().sort(doc! { "time": -1 }).build();
+        let mut cursor = self
+            .login_attempts
+            .find(filter, find_options)
+            .await
+            .context(ApiError::Net)?;
+        let mut attempts = vec![];
+        while let Some(attempt) = cursor.next().await {
+            let attempt = attempt.context(ApiError::Net)?;
+            attempts.push(login_attempt_from_document(&attempt)?);
+        }
+        Ok(attempts)
+    }
+
+    async fn delete_login_attempts(&self, before: DateTime<Utc>) -> anyhow::Result<u32> {
+        let filter = doc! {
+            "time": { "$lt": before },
+        };
+        let result = self
+            .login_attempts
+            .delete_many(filter, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.deleted_count as u32)
+    }
+
+    async fn insert_api_key(&self, key: ApiKey) -> anyhow::Result<()> {
+        self.api_keys
+            .insert_one(to_document(&key)?, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_api_keys(&self, mail: &str) -> anyhow::Result<Vec<ApiKey>> {
+        let filter = doc! {
+            "mail": mail,
+        };
+        let find_options = FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .build();
+        let mut cursor = self
+            .api_keys
+            .find(filter, find_options)
+            .await
+            .context(ApiError::Net)?;
+        let mut keys = vec![];
+        while let Some(key) = cursor.next().await {
+            let key = key.context(ApiError::Net)?;
+            keys.push(bson::from_bson(Bson::Document(key)).context(ApiError::Unknown)?);
+        }
+        Ok(keys)
+    }
+
+    async fn find_api_key_by_hash(&self, hashed_key: &str) -> anyhow::Result<Option<ApiKey>> {
+        let filter = doc! {
+            "hashed_key": hashed_key,
+        };
+        find_one(&self.api_keys, filter).await
+    }
+
+    async fn delete_api_key(&self, mail: &str, id: &str) -> anyhow::Result<bool> {
+        let filter = doc! {
+            "mail": mail,
+            "id": id,
+        };
+        let result = self
+            .api_keys
+            .delete_one(filter, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.deleted_count > 0)
+    }
+
+    async fn update_api_key_last_used(&self, id: &str, last_used_at: i64) -> anyhow::Result<()> {
+        let query = doc! {
+            "id": id,
+        };
+        let update = doc! {
+            "$set": {
+                "last_used_at": last_used_at,
+            }
+        };
+        self.api_keys
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_totp(&self, mail: &str) -> anyhow::Result<Option<Totp>> {
+        let filter = doc! {
+            "mail": mail,
+        };
+        find_one(&self.totps, filter).await
+    }
+
+    async fn set_totp(&self, totp: Totp) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": &totp.mail,
+        };
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.totps
+            .replace_one(query, to_document(&totp)?, options)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn delete_totp(&self, mail: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        self.totps
+            .delete_one(query, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, mail: &str, step: i64) -> anyhow::Result<bool> {
+        let query = doc! {
+            "mail": mail,
+            "last_step": { "$lt": step },
+        };
+        let update = doc! {
+            "$set": {
+                "last_step": step,
+            }
+        };
+        let result = self
+            .totps
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.modified_count > 0)
+    }
+
+    async fn use_totp_recovery_code(&self, mail: &str, hashed_code: &str) -> anyhow::Result<bool> {
+        let query = doc! {
+            "mail": mail,
+            "recovery_codes": hashed_code,
+        };
+        let update = doc! {
+            "$pull": {
+                "recovery_codes": hashed_code,
+            }
+        };
+        let result = self
+            .totps
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.modified_count > 0)
+    }
+
+    async fn insert_mail_verification(&self, verification: MailVerification) -> anyhow::Result<()> {
+        self.mail_verifications
+            .insert_one(to_document(&verification)?, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn take_mail_verification(
+        &self,
+        hashed_token: &str,
+    ) -> anyhow::Result<Option<MailVerification>> {
+        let filter = doc! {
+            "hashed_token": hashed_token,
+        };
+        match self
+            .mail_verifications
+            .find_one_and_delete(filter, None)
+            .await
+            .context(ApiError::Net)?
+        {
+            Some(doc) => Ok(Some(
+                bson::from_bson(Bson::Document(doc)).context(ApiError::Unknown)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn find_latest_mail_verification(
+        &self,
+        mail: &str,
+    ) -> anyhow::Result<Option<MailVerification>> {
+        let filter = doc! {
+            "mail": mail,
+        };
+        let options = FindOneOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        if let Some(doc) = self
+            .mail_verifications
+            .find_one(filter, options)
+            .await
+            .context(ApiError::Net)?
+        {
+            Ok(Some(
+                bson::from_bson(Bson::Document(doc)).context(ApiError::Unknown)?,
+            ))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn delete_mail_verifications(&self, mail: &str) -> anyhow::Result<()> {
+        let query = doc! {
+            "mail": mail,
+        };
+        self.mail_verifications
+            .delete_many(query, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn insert_audit_entry(&self, entry: AuditEntry) -> anyhow::Result<()> {
+        self.audit_log
+            .insert_one(to_document(&entry)?, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn count_audit_entries(&self, filter: &AuditFilter) -> anyhow::Result<u32> {
+        let count = self
+            .audit_log
+            .count_documents(audit_filter(filter), None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(count as u32)
+    }
+
+    async fn find_audit_entries(
+        &self,
+        filter: &AuditFilter,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        let find_options = FindOptions::builder()
+            .sort(doc! { "time": -1, "_id": -1 })
+            .skip(skip as i64)
+            .limit(limit as i64)
+            .build();
+        find_all(&self.audit_log, audit_filter(filter), find_options).await
+    }
+
+    async fn insert_org(&self, org: Organization) -> anyhow::Result<()> {
+        self.orgs
+            .insert_one(to_document(&org)?, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_org(&self, id: &str) -> anyhow::Result<Option<Organization>> {
+        let filter = doc! {
+            "id": id,
+        };
+        find_one(&self.orgs, filter).await
+    }
+
+    async fn find_orgs(&self) -> anyhow::Result<Vec<Organization>> {
+        let find_options = FindOptions::builder().sort(doc! { "id": 1 }).build();
+        find_all(&self.orgs, doc! {}, find_options).await
+    }
+
+    async fn find_device(&self, org: &str, id: &str) -> anyhow::Result<Option<Device>> {
+        let filter = doc! {
+            "org": org_filter(org),
+            "id": id,
+        };
+        find_one(&self.devices, filter).await
+    }
+
+    async fn insert_device(&self, device: Device) -> anyhow::Result<()> {
+        self.devices
+            .insert_one(to_document(&device)?, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn update_device(
+        &self,
+        org: &str,
+        id: &str,
+        name: &str,
+        info: &str,
+    ) -> anyhow::Result<()> {
+        let query = doc! {
+            "org": org_filter(org),
+            "id": id,
+        };
+        let update = doc! {
+            "$set": {
+                "name": name,
+                "info": info,
+            }
+        };
+        self.devices
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_device_claim_code(
+        &self,
+        org: &str,
+        id: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<()> {
+        let query = doc! {
+            "org": org_filter(org),
+            "id": id,
+        };
+        let update = doc! {
+            "$set": {
+                "claim_code": claim_code,
+            }
+        };
+        self.devices
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn claim_device(
+        &self,
+        org: &str,
+        id: &str,
+        mail: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<bool> {
+        let query = doc! {
+            "org": org_filter(org),
+            "id": id,
+            "owner": null,
+            "claim_code": claim_code,
+        };
+        let update = doc! {
+            "$set": {
+                "owner": mail,
+            },
+            "$unset": {
+                "claim_code": "",
+            }
+        };
+        let result = self
+            .devices
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.modified_count > 0)
+    }
+
+    async fn set_device_desired(
+        &self,
+        org: &str,
+        id: &str,
+        desired: &str,
+        version: i64,
+    ) -> anyhow::Result<bool> {
+        let query = doc! {
+            "org": org_filter(org),
+            "id": id,
+            "desired_version": version_filter(version),
+        };
+        let update = doc! {
+            "$set": {
+                "desired": desired,
+                "desired_version": version + 1,
+            }
+        };
+        let result = self
+            .devices
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.modified_count > 0)
+    }
+
+    async fn set_device_reported(
+        &self,
+        org: &str,
+        id: &str,
+        reported: &str,
+        version: i64,
+        reported_at: i64,
+    ) -> anyhow::Result<bool> {
+        let query = doc! {
+            "org": org_filter(org),
+            "id": id,
+            "reported_version": version_filter(version),
+        };
+        let update = doc! {
+            "$set": {
+                "reported": reported,
+                "reported_version": version + 1,
+                "reported_at": reported_at,
+            }
+        };
+        let result = self
+            .devices
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.modified_count > 0)
+    }
+
+    async fn count_devices(&self) -> anyhow::Result<u32> {
+        let count = self
+            .devices
+            .count_documents(doc! {}, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(count as u32)
+    }
+
+    async fn find_devices(&self, skip: usize, limit: usize) -> anyhow::Result<Vec<Device>> {
+        let find_options = FindOptions::builder()
+            .sort(doc! { "org": 1, "id": 1 })
+            .skip(skip as i64)
+            .limit(limit as i64)
+            .build();
+        find_all(&self.devices, doc! {}, find_options).await
+    }
+
+    async fn insert_message(&self, msg: Message) -> anyhow::Result<bool> {
+        if self.time_series {
+            return match self.filter_existing_messages(vec![msg]).await?.pop() {
+                Some(msg) => {
+                    self.messages
+                        .insert_one(self.message_to_document(&msg)?, None)
+                        .await
+                        .context(ApiError::Net)?;
+                    Ok(true)
+                }
+                None => Ok(false),
+            };
+        }
+
+        match self.messages.insert_one(to_document(&msg)?, None).await {
+            Ok(_) => Ok(true),
+            Err(err) if duplicated_key_count(&err) > 0 => Ok(false),
+            Err(err) => Err(err).context(ApiError::Net),
+        }
+    }
+
+    async fn insert_messages(&self, msgs: Vec<Message>) -> anyhow::Result<u32> {
+        if self.time_series {
+            let count = msgs.len();
+            let msgs = self.filter_existing_messages(msgs).await?;
+            let duplicated = (count - msgs.len()) as u32;
+            if !msgs.is_empty() {
+                let mut docs = Vec::with_capacity(msgs.len());
+                for msg in &msgs {
+                    docs.push(self.message_to_document(msg)?);
+                }
+                self.messages
+                    .insert_many(docs, None)
+                    .await
+                    .context(ApiError::Net)?;
+            }
+            return Ok(duplicated);
+        }
+
+        let mut docs = Vec::with_capacity(msgs.len());
+        for msg in &msgs {
+            docs.push(to_document(msg)?);
+        }
+        let insert_options = InsertManyOptions::builder().ordered(false).build();
+        match self.messages.insert_many(docs, insert_options).await {
+            Ok(_) => Ok(0),
+            Err(err) => {
+                let count = duplicated_key_count(&err);
+                if count == 0 || has_other_write_errors(&err) {
+                    return Err(err).context(ApiError::Net);
+                }
+                Ok(count)
+            }
+        }
+    }
+
+    async fn count_messages(&self, org: &str, id: &str, alert_only: bool) -> anyhow::Result<u32> {
+        let mut count_filter = doc! {
+            "org": org_filter(org),
+            "id": id,
+        };
+        if alert_only {
+            count_filter.insert("alert", true);
+        }
+        let count = self
+            .messages
+            .count_documents(count_filter, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(count as u32)
+    }
+
+    async fn count_messages_in_range(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<u32> {
+        let filter = self.range_filter(org, id, start_timestamp, end_timestamp);
+        let count = self
+            .messages
+            .count_documents(filter, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(count as u32)
+    }
+
+    async fn find_messages(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Message>> {
+        let filter = self.range_filter(org, id, start_timestamp, end_timestamp);
+        let find_options = FindOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .skip(skip as i64)
+            .limit(limit as i64)
+            .build();
+        let mut cursor = self
+            .messages
+            .find(filter, find_options)
+            .await
+            .context(ApiError::Net)?;
+        let mut messages = vec![];
+        while let Some(msg) = cursor.next().await {
+            let mut msg = msg.context(ApiError::Unknown)?;
+            if let Ok(date) = msg.get_datetime("timestamp") {
+                let timestamp = date.timestamp_millis();
+                msg.insert("timestamp", timestamp);
+            }
+            let msg: Message = bson::from_bson(Bson::Document(msg)).context(ApiError::Unknown)?;
+            messages.push(msg);
+        }
+        Ok(messages)
+    }
+
+    async fn find_message_keys(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<HashSet<MessageKey>> {
+        let filter = self.range_filter(org, id, start_timestamp, end_timestamp);
+        let find_options = FindOptions::builder()
+            .projection(doc! { "timestamp": 1, "msg_id": 1 })
+            .build();
+        let mut cursor = self
+            .messages
+            .find(filter, find_options)
+            .await
+            .context(ApiError::Net)?;
+        let mut keys = HashSet::new();
+        while let Some(msg) = cursor.next().await {
+            let msg = msg.context(ApiError::Net)?;
+            let timestamp = match msg.get_datetime("timestamp") {
+                Ok(date) => date.timestamp_millis(),
+                Err(_) => msg.get_i64("timestamp").context(ApiError::Unknown)?,
+            };
+            let msg_id = msg.get_str("msg_id").ok().map(|msg_id| msg_id.to_string());
+            keys.insert((timestamp, msg_id));
+        }
+        Ok(keys)
+    }
+
+    async fn insert_command(&self, command: Command) -> anyhow::Result<()> {
+        self.commands
+            .insert_one(to_document(&command)?, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_command(&self, id: &str) -> anyhow::Result<Option<Command>> {
+        let filter = doc! {
+            "id": id,
+        };
+        find_one(&self.commands, filter).await
+    }
+
+    async fn update_command_status(
+        &self,
+        id: &str,
+        status: CommandStatus,
+        result: &str,
+        updated_at: i64,
+    ) -> anyhow::Result<bool> {
+        let query = doc! {
+            "id": id,
+            "status": open_command_statuses(),
+        };
+        let update = doc! {
+            "$set": {
+                "status": status.as_str(),
+                "result": result,
+                "updated_at": updated_at,
+            }
+        };
+        let result = self
+            .commands
+            .update_one(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.modified_count > 0)
+    }
+
+    async fn expire_commands(&self, created_before: i64, now: i64) -> anyhow::Result<u32> {
+        let query = doc! {
+            "status": open_command_statuses(),
+            "created_at": { "$lt": created_before },
+        };
+        let update = doc! {
+            "$set": {
+                "status": CommandStatus::Timeout.as_str(),
+                "updated_at": now,
+            }
+        };
+        let result = self
+            .commands
+            .update_many(query, update, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.modified_count as u32)
+    }
+
+    async fn count_commands(&self, org: &str, device_id: &str) -> anyhow::Result<u32> {
+        let filter = doc! {
+            "org": org,
+            "device_id": device_id,
+        };
+        let count = self
+            .commands
+            .count_documents(filter, None)
+            .await
+            .context(ApiError::Net)?;
+        Ok(count as u32)
+    }
+
+    async fn find_commands(
+        &self,
+        org: &str,
+        device_id: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Command>> {
+        let filter = doc! {
+            "org": org,
+            "device_id": device_id,
+        };
+        let find_options = FindOptions::builder()
+            .sort(doc! { "created_at": -1, "_id": -1 })
+            .skip(skip as i64)
+            .limit(limit as i64)
+            .build();
+        find_all(&self.commands, filter, find_options).await
+    }
+}
+
+/// Matches the statuses of commands which aren't final
+fn open_command_statuses() -> Document {
+    doc! {
+        "$in": [CommandStatus::Pending.as_str(), CommandStatus::Sent.as_str()],
+    }
+}
+
+/// Timestamps out of the range of dates, like `i64::MAX` used as an open end, are clamped
+fn timestamp_to_date(timestamp: i64) -> Bson {
+    let date = Utc
+        .timestamp_millis_opt(timestamp)
+        .single()
+        .unwrap_or(if timestamp < 0 {
+            chrono::MIN_DATETIME
+        } else {
+            chrono::MAX_DATETIME
+        });
+    Bson::DateTime(date)
+}
+
+fn login_record_from_document(record: &Document) -> anyhow::Result<LoginRecord> {
+    let login_time = *record
+        .get_datetime("login_time")
+        .context(ApiError::Unknown)?;
+    Ok(LoginRecord {
+        login_token: record
+            .get_str("login_token")
+            .unwrap_or_default()
+            .to_string(),
+        // records written before users were recorded can't be resolved to a user
+        mail: record.get_str("mail").unwrap_or_default().to_string(),
+        login_time,
+        last_active: record
+            .get_datetime("last_active")
+            .map_or(login_time, |time| *time),
+        remember: record.get_bool("remember").unwrap_or(false),
+        ip: record.get_str("ip").unwrap_or_default().to_string(),
+    })
+}
+
+fn login_attempt_from_document(attempt: &Document) -> anyhow::Result<LoginAttempt> {
+    Ok(LoginAttempt {
+        mail: attempt
+            .get_str("mail")
+            .context(ApiError::Unknown)?
+            .to_string(),
+        time: *attempt.get_datetime("time").context(ApiError::Unknown)?,
+        success: attempt.get_bool("success").context(ApiError::Unknown)?,
+        ip: attempt
+            .get_str("ip")
+            .context(ApiError::Unknown)?
+            .to_string(),
+    })
+}
+
+async fn find_one<T: DeserializeOwned>(
+    collection: &Collection,
+    filter: Document,
+) -> anyhow::Result<Option<T>> {
+    if let Some(doc) = collection
+        .find_one(filter, None)
+        .await
+        .context(ApiError::Net)?
+    {
+        let value = bson::from_bson(Bson::Document(doc)).context(ApiError::Unknown)?;
+        Ok(Some(value))
+    } else {
+        Ok(None)
+    }
+}
+
+async fn find_all<T: DeserializeOwned>(
+    collection: &Collection,
+    filter: Document,
+    options: FindOptions,
+) -> anyhow::Result<Vec<T>> {
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context(ApiError::Net)?;
+    let mut values = vec![];
+    while let Some(doc) = cursor.next().await {
+        let doc = doc.context(ApiError::Net)?;
+        values.push(bson::from_bson(Bson::Document(doc)).context(ApiError::Unknown)?);
+    }
+    Ok(values)
+}
+
+/// Users whose mail or name contains `query`, ignoring case
+fn user_query_filter(query: &str) -> Document {
+    if query.is_empty() {
+        return doc! {};
+    }
+    let pattern = regex::escape(query);
+    doc! {
+        "$or": [
+            { "mail": { "$regex": &pattern, "$options": "i" } },
+            { "name": { "$regex": &pattern, "$options": "i" } },
+        ]
+    }
+}
+
+fn audit_filter(filter: &AuditFilter) -> Document {
+    let mut doc = doc! {
+        "time": { "$gte": filter.from, "$lte": filter.to },
+    };
+    if let Some(actor) = &filter.actor {
+        doc.insert("actor", actor);
+    }
+    if let Some(action) = filter.action {
+        doc.insert("action", action.as_str());
+    }
+    if let Some(target) = &filter.target {
+        doc.insert("target", target);
+    }
+    doc
+}
+
+/// Documents of the default organization may have no `org`, see `Device`
+fn org_filter(org: &str) -> Bson {
+    if org == DEFAULT_ORG {
+        bson::bson!({ "$in": [org, Bson::Null] })
+    } else {
+        Bson::String(org.to_string())
+    }
+}
+
+/// Devices made before twins have no versions, which are 0
+fn version_filter(version: i64) -> Bson {
+    if version == 0 {
+        bson::bson!({ "$in": [version, Bson::Null] })
+    } else {
+        Bson::Int64(version)
+    }
+}
+
+fn followed_device(org: &str, id: &str) -> anyhow::Result<Bson> {
+    let device = FollowedDevice {
+        org: org.to_string(),
+        id: id.to_string(),
+    };
+    bson::to_bson(&device).context(ApiError::Unknown)
+}
+
+fn to_document<T: Serialize>(value: &T) -> anyhow::Result<Document> {
+    let serialized = bson::to_bson(value).context(ApiError::Unknown)?;
+    let doc = serialized.as_document().context(ApiError::Unknown)?;
+    Ok(doc.to_owned())
+}
+
+fn duplicated_key_count(err: &mongodb::error::Error) -> u32 {
+    match err.kind.as_ref() {
+        ErrorKind::WriteError(WriteFailure::WriteError(err)) => {
+            (err.code == DUPLICATE_KEY_ERROR_CODE) as u32
+        }
+        ErrorKind::BulkWriteError(failure) => failure.write_errors.as_ref().map_or(0, |errs| {
+            errs.iter()
+                .filter(|err| err.code == DUPLICATE_KEY_ERROR_CODE)
+                .count() as u32
+        }),
+        _ => 0,
+    }
+}
+
+fn command_error_code(err: &mongodb::error::Error) -> Option<i32> {
+    match err.kind.as_ref() {
+        ErrorKind::CommandError(err) => Some(err.code),
+        _ => None,
+    }
+}
+
+fn has_other_write_errors(err: &mongodb::error::Error) -> bool {
+    match err.kind.as_ref() {
+        ErrorKind::BulkWriteError(failure) => {
+            failure.write_concern_error.is_some()
+                || failure.write_errors.as_ref().map_or(false, |errs| {
+                    errs.iter().any(|err| err.code != DUPLICATE_KEY_ERROR_CODE)
+                })
+        }
+        _ => false,
+    }
+}
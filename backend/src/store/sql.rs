@@ -0,0 +1,1512 @@
+use super::{
+    ApiKey, AuditEntry, AuditFilter, Command, Device, FollowedDevice, LoginAttempt, LoginRecord,
+    MailVerification, Message, MessageKey, Organization, Store, Totp, User,
+};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use common::{
+    error::ApiError,
+    request::{CommandStatus, Role},
+};
+use sqlx::{
+    any::{AnyConnection, AnyKind, AnyPool, AnyPoolOptions, AnyRow},
+    migrate::Migrator,
+    Row,
+};
+use std::collections::HashSet;
+
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// A store on SQLite or PostgreSQL, chosen by the scheme of the connection string
+pub struct SqlStore {
+    pool: AnyPool,
+}
+
+impl SqlStore {
+    pub async fn new(db_url: &str) -> anyhow::Result<Self> {
+        let pool = AnyPoolOptions::new()
+            .max_connections(8)
+            .connect(db_url)
+            .await?;
+        match pool.any_kind() {
+            AnyKind::Sqlite => SQLITE_MIGRATOR.run(&pool).await?,
+            AnyKind::Postgres => POSTGRES_MIGRATOR.run(&pool).await?,
+            #[allow(unreachable_patterns)]
+            _ => anyhow::bail!("Only SQLite and PostgreSQL are supported"),
+        }
+        Ok(Self { pool })
+    }
+
+    async fn user_from_row(&self, row: Option<AnyRow>) -> anyhow::Result<Option<User>> {
+        let mut conn = self.pool.acquire().await.context(ApiError::Net)?;
+        load_user(&mut conn, row).await
+    }
+}
+
+#[async_trait]
+impl Store for SqlStore {
+    async fn find_user_by_mail(&self, mail: &str) -> anyhow::Result<Option<User>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE mail = $1",
+            USER_COLUMNS
+        ))
+        .bind(mail)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        self.user_from_row(row).await
+    }
+
+    async fn find_user_by_name(&self, name: &str) -> anyhow::Result<Option<User>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE name = $1",
+            USER_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        self.user_from_row(row).await
+    }
+
+    async fn find_user_by_oidc_subject(&self, subject: &str) -> anyhow::Result<Option<User>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE oidc_subject = $1",
+            USER_COLUMNS
+        ))
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        self.user_from_row(row).await
+    }
+
+    async fn insert_user(&self, user: User) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await.context(ApiError::Net)?;
+        sqlx::query(
+            "INSERT INTO users \
+             (mail, name, password, verified, role, disabled, deletion_due_at, language, timezone, \
+             oidc_subject, org) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(&user.mail)
+        .bind(&user.name)
+        .bind(&user.password)
+        .bind(user.verified)
+        .bind(user.role.as_str())
+        .bind(user.disabled)
+        .bind(user.deletion_due_at)
+        .bind(&user.language)
+        .bind(&user.timezone)
+        .bind(&user.oidc_subject)
+        .bind(&user.org)
+        .execute(&mut tx)
+        .await
+        .context(ApiError::Net)?;
+        insert_user_orgs_and_devices(&mut tx, &user.mail, &user)
+            .await
+            .context(ApiError::Net)?;
+        tx.commit().await.context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn add_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()> {
+        sqlx::query("INSERT INTO user_devices (mail, org_id, device_id) VALUES ($1, $2, $3)")
+            .bind(mail)
+            .bind(org)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn remove_user_device(&self, mail: &str, org: &str, id: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM user_devices WHERE mail = $1 AND org_id = $2 AND device_id = $3")
+            .bind(mail)
+            .bind(org)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn add_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO org_members (mail, org_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(mail)
+        .bind(org)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn remove_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await.context(ApiError::Net)?;
+        for query in &[
+            "DELETE FROM user_devices WHERE mail = $1 AND org_id = $2",
+            "DELETE FROM org_members WHERE mail = $1 AND org_id = $2",
+        ] {
+            sqlx::query(query)
+                .bind(mail)
+                .bind(org)
+                .execute(&mut tx)
+                .await
+                .context(ApiError::Net)?;
+        }
+        tx.commit().await.context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_org(&self, mail: &str, org: &str) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET org = $2 WHERE mail = $1")
+            .bind(mail)
+            .bind(org)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_verified(&self, mail: &str) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET verified = $2 WHERE mail = $1")
+            .bind(mail)
+            .bind(true)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_oidc_subject(&self, mail: &str, subject: &str) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET oidc_subject = $2, verified = $3 WHERE mail = $1")
+            .bind(mail)
+            .bind(subject)
+            .bind(true)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_role(&self, mail: &str, role: Role) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET role = $2 WHERE mail = $1")
+            .bind(mail)
+            .bind(role.as_str())
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_disabled(&self, mail: &str, disabled: bool) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET disabled = $2 WHERE mail = $1")
+            .bind(mail)
+            .bind(disabled)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_deletion_due(
+        &self,
+        mail: &str,
+        deletion_due_at: Option<i64>,
+    ) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET deletion_due_at = $2 WHERE mail = $1")
+            .bind(mail)
+            .bind(deletion_due_at)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_name(&self, mail: &str, name: &str) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET name = $2 WHERE mail = $1")
+            .bind(mail)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_user_preferences(
+        &self,
+        mail: &str,
+        language: &str,
+        timezone: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query("UPDATE users SET language = $2, timezone = $3 WHERE mail = $1")
+            .bind(mail)
+            .bind(language)
+            .bind(timezone)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn change_user_mail(&self, mail: &str, new_mail: &str) -> anyhow::Result<()> {
+        // the rows are read in the transaction too, so that an API key or a TOTP change made in
+        // the meantime isn't lost
+        let mut tx = self.pool.begin().await.context(ApiError::Net)?;
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE mail = $1",
+            USER_COLUMNS
+        ))
+        .bind(mail)
+        .fetch_optional(&mut tx)
+        .await
+        .context(ApiError::Net)?;
+        let user = match load_user(&mut tx, row).await? {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+        let api_keys = sqlx::query(&format!(
+            "SELECT {} FROM api_keys WHERE mail = $1 ORDER BY created_at",
+            API_KEY_COLUMNS
+        ))
+        .bind(mail)
+        .fetch_all(&mut tx)
+        .await
+        .context(ApiError::Net)?
+        .iter()
+        .map(api_key_from_row)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+        let totp = load_totp(&mut tx, mail).await?;
+
+        // the foreign keys don't follow an update of the mail, so the rows referencing the user
+        // are deleted and inserted again with the new one
+        for query in &[
+            "DELETE FROM user_devices WHERE mail = $1",
+            "DELETE FROM org_members WHERE mail = $1",
+            "DELETE FROM api_keys WHERE mail = $1",
+            "DELETE FROM totps WHERE mail = $1",
+            "DELETE FROM mail_verifications WHERE mail = $1",
+        ] {
+            sqlx::query(query)
+                .bind(mail)
+                .execute(&mut tx)
+                .await
+                .context(ApiError::Net)?;
+        }
+        for query in &[
+            "UPDATE users SET mail = $2, verified = TRUE WHERE mail = $1",
+            "UPDATE login_records SET mail = $2 WHERE mail = $1",
+            "UPDATE devices SET owner = $2 WHERE owner = $1",
+        ] {
+            sqlx::query(query)
+                .bind(mail)
+                .bind(new_mail)
+                .execute(&mut tx)
+                .await
+                .context(ApiError::Net)?;
+        }
+        insert_user_orgs_and_devices(&mut tx, new_mail, &user)
+            .await
+            .context(ApiError::Net)?;
+        for key in api_keys {
+            let key = ApiKey {
+                mail: new_mail.to_string(),
+                ..key
+            };
+            insert_api_key_query(&key)
+                .execute(&mut tx)
+                .await
+                .context(ApiError::Net)?;
+        }
+        if let Some(totp) = totp {
+            sqlx::query(
+                "INSERT INTO totps (mail, secret, confirmed, last_step) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(new_mail)
+            .bind(&totp.secret)
+            .bind(totp.confirmed)
+            .bind(totp.last_step)
+            .execute(&mut tx)
+            .await
+            .context(ApiError::Net)?;
+            for code in &totp.recovery_codes {
+                sqlx::query("INSERT INTO totp_recovery_codes (mail, hashed_code) VALUES ($1, $2)")
+                    .bind(new_mail)
+                    .bind(code)
+                    .execute(&mut tx)
+                    .await
+                    .context(ApiError::Net)?;
+            }
+        }
+        tx.commit().await.context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_users_due_for_deletion(&self, now: i64) -> anyhow::Result<Vec<User>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE deletion_due_at <= $1",
+            USER_COLUMNS
+        ))
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let mut users = Vec::with_capacity(rows.len());
+        for row in rows {
+            users.extend(self.user_from_row(Some(row)).await?);
+        }
+        Ok(users)
+    }
+
+    async fn count_users(&self, query: &str) -> anyhow::Result<u32> {
+        let row = sqlx::query(&format!(
+            "SELECT COUNT(*) AS count FROM users WHERE {}",
+            USER_QUERY_CONDITION
+        ))
+        .bind(like_pattern(query))
+        .fetch_one(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let count: i64 = row.try_get("count").context(ApiError::Unknown)?;
+        Ok(count as u32)
+    }
+
+    async fn find_users(
+        &self,
+        query: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<User>> {
+        let limit = if limit == 0 { i64::MAX } else { limit as i64 };
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE {} ORDER BY mail LIMIT $2 OFFSET $3",
+            USER_COLUMNS, USER_QUERY_CONDITION
+        ))
+        .bind(like_pattern(query))
+        .bind(limit)
+        .bind(skip as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let mut users = Vec::with_capacity(rows.len());
+        for row in rows {
+            users.extend(self.user_from_row(Some(row)).await?);
+        }
+        Ok(users)
+    }
+
+    async fn delete_user(&self, mail: &str) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await.context(ApiError::Net)?;
+        // memberships, followed devices, API keys, TOTP secrets and mail verifications are
+        // deleted by the foreign keys
+        for query in &[
+            "UPDATE devices SET owner = NULL WHERE owner = $1",
+            "DELETE FROM login_records WHERE mail = $1",
+            "DELETE FROM login_attempts WHERE mail = $1",
+            "DELETE FROM users WHERE mail = $1",
+        ] {
+            sqlx::query(query)
+                .bind(mail)
+                .execute(&mut tx)
+                .await
+                .context(ApiError::Net)?;
+        }
+        tx.commit().await.context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn insert_login_record(&self, record: LoginRecord) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO login_records \
+             (login_token, mail, login_time, last_active, remember, ip) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&record.login_token)
+        .bind(&record.mail)
+        .bind(record.login_time.timestamp_millis())
+        .bind(record.last_active.timestamp_millis())
+        .bind(record.remember)
+        .bind(&record.ip)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_login_record(&self, login_token: &str) -> anyhow::Result<Option<LoginRecord>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM login_records WHERE login_token = $1 ORDER BY login_time DESC LIMIT 1",
+            LOGIN_RECORD_COLUMNS
+        ))
+        .bind(login_token)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        row.as_ref().map(login_record_from_row).transpose()
+    }
+
+    async fn delete_login_records(&self, login_token: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM login_records WHERE login_token = $1")
+            .bind(login_token)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn delete_user_login_records(&self, mail: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM login_records WHERE mail = $1")
+            .bind(mail)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn touch_login_record(
+        &self,
+        login_token: &str,
+        last_active: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        sqlx::query("UPDATE login_records SET last_active = $2 WHERE login_token = $1")
+            .bind(login_token)
+            .bind(last_active.timestamp_millis())
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn insert_login_attempt(&self, attempt: LoginAttempt) -> anyhow::Result<()> {
+        sqlx::query("INSERT INTO login_attempts (mail, time, success, ip) VALUES ($1, $2, $3, $4)")
+            .bind(&attempt.mail)
+            .bind(attempt.time.timestamp_millis())
+            .bind(attempt.success)
+            .bind(&attempt.ip)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_login_attempts(
+        &self,
+        mail: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<LoginAttempt>> {
+        let rows = sqlx::query(
+            "SELECT mail, time, success, ip FROM login_attempts WHERE mail = $1 AND time >= $2 \
+             ORDER BY time DESC",
+        )
+        .bind(mail)
+        .bind(since.timestamp_millis())
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        rows.iter().map(login_attempt_from_row).collect()
+    }
+
+    async fn delete_login_attempts(&self, before: DateTime<Utc>) -> anyhow::Result<u32> {
+        let result = sqlx::query("DELETE FROM login_attempts WHERE time < $1")
+            .bind(before.timestamp_millis())
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.rows_affected() as u32)
+    }
+
+    async fn insert_api_key(&self, key: ApiKey) -> anyhow::Result<()> {
+        insert_api_key_query(&key)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_api_keys(&self, mail: &str) -> anyhow::Result<Vec<ApiKey>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM api_keys WHERE mail = $1 ORDER BY created_at",
+            API_KEY_COLUMNS
+        ))
+        .bind(mail)
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        rows.iter().map(api_key_from_row).collect()
+    }
+
+    async fn find_api_key_by_hash(&self, hashed_key: &str) -> anyhow::Result<Option<ApiKey>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM api_keys WHERE hashed_key = $1",
+            API_KEY_COLUMNS
+        ))
+        .bind(hashed_key)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        row.as_ref().map(api_key_from_row).transpose()
+    }
+
+    async fn delete_api_key(&self, mail: &str, id: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE mail = $1 AND id = $2")
+            .bind(mail)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_api_key_last_used(&self, id: &str, last_used_at: i64) -> anyhow::Result<()> {
+        sqlx::query("UPDATE api_keys SET last_used_at = $2 WHERE id = $1")
+            .bind(id)
+            .bind(last_used_at)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_totp(&self, mail: &str) -> anyhow::Result<Option<Totp>> {
+        let mut conn = self.pool.acquire().await.context(ApiError::Net)?;
+        load_totp(&mut conn, mail).await
+    }
+
+    async fn set_totp(&self, totp: Totp) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await.context(ApiError::Net)?;
+        sqlx::query(
+            "INSERT INTO totps (mail, secret, confirmed, last_step) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (mail) DO UPDATE \
+             SET secret = $2, confirmed = $3, last_step = $4",
+        )
+        .bind(&totp.mail)
+        .bind(&totp.secret)
+        .bind(totp.confirmed)
+        .bind(totp.last_step)
+        .execute(&mut tx)
+        .await
+        .context(ApiError::Net)?;
+        sqlx::query("DELETE FROM totp_recovery_codes WHERE mail = $1")
+            .bind(&totp.mail)
+            .execute(&mut tx)
+            .await
+            .context(ApiError::Net)?;
+        for code in &totp.recovery_codes {
+            sqlx::query("INSERT INTO totp_recovery_codes (mail, hashed_code) VALUES ($1, $2)")
+                .bind(&totp.mail)
+                .bind(code)
+                .execute(&mut tx)
+                .await
+                .context(ApiError::Net)?;
+        }
+        tx.commit().await.context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn delete_totp(&self, mail: &str) -> anyhow::Result<()> {
+        // recovery codes are deleted by the foreign key
+        sqlx::query("DELETE FROM totps WHERE mail = $1")
+            .bind(mail)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, mail: &str, step: i64) -> anyhow::Result<bool> {
+        let result =
+            sqlx::query("UPDATE totps SET last_step = $2 WHERE mail = $1 AND last_step < $2")
+                .bind(mail)
+                .bind(step)
+                .execute(&self.pool)
+                .await
+                .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_totp_recovery_code(&self, mail: &str, hashed_code: &str) -> anyhow::Result<bool> {
+        let result =
+            sqlx::query("DELETE FROM totp_recovery_codes WHERE mail = $1 AND hashed_code = $2")
+                .bind(mail)
+                .bind(hashed_code)
+                .execute(&self.pool)
+                .await
+                .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn insert_mail_verification(&self, verification: MailVerification) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO mail_verifications (hashed_token, mail, created_at, expires_at, new_mail) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&verification.hashed_token)
+        .bind(&verification.mail)
+        .bind(verification.created_at)
+        .bind(verification.expires_at)
+        .bind(&verification.new_mail)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn take_mail_verification(
+        &self,
+        hashed_token: &str,
+    ) -> anyhow::Result<Option<MailVerification>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM mail_verifications WHERE hashed_token = $1",
+            MAIL_VERIFICATION_COLUMNS
+        ))
+        .bind(hashed_token)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let verification = match row {
+            Some(row) => mail_verification_from_row(&row)?,
+            None => return Ok(None),
+        };
+        // only the request that actually deletes the row gets it
+        let result = sqlx::query("DELETE FROM mail_verifications WHERE hashed_token = $1")
+            .bind(hashed_token)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(if result.rows_affected() > 0 {
+            Some(verification)
+        } else {
+            None
+        })
+    }
+
+    async fn find_latest_mail_verification(
+        &self,
+        mail: &str,
+    ) -> anyhow::Result<Option<MailVerification>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM mail_verifications WHERE mail = $1 ORDER BY created_at DESC LIMIT 1",
+            MAIL_VERIFICATION_COLUMNS
+        ))
+        .bind(mail)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        row.map(|row| mail_verification_from_row(&row)).transpose()
+    }
+
+    async fn delete_mail_verifications(&self, mail: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM mail_verifications WHERE mail = $1")
+            .bind(mail)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn insert_audit_entry(&self, entry: AuditEntry) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO audit_log (actor, action, target, changes, ip, time) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&entry.actor)
+        .bind(entry.action.as_str())
+        .bind(&entry.target)
+        .bind(serde_json::to_string(&entry.changes).context(ApiError::Unknown)?)
+        .bind(&entry.ip)
+        .bind(entry.time)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn count_audit_entries(&self, filter: &AuditFilter) -> anyhow::Result<u32> {
+        let row = bind_audit_filter(
+            sqlx::query(&format!(
+                "SELECT COUNT(*) AS count FROM audit_log WHERE {}",
+                AUDIT_FILTER_CONDITION
+            )),
+            filter,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let count: i64 = row.try_get("count").context(ApiError::Unknown)?;
+        Ok(count as u32)
+    }
+
+    async fn find_audit_entries(
+        &self,
+        filter: &AuditFilter,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        let limit = if limit == 0 { i64::MAX } else { limit as i64 };
+        let query = format!(
+            "SELECT {} FROM audit_log WHERE {} ORDER BY time DESC, seq DESC LIMIT $6 OFFSET $7",
+            AUDIT_ENTRY_COLUMNS, AUDIT_FILTER_CONDITION
+        );
+        bind_audit_filter(sqlx::query(&query), filter)
+            .bind(limit)
+            .bind(skip as i64)
+            .fetch_all(&self.pool)
+            .await
+            .context(ApiError::Net)?
+            .iter()
+            .map(audit_entry_from_row)
+            .collect()
+    }
+
+    async fn insert_org(&self, org: Organization) -> anyhow::Result<()> {
+        sqlx::query("INSERT INTO organizations (id, name, created_at) VALUES ($1, $2, $3)")
+            .bind(&org.id)
+            .bind(&org.name)
+            .bind(org.created_at)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_org(&self, id: &str) -> anyhow::Result<Option<Organization>> {
+        let row = sqlx::query("SELECT id, name, created_at FROM organizations WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        row.as_ref().map(org_from_row).transpose()
+    }
+
+    async fn find_orgs(&self) -> anyhow::Result<Vec<Organization>> {
+        let rows = sqlx::query("SELECT id, name, created_at FROM organizations ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        rows.iter().map(org_from_row).collect()
+    }
+
+    async fn find_device(&self, org: &str, id: &str) -> anyhow::Result<Option<Device>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM devices WHERE org_id = $1 AND id = $2",
+            DEVICE_COLUMNS
+        ))
+        .bind(org)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        row.as_ref().map(device_from_row).transpose()
+    }
+
+    async fn insert_device(&self, device: Device) -> anyhow::Result<()> {
+        sqlx::query(&format!(
+            "INSERT INTO devices ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            DEVICE_COLUMNS
+        ))
+        .bind(&device.org)
+        .bind(&device.id)
+        .bind(&device.name)
+        .bind(&device.info)
+        .bind(&device.claim_code)
+        .bind(&device.owner)
+        .bind(&device.desired)
+        .bind(device.desired_version)
+        .bind(&device.reported)
+        .bind(device.reported_version)
+        .bind(device.reported_at)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn update_device(
+        &self,
+        org: &str,
+        id: &str,
+        name: &str,
+        info: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query("UPDATE devices SET name = $3, info = $4 WHERE org_id = $1 AND id = $2")
+            .bind(org)
+            .bind(id)
+            .bind(name)
+            .bind(info)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn set_device_claim_code(
+        &self,
+        org: &str,
+        id: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query("UPDATE devices SET claim_code = $3 WHERE org_id = $1 AND id = $2")
+            .bind(org)
+            .bind(id)
+            .bind(claim_code)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn claim_device(
+        &self,
+        org: &str,
+        id: &str,
+        mail: &str,
+        claim_code: &str,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE devices SET owner = $3, claim_code = NULL \
+             WHERE org_id = $1 AND id = $2 AND owner IS NULL AND claim_code = $4",
+        )
+        .bind(org)
+        .bind(id)
+        .bind(mail)
+        .bind(claim_code)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_device_desired(
+        &self,
+        org: &str,
+        id: &str,
+        desired: &str,
+        version: i64,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE devices SET desired = $3, desired_version = desired_version + 1 \
+             WHERE org_id = $1 AND id = $2 AND desired_version = $4",
+        )
+        .bind(org)
+        .bind(id)
+        .bind(desired)
+        .bind(version)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_device_reported(
+        &self,
+        org: &str,
+        id: &str,
+        reported: &str,
+        version: i64,
+        reported_at: i64,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE devices \
+             SET reported = $3, reported_version = reported_version + 1, reported_at = $5 \
+             WHERE org_id = $1 AND id = $2 AND reported_version = $4",
+        )
+        .bind(org)
+        .bind(id)
+        .bind(reported)
+        .bind(version)
+        .bind(reported_at)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count_devices(&self) -> anyhow::Result<u32> {
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM devices")
+            .fetch_one(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        let count: i64 = row.try_get("count").context(ApiError::Unknown)?;
+        Ok(count as u32)
+    }
+
+    async fn find_devices(&self, skip: usize, limit: usize) -> anyhow::Result<Vec<Device>> {
+        let limit = if limit == 0 { i64::MAX } else { limit as i64 };
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM devices ORDER BY org_id, id LIMIT $1 OFFSET $2",
+            DEVICE_COLUMNS
+        ))
+        .bind(limit)
+        .bind(skip as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        rows.iter().map(device_from_row).collect()
+    }
+
+    async fn insert_message(&self, msg: Message) -> anyhow::Result<bool> {
+        let result = insert_message_query(&msg)
+            .execute(&self.pool)
+            .await
+            .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn insert_messages(&self, msgs: Vec<Message>) -> anyhow::Result<u32> {
+        let mut tx = self.pool.begin().await.context(ApiError::Net)?;
+        let mut duplicated = 0;
+        for msg in &msgs {
+            let result = insert_message_query(msg)
+                .execute(&mut tx)
+                .await
+                .context(ApiError::Net)?;
+            if result.rows_affected() == 0 {
+                duplicated += 1;
+            }
+        }
+        tx.commit().await.context(ApiError::Net)?;
+        Ok(duplicated)
+    }
+
+    async fn count_messages(&self, org: &str, id: &str, alert_only: bool) -> anyhow::Result<u32> {
+        let query = if alert_only {
+            sqlx::query(
+                "SELECT COUNT(*) AS count FROM messages \
+                 WHERE org_id = $1 AND device_id = $2 AND alert = $3",
+            )
+            .bind(org)
+            .bind(id)
+            .bind(true)
+        } else {
+            sqlx::query(
+                "SELECT COUNT(*) AS count FROM messages WHERE org_id = $1 AND device_id = $2",
+            )
+            .bind(org)
+            .bind(id)
+        };
+        let row = query.fetch_one(&self.pool).await.context(ApiError::Net)?;
+        let count: i64 = row.try_get("count").context(ApiError::Unknown)?;
+        Ok(count as u32)
+    }
+
+    async fn count_messages_in_range(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<u32> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS count FROM messages \
+             WHERE org_id = $1 AND device_id = $2 AND timestamp >= $3 AND timestamp <= $4",
+        )
+        .bind(org)
+        .bind(id)
+        .bind(start_timestamp)
+        .bind(end_timestamp)
+        .fetch_one(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let count: i64 = row.try_get("count").context(ApiError::Unknown)?;
+        Ok(count as u32)
+    }
+
+    async fn find_messages(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Message>> {
+        let limit = if limit == 0 { i64::MAX } else { limit as i64 };
+        let rows = sqlx::query(
+            "SELECT org_id, device_id, info, value, alert, lng, lat, timestamp, msg_id \
+             FROM messages \
+             WHERE org_id = $1 AND device_id = $2 AND timestamp >= $3 AND timestamp <= $4 \
+             ORDER BY timestamp DESC LIMIT $5 OFFSET $6",
+        )
+        .bind(org)
+        .bind(id)
+        .bind(start_timestamp)
+        .bind(end_timestamp)
+        .bind(limit)
+        .bind(skip as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        rows.iter()
+            .map(|row| message_from_row(row).context(ApiError::Unknown))
+            .collect()
+    }
+
+    async fn find_message_keys(
+        &self,
+        org: &str,
+        id: &str,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> anyhow::Result<HashSet<MessageKey>> {
+        let rows = sqlx::query(
+            "SELECT timestamp, msg_id FROM messages \
+             WHERE org_id = $1 AND device_id = $2 AND timestamp >= $3 AND timestamp <= $4",
+        )
+        .bind(org)
+        .bind(id)
+        .bind(start_timestamp)
+        .bind(end_timestamp)
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let mut keys = HashSet::with_capacity(rows.len());
+        for row in rows {
+            let timestamp: i64 = row.try_get("timestamp").context(ApiError::Unknown)?;
+            let msg_id: String = row.try_get("msg_id").context(ApiError::Unknown)?;
+            keys.insert((timestamp, from_msg_id(msg_id)));
+        }
+        Ok(keys)
+    }
+
+    async fn insert_command(&self, command: Command) -> anyhow::Result<()> {
+        sqlx::query(&format!(
+            "INSERT INTO commands ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            COMMAND_COLUMNS
+        ))
+        .bind(&command.id)
+        .bind(&command.org)
+        .bind(&command.device_id)
+        .bind(&command.name)
+        .bind(&command.params)
+        .bind(command.status.as_str())
+        .bind(&command.issued_by)
+        .bind(command.created_at)
+        .bind(command.updated_at)
+        .bind(&command.result)
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(())
+    }
+
+    async fn find_command(&self, id: &str) -> anyhow::Result<Option<Command>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM commands WHERE id = $1",
+            COMMAND_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        row.as_ref().map(command_from_row).transpose()
+    }
+
+    async fn update_command_status(
+        &self,
+        id: &str,
+        status: CommandStatus,
+        result: &str,
+        updated_at: i64,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE commands SET status = $2, result = $3, updated_at = $4 \
+             WHERE id = $1 AND status IN ($5, $6)",
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .bind(result)
+        .bind(updated_at)
+        .bind(CommandStatus::Pending.as_str())
+        .bind(CommandStatus::Sent.as_str())
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn expire_commands(&self, created_before: i64, now: i64) -> anyhow::Result<u32> {
+        let result = sqlx::query(
+            "UPDATE commands SET status = $2, updated_at = $3 \
+             WHERE created_at < $1 AND status IN ($4, $5)",
+        )
+        .bind(created_before)
+        .bind(CommandStatus::Timeout.as_str())
+        .bind(now)
+        .bind(CommandStatus::Pending.as_str())
+        .bind(CommandStatus::Sent.as_str())
+        .execute(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        Ok(result.rows_affected() as u32)
+    }
+
+    async fn count_commands(&self, org: &str, device_id: &str) -> anyhow::Result<u32> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS count FROM commands WHERE org_id = $1 AND device_id = $2",
+        )
+        .bind(org)
+        .bind(device_id)
+        .fetch_one(&self.pool)
+        .await
+        .context(ApiError::Net)?;
+        let count: i64 = row.try_get("count").context(ApiError::Unknown)?;
+        Ok(count as u32)
+    }
+
+    async fn find_commands(
+        &self,
+        org: &str,
+        device_id: &str,
+        skip: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Command>> {
+        let limit = if limit == 0 { i64::MAX } else { limit as i64 };
+        sqlx::query(&format!(
+            "SELECT {} FROM commands WHERE org_id = $1 AND device_id = $2 \
+             ORDER BY created_at DESC, seq DESC LIMIT $3 OFFSET $4",
+            COMMAND_COLUMNS
+        ))
+        .bind(org)
+        .bind(device_id)
+        .bind(limit)
+        .bind(skip as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context(ApiError::Net)?
+        .iter()
+        .map(command_from_row)
+        .collect()
+    }
+}
+
+const USER_COLUMNS: &str = "mail, name, password, verified, role, disabled, deletion_due_at, \
+                            language, timezone, oidc_subject, org";
+
+/// A user with the devices it follows and the organizations it belongs to
+async fn load_user(conn: &mut AnyConnection, row: Option<AnyRow>) -> anyhow::Result<Option<User>> {
+    if let Some(row) = row {
+        let mail: String = row.try_get("mail").context(ApiError::Unknown)?;
+        let devices =
+            sqlx::query("SELECT org_id, device_id FROM user_devices WHERE mail = $1 ORDER BY seq")
+                .bind(&mail)
+                .fetch_all(&mut *conn)
+                .await
+                .context(ApiError::Net)?
+                .into_iter()
+                .map(|row| {
+                    Ok(FollowedDevice {
+                        org: row.try_get("org_id")?,
+                        id: row.try_get("device_id")?,
+                    })
+                })
+                .collect::<Result<_, sqlx::Error>>()
+                .context(ApiError::Unknown)?;
+        let orgs = sqlx::query("SELECT org_id FROM org_members WHERE mail = $1 ORDER BY org_id")
+            .bind(&mail)
+            .fetch_all(&mut *conn)
+            .await
+            .context(ApiError::Net)?
+            .into_iter()
+            .map(|row| row.try_get("org_id"))
+            .collect::<Result<_, _>>()
+            .context(ApiError::Unknown)?;
+        Ok(Some(User {
+            mail,
+            name: row.try_get("name").context(ApiError::Unknown)?,
+            password: row.try_get("password").context(ApiError::Unknown)?,
+            devices,
+            verified: row.try_get("verified").context(ApiError::Unknown)?,
+            role: row
+                .try_get::<String, _>("role")
+                .context(ApiError::Unknown)?
+                .parse()
+                .context(ApiError::Unknown)?,
+            disabled: row.try_get("disabled").context(ApiError::Unknown)?,
+            deletion_due_at: row.try_get("deletion_due_at").context(ApiError::Unknown)?,
+            language: row.try_get("language").context(ApiError::Unknown)?,
+            timezone: row.try_get("timezone").context(ApiError::Unknown)?,
+            oidc_subject: row.try_get("oidc_subject").context(ApiError::Unknown)?,
+            orgs,
+            org: row.try_get("org").context(ApiError::Unknown)?,
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Two-factor authentication of a user with the hashed recovery codes
+async fn load_totp(conn: &mut AnyConnection, mail: &str) -> anyhow::Result<Option<Totp>> {
+    let row = sqlx::query("SELECT secret, confirmed, last_step FROM totps WHERE mail = $1")
+        .bind(mail)
+        .fetch_optional(&mut *conn)
+        .await
+        .context(ApiError::Net)?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let recovery_codes = sqlx::query("SELECT hashed_code FROM totp_recovery_codes WHERE mail = $1")
+        .bind(mail)
+        .fetch_all(&mut *conn)
+        .await
+        .context(ApiError::Net)?
+        .into_iter()
+        .map(|row| row.try_get("hashed_code"))
+        .collect::<Result<_, _>>()
+        .context(ApiError::Unknown)?;
+    Ok(Some(Totp {
+        mail: mail.to_string(),
+        secret: row.try_get("secret").context(ApiError::Unknown)?,
+        confirmed: row.try_get("confirmed").context(ApiError::Unknown)?,
+        last_step: row.try_get("last_step").context(ApiError::Unknown)?,
+        recovery_codes,
+    }))
+}
+
+/// Memberships and followed devices of `user`, inserted for the user with the mail `mail`
+async fn insert_user_orgs_and_devices(
+    tx: &mut sqlx::Transaction<'_, sqlx::Any>,
+    mail: &str,
+    user: &User,
+) -> Result<(), sqlx::Error> {
+    for org in &user.orgs {
+        sqlx::query("INSERT INTO org_members (mail, org_id) VALUES ($1, $2)")
+            .bind(mail)
+            .bind(org)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for dev in &user.devices {
+        sqlx::query("INSERT INTO user_devices (mail, org_id, device_id) VALUES ($1, $2, $3)")
+            .bind(mail)
+            .bind(&dev.org)
+            .bind(&dev.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Users whose mail or name matches the pattern of `like_pattern`
+const USER_QUERY_CONDITION: &str =
+    "(LOWER(mail) LIKE $1 ESCAPE '\\' OR LOWER(name) LIKE $1 ESCAPE '\\')";
+
+/// `LIKE` pattern matching strings that contain `query`, ignoring case
+fn like_pattern(query: &str) -> String {
+    let mut pattern = String::with_capacity(query.len() + 2);
+    pattern.push('%');
+    for c in query.to_lowercase().chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+const LOGIN_RECORD_COLUMNS: &str = "login_token, mail, login_time, last_active, remember, ip";
+
+fn login_record_from_row(row: &AnyRow) -> anyhow::Result<LoginRecord> {
+    let login_time: i64 = row.try_get("login_time").context(ApiError::Unknown)?;
+    let last_active: i64 = row.try_get("last_active").context(ApiError::Unknown)?;
+    Ok(LoginRecord {
+        login_token: row.try_get("login_token").context(ApiError::Unknown)?,
+        mail: row.try_get("mail").context(ApiError::Unknown)?,
+        login_time: Utc.timestamp_millis(login_time),
+        // 0 for records written before sessions were renewed
+        last_active: Utc.timestamp_millis(last_active.max(login_time)),
+        remember: row.try_get("remember").context(ApiError::Unknown)?,
+        ip: row.try_get("ip").context(ApiError::Unknown)?,
+    })
+}
+
+fn login_attempt_from_row(row: &AnyRow) -> anyhow::Result<LoginAttempt> {
+    let time: i64 = row.try_get("time").context(ApiError::Unknown)?;
+    Ok(LoginAttempt {
+        mail: row.try_get("mail").context(ApiError::Unknown)?,
+        time: Utc.timestamp_millis(time),
+        success: row.try_get("success").context(ApiError::Unknown)?,
+        ip: row.try_get("ip").context(ApiError::Unknown)?,
+    })
+}
+
+const API_KEY_COLUMNS: &str =
+    "id, mail, org_id, name, scope, hashed_key, created_at, expires_at, last_used_at";
+
+fn insert_api_key_query(
+    key: &ApiKey,
+) -> sqlx::query::Query<'_, sqlx::Any, sqlx::any::AnyArguments<'_>> {
+    sqlx::query(
+        "INSERT INTO api_keys \
+         (id, mail, org_id, name, scope, hashed_key, created_at, expires_at, last_used_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(&key.id)
+    .bind(&key.mail)
+    .bind(&key.org)
+    .bind(&key.name)
+    .bind(key.scope.as_str())
+    .bind(&key.hashed_key)
+    .bind(key.created_at)
+    .bind(key.expires_at)
+    .bind(key.last_used_at)
+}
+
+fn api_key_from_row(row: &AnyRow) -> anyhow::Result<ApiKey> {
+    let scope: String = row.try_get("scope").context(ApiError::Unknown)?;
+    Ok(ApiKey {
+        id: row.try_get("id").context(ApiError::Unknown)?,
+        mail: row.try_get("mail").context(ApiError::Unknown)?,
+        org: row.try_get("org_id").context(ApiError::Unknown)?,
+        name: row.try_get("name").context(ApiError::Unknown)?,
+        scope: scope.parse().context(ApiError::Unknown)?,
+        hashed_key: row.try_get("hashed_key").context(ApiError::Unknown)?,
+        created_at: row.try_get("created_at").context(ApiError::Unknown)?,
+        expires_at: row.try_get("expires_at").context(ApiError::Unknown)?,
+        last_used_at: row.try_get("last_used_at").context(ApiError::Unknown)?,
+    })
+}
+
+const MAIL_VERIFICATION_COLUMNS: &str = "hashed_token, mail, created_at, expires_at, new_mail";
+
+fn mail_verification_from_row(row: &AnyRow) -> anyhow::Result<MailVerification> {
+    Ok(MailVerification {
+        hashed_token: row.try_get("hashed_token").context(ApiError::Unknown)?,
+        mail: row.try_get("mail").context(ApiError::Unknown)?,
+        created_at: row.try_get("created_at").context(ApiError::Unknown)?,
+        expires_at: row.try_get("expires_at").context(ApiError::Unknown)?,
+        new_mail: row.try_get("new_mail").context(ApiError::Unknown)?,
+    })
+}
+
+const AUDIT_ENTRY_COLUMNS: &str = "actor, action, target, changes, ip, time";
+
+/// Empty strings of the filter match everything, see `bind_audit_filter`
+const AUDIT_FILTER_CONDITION: &str = "($1 = '' OR actor = $1) AND ($2 = '' OR action = $2) \
+     AND ($3 = '' OR target = $3) AND time >= $4 AND time <= $5";
+
+fn bind_audit_filter<'q>(
+    query: sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>>,
+    filter: &'q AuditFilter,
+) -> sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>> {
+    query
+        .bind(filter.actor.as_deref().unwrap_or(""))
+        .bind(filter.action.map_or("", |action| action.as_str()))
+        .bind(filter.target.as_deref().unwrap_or(""))
+        .bind(filter.from)
+        .bind(filter.to)
+}
+
+fn audit_entry_from_row(row: &AnyRow) -> anyhow::Result<AuditEntry> {
+    let changes: String = row.try_get("changes").context(ApiError::Unknown)?;
+    Ok(AuditEntry {
+        actor: row.try_get("actor").context(ApiError::Unknown)?,
+        action: row
+            .try_get::<String, _>("action")
+            .context(ApiError::Unknown)?
+            .parse()
+            .context(ApiError::Unknown)?,
+        target: row.try_get("target").context(ApiError::Unknown)?,
+        changes: serde_json::from_str(&changes).context(ApiError::Unknown)?,
+        ip: row.try_get("ip").context(ApiError::Unknown)?,
+        time: row.try_get("time").context(ApiError::Unknown)?,
+    })
+}
+
+const COMMAND_COLUMNS: &str =
+    "id, org_id, device_id, name, params, status, issued_by, created_at, updated_at, result";
+
+fn command_from_row(row: &AnyRow) -> anyhow::Result<Command> {
+    Ok(Command {
+        id: row.try_get("id").context(ApiError::Unknown)?,
+        org: row.try_get("org_id").context(ApiError::Unknown)?,
+        device_id: row.try_get("device_id").context(ApiError::Unknown)?,
+        name: row.try_get("name").context(ApiError::Unknown)?,
+        params: row.try_get("params").context(ApiError::Unknown)?,
+        status: row
+            .try_get::<String, _>("status")
+            .context(ApiError::Unknown)?
+            .parse()
+            .context(ApiError::Unknown)?,
+        issued_by: row.try_get("issued_by").context(ApiError::Unknown)?,
+        created_at: row.try_get("created_at").context(ApiError::Unknown)?,
+        updated_at: row.try_get("updated_at").context(ApiError::Unknown)?,
+        result: row.try_get("result").context(ApiError::Unknown)?,
+    })
+}
+
+fn org_from_row(row: &AnyRow) -> anyhow::Result<Organization> {
+    Ok(Organization {
+        id: row.try_get("id").context(ApiError::Unknown)?,
+        name: row.try_get("name").context(ApiError::Unknown)?,
+        created_at: row.try_get("created_at").context(ApiError::Unknown)?,
+    })
+}
+
+const DEVICE_COLUMNS: &str = "org_id, id, name, info, claim_code, owner, \
+     desired, desired_version, reported, reported_version, reported_at";
+
+fn device_from_row(row: &AnyRow) -> anyhow::Result<Device> {
+    Ok(Device {
+        org: row.try_get("org_id").context(ApiError::Unknown)?,
+        id: row.try_get("id").context(ApiError::Unknown)?,
+        name: row.try_get("name").context(ApiError::Unknown)?,
+        info: row.try_get("info").context(ApiError::Unknown)?,
+        claim_code: row.try_get("claim_code").context(ApiError::Unknown)?,
+        owner: row.try_get("owner").context(ApiError::Unknown)?,
+        desired: row.try_get("desired").context(ApiError::Unknown)?,
+        desired_version: row.try_get("desired_version").context(ApiError::Unknown)?,
+        reported: row.try_get("reported").context(ApiError::Unknown)?,
+        reported_version: row.try_get("reported_version").context(ApiError::Unknown)?,
+        reported_at: row.try_get("reported_at").context(ApiError::Unknown)?,
+    })
+}
+
+fn insert_message_query(
+    msg: &Message,
+) -> sqlx::query::Query<'_, sqlx::Any, sqlx::any::AnyArguments<'_>> {
+    sqlx::query(
+        "INSERT INTO messages \
+         (org_id, device_id, info, value, alert, lng, lat, timestamp, msg_id) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT DO NOTHING",
+    )
+    .bind(&msg.org)
+    .bind(&msg.id)
+    .bind(&msg.info)
+    .bind(msg.value)
+    .bind(msg.alert)
+    .bind(msg.lng)
+    .bind(msg.lat)
+    .bind(msg.timestamp)
+    .bind(msg.msg_id.clone().unwrap_or_default())
+}
+
+fn message_from_row(row: &AnyRow) -> Result<Message, sqlx::Error> {
+    let msg_id: String = row.try_get("msg_id")?;
+    Ok(Message {
+        org: row.try_get("org_id")?,
+        id: row.try_get("device_id")?,
+        info: row.try_get("info")?,
+        value: row.try_get("value")?,
+        alert: row.try_get("alert")?,
+        lng: row.try_get("lng")?,
+        lat: row.try_get("lat")?,
+        timestamp: row.try_get("timestamp")?,
+        msg_id: from_msg_id(msg_id),
+    })
+}
+
+/// Message ids are stored as '' when not set, since NULLs are never equal in unique indices
+fn from_msg_id(msg_id: String) -> Option<String> {
+    if msg_id.is_empty() {
+        None
+    } else {
+        Some(msg_id)
+    }
+}
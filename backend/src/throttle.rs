@@ -0,0 +1,75 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Exponential backoff of failed attempts by key (e.g. an IP address). The first
+/// `free_attempts` failures are not throttled, after that every failure blocks the key for twice
+/// as long as the previous one, up to `max_delay`.
+pub struct Throttle {
+    entries: Mutex<HashMap<String, Entry>>,
+    free_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+struct Entry {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Option<Instant>,
+}
+
+/// Keys are forgotten after being quiet for this long
+const FORGET_AFTER: Duration = Duration::from_secs(3600);
+/// Forgotten keys are only pruned when there are more entries than this
+const PRUNE_THRESHOLD: usize = 10_000;
+
+impl Throttle {
+    pub fn new(free_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            free_attempts,
+            base_delay,
+            max_delay,
+        }
+    }
+
+    /// Seconds until the key may try again (rounded up), `None` if it is not blocked
+    pub fn blocked_for(&self, key: &str) -> Option<u64> {
+        let entries = self.entries.lock().unwrap();
+        let blocked_until = entries.get(key)?.blocked_until?;
+        let remaining = blocked_until.checked_duration_since(Instant::now())?;
+        Some(remaining.as_secs() + (remaining.subsec_nanos() > 0) as u64)
+    }
+
+    pub fn record_failure(&self, key: &str) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() > PRUNE_THRESHOLD {
+            entries.retain(|_, entry| now.duration_since(entry.last_failure) < FORGET_AFTER);
+        }
+        let entry = entries.entry(key.to_string()).or_insert(Entry {
+            failures: 0,
+            last_failure: now,
+            blocked_until: None,
+        });
+        if now.duration_since(entry.last_failure) >= FORGET_AFTER {
+            entry.failures = 0;
+        }
+        entry.failures += 1;
+        entry.last_failure = now;
+        if entry.failures > self.free_attempts {
+            let exponent = (entry.failures - self.free_attempts - 1).min(31);
+            let delay = self
+                .base_delay
+                .checked_mul(1 << exponent)
+                .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+            entry.blocked_until = Some(now + delay);
+        }
+    }
+
+    pub fn record_success(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
@@ -0,0 +1,121 @@
+//! Time-based one-time passwords (RFC 6238) as generated by authenticator apps: HMAC-SHA1, 6
+//! digits, 30 seconds per time step
+
+use anyhow::Context;
+use common::error::ApiError;
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use sha1::Sha1;
+
+pub const DIGITS: usize = 6;
+pub const STEP_SECS: i64 = 30;
+/// Codes of this many steps before or after the current one are accepted too, for clocks that
+/// are a bit off
+const SKEW_STEPS: i64 = 1;
+const SECRET_LEN: usize = 20;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A random secret, base32 encoded
+pub fn generate_secret() -> String {
+    let secret: [u8; SECRET_LEN] = rand::thread_rng().gen();
+    base32_encode(&secret)
+}
+
+/// `otpauth://` URI of a secret, which authenticator apps read from a QR code
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        DIGITS,
+        STEP_SECS,
+    )
+}
+
+/// The code of a base32 secret at some time (seconds since epoch)
+pub fn code(secret: &str, time_secs: i64) -> anyhow::Result<String> {
+    let key = base32_decode(secret).context(ApiError::Unknown)?;
+    Ok(code_at_step(&key, time_secs.div_euclid(STEP_SECS)))
+}
+
+/// The time step a code is valid in, `None` if it doesn't match any step close to the time
+pub fn verify(secret: &str, code: &str, time_secs: i64) -> anyhow::Result<Option<i64>> {
+    let key = base32_decode(secret).context(ApiError::Unknown)?;
+    let code = code.trim();
+    if code.len() != DIGITS || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let step = time_secs.div_euclid(STEP_SECS);
+    Ok((step - SKEW_STEPS..=step + SKEW_STEPS).find(|step| code_at_step(&key, *step) == code))
+}
+
+fn code_at_step(key: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS as u32),
+        width = DIGITS
+    )
+}
+
+/// RFC 4648 base32 without padding
+fn base32_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits) as u8);
+        }
+    }
+    Some(data)
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
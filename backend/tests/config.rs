@@ -0,0 +1,235 @@
+use bs_backend::config::{AuthProviderKind, DbKind, MailSenderKind, ServerConfig};
+use serde_json::{json, Value};
+
+/// A valid config with `fields` set, or replaced if the base sets them too
+fn config(fields: Value) -> ServerConfig {
+    let mut value = json!({ "db_url": "localhost:27017" });
+    for (field, field_value) in fields.as_object().unwrap() {
+        value[field] = field_value.clone();
+    }
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn bind_addresses() {
+    let cases = [
+        ("[::1]:8080", "[::1]:8080"),
+        ("[::1]", "[::1]:9000"),
+        ("::1", "[::1]:9000"),
+        ("0.0.0.0", "0.0.0.0:9000"),
+        ("0.0.0.0:8080", "0.0.0.0:8080"),
+        ("localhost", "localhost:9000"),
+        ("bs.example.com:8080", "bs.example.com:8080"),
+    ];
+    for (bind, addr) in cases.iter() {
+        let mut config = config(json!({}));
+        config.set_bind(bind);
+        assert_eq!(config.addr(), *addr, "{}", bind);
+        assert!(config.validate().is_ok(), "{}", bind);
+    }
+}
+
+#[test]
+fn env_overrides() {
+    let mut config = config(json!({ "oidc_issuer": "https://login.example.com" }));
+    let vars = [
+        ("BS_ADDR_IP", "::1"),
+        ("BS_MQTT_PORT", "1884"),
+        ("BS_DB_TIME_SERIES", "yes"),
+        ("BS_PUBLIC_URL", "https://bs.example.com/"),
+        // empty unsets optional fields
+        ("BS_OIDC_ISSUER", ""),
+        ("BS_DB_KIND", "postgres"),
+        ("BS_MAIL_SENDER", "smtp"),
+        ("BS_AUTH_PROVIDER", "LDAP"),
+    ];
+    for (key, value) in vars.iter() {
+        std::env::set_var(key, value);
+    }
+    let res = config.apply_env();
+    for (key, _) in vars.iter() {
+        std::env::remove_var(key);
+    }
+    res.unwrap();
+    assert_eq!(config.addr(), "[::1]:9000");
+    assert_eq!(config.mqtt_port(), 1884);
+    assert!(config.db_time_series());
+    assert_eq!(config.public_url(), "https://bs.example.com");
+    assert_eq!(config.oidc_issuer(), None);
+    assert_eq!(config.db_kind(), DbKind::Postgres);
+    assert_eq!(config.mail_sender(), MailSenderKind::Smtp);
+    assert_eq!(config.auth_provider(), AuthProviderKind::Ldap);
+
+    let cases = [
+        (
+            "BS_MQTT_PORT",
+            "high",
+            "Invalid environment variable BS_MQTT_PORT: 'high' is not an integer",
+        ),
+        (
+            "BS_LDAP_STARTTLS",
+            "maybe",
+            "Invalid environment variable BS_LDAP_STARTTLS: 'maybe' is not a boolean",
+        ),
+        (
+            "BS_DB_KIND",
+            "oracle",
+            "Invalid environment variable BS_DB_KIND: \
+             'oracle' is not one of mongodb, sqlite and postgres",
+        ),
+    ];
+    for (key, value, message) in cases.iter() {
+        std::env::set_var(key, value);
+        let res = config.apply_env();
+        std::env::remove_var(key);
+        let err = format!("{:#}", res.unwrap_err());
+        assert!(err.starts_with(message), "{}", err);
+    }
+}
+
+#[test]
+fn validation_messages() {
+    assert!(config(json!({})).validate().is_ok());
+    let cases = vec![
+        (
+            json!({ "addr_ip": "not a host" }),
+            "addr_ip 'not a host' is neither an IP address nor a host name",
+        ),
+        (
+            json!({ "addr_port": "0" }),
+            "addr_port '0' is not a port number in 1-65535",
+        ),
+        (
+            json!({ "db_uri": "localhost:27017" }),
+            "db_uri must start with 'mongodb://' or 'mongodb+srv://'",
+        ),
+        (
+            json!({ "db_url": "" }),
+            "Either db_uri or db_url must be set",
+        ),
+        (
+            json!({ "db_password": "secret" }),
+            "db_password is set but db_username is empty",
+        ),
+        (
+            json!({ "db_kind": "sqlite", "db_uri": "postgres://localhost/bs" }),
+            "db_uri must start with 'sqlite:' when db_kind is sqlite",
+        ),
+        (
+            json!({ "db_kind": "postgres", "db_uri": "sqlite:bs.db" }),
+            "db_uri must start with 'postgres://' when db_kind is postgres",
+        ),
+        (
+            json!({ "db_kind": "sqlite" }),
+            "db_uri must be set when db_kind is sqlite or postgres",
+        ),
+        (
+            json!({ "db_name": "bs.proj" }),
+            "db_name 'bs.proj' is not a valid MongoDB database name",
+        ),
+        (
+            json!({ "db_kind": "sqlite", "db_uri": "sqlite:bs.db", "db_time_series": true }),
+            "db_time_series is only supported by MongoDB",
+        ),
+        (
+            json!({ "mqtt_host": "-broker" }),
+            "mqtt_host '-broker' is neither an IP address nor a host name",
+        ),
+        (
+            json!({ "mqtt_port": 70000 }),
+            "mqtt_port '70000' is not a port number in 1-65535",
+        ),
+        (
+            json!({ "session_idle_secs": 0 }),
+            "session_idle_secs must be positive",
+        ),
+        (
+            json!({ "remember_me_secs": 60 }),
+            "remember_me_secs must not be less than session_idle_secs",
+        ),
+        (
+            json!({ "account_deletion_grace_secs": -1 }),
+            "account_deletion_grace_secs must not be negative",
+        ),
+        (
+            json!({ "command_timeout_secs": 0 }),
+            "command_timeout_secs must be positive",
+        ),
+        (
+            json!({ "public_url": "bs.example.com" }),
+            "public_url must start with 'http://' or 'https://'",
+        ),
+        (
+            json!({ "mail_sender": "smtp" }),
+            "smtp_host must be set when mail_sender is smtp",
+        ),
+        (
+            json!({ "smtp_port": 0 }),
+            "smtp_port '0' is not a port number in 1-65535",
+        ),
+        (
+            json!({ "smtp_password": "secret" }),
+            "smtp_password is set but smtp_username is empty",
+        ),
+        (
+            json!({ "oidc_issuer": "login.example.com", "oidc_client_id": "bs" }),
+            "oidc_issuer must start with 'http://' or 'https://'",
+        ),
+        (
+            json!({ "oidc_issuer": "https://login.example.com" }),
+            "oidc_client_id must be set when oidc_issuer is set",
+        ),
+        (
+            json!({
+                "oidc_issuer": "https://login.example.com",
+                "oidc_client_id": "bs",
+                "oidc_scopes": "email profile"
+            }),
+            "oidc_scopes must include 'openid'",
+        ),
+        (
+            json!({
+                "auth_provider": "ldap",
+                "ldap_url": "ldap.example.com",
+                "ldap_base_dn": "dc=example"
+            }),
+            "ldap_url must start with 'ldap://' or 'ldaps://'",
+        ),
+        (
+            json!({
+                "auth_provider": "ldap",
+                "ldap_url": "ldaps://ldap.example.com",
+                "ldap_starttls": true,
+                "ldap_base_dn": "dc=example"
+            }),
+            "ldap_starttls can't be used with 'ldaps://'",
+        ),
+        (
+            json!({ "auth_provider": "ldap", "ldap_url": "ldap://ldap.example.com" }),
+            "ldap_base_dn must be set when auth_provider is ldap",
+        ),
+        (
+            json!({
+                "auth_provider": "ldap",
+                "ldap_url": "ldap://ldap.example.com",
+                "ldap_base_dn": "dc=example",
+                "ldap_user_filter": "(uid=admin)"
+            }),
+            "ldap_user_filter must contain '{mail}'",
+        ),
+        (
+            json!({
+                "auth_provider": "ldap",
+                "ldap_url": "ldap://ldap.example.com",
+                "ldap_base_dn": "dc=example",
+                "ldap_bind_password": "secret"
+            }),
+            "ldap_bind_password is set but ldap_bind_dn is empty",
+        ),
+    ];
+    for (fields, message) in cases {
+        let err = config(fields).validate().unwrap_err().to_string();
+        // only the error of the field
+        assert_eq!(err, format!("Invalid server config:\n  {}", message));
+    }
+}
@@ -0,0 +1,98 @@
+use actix_web::{http::Method, test, web, App};
+use bs_backend::{
+    database::Database,
+    server::{self, routes},
+    store::MemoryStore,
+};
+use common::{
+    request::{LoginRequest, RegisterRequest},
+    response::{LoginResponse, SimpleResponse},
+};
+use std::sync::Arc;
+
+const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+fn spec_string() -> String {
+    serde_json::to_string_pretty(&server::openapi_spec()).unwrap() + "\n"
+}
+
+/// Run with `UPDATE_OPENAPI=1` to rewrite `openapi.json` after changing the API
+#[test]
+fn spec_is_up_to_date() {
+    let spec = spec_string();
+    if std::env::var_os("UPDATE_OPENAPI").is_some() {
+        std::fs::write(SPEC_PATH, spec).unwrap();
+        return;
+    }
+    let saved = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+    assert!(
+        saved == spec,
+        "openapi.json is out of date, run `UPDATE_OPENAPI=1 cargo test --test openapi`"
+    );
+}
+
+#[actix_rt::test]
+async fn every_route_has_a_handler() {
+    let db = web::Data::new(Database::new(Arc::new(MemoryStore::default())));
+    let mut app =
+        test::init_service(App::new().app_data(db.clone()).configure(server::config)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .set_json(&RegisterRequest {
+            mail: "test@example.com".to_string(),
+            name: "tester".to_string(),
+            password: "hashed_password".to_string(),
+        })
+        .to_request();
+    test::call_service(&mut app, req).await;
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(&LoginRequest {
+            mail: "test@example.com".to_string(),
+            password: "hashed_password".to_string(),
+            ..Default::default()
+        })
+        .to_request();
+    let res: LoginResponse = test::read_response_json(&mut app, req).await;
+    let bearer = format!("Bearer {}", res.login_token);
+
+    // logging out or refreshing the session would turn missing routes into 401
+    let mut routes = routes::routes();
+    routes.sort_by_key(|route| route.method != "get" && route.path.starts_with("/api/v2/session"));
+    for route in routes {
+        let mut path = route.path.to_string();
+        for param in route.path_params() {
+            path = path.replace(&format!("{{{}}}", param), "nothing");
+        }
+        let method = Method::from_bytes(route.method.to_uppercase().as_bytes()).unwrap();
+        let req = test::TestRequest::with_uri(&path)
+            .method(method)
+            .header("Authorization", bearer.as_str())
+            .set_json(&serde_json::json!({}))
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        let is_file = route.file.map_or(false, |content_type| {
+            res.headers()
+                .get("Content-Type")
+                .map_or(false, |value| value == content_type)
+        });
+        // errors of handlers always have a code, a bare 404 means no route matched
+        let body = test::read_body(res).await;
+        let res: Option<SimpleResponse> = serde_json::from_slice(&body).ok();
+        assert!(
+            is_file || res.map_or(false, |res| res.success || res.code.is_some()),
+            "{} {} has no handler",
+            route.method,
+            route.path
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn spec_is_served() {
+    let mut app = test::init_service(App::new().configure(server::config)).await;
+    let req = test::TestRequest::get().uri("/openapi.json").to_request();
+    let spec: serde_json::Value = test::read_response_json(&mut app, req).await;
+    assert_eq!(spec, server::openapi_spec());
+}
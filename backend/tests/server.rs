@@ -0,0 +1,3138 @@
+use actix_web::{http::StatusCode, test, web, App, HttpRequest, HttpResponse};
+use async_trait::async_trait;
+use bs_backend::{
+    auth::{AuthProvider, Authentication, Credentials, LocalAuth},
+    database::{Database, Message},
+    mail::{Mail, MailSender},
+    mqtt::{CommandMqtt, CommandPublisher, DeltaMqtt},
+    oidc::OidcClient,
+    server,
+    store::{AuditFilter, MemoryStore, Store, DEFAULT_ORG},
+    totp,
+};
+use common::{
+    error::ApiError,
+    request::{
+        AdminUserRequest, ApiKeyScope, AuditAction, CancelAccountDeletionRequest,
+        ChangeMailRequest, CommandStatus, ConfirmTotpRequest, CreateApiKeyRequest,
+        CreateDeviceRequest, CreateOrgRequest, DeleteAccountRequest, DesiredRequest,
+        DisableTotpRequest, EnrollTotpRequest, ExportAccountRequest, FetchAllDevicesRequest,
+        FetchApiKeyListRequest, FetchAuditLogRequest, FetchCommandListRequest,
+        FetchDeviceListRequest, FetchDeviceProfileRequest, FetchDeviceRequest,
+        FetchDeviceTwinRequest, FetchMessageListRequest, FetchOrgListRequest, FetchProfileRequest,
+        FetchTotpStatusRequest, FetchUserListRequest, ImportMessagesRequest, LoginRequest,
+        ModifyDeviceRequest, NewApiKeyRequest, NewCommandRequest, NewDeviceRequest, NewMailRequest,
+        OrgMemberRequest, OrgSwitch, PasswordRequest, ProfilePatch, ProvisionDeviceRequest,
+        ProvisionRequest, RegisterRequest, RemoveDeviceRequest, ResendVerificationRequest,
+        RevokeApiKeyRequest, Role, SendCommandRequest, SsoLoginRequest, SwitchOrgRequest,
+        UpdateDeviceRequest, UpdateDeviceTwinRequest, UpdateProfileRequest, UpdateUserRequest,
+        UserPatch, VerifyMailRequest,
+    },
+    response::{
+        AuditEntryInfo, ConfirmTotpResponse, CreateApiKeyResponse, DeviceInfo, EnrollTotpResponse,
+        FetchAllDevicesResponse, FetchApiKeyListResponse, FetchAuditLogResponse,
+        FetchCommandListResponse, FetchDeviceListResponse, FetchDeviceProfileResponse,
+        FetchDeviceResponse, FetchDeviceTwinResponse, FetchLoginOptionsResponse,
+        FetchMessageListResponse, FetchOrgListResponse, FetchProfileResponse,
+        FetchSsoStatusResponse, FetchTotpStatusResponse, FetchUserListResponse,
+        ImportMessagesResponse, LoginResponse, ProfileInfo, ProvisionDeviceResponse,
+        SendCommandResponse, SimpleResponse, SsoAuthorizeResponse,
+    },
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, Mutex},
+};
+use zip::ZipArchive;
+
+const MAIL: &str = "test@example.com";
+const NAME: &str = "tester";
+const PASSWORD: &str = "hashed_password";
+
+fn database() -> web::Data<Database> {
+    web::Data::new(Database::new(Arc::new(MemoryStore::default())))
+}
+
+macro_rules! post {
+    ( $app:ident, $uri:expr, $req:expr, $res_ty:ty $(,)? ) => {{
+        let req = test::TestRequest::post()
+            .uri($uri)
+            .set_json(&$req)
+            .to_request();
+        let res: $res_ty = test::read_response_json(&mut $app, req).await;
+        res
+    }};
+}
+
+macro_rules! init_app {
+    ( $db:expr ) => {
+        test::init_service(App::new().app_data($db.clone()).configure(server::config)).await
+    };
+}
+
+macro_rules! register_and_login {
+    ( $app:ident ) => {{
+        let res = post!(
+            $app,
+            "/register",
+            RegisterRequest {
+                mail: MAIL.to_string(),
+                name: NAME.to_string(),
+                password: PASSWORD.to_string(),
+            },
+            SimpleResponse,
+        );
+        assert!(res.success, "{}", res.err);
+
+        let res = post!(
+            $app,
+            "/login",
+            LoginRequest {
+                mail: MAIL.to_string(),
+                password: PASSWORD.to_string(),
+                ..Default::default()
+            },
+            LoginResponse,
+        );
+        assert!(res.success, "{}", res.err);
+        res.login_token
+    }};
+}
+
+/// Provision a device and return its claim code
+async fn provision(db: &Database, org: &str, id: &str) -> String {
+    db.register_device(MAIL, org, id, "", "127.0.0.1")
+        .await
+        .unwrap()
+}
+
+/// Register, log in and follow a newly provisioned device "dev", returns the login token
+macro_rules! login_and_follow {
+    ( $app:ident, $db:expr ) => {{
+        let login_token = register_and_login!($app);
+        let claim_code = provision(&$db, DEFAULT_ORG, "dev").await;
+        let res = post!(
+            $app,
+            "/create_device",
+            CreateDeviceRequest {
+                login_token: login_token.clone(),
+                id: "dev".to_string(),
+                claim_code,
+            },
+            SimpleResponse,
+        );
+        assert!(res.success, "{}", res.err);
+        login_token
+    }};
+}
+
+fn message(id: &str, value: i32, alert: bool, timestamp: i64) -> Message {
+    Message::new(
+        DEFAULT_ORG.to_string(),
+        id.to_string(),
+        "info".to_string(),
+        value,
+        alert,
+        120.0,
+        30.0,
+        timestamp,
+        None,
+    )
+}
+
+#[actix_rt::test]
+async fn register_and_login_flow() {
+    let db = database();
+    let mut app = init_app!(db);
+
+    let login_token = register_and_login!(app);
+    assert!(!login_token.is_empty());
+
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: MAIL.to_string(),
+            name: "another".to_string(),
+            password: PASSWORD.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(!res.success);
+    assert_eq!(res.err, "error-dup-email");
+
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: "another@example.com".to_string(),
+            name: NAME.to_string(),
+            password: PASSWORD.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.err, "error-dup-username");
+
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: MAIL.to_string(),
+            password: "wrong".to_string(),
+            ..Default::default()
+        },
+        LoginResponse,
+    );
+    assert_eq!(res.err, "error-wrong-password");
+
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: "nobody@example.com".to_string(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        },
+        LoginResponse,
+    );
+    assert_eq!(res.err, "error-no-user");
+}
+
+#[actix_rt::test]
+async fn check_login_and_logout() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+
+    let res = post!(app, "/check_login", login_token, SimpleResponse);
+    assert!(res.success);
+
+    let res = post!(app, "/logout", login_token, SimpleResponse);
+    assert!(res.success);
+
+    let res = post!(app, "/check_login", login_token, SimpleResponse);
+    assert!(!res.success);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+    assert_eq!(res.err, "error-login-expired");
+}
+
+#[actix_rt::test]
+async fn device_routes_require_login() {
+    let db = database();
+    let mut app = init_app!(db);
+
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        FetchDeviceListRequest {
+            login_token: "invalid".to_string(),
+        },
+        FetchDeviceListResponse,
+    );
+    assert!(!res.success);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+}
+
+#[actix_rt::test]
+async fn errors_have_http_status_codes() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+
+    let cases = vec![
+        (
+            "/check_login",
+            serde_json::json!("invalid"),
+            StatusCode::UNAUTHORIZED,
+            ApiError::LoginExpired,
+        ),
+        (
+            "/login",
+            serde_json::json!(LoginRequest {
+                mail: MAIL.to_string(),
+                password: "wrong".to_string(),
+                ..Default::default()
+            }),
+            StatusCode::UNAUTHORIZED,
+            ApiError::WrongPassword,
+        ),
+        (
+            "/fetch_device",
+            serde_json::json!(FetchDeviceRequest {
+                login_token,
+                id: "nothing".to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+            ApiError::NoDevice,
+        ),
+        (
+            "/register",
+            serde_json::json!(RegisterRequest {
+                mail: MAIL.to_string(),
+                name: NAME.to_string(),
+                password: PASSWORD.to_string(),
+            }),
+            StatusCode::CONFLICT,
+            ApiError::DupEmail,
+        ),
+        (
+            "/login",
+            serde_json::json!({ "mail": MAIL }),
+            StatusCode::BAD_REQUEST,
+            ApiError::InvalidRequest,
+        ),
+    ];
+    for (uri, body, status, code) in cases {
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(&body)
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), status, "{}", uri);
+        let res: SimpleResponse = test::read_body_json(res).await;
+        assert!(!res.success);
+        assert_eq!(res.code, Some(code), "{}", uri);
+        assert_eq!(res.err, code.message_id());
+    }
+}
+
+#[actix_rt::test]
+async fn create_modify_and_remove_device() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let claim_code = provision(&db, DEFAULT_ORG, "device0").await;
+
+    let res = post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+            claim_code,
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    let res = post!(
+        app,
+        "/modify_device",
+        ModifyDeviceRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+            name: "Device Zero".to_string(),
+            info: "on the roof".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    let res = post!(
+        app,
+        "/fetch_device",
+        FetchDeviceRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+        },
+        FetchDeviceResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.name, "Device Zero");
+    assert_eq!(res.info, "on the roof");
+
+    let res = post!(
+        app,
+        "/modify_device",
+        ModifyDeviceRequest {
+            login_token: login_token.clone(),
+            id: "nothing".to_string(),
+            name: "Nothing".to_string(),
+            info: "".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.err, "error-no-device");
+
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        FetchDeviceListRequest {
+            login_token: login_token.clone(),
+        },
+        FetchDeviceListResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.devices.len(), 1);
+    assert_eq!(res.devices[0].id, "device0");
+
+    let res = post!(
+        app,
+        "/remove_device",
+        RemoveDeviceRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    let res = post!(
+        app,
+        "/remove_device",
+        RemoveDeviceRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.err, "error-no-device");
+
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        FetchDeviceListRequest { login_token },
+        FetchDeviceListResponse,
+    );
+    assert!(res.devices.is_empty());
+}
+
+#[actix_rt::test]
+async fn fetch_profile_and_messages() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let claim_code = provision(&db, DEFAULT_ORG, "device0").await;
+
+    post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+            claim_code,
+        },
+        SimpleResponse,
+    );
+    for i in 0..10 {
+        let inserted = db
+            .insert_message(message("device0", i, i % 3 == 0, 1000 * i as i64))
+            .await
+            .unwrap();
+        assert!(inserted);
+    }
+    let inserted = db
+        .insert_message(message("device0", 0, true, 0))
+        .await
+        .unwrap();
+    assert!(!inserted);
+    assert_eq!(db.duplicated_message_count(), 1);
+
+    let res = post!(
+        app,
+        "/fetch_device_profile",
+        FetchDeviceProfileRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+        },
+        FetchDeviceProfileResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.message_count, 10);
+    assert_eq!(res.alert_message_count, 4);
+
+    let res = post!(
+        app,
+        "/fetch_message_list",
+        FetchMessageListRequest {
+            login_token,
+            id: "device0".to_string(),
+            start_timestamp: 2000,
+            end_timestamp: 8000,
+            first_index: 2,
+            limit: 3,
+        },
+        FetchMessageListResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.count, 7);
+    let timestamps: Vec<_> = res.messages.iter().map(|msg| msg.timestamp).collect();
+    assert_eq!(timestamps, vec![6000, 5000, 4000]);
+}
+
+#[actix_rt::test]
+async fn import_messages() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+
+    db.insert_message(message("device0", 1, false, 1000))
+        .await
+        .unwrap();
+
+    let row = "timestamp,value,alert,lng,lat\n2000,2,0,120,30\n";
+    let import = |id: &str| ImportMessagesRequest {
+        login_token: login_token.clone(),
+        id: id.to_string(),
+        format: "csv".to_string(),
+        content: row.to_string(),
+        dry_run: false,
+    };
+    let res = post!(
+        app,
+        "/import_messages",
+        import("device0"),
+        ImportMessagesResponse
+    );
+    assert_eq!(res.code, Some(ApiError::NoDevice));
+    let claim_code = provision(&db, DEFAULT_ORG, "device0").await;
+    let res = post!(
+        app,
+        "/import_messages",
+        import("device0"),
+        ImportMessagesResponse
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    db.follow_device(MAIL, MAIL, DEFAULT_ORG, "device0", &claim_code, "127.0.0.1")
+        .await
+        .unwrap();
+
+    let content = "timestamp,value,alert,lng,lat,info\n\
+                   1000,1,0,120,30,dup\n\
+                   2000,2,1,120,30,ok\n\
+                   3000,3,0,500,30,bad position\n\
+                   not a number,4,0,120,30,bad\n\
+                   2000,2,1,120,30,dup in file\n";
+    let req = ImportMessagesRequest {
+        login_token: login_token.clone(),
+        id: "device0".to_string(),
+        format: "csv".to_string(),
+        content: content.to_string(),
+        dry_run: true,
+    };
+    let res = post!(app, "/import_messages", req, ImportMessagesResponse);
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.imported, 1);
+    assert_eq!(res.duplicated, 2);
+    let rows: Vec<_> = res.errors.iter().map(|err| err.row).collect();
+    assert_eq!(rows, vec![2, 4, 5, 6]);
+    assert_eq!(res.errors[1].err, "error-invalid-position");
+
+    let content = "{\"timestamp\":2000,\"value\":2,\"alert\":1,\"lng\":120,\"lat\":30}\n\
+                   {\"timestamp\":3000,\"value\":3,\"alert\":0,\"lng\":120,\"lat\":30}\n";
+    let req = ImportMessagesRequest {
+        login_token,
+        id: "device0".to_string(),
+        format: "ndjson".to_string(),
+        content: content.to_string(),
+        dry_run: false,
+    };
+    let res = post!(app, "/import_messages", req, ImportMessagesResponse);
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.imported, 2);
+    assert!(res.errors.is_empty());
+
+    let keys = db
+        .fetch_message_keys(DEFAULT_ORG, "device0", 0, i64::MAX)
+        .await
+        .unwrap();
+    assert_eq!(keys.len(), 3);
+}
+
+#[actix_rt::test]
+async fn api_v2_devices_and_messages() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let bearer = format!("Bearer {}", login_token);
+
+    let req = test::TestRequest::get().uri("/api/v2/devices").to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let claim_code = provision(&db, DEFAULT_ORG, "device0").await;
+    let req = test::TestRequest::post()
+        .uri("/api/v2/devices")
+        .header("Authorization", bearer.as_str())
+        .set_json(&NewDeviceRequest {
+            id: "device0".to_string(),
+            claim_code,
+        })
+        .to_request();
+    let res: SimpleResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v2/devices/device0")
+        .header("Authorization", bearer.as_str())
+        .set_json(&UpdateDeviceRequest {
+            name: Some("Device Zero".to_string()),
+            info: None,
+        })
+        .to_request();
+    let res: SimpleResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res: FetchDeviceListResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.devices.len(), 1);
+    assert_eq!(res.devices[0].name, "Device Zero");
+
+    for i in 0..5 {
+        db.insert_message(message("device0", i, false, 1000 * i as i64))
+            .await
+            .unwrap();
+    }
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices/device0/messages?from=1000&to=3000")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res: FetchMessageListResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.count, 3);
+    let timestamps: Vec<_> = res.messages.iter().map(|msg| msg.timestamp).collect();
+    assert_eq!(timestamps, vec![3000, 2000, 1000]);
+
+    let claim_code = provision(&db, DEFAULT_ORG, "device1").await;
+    db.follow_device(MAIL, MAIL, DEFAULT_ORG, "device1", &claim_code, "127.0.0.1")
+        .await
+        .unwrap();
+    db.unfollow_device(MAIL, MAIL, DEFAULT_ORG, "device1", "127.0.0.1")
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices/device1")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v2/session")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // the old routes share the same sessions
+    let res = post!(app, "/check_login", login_token, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+}
+
+#[actix_rt::test]
+async fn api_keys_with_scopes() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+
+    let res = post!(
+        app,
+        "/create_api_key",
+        CreateApiKeyRequest {
+            login_token: login_token.clone(),
+            name: "reader".to_string(),
+            scope: ApiKeyScope::ReadOnly,
+            expires_at: None,
+        },
+        CreateApiKeyResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert!(res.key.starts_with("bsk_"));
+    let read_key = res.key;
+    let read_key_id = res.info.id;
+
+    let res = post!(
+        app,
+        "/create_api_key",
+        CreateApiKeyRequest {
+            login_token: login_token.clone(),
+            name: "expired".to_string(),
+            scope: ApiKeyScope::Admin,
+            expires_at: Some(1),
+        },
+        CreateApiKeyResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+
+    // read-only keys can read devices, but not change them
+    let claim_code = provision(&db, DEFAULT_ORG, "device0").await;
+    db.follow_device(MAIL, MAIL, DEFAULT_ORG, "device0", &claim_code, "127.0.0.1")
+        .await
+        .unwrap();
+    let bearer = format!("Bearer {}", read_key);
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res: FetchDeviceListResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.devices.len(), 1);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v2/devices/device0")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = post!(
+        app,
+        "/remove_device",
+        RemoveDeviceRequest {
+            login_token: read_key.clone(),
+            id: "device0".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v2/api_keys")
+        .header("Authorization", bearer.as_str())
+        .set_json(&NewApiKeyRequest {
+            name: "escalated".to_string(),
+            scope: ApiKeyScope::Admin,
+            expires_at: None,
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = post!(
+        app,
+        "/fetch_api_key_list",
+        FetchApiKeyListRequest {
+            login_token: login_token.clone(),
+        },
+        FetchApiKeyListResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.keys.len(), 1);
+    assert_eq!(res.keys[0].name, "reader");
+    assert!(res.keys[0].last_used_at.is_some());
+
+    let res = post!(
+        app,
+        "/revoke_api_key",
+        RevokeApiKeyRequest {
+            login_token: login_token.clone(),
+            id: read_key_id.clone(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/check_login", read_key, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+
+    let res = post!(
+        app,
+        "/revoke_api_key",
+        RevokeApiKeyRequest {
+            login_token,
+            id: read_key_id,
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::NoApiKey));
+}
+
+#[actix_rt::test]
+async fn sessions_slide_and_refresh() {
+    let db = web::Data::new(
+        Database::new(Arc::new(MemoryStore::default())).with_session_timeouts(1, 60),
+    );
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+
+    // each request renews the session, so it outlives the idle timeout while being used
+    for _ in 0..3 {
+        std::thread::sleep(std::time::Duration::from_millis(600));
+        let res = post!(app, "/check_login", login_token, SimpleResponse);
+        assert!(res.success, "{}", res.err);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(1200));
+    let res = post!(app, "/check_login", login_token, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+
+    let before = chrono::Utc::now().timestamp_millis();
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: MAIL.to_string(),
+            password: PASSWORD.to_string(),
+            remember: true,
+            ..Default::default()
+        },
+        LoginResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert!(res.expires_at >= before + 60 * 1000);
+    let old_token = res.login_token;
+
+    let res = post!(app, "/refresh_login", old_token, LoginResponse);
+    assert!(res.success, "{}", res.err);
+    assert_ne!(res.login_token, old_token);
+    assert!(res.expires_at >= before + 60 * 1000);
+    let new_token = res.login_token;
+    let res = post!(app, "/check_login", old_token, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+
+    // remembered sessions survive the short idle timeout
+    std::thread::sleep(std::time::Duration::from_millis(1200));
+    let bearer = format!("Bearer {}", new_token);
+    let req = test::TestRequest::get()
+        .uri("/api/v2/session")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn failed_logins_lock_account_and_throttle_ip() {
+    let db = database();
+    let mut app = init_app!(db);
+    register_and_login!(app);
+
+    let login = |password: &str, ip: &str| {
+        test::TestRequest::post()
+            .uri("/login")
+            .peer_addr(ip.parse().unwrap())
+            .set_json(&LoginRequest {
+                mail: MAIL.to_string(),
+                password: password.to_string(),
+                ..Default::default()
+            })
+            .to_request()
+    };
+
+    // a successful login ends the failures in a row, also after its session has ended
+    let ip = "10.0.3.1:1000";
+    for _ in 0..3 {
+        let res = test::call_service(&mut app, login("wrong", ip)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+    let res = test::call_service(&mut app, login(PASSWORD, ip)).await;
+    let res: LoginResponse = test::read_body_json(res).await;
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/logout", res.login_token, SimpleResponse);
+    assert!(res.success, "{}", res.err);
+    for _ in 0..4 {
+        let res = test::call_service(&mut app, login("wrong", ip)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+    let res = test::call_service(&mut app, login(PASSWORD, ip)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    for i in 0..5 {
+        let ip = format!("10.0.0.{}:1000", i);
+        let res = test::call_service(&mut app, login("wrong", &ip)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+    // the account is locked, even for the right password from another address
+    let res = test::call_service(&mut app, login(PASSWORD, "10.0.1.1:1000")).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = res.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 15 * 60);
+    let res: LoginResponse = test::read_body_json(res).await;
+    assert_eq!(res.code, Some(ApiError::TooManyAttempts));
+    assert_eq!(res.retry_after, Some(retry_after));
+
+    // an address failing over and over is blocked, whatever it tries
+    let ip = "10.0.2.1:1000";
+    for _ in 0..10 {
+        let req = test::TestRequest::post()
+            .uri("/register")
+            .peer_addr(ip.parse().unwrap())
+            .set_json(&RegisterRequest {
+                mail: MAIL.to_string(),
+                name: NAME.to_string(),
+                password: PASSWORD.to_string(),
+            })
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+    }
+    let req = test::TestRequest::post()
+        .uri("/register")
+        .peer_addr(ip.parse().unwrap())
+        .set_json(&RegisterRequest {
+            mail: "another@example.com".to_string(),
+            name: "another".to_string(),
+            password: PASSWORD.to_string(),
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let res: SimpleResponse = test::read_body_json(res).await;
+    assert_eq!(res.code, Some(ApiError::TooManyAttempts));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v2/sessions")
+        .peer_addr(ip.parse().unwrap())
+        .set_json(&LoginRequest {
+            mail: "another@example.com".to_string(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_rt::test]
+async fn two_factor_authentication() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let login = |totp_code: Option<String>| LoginRequest {
+        mail: MAIL.to_string(),
+        password: PASSWORD.to_string(),
+        totp_code,
+        ..Default::default()
+    };
+
+    let res = post!(
+        app,
+        "/enroll_totp",
+        EnrollTotpRequest {
+            login_token: login_token.clone(),
+        },
+        EnrollTotpResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert!(res.uri.starts_with("otpauth://totp/"));
+    let secret = res.secret;
+    // not enabled before being confirmed
+    let res = post!(app, "/login", login(None), LoginResponse);
+    assert!(res.success, "{}", res.err);
+
+    let now = chrono::Utc::now().timestamp();
+    let res = post!(
+        app,
+        "/confirm_totp",
+        ConfirmTotpRequest {
+            login_token: login_token.clone(),
+            code: "000000".to_string(),
+        },
+        ConfirmTotpResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::WrongTotpCode));
+    let res = post!(
+        app,
+        "/confirm_totp",
+        ConfirmTotpRequest {
+            login_token: login_token.clone(),
+            code: totp::code(&secret, now).unwrap(),
+        },
+        ConfirmTotpResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.recovery_codes.len(), 10);
+    let recovery_codes = res.recovery_codes;
+
+    let res = post!(
+        app,
+        "/fetch_totp_status",
+        FetchTotpStatusRequest {
+            login_token: login_token.clone(),
+        },
+        FetchTotpStatusResponse,
+    );
+    assert!(res.enabled);
+    assert_eq!(res.recovery_codes_left, 10);
+
+    let res = post!(app, "/login", login(None), LoginResponse);
+    assert_eq!(res.code, Some(ApiError::TotpRequired));
+    // the code used to confirm can't be used again
+    let res = post!(
+        app,
+        "/login",
+        login(totp::code(&secret, now).ok()),
+        LoginResponse
+    );
+    assert_eq!(res.code, Some(ApiError::WrongTotpCode));
+    let res = post!(
+        app,
+        "/login",
+        login(totp::code(&secret, now + totp::STEP_SECS).ok()),
+        LoginResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    // recovery codes can be used once, ignoring case and dashes
+    let recovery_code = recovery_codes[0].to_uppercase().replace('-', "");
+    let res = post!(
+        app,
+        "/login",
+        login(Some(recovery_code.clone())),
+        LoginResponse
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/login", login(Some(recovery_code)), LoginResponse);
+    assert_eq!(res.code, Some(ApiError::WrongTotpCode));
+
+    let res = post!(
+        app,
+        "/disable_totp",
+        DisableTotpRequest {
+            login_token: login_token.clone(),
+            code: recovery_codes[1].clone(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/login", login(None), LoginResponse);
+    assert!(res.success, "{}", res.err);
+
+    // an administrator can reset it for users who have lost everything
+    let res = post!(
+        app,
+        "/enroll_totp",
+        EnrollTotpRequest {
+            login_token: login_token.clone(),
+        },
+        EnrollTotpResponse,
+    );
+    let res = post!(
+        app,
+        "/confirm_totp",
+        ConfirmTotpRequest {
+            login_token,
+            code: totp::code(&res.secret, now).unwrap(),
+        },
+        ConfirmTotpResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/login", login(None), LoginResponse);
+    assert_eq!(res.code, Some(ApiError::TotpRequired));
+    db.reset_totp(MAIL).await.unwrap();
+    let res = post!(app, "/login", login(None), LoginResponse);
+    assert!(res.success, "{}", res.err);
+}
+
+/// Keeps sent mails so that tests can follow the links in them
+#[derive(Default)]
+struct CapturedMails(Mutex<Vec<Mail>>);
+
+impl CapturedMails {
+    /// Token of the verification link in the latest mail
+    fn latest_token(&self) -> String {
+        let mails = self.0.lock().unwrap();
+        let body = &mails.last().expect("no mail is sent").body;
+        let start = body.find("#verify_mail/").unwrap() + "#verify_mail/".len();
+        body[start..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl MailSender for CapturedMails {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        self.0.lock().unwrap().push(mail);
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn mail_verification() {
+    let mails = Arc::new(CapturedMails::default());
+    let db = web::Data::new(
+        Database::new(Arc::new(MemoryStore::default()))
+            .with_mail_sender(mails.clone(), "http://example.com".to_string()),
+    );
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    {
+        let sent = mails.0.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, MAIL);
+        assert!(sent[0].body.contains("http://example.com/#verify_mail/"));
+    }
+    let first_token = mails.latest_token();
+
+    // unverified users can log in but not follow devices
+    let claim_code = provision(&db, DEFAULT_ORG, "dev").await;
+    let res = post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+            claim_code: claim_code.clone(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::MailNotVerified));
+    let req = test::TestRequest::post()
+        .uri("/api/v2/devices")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .set_json(&NewDeviceRequest {
+            id: "dev".to_string(),
+            claim_code: claim_code.clone(),
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // resending is throttled
+    let res = post!(
+        app,
+        "/resend_verification",
+        ResendVerificationRequest {
+            login_token: login_token.clone(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::TooManyAttempts));
+    assert_eq!(mails.0.lock().unwrap().len(), 1);
+
+    let res = post!(
+        app,
+        "/verify_mail",
+        VerifyMailRequest {
+            token: "wrong".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidVerification));
+    let res = post!(
+        app,
+        "/verify_mail",
+        VerifyMailRequest {
+            token: first_token.clone(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    // links work only once
+    let req = test::TestRequest::post()
+        .uri("/api/v2/verify_mail")
+        .set_json(&VerifyMailRequest { token: first_token })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+            claim_code,
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/resend_verification",
+        ResendVerificationRequest { login_token },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+}
+
+#[actix_rt::test]
+async fn admin_console() {
+    const OTHER_MAIL: &str = "other@example.com";
+
+    let db = database();
+    let mut app = init_app!(db);
+    let admin_token = register_and_login!(app);
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: OTHER_MAIL.to_string(),
+            name: "other".to_string(),
+            password: PASSWORD.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let login = LoginRequest {
+        mail: OTHER_MAIL.to_string(),
+        password: PASSWORD.to_string(),
+        ..Default::default()
+    };
+    let res = post!(app, "/login", login, LoginResponse);
+    assert_eq!(res.role, Role::User);
+    let user_token = res.login_token;
+    let claim_code = provision(&db, DEFAULT_ORG, "dev").await;
+    let res = post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: user_token.clone(),
+            id: "dev".to_string(),
+            claim_code,
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    // nobody is an administrator until made one by the command line
+    let res = post!(
+        app,
+        "/admin/fetch_user_list",
+        FetchUserListRequest {
+            login_token: admin_token.clone(),
+            ..Default::default()
+        },
+        FetchUserListResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    db.set_role(MAIL, Role::Admin).await.unwrap();
+
+    let res = post!(
+        app,
+        "/admin/fetch_user_list",
+        FetchUserListRequest {
+            login_token: admin_token.clone(),
+            ..Default::default()
+        },
+        FetchUserListResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.count, 2);
+    // ordered by mail
+    assert_eq!(res.users[0].mail, OTHER_MAIL);
+    assert_eq!(res.users[0].device_count, 1);
+    assert_eq!(res.users[1].mail, MAIL);
+    assert_eq!(res.users[1].role, Role::Admin);
+    let res = post!(
+        app,
+        "/admin/fetch_user_list",
+        FetchUserListRequest {
+            login_token: admin_token.clone(),
+            query: "OTHER".to_string(),
+            first_index: 0,
+            limit: 10,
+        },
+        FetchUserListResponse,
+    );
+    assert_eq!(res.count, 1);
+    assert_eq!(res.users[0].mail, OTHER_MAIL);
+
+    let res = post!(
+        app,
+        "/admin/fetch_device_list",
+        FetchAllDevicesRequest {
+            login_token: admin_token.clone(),
+            first_index: 0,
+            limit: 10,
+        },
+        FetchAllDevicesResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.count, 1);
+    assert_eq!(res.devices[0].id, "dev");
+
+    let res = post!(
+        app,
+        "/admin/impersonate",
+        AdminUserRequest {
+            login_token: admin_token.clone(),
+            mail: OTHER_MAIL.to_string(),
+        },
+        LoginResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.mail, OTHER_MAIL);
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        FetchDeviceListRequest {
+            login_token: res.login_token,
+        },
+        FetchDeviceListResponse,
+    );
+    assert_eq!(res.devices.len(), 1);
+
+    // users can't use the admin routes, administrators can't change themselves
+    let res = post!(
+        app,
+        "/admin/modify_user",
+        UpdateUserRequest {
+            login_token: user_token.clone(),
+            mail: MAIL.to_string(),
+            disabled: Some(true),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/admin/remove_user",
+        AdminUserRequest {
+            login_token: admin_token.clone(),
+            mail: MAIL.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+    // nor other administrators
+    db.set_role(OTHER_MAIL, Role::Admin).await.unwrap();
+    let res = post!(
+        app,
+        "/admin/modify_user",
+        UpdateUserRequest {
+            login_token: admin_token.clone(),
+            mail: OTHER_MAIL.to_string(),
+            role: Some(Role::User),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/admin/remove_user",
+        AdminUserRequest {
+            login_token: admin_token.clone(),
+            mail: OTHER_MAIL.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/admin/reset_totp",
+        AdminUserRequest {
+            login_token: admin_token.clone(),
+            mail: OTHER_MAIL.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    db.set_role(OTHER_MAIL, Role::User).await.unwrap();
+
+    // two-factor authentication of users who have lost everything can be reset
+    let res = post!(
+        app,
+        "/enroll_totp",
+        EnrollTotpRequest {
+            login_token: user_token.clone(),
+        },
+        EnrollTotpResponse,
+    );
+    let res = post!(
+        app,
+        "/confirm_totp",
+        ConfirmTotpRequest {
+            login_token: user_token.clone(),
+            code: totp::code(&res.secret, chrono::Utc::now().timestamp()).unwrap(),
+        },
+        ConfirmTotpResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/login", login, LoginResponse);
+    assert_eq!(res.code, Some(ApiError::TotpRequired));
+    let res = post!(
+        app,
+        "/admin/reset_totp",
+        AdminUserRequest {
+            login_token: user_token.clone(),
+            mail: OTHER_MAIL.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v2/admin/users/{}/totp", OTHER_MAIL))
+        .header("Authorization", format!("Bearer {}", admin_token))
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = post!(app, "/login", login, LoginResponse);
+    assert!(res.success, "{}", res.err);
+    let filter = AuditFilter {
+        action: Some(AuditAction::ResetTotp),
+        ..AuditFilter::default()
+    };
+    let (count, entries) = db.audit_log(&filter, 0, 10).await.unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(entries[0].actor, MAIL);
+    assert_eq!(entries[0].target, OTHER_MAIL);
+
+    // disabling ends the sessions of the user
+    let req = test::TestRequest::patch()
+        .uri(&format!("/api/v2/admin/users/{}", OTHER_MAIL))
+        .header("Authorization", format!("Bearer {}", admin_token))
+        .set_json(&UserPatch {
+            disabled: Some(true),
+            ..Default::default()
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = post!(app, "/check_login", user_token, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+    let res = post!(app, "/login", login, LoginResponse);
+    assert_eq!(res.code, Some(ApiError::AccountDisabled));
+    let res = post!(
+        app,
+        "/admin/impersonate",
+        AdminUserRequest {
+            login_token: admin_token.clone(),
+            mail: OTHER_MAIL.to_string(),
+        },
+        LoginResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::AccountDisabled));
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v2/admin/users/{}", OTHER_MAIL))
+        .header("Authorization", format!("Bearer {}", admin_token))
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let req = test::TestRequest::get()
+        .uri("/api/v2/admin/users")
+        .header("Authorization", format!("Bearer {}", admin_token))
+        .to_request();
+    let res: FetchUserListResponse = test::read_response_json(&mut app, req).await;
+    assert_eq!(res.count, 1);
+    // devices are kept
+    let req = test::TestRequest::get()
+        .uri("/api/v2/admin/devices")
+        .header("Authorization", format!("Bearer {}", admin_token))
+        .to_request();
+    let res: FetchAllDevicesResponse = test::read_response_json(&mut app, req).await;
+    assert_eq!(res.count, 1);
+}
+
+#[actix_rt::test]
+async fn organizations() {
+    const OTHER_MAIL: &str = "other@example.com";
+
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: OTHER_MAIL.to_string(),
+            name: "other".to_string(),
+            password: PASSWORD.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    let create_org = CreateOrgRequest {
+        login_token: login_token.clone(),
+        id: "acme".to_string(),
+        name: "Acme".to_string(),
+    };
+    let res = post!(app, "/admin/create_org", create_org, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    db.set_role(MAIL, Role::Admin).await.unwrap();
+    let res = post!(app, "/admin/create_org", create_org, SimpleResponse);
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/admin/create_org", create_org, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::DupOrg));
+    let res = post!(
+        app,
+        "/admin/create_org",
+        CreateOrgRequest {
+            login_token: login_token.clone(),
+            id: "Not An Id".to_string(),
+            name: "Invalid".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+    // levels of the MQTT topics of commands, replies and twins
+    let res = post!(
+        app,
+        "/admin/create_org",
+        CreateOrgRequest {
+            login_token: login_token.clone(),
+            id: "replies".to_string(),
+            name: "Replies".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+
+    // the creator is a member of the organization, but keeps working in the default one
+    let fetch_orgs = FetchOrgListRequest {
+        login_token: login_token.clone(),
+    };
+    let res = post!(app, "/fetch_org_list", fetch_orgs, FetchOrgListResponse);
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.org, DEFAULT_ORG);
+    let ids: Vec<_> = res.orgs.iter().map(|org| org.id.as_str()).collect();
+    assert_eq!(ids, vec!["acme", DEFAULT_ORG]);
+
+    // the same device id is a different device in each organization
+    let create_device = |claim_code: String| CreateDeviceRequest {
+        login_token: login_token.clone(),
+        id: "device0".to_string(),
+        claim_code,
+    };
+    let claim_code = provision(&db, DEFAULT_ORG, "device0").await;
+    let res = post!(
+        app,
+        "/create_device",
+        create_device(claim_code),
+        SimpleResponse
+    );
+    assert!(res.success, "{}", res.err);
+    db.insert_message(message("device0", 1, false, 1000))
+        .await
+        .unwrap();
+    for i in 0..2 {
+        let msg = Message::new(
+            "acme".to_string(),
+            "device0".to_string(),
+            "info".to_string(),
+            i,
+            true,
+            120.0,
+            30.0,
+            1000 * i as i64,
+            None,
+        );
+        db.insert_message(msg).await.unwrap();
+    }
+    let res = post!(
+        app,
+        "/create_api_key",
+        CreateApiKeyRequest {
+            login_token: login_token.clone(),
+            name: "default".to_string(),
+            scope: ApiKeyScope::ReadOnly,
+            expires_at: None,
+        },
+        CreateApiKeyResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let default_key = res.key;
+
+    let res = post!(
+        app,
+        "/switch_org",
+        SwitchOrgRequest {
+            login_token: login_token.clone(),
+            org: "nowhere".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/switch_org",
+        SwitchOrgRequest {
+            login_token: login_token.clone(),
+            org: "acme".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let fetch_devices = FetchDeviceListRequest {
+        login_token: login_token.clone(),
+    };
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        fetch_devices,
+        FetchDeviceListResponse
+    );
+    assert!(res.success, "{}", res.err);
+    assert!(res.devices.is_empty());
+    let res = post!(
+        app,
+        "/admin/provision_device",
+        ProvisionDeviceRequest {
+            login_token: login_token.clone(),
+            id: "device0".to_string(),
+            name: "".to_string(),
+        },
+        ProvisionDeviceResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/create_device",
+        create_device(res.claim_code),
+        SimpleResponse
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        fetch_devices,
+        FetchDeviceListResponse
+    );
+    assert_eq!(res.devices.len(), 1);
+    assert_eq!(res.devices[0].org, "acme");
+    assert_eq!(res.devices[0].message_count, 2);
+    assert_eq!(res.devices[0].alert_message_count, 2);
+
+    // API keys stay in the organization they were created in
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices")
+        .header("Authorization", format!("Bearer {}", default_key))
+        .to_request();
+    let res: FetchDeviceListResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.devices.len(), 1);
+    assert_eq!(res.devices[0].org, DEFAULT_ORG);
+    assert_eq!(res.devices[0].message_count, 1);
+    let res = post!(
+        app,
+        "/create_api_key",
+        CreateApiKeyRequest {
+            login_token: login_token.clone(),
+            name: "acme".to_string(),
+            scope: ApiKeyScope::ReadOnly,
+            expires_at: None,
+        },
+        CreateApiKeyResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let acme_bearer = format!("Bearer {}", res.key);
+
+    // only members can work in an organization, and only see the devices they follow
+    let other_token = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: OTHER_MAIL.to_string(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        },
+        LoginResponse,
+    )
+    .login_token;
+    let res = post!(
+        app,
+        "/switch_org",
+        SwitchOrgRequest {
+            login_token: other_token.clone(),
+            org: "acme".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let bearer = format!("Bearer {}", login_token);
+    let uri = format!("/api/v2/admin/orgs/acme/members/{}", OTHER_MAIL);
+    let req = test::TestRequest::put()
+        .uri(&uri)
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res: SimpleResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    let req = test::TestRequest::put()
+        .uri("/api/v2/org")
+        .header("Authorization", format!("Bearer {}", other_token))
+        .set_json(&OrgSwitch {
+            org: "acme".to_string(),
+        })
+        .to_request();
+    let res: SimpleResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices/device0/profile")
+        .header("Authorization", format!("Bearer {}", other_token))
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // removing a member unfollows the devices of the organization and moves the user back
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v2/admin/orgs/acme/members/{}", MAIL))
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res: SimpleResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        fetch_devices,
+        FetchDeviceListResponse
+    );
+    assert_eq!(res.devices.len(), 1);
+    assert_eq!(res.devices[0].org, DEFAULT_ORG);
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices")
+        .header("Authorization", acme_bearer.as_str())
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = post!(
+        app,
+        "/admin/remove_org_member",
+        OrgMemberRequest {
+            login_token: login_token.clone(),
+            org: DEFAULT_ORG.to_string(),
+            mail: MAIL.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+
+    let req = test::TestRequest::get()
+        .uri("/api/v2/admin/orgs")
+        .header("Authorization", bearer.as_str())
+        .to_request();
+    let res: FetchOrgListResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.orgs.len(), 2);
+    let filter = AuditFilter {
+        target: Some(MAIL.to_string()),
+        action: Some(AuditAction::RemoveOrgMember),
+        ..AuditFilter::default()
+    };
+    let (count, _) = db.audit_log(&filter, 0, 0).await.unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn device_claim_codes() {
+    const OTHER_MAIL: &str = "other@example.com";
+
+    let db = database();
+    let mut app = init_app!(db);
+    let admin_token = register_and_login!(app);
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: OTHER_MAIL.to_string(),
+            name: "other".to_string(),
+            password: PASSWORD.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let login = LoginRequest {
+        mail: OTHER_MAIL.to_string(),
+        password: PASSWORD.to_string(),
+        ..Default::default()
+    };
+    let user_token = post!(app, "/login", login, LoginResponse).login_token;
+
+    // only administrators provision devices
+    let provision_request = ProvisionDeviceRequest {
+        login_token: admin_token.clone(),
+        id: "dev".to_string(),
+        name: "Sensor".to_string(),
+    };
+    let res = post!(
+        app,
+        "/admin/provision_device",
+        provision_request,
+        ProvisionDeviceResponse
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    db.set_role(MAIL, Role::Admin).await.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/api/v2/admin/devices")
+        .header("Authorization", format!("Bearer {}", admin_token))
+        .set_json(&ProvisionRequest {
+            id: "dev".to_string(),
+            name: "Sensor".to_string(),
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let res: ProvisionDeviceResponse = test::read_body_json(res).await;
+    assert_eq!(res.id, "dev");
+    assert_eq!(res.claim_code.len(), 14);
+    assert_eq!(res.claim_code.matches('-').count(), 2);
+    let claim_code = res.claim_code;
+
+    // devices can't be followed without being provisioned or with a wrong code
+    let create_device = |login_token: &str, id: &str, claim_code: &str| CreateDeviceRequest {
+        login_token: login_token.to_string(),
+        id: id.to_string(),
+        claim_code: claim_code.to_string(),
+    };
+    let res = post!(
+        app,
+        "/create_device",
+        create_device(&user_token, "unknown", &claim_code),
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::NoDevice));
+    let res = post!(
+        app,
+        "/create_device",
+        create_device(&user_token, "dev", "WRONG-CODE"),
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidClaimCode));
+
+    // codes are compared ignoring case and dashes, and work only once
+    let typed_code = claim_code.replace('-', "").to_lowercase();
+    let res = post!(
+        app,
+        "/create_device",
+        create_device(&user_token, "dev", &typed_code),
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/create_device",
+        create_device(&admin_token, "dev", &claim_code),
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::DeviceClaimed));
+    let res = post!(
+        app,
+        "/admin/provision_device",
+        provision_request,
+        ProvisionDeviceResponse
+    );
+    assert_eq!(res.code, Some(ApiError::DeviceClaimed));
+
+    // the owner follows the device again without a code
+    let res = post!(
+        app,
+        "/remove_device",
+        RemoveDeviceRequest {
+            login_token: user_token.clone(),
+            id: "dev".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/create_device",
+        create_device(&user_token, "dev", ""),
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    // only followers read or rename the device
+    let res = post!(
+        app,
+        "/fetch_device",
+        FetchDeviceRequest {
+            login_token: admin_token.clone(),
+            id: "dev".to_string(),
+        },
+        FetchDeviceResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/fetch_device_profile",
+        FetchDeviceProfileRequest {
+            login_token: admin_token.clone(),
+            id: "dev".to_string(),
+        },
+        FetchDeviceProfileResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/fetch_message_list",
+        FetchMessageListRequest {
+            login_token: admin_token.clone(),
+            id: "dev".to_string(),
+            ..Default::default()
+        },
+        FetchMessageListResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/modify_device",
+        ModifyDeviceRequest {
+            login_token: admin_token.clone(),
+            id: "dev".to_string(),
+            name: "Renamed".to_string(),
+            info: "".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+
+    // a mail in the request is ignored, devices are always followed by the caller
+    let res = post!(
+        app,
+        "/create_device",
+        serde_json::json!({
+            "login_token": admin_token,
+            "mail": OTHER_MAIL,
+            "id": "dev",
+        }),
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::DeviceClaimed));
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        serde_json::json!({ "login_token": admin_token, "mail": OTHER_MAIL }),
+        FetchDeviceListResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert!(res.devices.is_empty());
+    let res = post!(
+        app,
+        "/fetch_device",
+        serde_json::json!({
+            "login_token": admin_token,
+            "mail": OTHER_MAIL,
+            "id": "dev",
+        }),
+        FetchDeviceResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+
+    let req = test::TestRequest::get()
+        .uri("/api/v2/admin/devices")
+        .header("Authorization", format!("Bearer {}", admin_token))
+        .to_request();
+    let res: FetchAllDevicesResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.devices.len(), 1);
+    assert_eq!(res.devices[0].name, "Sensor");
+    assert_eq!(res.devices[0].owner, OTHER_MAIL);
+}
+
+/// Keeps published commands and twin deltas instead of sending them to a broker
+#[derive(Default)]
+struct CapturedPublishes(Mutex<Vec<(String, Vec<u8>)>>);
+
+impl CapturedPublishes {
+    fn count(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    /// Topic and payload of the latest publish
+    fn latest<T: DeserializeOwned>(&self) -> (String, T) {
+        let published = self.0.lock().unwrap();
+        let (topic, payload) = published.last().expect("nothing is published");
+        (topic.clone(), serde_json::from_slice(payload).unwrap())
+    }
+}
+
+impl CommandPublisher for CapturedPublishes {
+    fn publish(&self, topic: &str, payload: Vec<u8>) -> anyhow::Result<()> {
+        self.0.lock().unwrap().push((topic.to_string(), payload));
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn device_commands() {
+    const OTHER_MAIL: &str = "other@example.com";
+
+    let published = Arc::new(CapturedPublishes::default());
+    let db = web::Data::new(
+        Database::new(Arc::new(MemoryStore::default())).with_command_publisher(published.clone()),
+    );
+    let mut app = init_app!(db);
+    let login_token = login_and_follow!(app, db);
+
+    let send_command = |name: &str| SendCommandRequest {
+        login_token: login_token.clone(),
+        id: "dev".to_string(),
+        name: name.to_string(),
+        params: r#"{"delay":5}"#.to_string(),
+    };
+    let res = post!(app, "/send_command", send_command(" "), SendCommandResponse);
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+    let res = post!(
+        app,
+        "/send_command",
+        send_command("reboot"),
+        SendCommandResponse
+    );
+    assert!(res.success, "{}", res.err);
+    let reboot = res.command;
+    assert_eq!(reboot.status, CommandStatus::Sent);
+    assert_eq!(reboot.issued_by, MAIL);
+    assert_eq!(published.count(), 1);
+    let (topic, command): (String, CommandMqtt) = published.latest();
+    assert_eq!(topic, "testapp/commands/default/dev");
+    assert_eq!(command.id, reboot.id);
+    assert_eq!(command.name, "reboot");
+    assert_eq!(command.params, r#"{"delay":5}"#);
+
+    // only the device the command is sent to can reply, and only once
+    assert!(!db
+        .acknowledge_command(DEFAULT_ORG, "other", &reboot.id, true, "")
+        .await
+        .unwrap());
+    assert!(db
+        .acknowledge_command(DEFAULT_ORG, "dev", &reboot.id, true, "rebooting")
+        .await
+        .unwrap());
+    assert!(!db
+        .acknowledge_command(DEFAULT_ORG, "dev", &reboot.id, false, "")
+        .await
+        .unwrap());
+
+    let req = test::TestRequest::post()
+        .uri("/api/v2/devices/dev/commands")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .set_json(&NewCommandRequest {
+            name: "set_interval".to_string(),
+            params: "".to_string(),
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let res: SendCommandResponse = test::read_body_json(res).await;
+    let set_interval = res.command;
+    assert!(db
+        .acknowledge_command(DEFAULT_ORG, "dev", &set_interval.id, false, "out of range")
+        .await
+        .unwrap());
+
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices/dev/commands?limit=10")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .to_request();
+    let res: FetchCommandListResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.count, 2);
+    assert_eq!(res.commands[0].id, set_interval.id);
+    assert_eq!(res.commands[0].status, CommandStatus::Failed);
+    assert_eq!(res.commands[0].result, "out of range");
+    assert_eq!(res.commands[1].status, CommandStatus::Acked);
+    assert_eq!(res.commands[1].result, "rebooting");
+    let filter = AuditFilter {
+        action: Some(AuditAction::SendCommand),
+        ..AuditFilter::default()
+    };
+    let (count, _) = db.audit_log(&filter, 0, 0).await.unwrap();
+    assert_eq!(count, 2);
+
+    // users who don't follow the device can't send commands or see them
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: OTHER_MAIL.to_string(),
+            name: "other".to_string(),
+            password: PASSWORD.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let login = LoginRequest {
+        mail: OTHER_MAIL.to_string(),
+        password: PASSWORD.to_string(),
+        ..Default::default()
+    };
+    let other_token = post!(app, "/login", login, LoginResponse).login_token;
+    let res = post!(
+        app,
+        "/send_command",
+        SendCommandRequest {
+            login_token: other_token.clone(),
+            ..send_command("reboot")
+        },
+        SendCommandResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    let res = post!(
+        app,
+        "/fetch_command_list",
+        FetchCommandListRequest {
+            login_token: other_token,
+            id: "dev".to_string(),
+            first_index: 0,
+            limit: 0,
+        },
+        FetchCommandListResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+
+    // commands not replied in time time out
+    let db = web::Data::new(
+        Database::new(Arc::new(MemoryStore::default()))
+            .with_command_publisher(Arc::new(CapturedPublishes::default()))
+            .with_command_timeout(0),
+    );
+    let mut app = init_app!(db);
+    let login_token = login_and_follow!(app, db);
+    let command = db
+        .issue_command(MAIL, DEFAULT_ORG, "dev", "reboot", "", "127.0.0.1")
+        .await
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert_eq!(db.expire_commands().await.unwrap(), 1);
+    assert!(!db
+        .acknowledge_command(DEFAULT_ORG, "dev", &command.id, true, "")
+        .await
+        .unwrap());
+    let res = post!(
+        app,
+        "/fetch_command_list",
+        FetchCommandListRequest {
+            login_token,
+            id: "dev".to_string(),
+            first_index: 0,
+            limit: 0,
+        },
+        FetchCommandListResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.commands[0].status, CommandStatus::Timeout);
+
+    // commands fail without a broker to publish them to
+    let db = database();
+    let mut app = init_app!(db);
+    login_and_follow!(app, db);
+    let command = db
+        .issue_command(MAIL, DEFAULT_ORG, "dev", "reboot", "", "127.0.0.1")
+        .await
+        .unwrap();
+    assert_eq!(command.status, CommandStatus::Failed);
+    assert!(!command.result.is_empty());
+}
+
+#[actix_rt::test]
+async fn device_twins() {
+    let published = Arc::new(CapturedPublishes::default());
+    let db = web::Data::new(
+        Database::new(Arc::new(MemoryStore::default())).with_command_publisher(published.clone()),
+    );
+    let mut app = init_app!(db);
+    let login_token = login_and_follow!(app, db);
+    let parse = |text: &str| serde_json::from_str::<Value>(text).unwrap();
+    let object = |value: Value| match value {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    };
+
+    let req = test::TestRequest::get()
+        .uri("/api/v2/devices/dev/twin")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .to_request();
+    let res: FetchDeviceTwinResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.twin.desired, "{}");
+    assert_eq!(res.twin.desired_version, 0);
+    assert_eq!(res.twin.reported_at, 0);
+
+    let put_desired = |desired: &str, version: i64| {
+        test::TestRequest::put()
+            .uri("/api/v2/devices/dev/twin/desired")
+            .header("Authorization", format!("Bearer {}", login_token))
+            .set_json(&DesiredRequest {
+                desired: desired.to_string(),
+                version,
+            })
+            .to_request()
+    };
+    let desired = r#"{"interval":5,"led":{"color":"red"}}"#;
+    let res: FetchDeviceTwinResponse =
+        test::read_response_json(&mut app, put_desired(desired, 0)).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.twin.desired_version, 1);
+    assert_eq!(parse(&res.twin.delta), parse(desired));
+    let (topic, delta): (String, DeltaMqtt) = published.latest();
+    assert_eq!(topic, "testapp/twin/default/dev");
+    assert_eq!(delta.version, 1);
+    assert_eq!(delta.state, parse(desired));
+
+    // a stale version must not overwrite the newer configuration
+    let res = test::call_service(&mut app, put_desired(r#"{"interval":1}"#, 0)).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let res: SimpleResponse = test::read_body_json(res).await;
+    assert_eq!(res.code, Some(ApiError::VersionConflict));
+    let update = |desired: &str| UpdateDeviceTwinRequest {
+        login_token: login_token.clone(),
+        id: "dev".to_string(),
+        desired: desired.to_string(),
+        version: 1,
+    };
+    let res = post!(
+        app,
+        "/update_device_twin",
+        update("[1]"),
+        FetchDeviceTwinResponse
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+
+    db.report_state(
+        DEFAULT_ORG,
+        "dev",
+        object(serde_json::json!({
+            "interval": 10,
+            "led": { "color": "red", "on": true },
+            "fw": "1.0",
+        })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(published.count(), 2);
+    let (_, delta): (String, DeltaMqtt) = published.latest();
+    assert_eq!(delta.state, serde_json::json!({ "interval": 5 }));
+    // a report without a remaining delta publishes nothing, null removes a key
+    db.report_state(
+        DEFAULT_ORG,
+        "dev",
+        object(serde_json::json!({ "interval": 5, "fw": null })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(published.count(), 2);
+
+    let res = post!(
+        app,
+        "/fetch_device_twin",
+        FetchDeviceTwinRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+        },
+        FetchDeviceTwinResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(
+        parse(&res.twin.reported),
+        serde_json::json!({ "interval": 5, "led": { "color": "red", "on": true } })
+    );
+    assert_eq!(res.twin.reported_version, 2);
+    assert!(res.twin.reported_at > 0);
+    assert_eq!(res.twin.delta, "{}");
+
+    let res = post!(
+        app,
+        "/update_device_twin",
+        update(r#"{"interval":5,"led":{"color":"blue"}}"#),
+        FetchDeviceTwinResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.twin.desired_version, 2);
+    let (_, delta): (String, DeltaMqtt) = published.latest();
+    assert_eq!(
+        delta.state,
+        serde_json::json!({ "led": { "color": "blue" } })
+    );
+    let filter = AuditFilter {
+        action: Some(AuditAction::UpdateDesired),
+        ..AuditFilter::default()
+    };
+    let (count, _) = db.audit_log(&filter, 0, 0).await.unwrap();
+    assert_eq!(count, 2);
+}
+
+#[actix_rt::test]
+async fn audit_log() {
+    let db = database();
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: MAIL.to_string(),
+            password: "wrong".to_string(),
+            ..Default::default()
+        },
+        LoginResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+    let claim_code = provision(&db, DEFAULT_ORG, "dev").await;
+    let res = post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+            claim_code,
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/modify_device",
+        ModifyDeviceRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+            name: "renamed".to_string(),
+            info: "".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/remove_device",
+        RemoveDeviceRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    let request = FetchAuditLogRequest {
+        login_token: login_token.clone(),
+        target: "dev".to_string(),
+        ..Default::default()
+    };
+    let res = post!(
+        app,
+        "/admin/fetch_audit_log",
+        request,
+        FetchAuditLogResponse
+    );
+    assert_eq!(res.code, Some(ApiError::Forbidden));
+    db.set_role(MAIL, Role::Admin).await.unwrap();
+
+    let res = post!(
+        app,
+        "/admin/fetch_audit_log",
+        request,
+        FetchAuditLogResponse
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.count, 4);
+    let actions: Vec<_> = res.entries.iter().map(|entry| entry.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            AuditAction::RemoveDevice,
+            AuditAction::ModifyDevice,
+            AuditAction::CreateDevice,
+            AuditAction::ProvisionDevice
+        ]
+    );
+    assert!(res.entries.iter().all(|entry| entry.actor == MAIL));
+    // only the changed fields are recorded
+    assert_eq!(res.entries[1].changes.len(), 1);
+    assert_eq!(res.entries[1].changes[0].field, "name");
+    assert_eq!(res.entries[1].changes[0].before, "dev");
+    assert_eq!(res.entries[1].changes[0].after, "renamed");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v2/admin/audit?action=login_failed")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .to_request();
+    let res: FetchAuditLogResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.count, 1);
+    assert_eq!(res.entries[0].target, MAIL);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v2/admin/audit?actor={}&limit=1", MAIL))
+        .header("Authorization", format!("Bearer {}", login_token))
+        .to_request();
+    let res: FetchAuditLogResponse = test::read_response_json(&mut app, req).await;
+    // registration, a login and a failed one, 4 device actions
+    assert_eq!(res.count, 7);
+    assert_eq!(res.entries.len(), 1);
+}
+
+#[actix_rt::test]
+async fn account_deletion_and_export() {
+    let db = web::Data::new(Database::new(Arc::new(MemoryStore::default())).with_deletion_grace(0));
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let claim_code = provision(&db, DEFAULT_ORG, "dev").await;
+    let res = post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+            claim_code,
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    let req = test::TestRequest::post()
+        .uri("/export_account")
+        .set_json(&ExportAccountRequest {
+            login_token: login_token.clone(),
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(
+        res.headers().get("Content-Type").unwrap(),
+        "application/zip"
+    );
+    let body = test::read_body(res).await;
+    let mut zip = ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+    let profile: ProfileInfo =
+        serde_json::from_reader(zip.by_name("profile.json").unwrap()).unwrap();
+    assert_eq!(profile.mail, MAIL);
+    let devices: Vec<DeviceInfo> =
+        serde_json::from_reader(zip.by_name("devices.json").unwrap()).unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].id, "dev");
+    let audit_log: Vec<AuditEntryInfo> =
+        serde_json::from_reader(zip.by_name("audit_log.json").unwrap()).unwrap();
+    assert_eq!(audit_log[0].action, AuditAction::CreateDevice);
+
+    let res = post!(
+        app,
+        "/delete_account",
+        DeleteAccountRequest {
+            login_token: login_token.clone(),
+            password: "wrong".to_string(),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+    let res = post!(
+        app,
+        "/delete_account",
+        DeleteAccountRequest {
+            login_token: login_token.clone(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/check_login", login_token, SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::LoginExpired));
+
+    // the user can still log in to cancel it during the grace period
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: MAIL.to_string(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        },
+        LoginResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let login_token = res.login_token;
+    let res = post!(
+        app,
+        "/fetch_profile",
+        FetchProfileRequest {
+            login_token: login_token.clone(),
+        },
+        FetchProfileResponse,
+    );
+    assert!(res.profile.deletion_due_at.is_some());
+    let res = post!(
+        app,
+        "/cancel_account_deletion",
+        CancelAccountDeletionRequest {
+            login_token: login_token.clone(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(db.delete_due_accounts().await.unwrap(), 0);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v2/account/deletion")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .set_json(&PasswordRequest {
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        })
+        .to_request();
+    let res: SimpleResponse = test::read_response_json(&mut app, req).await;
+    assert!(res.success, "{}", res.err);
+    assert_eq!(db.delete_due_accounts().await.unwrap(), 1);
+
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: MAIL.to_string(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        },
+        LoginResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::NoUser));
+    let filter = AuditFilter {
+        target: Some(MAIL.to_string()),
+        ..AuditFilter::default()
+    };
+    let (_, entries) = db.audit_log(&filter, 0, 1).await.unwrap();
+    assert_eq!(entries[0].action, AuditAction::DeleteAccount);
+}
+
+#[actix_rt::test]
+async fn profile_editing() {
+    const NEW_MAIL: &str = "new@example.com";
+    let mails = Arc::new(CapturedMails::default());
+    let db = web::Data::new(
+        Database::new(Arc::new(MemoryStore::default()))
+            .with_mail_sender(mails.clone(), "http://example.com".to_string()),
+    );
+    let mut app = init_app!(db);
+    let login_token = register_and_login!(app);
+    let res = post!(
+        app,
+        "/verify_mail",
+        VerifyMailRequest {
+            token: mails.latest_token(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: "other@example.com".to_string(),
+            name: "another".to_string(),
+            password: PASSWORD.to_string(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    let update =
+        |name: Option<&str>, language: Option<&str>, timezone: Option<&str>| UpdateProfileRequest {
+            login_token: login_token.clone(),
+            name: name.map(str::to_string),
+            language: language.map(str::to_string),
+            timezone: timezone.map(str::to_string),
+        };
+    let res = post!(
+        app,
+        "/update_profile",
+        update(Some("another"), None, None),
+        SimpleResponse
+    );
+    assert_eq!(res.code, Some(ApiError::DupUsername));
+    let long_name = "n".repeat(33);
+    for name in &["", "abc", "with space", long_name.as_str()] {
+        let res = post!(
+            app,
+            "/update_profile",
+            update(Some(name), None, None),
+            SimpleResponse
+        );
+        assert_eq!(res.code, Some(ApiError::InvalidRequest), "{}", name);
+    }
+    let res = post!(
+        app,
+        "/update_profile",
+        update(None, Some("fr-FR"), None),
+        SimpleResponse
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+    let res = post!(
+        app,
+        "/update_profile",
+        update(Some("renamed"), None, Some("Mars/Olympus")),
+        SimpleResponse
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+    let res = post!(
+        app,
+        "/update_profile",
+        update(Some("renamed"), Some("en-US"), Some("Asia/Shanghai")),
+        SimpleResponse
+    );
+    assert!(res.success, "{}", res.err);
+
+    // preferences come with the login, so that they follow the user across browsers
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: MAIL.to_string(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        },
+        LoginResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.name, "renamed");
+    assert_eq!(res.language, "en-US");
+    assert_eq!(res.timezone, "Asia/Shanghai");
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v2/account")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .set_json(&ProfilePatch {
+            timezone: Some(String::new()),
+            ..Default::default()
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let profile = db.profile(MAIL).await.unwrap();
+    assert_eq!(profile.name, "renamed");
+    assert_eq!(profile.language, "en-US");
+    assert_eq!(profile.timezone, "");
+
+    // the mail changes once the link sent to the new address is opened
+    let claim_code = provision(&db, DEFAULT_ORG, "dev").await;
+    let res = post!(
+        app,
+        "/create_device",
+        CreateDeviceRequest {
+            login_token: login_token.clone(),
+            id: "dev".to_string(),
+            claim_code,
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let change_mail = |mail: &str, password: &str| ChangeMailRequest {
+        login_token: login_token.clone(),
+        mail: mail.to_string(),
+        password: password.to_string(),
+        ..Default::default()
+    };
+    let res = post!(
+        app,
+        "/change_mail",
+        change_mail(NEW_MAIL, "wrong"),
+        SimpleResponse
+    );
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+    let res = post!(
+        app,
+        "/change_mail",
+        change_mail("other@example.com", PASSWORD),
+        SimpleResponse
+    );
+    assert_eq!(res.code, Some(ApiError::DupEmail));
+    let res = post!(
+        app,
+        "/change_mail",
+        change_mail(NEW_MAIL, PASSWORD),
+        SimpleResponse
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(mails.0.lock().unwrap().last().unwrap().to, NEW_MAIL);
+    let profile = db.profile(MAIL).await.unwrap();
+    assert_eq!(profile.pending_mail.as_deref(), Some(NEW_MAIL));
+
+    let res = post!(
+        app,
+        "/verify_mail",
+        VerifyMailRequest {
+            token: mails.latest_token(),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    // the session and the followed devices move to the new address
+    let res = post!(
+        app,
+        "/fetch_profile",
+        FetchProfileRequest {
+            login_token: login_token.clone(),
+        },
+        FetchProfileResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.profile.mail, NEW_MAIL);
+    assert_eq!(res.profile.pending_mail, None);
+    let res = post!(
+        app,
+        "/fetch_device_list",
+        FetchDeviceListRequest {
+            login_token: login_token.clone(),
+        },
+        FetchDeviceListResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.devices.len(), 1);
+    let login = |mail: &str| LoginRequest {
+        mail: mail.to_string(),
+        password: PASSWORD.to_string(),
+        ..Default::default()
+    };
+    let res = post!(app, "/login", login(MAIL), LoginResponse);
+    assert_eq!(res.code, Some(ApiError::NoUser));
+    let res = post!(app, "/login", login(NEW_MAIL), LoginResponse);
+    assert!(res.success, "{}", res.err);
+    let filter = AuditFilter {
+        target: Some(NEW_MAIL.to_string()),
+        action: Some(AuditAction::UpdateUser),
+        ..AuditFilter::default()
+    };
+    let (_, entries) = db.audit_log(&filter, 0, 0).await.unwrap();
+    assert_eq!(entries[0].changes[0].field, "mail");
+    assert_eq!(entries[0].changes[0].before, MAIL);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v2/account/mail")
+        .header("Authorization", format!("Bearer {}", login_token))
+        .set_json(&NewMailRequest {
+            mail: "other@example.com".to_string(),
+            password: PASSWORD.to_string(),
+            ..Default::default()
+        })
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+}
+
+/// Account the mock provider signs in, and the login it was issued for
+#[derive(Clone)]
+struct IdpGrant {
+    subject: String,
+    mail: String,
+    mail_verified: bool,
+    name: String,
+    nonce: String,
+    code_challenge: String,
+}
+
+type IdpGrants = Arc<Mutex<HashMap<String, IdpGrant>>>;
+
+/// OpenID Connect provider whose authorization codes are handed out by the test instead of a
+/// login page
+fn mock_idp(grants: IdpGrants) -> test::TestServer {
+    fn issuer(req: &HttpRequest) -> String {
+        format!("http://{}", req.connection_info().host())
+    }
+
+    test::start(move || {
+        App::new()
+            .data(grants.clone())
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(|req: HttpRequest| async move {
+                    let issuer = issuer(&req);
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "issuer": issuer,
+                        "authorization_endpoint": format!("{}/authorize", issuer),
+                        "token_endpoint": format!("{}/token", issuer),
+                    }))
+                }),
+            )
+            .route(
+                "/token",
+                web::post().to(
+                    |req: HttpRequest,
+                     form: web::Form<HashMap<String, String>>,
+                     grants: web::Data<IdpGrants>| async move {
+                        let grant = match grants.lock().unwrap().remove(&form["code"]) {
+                            Some(grant) => grant,
+                            None => return HttpResponse::BadRequest().finish(),
+                        };
+                        let challenge = base64::encode_config(
+                            Sha256::digest(form["code_verifier"].as_bytes()),
+                            base64::URL_SAFE_NO_PAD,
+                        );
+                        if challenge != grant.code_challenge {
+                            return HttpResponse::BadRequest().finish();
+                        }
+                        let claims = serde_json::json!({
+                            "iss": issuer(&req),
+                            "sub": grant.subject,
+                            "aud": "bs-app",
+                            "exp": chrono::Utc::now().timestamp() + 300,
+                            "nonce": grant.nonce,
+                            "email": grant.mail,
+                            "email_verified": grant.mail_verified,
+                            "preferred_username": grant.name,
+                        });
+                        let id_token = format!(
+                            "{}.{}.",
+                            base64::encode_config(r#"{"alg":"none"}"#, base64::URL_SAFE_NO_PAD),
+                            base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD),
+                        );
+                        HttpResponse::Ok().json(serde_json::json!({
+                            "access_token": "access",
+                            "token_type": "Bearer",
+                            "id_token": id_token,
+                        }))
+                    },
+                ),
+            )
+    })
+}
+
+/// Value of a query parameter of a URL
+fn query_param(url: &str, name: &str) -> String {
+    let query = url.split_once('?').unwrap().1;
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(&format!("{}=", name)))
+        .unwrap()
+        .to_string()
+}
+
+#[actix_rt::test]
+async fn single_sign_on() {
+    let grants = IdpGrants::default();
+    let idp = mock_idp(grants.clone());
+    let issuer = format!("http://{}", idp.addr());
+    let db = web::Data::new(Database::new(Arc::new(MemoryStore::default())).with_oidc(
+        OidcClient::new(
+            &issuer,
+            "bs-app",
+            "secret",
+            "openid email profile",
+            "Example SSO",
+            "http://example.com/sso_callback",
+        ),
+    ));
+    let mut app = init_app!(db);
+    register_and_login!(app);
+
+    let res = post!(app, "/fetch_sso_status", (), FetchSsoStatusResponse);
+    assert!(res.enabled);
+    assert_eq!(res.provider_name, "Example SSO");
+
+    // the test plays the login page of the provider
+    let authorize = |app_res: SsoAuthorizeResponse, subject: &str, mail: &str, verified: bool| {
+        assert!(app_res.success, "{}", app_res.err);
+        assert!(app_res.url.starts_with(&format!("{}/authorize?", issuer)));
+        assert_eq!(query_param(&app_res.url, "code_challenge_method"), "S256");
+        let state = query_param(&app_res.url, "state");
+        let code = format!("code-{}", state);
+        grants.lock().unwrap().insert(
+            code.clone(),
+            IdpGrant {
+                subject: subject.to_string(),
+                mail: mail.to_string(),
+                mail_verified: verified,
+                name: "new.user".to_string(),
+                nonce: query_param(&app_res.url, "nonce"),
+                code_challenge: query_param(&app_res.url, "code_challenge"),
+            },
+        );
+        SsoLoginRequest { state, code }
+    };
+
+    // the account at the provider is linked to the user with the same verified mail
+    let res = post!(app, "/sso_authorize", (), SsoAuthorizeResponse);
+    let login = authorize(res, "subject-1", MAIL, true);
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/sso_callback?state={}&code={}",
+            login.state, login.code
+        ))
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(
+        res.headers().get("Location").unwrap(),
+        format!("/#sso_login/{}/{}", login.state, login.code).as_str()
+    );
+    let res = post!(app, "/sso_login", login, LoginResponse);
+    assert!(res.success, "{}", res.err);
+    assert_eq!(res.mail, MAIL);
+    assert_eq!(res.name, NAME);
+    let filter = AuditFilter {
+        target: Some(MAIL.to_string()),
+        action: Some(AuditAction::UpdateUser),
+        ..AuditFilter::default()
+    };
+    let (_, entries) = db.audit_log(&filter, 0, 0).await.unwrap();
+    assert_eq!(entries[0].changes[0].field, "sso");
+
+    // every login works only once
+    let res = post!(app, "/sso_authorize", (), SsoAuthorizeResponse);
+    let login = authorize(res, "subject-1", MAIL, true);
+    let replay = SsoLoginRequest {
+        state: login.state.clone(),
+        code: login.code.clone(),
+    };
+    let res = post!(app, "/sso_login", login, LoginResponse);
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/sso_login", replay, LoginResponse);
+    assert_eq!(res.code, Some(ApiError::SsoFailed));
+
+    // new accounts need a mail verified by the provider
+    let res = post!(app, "/sso_authorize", (), SsoAuthorizeResponse);
+    let login = authorize(res, "subject-2", "new@example.com", false);
+    let res = post!(app, "/sso_login", login, LoginResponse);
+    assert_eq!(res.code, Some(ApiError::MailNotVerified));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v2/sso/authorize")
+        .to_request();
+    let res: SsoAuthorizeResponse = test::read_response_json(&mut app, req).await;
+    let login = authorize(res, "subject-2", "new@example.com", true);
+    let req = test::TestRequest::post()
+        .uri("/api/v2/sso/sessions")
+        .set_json(&login)
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let res: LoginResponse = test::read_body_json(res).await;
+    assert_eq!(res.mail, "new@example.com");
+    assert_eq!(res.name, "new_user");
+
+    // accounts of single sign-on have no password, a recent login confirms changes instead
+    let login_token = res.login_token;
+    let res = post!(
+        app,
+        "/fetch_profile",
+        FetchProfileRequest {
+            login_token: login_token.clone(),
+        },
+        FetchProfileResponse,
+    );
+    assert!(res.profile.sso);
+    let res = post!(
+        app,
+        "/change_mail",
+        ChangeMailRequest {
+            login_token: login_token.clone(),
+            mail: "renamed@example.com".to_string(),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/delete_account",
+        DeleteAccountRequest {
+            login_token: login_token.clone(),
+            password: "wrong".to_string(),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+    let res = post!(
+        app,
+        "/delete_account",
+        DeleteAccountRequest {
+            login_token,
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+
+    // the provider refuses unknown codes
+    let res = post!(app, "/sso_authorize", (), SsoAuthorizeResponse);
+    let login = authorize(res, "subject-2", "new@example.com", true);
+    let res = post!(
+        app,
+        "/sso_login",
+        SsoLoginRequest {
+            state: login.state,
+            code: "unknown".to_string(),
+        },
+        LoginResponse
+    );
+    assert_eq!(res.code, Some(ApiError::SsoFailed));
+}
+
+/// Directory entry of the mock directory
+struct DirectoryEntry {
+    password: String,
+    uid: String,
+    admin: bool,
+}
+
+/// Directory in place of an LDAP server, entries are keyed by mail address
+#[derive(Default)]
+struct MockDirectory(Mutex<HashMap<String, DirectoryEntry>>);
+
+#[async_trait]
+impl AuthProvider for MockDirectory {
+    async fn authenticate(
+        &self,
+        _store: &dyn Store,
+        credentials: &Credentials,
+    ) -> anyhow::Result<Authentication> {
+        let password = match &credentials.plain_password {
+            Some(password) => password,
+            None => anyhow::bail!(ApiError::InvalidRequest),
+        };
+        Ok(match self.0.lock().unwrap().get(&credentials.mail) {
+            Some(entry) if &entry.password == password => Authentication::Authenticated {
+                name: Some(entry.uid.clone()),
+                role: Some(if entry.admin { Role::Admin } else { Role::User }),
+            },
+            Some(_) => Authentication::WrongPassword,
+            None => Authentication::Unknown,
+        })
+    }
+
+    fn needs_plain_password(&self) -> bool {
+        true
+    }
+}
+
+#[actix_rt::test]
+async fn directory_login() {
+    const DIRECTORY_MAIL: &str = "alice@example.com";
+    let directory = Arc::new(MockDirectory::default());
+    directory.0.lock().unwrap().insert(
+        DIRECTORY_MAIL.to_string(),
+        DirectoryEntry {
+            password: "directory secret".to_string(),
+            uid: "alice".to_string(),
+            admin: true,
+        },
+    );
+    let db = web::Data::new(
+        Database::new(Arc::new(MemoryStore::default()))
+            .with_auth_providers(vec![directory.clone(), Arc::new(LocalAuth)]),
+    );
+    let mut app = init_app!(db);
+
+    let res = post!(app, "/fetch_login_options", (), FetchLoginOptionsResponse);
+    assert!(res.plain_password);
+
+    let login = |mail: &str, password: &str| LoginRequest {
+        mail: mail.to_string(),
+        plain_password: Some(password.to_string()),
+        ..Default::default()
+    };
+    let res = post!(app, "/login", login(DIRECTORY_MAIL, "wrong"), LoginResponse);
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+
+    // the first login registers the user with the name and the role of the directory
+    let res = post!(
+        app,
+        "/login",
+        login(DIRECTORY_MAIL, "directory secret"),
+        LoginResponse
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/fetch_profile",
+        FetchProfileRequest {
+            login_token: res.login_token,
+        },
+        FetchProfileResponse,
+    );
+    assert_eq!(res.profile.name, "alice");
+    assert_eq!(res.profile.role, Role::Admin);
+    assert!(res.profile.verified);
+
+    // the role follows the directory on every login
+    directory
+        .0
+        .lock()
+        .unwrap()
+        .get_mut(DIRECTORY_MAIL)
+        .unwrap()
+        .admin = false;
+    let res = post!(
+        app,
+        "/login",
+        login(DIRECTORY_MAIL, "directory secret"),
+        LoginResponse
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(
+        app,
+        "/fetch_profile",
+        FetchProfileRequest {
+            login_token: res.login_token,
+        },
+        FetchProfileResponse,
+    );
+    assert_eq!(res.profile.role, Role::User);
+
+    // users the directory doesn't know log in by their local passwords
+    let local_password = "local secret";
+    let res = post!(
+        app,
+        "/register",
+        RegisterRequest {
+            mail: MAIL.to_string(),
+            name: NAME.to_string(),
+            password: format!("{:x}", Sha256::digest(local_password.as_bytes())),
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/login", login(MAIL, local_password), LoginResponse);
+    assert!(res.success, "{}", res.err);
+    let res = post!(app, "/login", login(MAIL, "wrong"), LoginResponse);
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+    let res = post!(
+        app,
+        "/login",
+        login("nobody@example.com", local_password),
+        LoginResponse
+    );
+    assert_eq!(res.code, Some(ApiError::NoUser));
+
+    // the directory can't check hashed passwords
+    let res = post!(
+        app,
+        "/login",
+        LoginRequest {
+            mail: MAIL.to_string(),
+            password: format!("{:x}", Sha256::digest(local_password.as_bytes())),
+            ..Default::default()
+        },
+        LoginResponse
+    );
+    assert_eq!(res.code, Some(ApiError::InvalidRequest));
+
+    // directory users confirm changes of the account by the password of the directory
+    let res = post!(
+        app,
+        "/login",
+        login(DIRECTORY_MAIL, "directory secret"),
+        LoginResponse
+    );
+    assert!(res.success, "{}", res.err);
+    let login_token = res.login_token;
+    let change_mail = |password: &str| ChangeMailRequest {
+        login_token: login_token.clone(),
+        mail: MAIL.to_string(),
+        plain_password: Some(password.to_string()),
+        ..Default::default()
+    };
+    let res = post!(app, "/change_mail", change_mail("wrong"), SimpleResponse);
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+    // the password is right, the mail is taken
+    let res = post!(
+        app,
+        "/change_mail",
+        change_mail("directory secret"),
+        SimpleResponse
+    );
+    assert_eq!(res.code, Some(ApiError::DupEmail));
+    let res = post!(
+        app,
+        "/delete_account",
+        DeleteAccountRequest {
+            login_token: login_token.clone(),
+            plain_password: Some("wrong".to_string()),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert_eq!(res.code, Some(ApiError::WrongPassword));
+    let res = post!(
+        app,
+        "/delete_account",
+        DeleteAccountRequest {
+            login_token,
+            plain_password: Some("directory secret".to_string()),
+            ..Default::default()
+        },
+        SimpleResponse,
+    );
+    assert!(res.success, "{}", res.err);
+}
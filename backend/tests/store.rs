@@ -0,0 +1,423 @@
+use bs_backend::store::{
+    AuditEntry, AuditFilter, Command, Device, LoginAttempt, LoginRecord, MemoryStore, Message,
+    SqlStore, Store, DEFAULT_ORG,
+};
+use chrono::{Duration, Utc};
+use common::request::{AuditAction, CommandStatus};
+use std::{collections::HashSet, sync::Arc};
+
+/// Run each test against a new `MemoryStore` and a new `SqlStore` on an in-memory SQLite
+/// database, so that both stores are checked to behave the same
+macro_rules! store_tests {
+    ( $( $name:ident ),+ $(,)? ) => {
+        mod memory {
+            $(
+                #[actix_rt::test]
+                async fn $name() {
+                    super::$name(super::Arc::new(super::MemoryStore::default())).await;
+                }
+            )+
+        }
+
+        mod sqlite {
+            $(
+                #[actix_rt::test]
+                async fn $name() {
+                    let store = super::SqlStore::new("sqlite::memory:").await.unwrap();
+                    super::$name(super::Arc::new(store)).await;
+                }
+            )+
+        }
+    };
+}
+
+store_tests!(
+    messages_are_deduplicated,
+    messages_in_range,
+    devices_and_twins,
+    commands,
+    audit_log,
+    login_records,
+);
+
+fn message(org: &str, timestamp: i64, alert: bool, msg_id: Option<&str>) -> Message {
+    Message::new(
+        org.to_string(),
+        "dev".to_string(),
+        "info".to_string(),
+        1,
+        alert,
+        120.0,
+        30.0,
+        timestamp,
+        msg_id.map(str::to_string),
+    )
+}
+
+fn device(org: &str, id: &str) -> Device {
+    Device {
+        org: org.to_string(),
+        id: id.to_string(),
+        name: id.to_string(),
+        info: "".to_string(),
+        claim_code: Some("hashed_code".to_string()),
+        owner: None,
+        desired: "{}".to_string(),
+        desired_version: 0,
+        reported: "{}".to_string(),
+        reported_version: 0,
+        reported_at: 0,
+    }
+}
+
+fn command(id: &str, created_at: i64) -> Command {
+    Command {
+        id: id.to_string(),
+        org: DEFAULT_ORG.to_string(),
+        device_id: "dev".to_string(),
+        name: "reboot".to_string(),
+        params: "".to_string(),
+        status: CommandStatus::Sent,
+        issued_by: "test@example.com".to_string(),
+        created_at,
+        updated_at: created_at,
+        result: "".to_string(),
+    }
+}
+
+async fn messages_are_deduplicated(store: Arc<dyn Store>) {
+    assert!(store
+        .insert_message(message(DEFAULT_ORG, 1000, false, None))
+        .await
+        .unwrap());
+    // messages without an id are duplicates of each other too
+    assert!(!store
+        .insert_message(message(DEFAULT_ORG, 1000, false, None))
+        .await
+        .unwrap());
+    assert!(store
+        .insert_message(message(DEFAULT_ORG, 1000, false, Some("a")))
+        .await
+        .unwrap());
+    assert!(store
+        .insert_message(message("acme", 1000, false, None))
+        .await
+        .unwrap());
+
+    let duplicated = store
+        .insert_messages(vec![
+            message(DEFAULT_ORG, 2000, false, None),
+            message(DEFAULT_ORG, 1000, false, Some("a")),
+            message(DEFAULT_ORG, 2000, false, None),
+            message(DEFAULT_ORG, 3000, true, Some("b")),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(duplicated, 2);
+
+    assert_eq!(
+        store
+            .count_messages(DEFAULT_ORG, "dev", false)
+            .await
+            .unwrap(),
+        4
+    );
+    assert_eq!(
+        store
+            .count_messages(DEFAULT_ORG, "dev", true)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(store.count_messages("acme", "dev", false).await.unwrap(), 1);
+
+    let keys = store
+        .find_message_keys(DEFAULT_ORG, "dev", 0, i64::MAX)
+        .await
+        .unwrap();
+    let expected: HashSet<_> = vec![
+        (1000, None),
+        (1000, Some("a".to_string())),
+        (2000, None),
+        (3000, Some("b".to_string())),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(keys, expected);
+
+    let msgs = store
+        .find_messages(DEFAULT_ORG, "dev", 3000, 3000, 0, 0)
+        .await
+        .unwrap();
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(msgs[0].msg_id.as_deref(), Some("b"));
+    assert!(msgs[0].alert);
+    let msgs = store
+        .find_messages(DEFAULT_ORG, "dev", 2000, 2000, 0, 0)
+        .await
+        .unwrap();
+    assert_eq!(msgs[0].msg_id, None);
+}
+
+async fn messages_in_range(store: Arc<dyn Store>) {
+    let msgs = (1..=7)
+        .map(|i| message(DEFAULT_ORG, i * 1000, false, None))
+        .collect();
+    assert_eq!(store.insert_messages(msgs).await.unwrap(), 0);
+    store
+        .insert_message(message("acme", 3000, false, None))
+        .await
+        .unwrap();
+
+    // both ends of the range are included
+    assert_eq!(
+        store
+            .count_messages_in_range(DEFAULT_ORG, "dev", 2000, 5000)
+            .await
+            .unwrap(),
+        4
+    );
+    let timestamps = |msgs: Vec<Message>| msgs.iter().map(|msg| msg.timestamp).collect::<Vec<_>>();
+    let msgs = store
+        .find_messages(DEFAULT_ORG, "dev", 2000, 5000, 1, 2)
+        .await
+        .unwrap();
+    assert_eq!(timestamps(msgs), vec![4000, 3000]);
+    // a limit of 0 means no limit
+    let msgs = store
+        .find_messages(DEFAULT_ORG, "dev", 0, i64::MAX, 5, 0)
+        .await
+        .unwrap();
+    assert_eq!(timestamps(msgs), vec![2000, 1000]);
+    let msgs = store
+        .find_messages(DEFAULT_ORG, "dev", 8000, i64::MAX, 0, 0)
+        .await
+        .unwrap();
+    assert!(msgs.is_empty());
+
+    let keys = store
+        .find_message_keys(DEFAULT_ORG, "dev", 3000, 3000)
+        .await
+        .unwrap();
+    assert_eq!(keys.into_iter().collect::<Vec<_>>(), vec![(3000, None)]);
+}
+
+async fn devices_and_twins(store: Arc<dyn Store>) {
+    store
+        .insert_device(device(DEFAULT_ORG, "dev"))
+        .await
+        .unwrap();
+    store.insert_device(device("acme", "dev")).await.unwrap();
+    store.insert_device(device(DEFAULT_ORG, "a")).await.unwrap();
+    assert!(store.find_device("other", "dev").await.unwrap().is_none());
+
+    store
+        .update_device(DEFAULT_ORG, "dev", "Device", "info")
+        .await
+        .unwrap();
+    let dev = store
+        .find_device(DEFAULT_ORG, "dev")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(dev.name, "Device");
+    assert_eq!(dev.info, "info");
+    let dev = store.find_device("acme", "dev").await.unwrap().unwrap();
+    assert_eq!(dev.name, "dev");
+
+    assert!(!store
+        .claim_device(DEFAULT_ORG, "dev", "test@example.com", "wrong_code")
+        .await
+        .unwrap());
+    assert!(store
+        .claim_device(DEFAULT_ORG, "dev", "test@example.com", "hashed_code")
+        .await
+        .unwrap());
+    assert!(!store
+        .claim_device(DEFAULT_ORG, "dev", "other@example.com", "hashed_code")
+        .await
+        .unwrap());
+    let dev = store
+        .find_device(DEFAULT_ORG, "dev")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(dev.owner.as_deref(), Some("test@example.com"));
+    assert_eq!(dev.claim_code, None);
+
+    assert!(store
+        .set_device_desired(DEFAULT_ORG, "dev", r#"{"interval":5}"#, 0)
+        .await
+        .unwrap());
+    assert!(!store
+        .set_device_desired(DEFAULT_ORG, "dev", r#"{"interval":1}"#, 0)
+        .await
+        .unwrap());
+    assert!(store
+        .set_device_reported(DEFAULT_ORG, "dev", r#"{"interval":5}"#, 0, 1000)
+        .await
+        .unwrap());
+    assert!(!store
+        .set_device_reported(DEFAULT_ORG, "dev", "{}", 0, 2000)
+        .await
+        .unwrap());
+    let dev = store
+        .find_device(DEFAULT_ORG, "dev")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(dev.desired, r#"{"interval":5}"#);
+    assert_eq!(dev.desired_version, 1);
+    assert_eq!(dev.reported, r#"{"interval":5}"#);
+    assert_eq!(dev.reported_version, 1);
+    assert_eq!(dev.reported_at, 1000);
+
+    assert_eq!(store.count_devices().await.unwrap(), 3);
+    let devices = store.find_devices(1, 0).await.unwrap();
+    let ids: Vec<_> = devices
+        .iter()
+        .map(|dev| (dev.org.as_str(), dev.id.as_str()))
+        .collect();
+    assert_eq!(ids, vec![(DEFAULT_ORG, "a"), (DEFAULT_ORG, "dev")]);
+}
+
+async fn commands(store: Arc<dyn Store>) {
+    for (i, id) in ["c1", "c2", "c3"].iter().enumerate() {
+        store
+            .insert_command(command(id, 1000 * (i as i64 + 1)))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(store.count_commands(DEFAULT_ORG, "dev").await.unwrap(), 3);
+    let commands = store.find_commands(DEFAULT_ORG, "dev", 1, 1).await.unwrap();
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].id, "c2");
+
+    assert!(store
+        .update_command_status("c3", CommandStatus::Acked, "done", 3500)
+        .await
+        .unwrap());
+    // final statuses don't change any more
+    assert!(!store
+        .update_command_status("c3", CommandStatus::Failed, "", 3600)
+        .await
+        .unwrap());
+    assert_eq!(store.expire_commands(2500, 4000).await.unwrap(), 2);
+    assert_eq!(store.expire_commands(5000, 5000).await.unwrap(), 0);
+
+    let statuses: Vec<_> = store
+        .find_commands(DEFAULT_ORG, "dev", 0, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|cmd| (cmd.id, cmd.status, cmd.result))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ("c3".to_string(), CommandStatus::Acked, "done".to_string()),
+            ("c2".to_string(), CommandStatus::Timeout, "".to_string()),
+            ("c1".to_string(), CommandStatus::Timeout, "".to_string()),
+        ]
+    );
+    let command = store.find_command("c1").await.unwrap().unwrap();
+    assert_eq!(command.updated_at, 4000);
+    assert!(store.find_command("c4").await.unwrap().is_none());
+}
+
+async fn audit_log(store: Arc<dyn Store>) {
+    let entries = [
+        ("a@example.com", AuditAction::Login, 1000),
+        ("b@example.com", AuditAction::Login, 2000),
+        ("a@example.com", AuditAction::CreateDevice, 3000),
+        ("a@example.com", AuditAction::Login, 4000),
+    ];
+    for (actor, action, time) in entries.iter() {
+        store
+            .insert_audit_entry(AuditEntry {
+                actor: actor.to_string(),
+                action: *action,
+                target: actor.to_string(),
+                changes: vec![],
+                ip: "127.0.0.1".to_string(),
+                time: *time,
+            })
+            .await
+            .unwrap();
+    }
+
+    let filter = AuditFilter {
+        actor: Some("a@example.com".to_string()),
+        action: Some(AuditAction::Login),
+        ..AuditFilter::default()
+    };
+    assert_eq!(store.count_audit_entries(&filter).await.unwrap(), 2);
+    let filter = AuditFilter {
+        from: 2000,
+        to: 4000,
+        ..AuditFilter::default()
+    };
+    assert_eq!(store.count_audit_entries(&filter).await.unwrap(), 3);
+    let times: Vec<_> = store
+        .find_audit_entries(&filter, 1, 5)
+        .await
+        .unwrap()
+        .iter()
+        .map(|entry| entry.time)
+        .collect();
+    assert_eq!(times, vec![3000, 2000]);
+}
+
+async fn login_records(store: Arc<dyn Store>) {
+    let now = Utc::now();
+    store
+        .insert_login_record(LoginRecord {
+            login_token: "token".to_string(),
+            mail: "test@example.com".to_string(),
+            login_time: now,
+            last_active: now,
+            remember: false,
+            ip: "127.0.0.1".to_string(),
+        })
+        .await
+        .unwrap();
+    for (i, success) in [false, true, false].iter().enumerate() {
+        store
+            .insert_login_attempt(LoginAttempt {
+                mail: "test@example.com".to_string(),
+                time: now - Duration::seconds(3 - i as i64),
+                success: *success,
+                ip: "127.0.0.1".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+    assert!(store.find_login_record("token").await.unwrap().is_some());
+
+    // the successful attempt still separates the failures after the sessions are gone
+    store
+        .delete_user_login_records("test@example.com")
+        .await
+        .unwrap();
+    assert!(store.find_login_record("token").await.unwrap().is_none());
+    let attempts = store
+        .find_login_attempts("test@example.com", now - Duration::minutes(1))
+        .await
+        .unwrap();
+    let successes: Vec<_> = attempts.iter().map(|attempt| attempt.success).collect();
+    assert_eq!(successes, vec![false, true, false]);
+
+    assert_eq!(
+        store
+            .delete_login_attempts(now - Duration::seconds(2))
+            .await
+            .unwrap(),
+        1
+    );
+    let attempts = store
+        .find_login_attempts("test@example.com", now - Duration::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(attempts.len(), 2);
+}
@@ -0,0 +1,51 @@
+use bs_backend::totp;
+
+/// "12345678901234567890" of the test vectors in RFC 6238, base32 encoded
+const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+#[test]
+fn codes_match_rfc_6238() {
+    // the 8-digit values in the RFC, truncated to the last 6 digits
+    let cases = [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+    ];
+    for (time, code) in cases.iter() {
+        assert_eq!(totp::code(SECRET, *time).unwrap(), *code, "{}", time);
+    }
+}
+
+#[test]
+fn verify_accepts_adjacent_steps() {
+    let time = 1234567890;
+    let step = time / totp::STEP_SECS;
+    let code = totp::code(SECRET, time).unwrap();
+    assert_eq!(totp::verify(SECRET, &code, time).unwrap(), Some(step));
+    assert_eq!(
+        totp::verify(SECRET, &code, time + totp::STEP_SECS).unwrap(),
+        Some(step)
+    );
+    assert_eq!(
+        totp::verify(SECRET, &code, time + 3 * totp::STEP_SECS).unwrap(),
+        None
+    );
+    assert_eq!(totp::verify(SECRET, "12345", time).unwrap(), None);
+    assert_eq!(totp::verify(SECRET, "abcdef", time).unwrap(), None);
+}
+
+#[test]
+fn secrets_and_provisioning_uri() {
+    let secret = totp::generate_secret();
+    assert_eq!(secret.len(), 32);
+    assert!(totp::code(&secret, 0).is_ok());
+
+    let uri = totp::provisioning_uri("bs app", "test@example.com", SECRET);
+    assert_eq!(
+        uri,
+        "otpauth://totp/bs%20app:test@example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+         &issuer=bs%20app&algorithm=SHA1&digits=6&period=30"
+    );
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Errors returned by the server.
+///
+/// The serialized name (`code`) is stable and meant to be matched by clients, while
+/// `message_id` is the fluent message id translated by the frontend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ApiError {
+    InvalidRequest,
+    ImportFormat,
+    InvalidVerification,
+    LoginExpired,
+    WrongPassword,
+    Forbidden,
+    MailNotVerified,
+    AccountDisabled,
+    TooManyAttempts,
+    TotpRequired,
+    WrongTotpCode,
+    SsoFailed,
+    InvalidClaimCode,
+    NoUser,
+    NoDevice,
+    NoApiKey,
+    NoOrg,
+    DupEmail,
+    DupUsername,
+    DupOrg,
+    DeviceClaimed,
+    VersionConflict,
+    Net,
+    Unknown,
+}
+
+impl ApiError {
+    pub const ALL: &'static [ApiError] = &[
+        ApiError::InvalidRequest,
+        ApiError::ImportFormat,
+        ApiError::InvalidVerification,
+        ApiError::LoginExpired,
+        ApiError::WrongPassword,
+        ApiError::Forbidden,
+        ApiError::MailNotVerified,
+        ApiError::AccountDisabled,
+        ApiError::TooManyAttempts,
+        ApiError::TotpRequired,
+        ApiError::WrongTotpCode,
+        ApiError::SsoFailed,
+        ApiError::InvalidClaimCode,
+        ApiError::NoUser,
+        ApiError::NoDevice,
+        ApiError::NoApiKey,
+        ApiError::NoOrg,
+        ApiError::DupEmail,
+        ApiError::DupUsername,
+        ApiError::DupOrg,
+        ApiError::DeviceClaimed,
+        ApiError::VersionConflict,
+        ApiError::Net,
+        ApiError::Unknown,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidRequest => "invalid_request",
+            ApiError::ImportFormat => "import_format",
+            ApiError::InvalidVerification => "invalid_verification",
+            ApiError::LoginExpired => "login_expired",
+            ApiError::WrongPassword => "wrong_password",
+            ApiError::Forbidden => "forbidden",
+            ApiError::MailNotVerified => "mail_not_verified",
+            ApiError::AccountDisabled => "account_disabled",
+            ApiError::TooManyAttempts => "too_many_attempts",
+            ApiError::TotpRequired => "totp_required",
+            ApiError::WrongTotpCode => "wrong_totp_code",
+            ApiError::SsoFailed => "sso_failed",
+            ApiError::InvalidClaimCode => "invalid_claim_code",
+            ApiError::NoUser => "no_user",
+            ApiError::NoDevice => "no_device",
+            ApiError::NoApiKey => "no_api_key",
+            ApiError::NoOrg => "no_org",
+            ApiError::DupEmail => "dup_email",
+            ApiError::DupUsername => "dup_username",
+            ApiError::DupOrg => "dup_org",
+            ApiError::DeviceClaimed => "device_claimed",
+            ApiError::VersionConflict => "version_conflict",
+            ApiError::Net => "net",
+            ApiError::Unknown => "unknown",
+        }
+    }
+
+    pub fn message_id(&self) -> &'static str {
+        match self {
+            ApiError::InvalidRequest => "error-invalid-request",
+            ApiError::ImportFormat => "error-import-format",
+            ApiError::InvalidVerification => "error-invalid-verification",
+            ApiError::LoginExpired => "error-login-expired",
+            ApiError::WrongPassword => "error-wrong-password",
+            ApiError::Forbidden => "error-forbidden",
+            ApiError::MailNotVerified => "error-mail-not-verified",
+            ApiError::AccountDisabled => "error-account-disabled",
+            ApiError::TooManyAttempts => "error-too-many-attempts",
+            ApiError::TotpRequired => "error-totp-required",
+            ApiError::WrongTotpCode => "error-wrong-totp-code",
+            ApiError::SsoFailed => "error-sso-failed",
+            ApiError::InvalidClaimCode => "error-invalid-claim-code",
+            ApiError::NoUser => "error-no-user",
+            ApiError::NoDevice => "error-no-device",
+            ApiError::NoApiKey => "error-no-api-key",
+            ApiError::NoOrg => "error-no-org",
+            ApiError::DupEmail => "error-dup-email",
+            ApiError::DupUsername => "error-dup-username",
+            ApiError::DupOrg => "error-dup-org",
+            ApiError::DeviceClaimed => "error-device-claimed",
+            ApiError::VersionConflict => "error-version-conflict",
+            ApiError::Net => "error-net",
+            ApiError::Unknown => "error-unknown",
+        }
+    }
+
+    /// HTTP status code of the response
+    pub fn status(&self) -> u16 {
+        match self {
+            ApiError::InvalidRequest | ApiError::ImportFormat | ApiError::InvalidVerification => {
+                400
+            }
+            ApiError::LoginExpired
+            | ApiError::WrongPassword
+            | ApiError::TotpRequired
+            | ApiError::WrongTotpCode
+            | ApiError::SsoFailed => 401,
+            ApiError::Forbidden
+            | ApiError::MailNotVerified
+            | ApiError::AccountDisabled
+            | ApiError::InvalidClaimCode => 403,
+            ApiError::TooManyAttempts => 429,
+            ApiError::NoUser | ApiError::NoDevice | ApiError::NoApiKey | ApiError::NoOrg => 404,
+            ApiError::DupEmail
+            | ApiError::DupUsername
+            | ApiError::DupOrg
+            | ApiError::DeviceClaimed
+            | ApiError::VersionConflict => 409,
+            ApiError::Net | ApiError::Unknown => 500,
+        }
+    }
+
+    pub fn from_message_id(message_id: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|err| err.message_id() == message_id)
+            .copied()
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message_id())
+    }
+}
+
+impl std::error::Error for ApiError {}
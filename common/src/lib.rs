@@ -1,2 +1,5 @@
+pub mod error;
+#[cfg(feature = "openapi")]
+pub mod openapi;
 pub mod request;
 pub mod response;
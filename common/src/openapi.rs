@@ -0,0 +1,211 @@
+//! OpenAPI 3 document of the server, built from a route table and the json schemas of the
+//! request and response types
+
+use crate::response::SimpleResponse;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+pub type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+/// Schema of `T`, a reference into `components/schemas` for structs and enums
+pub fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Auth {
+    None,
+    /// login token in the json body
+    Body,
+    /// login token in `Authorization: Bearer <token>`
+    Bearer,
+}
+
+pub struct Route {
+    /// method - lower case http method
+    pub method: &'static str,
+    /// path - path with `{param}` placeholders, which are always strings
+    pub path: &'static str,
+    pub summary: &'static str,
+    pub auth: Auth,
+    pub body: Option<SchemaFn>,
+    /// query - type whose fields are the query parameters
+    pub query: Option<SchemaFn>,
+    pub response: SchemaFn,
+    /// file - content type of the response if it is a file instead of json
+    pub file: Option<&'static str>,
+}
+
+impl Route {
+    pub fn new(method: &'static str, path: &'static str, summary: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            summary,
+            auth: Auth::None,
+            body: None,
+            query: None,
+            response: schema::<SimpleResponse>,
+            file: None,
+        }
+    }
+
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn body<T: JsonSchema>(mut self) -> Self {
+        self.body = Some(schema::<T>);
+        self
+    }
+
+    pub fn query<T: JsonSchema>(mut self) -> Self {
+        self.query = Some(schema::<T>);
+        self
+    }
+
+    pub fn response<T: JsonSchema>(mut self) -> Self {
+        self.response = schema::<T>;
+        self
+    }
+
+    /// The response is a file of `content_type`, errors are still json
+    pub fn file(mut self, content_type: &'static str) -> Self {
+        self.file = Some(content_type);
+        self
+    }
+
+    /// Path params, e.g. `["id"]` for "/devices/{id}"
+    pub fn path_params(&self) -> Vec<&'static str> {
+        self.path
+            .split('/')
+            .filter_map(|seg| seg.strip_prefix('{').and_then(|seg| seg.strip_suffix('}')))
+            .collect()
+    }
+}
+
+pub fn spec(title: &str, version: &str, routes: &[Route]) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+    for route in routes {
+        let operation = operation(&mut gen, route);
+        let item = paths
+            .entry(route.path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[route.method] = operation;
+    }
+
+    let schemas: Map<String, Value> = gen
+        .definitions()
+        .iter()
+        .map(|(name, schema)| (name.clone(), to_value(schema)))
+        .collect();
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": title,
+            "version": version,
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                },
+            },
+        },
+    })
+}
+
+fn operation(gen: &mut SchemaGenerator, route: &Route) -> Value {
+    let mut parameters: Vec<Value> = route
+        .path_params()
+        .into_iter()
+        .map(|name| {
+            json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            })
+        })
+        .collect();
+    if let Some(query) = route.query {
+        let query = query(gen);
+        parameters.extend(query_params(gen, &query));
+    }
+
+    let success_content = match route.file {
+        Some(content_type) => json!({
+            content_type: { "schema": { "type": "string", "format": "binary" } },
+        }),
+        None => json!({
+            "application/json": { "schema": to_value(&(route.response)(gen)) },
+        }),
+    };
+    let mut operation = json!({
+        "summary": route.summary,
+        "responses": {
+            "200": {
+                "description": "Success",
+                "content": success_content,
+            },
+            "default": {
+                "description": "Error, `code` tells the kind of it",
+                "content": {
+                    "application/json": { "schema": to_value(&schema::<SimpleResponse>(gen)) },
+                },
+            },
+        },
+    });
+    if !parameters.is_empty() {
+        operation["parameters"] = Value::Array(parameters);
+    }
+    if let Some(body) = route.body {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": {
+                "application/json": { "schema": to_value(&body(gen)) },
+            },
+        });
+    }
+    if route.auth == Auth::Bearer {
+        operation["security"] = json!([{ "bearer": [] }]);
+    }
+    operation
+}
+
+/// Every property of the (referenced) object schema is a query parameter
+fn query_params(gen: &SchemaGenerator, schema: &Schema) -> Vec<Value> {
+    let object = match gen.dereference(schema) {
+        Some(Schema::Object(SchemaObject {
+            instance_type: Some(ty),
+            object: Some(object),
+            ..
+        })) if *ty == InstanceType::Object.into() => object,
+        _ => return vec![],
+    };
+    object
+        .properties
+        .iter()
+        .map(|(name, schema)| {
+            json!({
+                "name": name,
+                "in": "query",
+                "required": object.required.contains(name),
+                "schema": to_value(schema),
+            })
+        })
+        .collect()
+}
+
+fn to_value(schema: &Schema) -> Value {
+    serde_json::to_value(schema).expect("Failed to serialize json schema")
+}
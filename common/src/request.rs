@@ -1,87 +1,826 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct LoginRequest {
-    pub mail: String,
-    pub password: String,
-}
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct RegisterRequest {
-    pub mail: String,
-    pub name: String,
-    pub password: String,
-}
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct CreateDeviceRequest {
-    pub login_token: String,
-    /// mail - user mail address
-    pub mail: String,
-    /// id - device id
-    pub id: String,
-}
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct RemoveDeviceRequest {
-    pub login_token: String,
-    /// mail - user mail address
-    pub mail: String,
-    /// id - device id
-    pub id: String,
-}
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct FetchDeviceRequest {
-    pub login_token: String,
-    /// id - device id
-    pub id: String,
-}
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct ModifyDeviceRequest {
-    pub login_token: String,
-    /// id - device id
-    pub id: String,
-    /// mail - user mail address
-    pub name: String,
-    pub info: String,
-}
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct FetchDeviceListRequest {
-    pub login_token: String,
-    /// mail - user mail address
-    pub mail: String,
-}
-
-#[derive(Default, Deserialize, Serialize)]
-pub struct FetchDeviceProfileRequest {
-    pub login_token: String,
-    /// id - device id
-    pub id: String,
-}
-
-#[derive(Deserialize, Serialize)]
-pub struct FetchMessageListRequest {
-    pub login_token: String,
-    /// id - device id
-    pub id: String,
-    pub start_timestamp: i64,
-    pub end_timestamp: i64,
-    pub first_index: usize,
-    pub limit: usize,
-}
-
-impl Default for FetchMessageListRequest {
-    fn default() -> Self {
-        Self {
-            login_token: String::default(),
-            id: String::default(),
-            start_timestamp: 0,
-            end_timestamp: std::i64::MAX,
-            first_index: 0,
-            limit: 20,
-        }
-    }
-}
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct LoginRequest {
+    pub mail: String,
+    pub password: String,
+    /// remember - keep the session for much longer without activity
+    #[serde(default)]
+    pub remember: bool,
+    /// totp_code - code of the authenticator app or a recovery code, required if two-factor
+    /// authentication is enabled
+    #[serde(default)]
+    pub totp_code: Option<String>,
+    /// plain_password - the password as entered, required instead of `password` if the server
+    /// checks passwords by a directory (`plain_password` of `/fetch_login_options`)
+    #[serde(default)]
+    pub plain_password: Option<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct RegisterRequest {
+    pub mail: String,
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CreateDeviceRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+    /// claim_code - one-time code of a provisioned device, not needed by the user who claimed it
+    #[serde(default)]
+    pub claim_code: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct RemoveDeviceRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchDeviceRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ModifyDeviceRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+    /// mail - user mail address
+    pub name: String,
+    pub info: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchDeviceListRequest {
+    pub login_token: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchDeviceProfileRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchMessageListRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    pub first_index: usize,
+    pub limit: usize,
+}
+
+impl Default for FetchMessageListRequest {
+    fn default() -> Self {
+        Self {
+            login_token: String::default(),
+            id: String::default(),
+            start_timestamp: 0,
+            end_timestamp: i64::MAX,
+            first_index: 0,
+            limit: 20,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImportMessagesRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+    /// format - "csv" or "ndjson"
+    pub format: String,
+    /// content - whole content of the file to be imported
+    pub content: String,
+    /// dry_run - only validate the file, nothing will be inserted
+    pub dry_run: bool,
+}
+
+impl Default for ImportMessagesRequest {
+    fn default() -> Self {
+        Self {
+            login_token: String::default(),
+            id: String::default(),
+            format: "csv".to_string(),
+            content: String::default(),
+            dry_run: false,
+        }
+    }
+}
+
+/// Body of `POST /api/v2/devices`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NewDeviceRequest {
+    /// id - device id
+    pub id: String,
+    /// claim_code - one-time code of a provisioned device, not needed by the user who claimed it
+    #[serde(default)]
+    pub claim_code: String,
+}
+
+/// Body of `PATCH /api/v2/devices/{id}`, fields left `None` are not changed
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UpdateDeviceRequest {
+    pub name: Option<String>,
+    pub info: Option<String>,
+}
+
+/// Query of `GET /api/v2/devices/{id}/messages`, timestamps are in milliseconds and inclusive
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct MessageQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub skip: Option<usize>,
+    /// limit - 20 if not given, 0 for no limit
+    pub limit: Option<usize>,
+}
+
+/// Body of `POST /api/v2/devices/{id}/messages/import`
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImportFileRequest {
+    /// format - "csv" or "ndjson"
+    pub format: String,
+    /// content - whole content of the file to be imported
+    pub content: String,
+    /// dry_run - only validate the file, nothing will be inserted
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl Default for ImportFileRequest {
+    fn default() -> Self {
+        Self {
+            format: "csv".to_string(),
+            content: String::default(),
+            dry_run: false,
+        }
+    }
+}
+
+/// What an API key is allowed to do, every scope includes the ones before it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    /// read_only - read devices and messages
+    #[default]
+    ReadOnly,
+    /// device_write - also follow, modify and unfollow devices and import messages
+    DeviceWrite,
+    /// admin - also manage API keys
+    Admin,
+}
+
+impl ApiKeyScope {
+    pub const ALL: &'static [ApiKeyScope] = &[
+        ApiKeyScope::ReadOnly,
+        ApiKeyScope::DeviceWrite,
+        ApiKeyScope::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::ReadOnly => "read_only",
+            ApiKeyScope::DeviceWrite => "device_write",
+            ApiKeyScope::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for ApiKeyScope {
+    type Err = crate::error::ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|scope| scope.as_str() == s)
+            .copied()
+            .ok_or(crate::error::ApiError::InvalidRequest)
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchApiKeyListRequest {
+    pub login_token: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CreateApiKeyRequest {
+    pub login_token: String,
+    pub name: String,
+    pub scope: ApiKeyScope,
+    /// expires_at - milliseconds since epoch, the key never expires if not given
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct RevokeApiKeyRequest {
+    pub login_token: String,
+    /// id - API key id
+    pub id: String,
+}
+
+/// Body of `POST /api/v2/api_keys`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NewApiKeyRequest {
+    pub name: String,
+    pub scope: ApiKeyScope,
+    /// expires_at - milliseconds since epoch, the key never expires if not given
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchTotpStatusRequest {
+    pub login_token: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct EnrollTotpRequest {
+    pub login_token: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ConfirmTotpRequest {
+    pub login_token: String,
+    /// code - code of the authenticator app
+    pub code: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DisableTotpRequest {
+    pub login_token: String,
+    /// code - code of the authenticator app or a recovery code
+    pub code: String,
+}
+
+/// Also the body of `POST /api/v2/verify_mail`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VerifyMailRequest {
+    /// token - the token in the link of the verification mail
+    pub token: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ResendVerificationRequest {
+    pub login_token: String,
+}
+
+/// Body of `POST /api/v2/totp/confirm` and `POST /api/v2/totp/disable`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+/// Role of a user
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    User,
+    /// admin - can also manage users and see every device
+    Admin,
+}
+
+impl Role {
+    pub const ALL: &'static [Role] = &[Role::User, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = crate::error::ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|role| role.as_str() == s)
+            .copied()
+            .ok_or(crate::error::ApiError::InvalidRequest)
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchUserListRequest {
+    pub login_token: String,
+    /// query - only users whose mail or name contains it, ignoring case
+    #[serde(default)]
+    pub query: String,
+    pub first_index: usize,
+    pub limit: usize,
+}
+
+/// Query of `GET /api/v2/admin/users`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UserQuery {
+    pub query: Option<String>,
+    pub skip: Option<usize>,
+    /// limit - 20 if not given, 0 for no limit
+    pub limit: Option<usize>,
+}
+
+/// Query of `GET /api/v2/admin/devices`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PageQuery {
+    pub skip: Option<usize>,
+    /// limit - 20 if not given, 0 for no limit
+    pub limit: Option<usize>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UpdateUserRequest {
+    pub login_token: String,
+    /// mail - the user to update
+    pub mail: String,
+    pub role: Option<Role>,
+    /// disabled - disabled users can't log in, their sessions and API keys stop working
+    pub disabled: Option<bool>,
+}
+
+/// Body of `PATCH /api/v2/admin/users/{mail}`, fields not given are left unchanged
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UserPatch {
+    pub role: Option<Role>,
+    pub disabled: Option<bool>,
+}
+
+/// Body of the admin routes acting on a user: `/admin/remove_user` and `/admin/impersonate`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AdminUserRequest {
+    pub login_token: String,
+    pub mail: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchAllDevicesRequest {
+    pub login_token: String,
+    pub first_index: usize,
+    pub limit: usize,
+}
+
+/// What an entry of the audit log records
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Register,
+    /// create_device - a device is followed
+    CreateDevice,
+    ModifyDevice,
+    /// remove_device - a device is unfollowed
+    RemoveDevice,
+    UpdateUser,
+    DeleteUser,
+    Impersonate,
+    /// request_deletion - the user asked for the account to be deleted after the grace period
+    RequestDeletion,
+    CancelDeletion,
+    /// delete_account - the account is deleted at the end of the grace period
+    DeleteAccount,
+    CreateOrg,
+    /// add_org_member - the target user is made a member of an organization
+    AddOrgMember,
+    RemoveOrgMember,
+    /// provision_device - a device is registered with a new claim code
+    ProvisionDevice,
+    /// send_command - a command is sent to a device
+    SendCommand,
+    /// update_desired - the desired configuration of a device is changed
+    UpdateDesired,
+    /// reset_totp - two-factor authentication of the target user is disabled by an administrator
+    ResetTotp,
+}
+
+impl AuditAction {
+    pub const ALL: &'static [AuditAction] = &[
+        AuditAction::Login,
+        AuditAction::LoginFailed,
+        AuditAction::Register,
+        AuditAction::CreateDevice,
+        AuditAction::ModifyDevice,
+        AuditAction::RemoveDevice,
+        AuditAction::UpdateUser,
+        AuditAction::DeleteUser,
+        AuditAction::Impersonate,
+        AuditAction::RequestDeletion,
+        AuditAction::CancelDeletion,
+        AuditAction::DeleteAccount,
+        AuditAction::CreateOrg,
+        AuditAction::AddOrgMember,
+        AuditAction::RemoveOrgMember,
+        AuditAction::ProvisionDevice,
+        AuditAction::SendCommand,
+        AuditAction::UpdateDesired,
+        AuditAction::ResetTotp,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::Register => "register",
+            AuditAction::CreateDevice => "create_device",
+            AuditAction::ModifyDevice => "modify_device",
+            AuditAction::RemoveDevice => "remove_device",
+            AuditAction::UpdateUser => "update_user",
+            AuditAction::DeleteUser => "delete_user",
+            AuditAction::Impersonate => "impersonate",
+            AuditAction::RequestDeletion => "request_deletion",
+            AuditAction::CancelDeletion => "cancel_deletion",
+            AuditAction::DeleteAccount => "delete_account",
+            AuditAction::CreateOrg => "create_org",
+            AuditAction::AddOrgMember => "add_org_member",
+            AuditAction::RemoveOrgMember => "remove_org_member",
+            AuditAction::ProvisionDevice => "provision_device",
+            AuditAction::SendCommand => "send_command",
+            AuditAction::UpdateDesired => "update_desired",
+            AuditAction::ResetTotp => "reset_totp",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditAction {
+    type Err = crate::error::ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|action| action.as_str() == s)
+            .copied()
+            .ok_or(crate::error::ApiError::InvalidRequest)
+    }
+}
+
+/// Filters of the audit log, empty strings and `None` match everything
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchAuditLogRequest {
+    pub login_token: String,
+    /// actor - mail of the user who did it
+    #[serde(default)]
+    pub actor: String,
+    pub action: Option<AuditAction>,
+    /// target - mail of a user or id of a device
+    #[serde(default)]
+    pub target: String,
+    /// from - milliseconds since epoch, so is `to`, both inclusive
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub first_index: usize,
+    pub limit: usize,
+}
+
+/// Query of `GET /api/v2/admin/audit`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub skip: Option<usize>,
+    /// limit - 20 if not given, 0 for no limit
+    pub limit: Option<usize>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchProfileRequest {
+    pub login_token: String,
+}
+
+/// Deleting the account needs the password again, so that a stolen session isn't enough.
+/// Single sign-on accounts may leave it empty if they logged in within the last minutes.
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DeleteAccountRequest {
+    pub login_token: String,
+    #[serde(default)]
+    pub password: String,
+    /// plain_password - the password as entered, required instead of `password` if the server
+    /// checks passwords by a directory
+    #[serde(default)]
+    pub plain_password: Option<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CancelAccountDeletionRequest {
+    pub login_token: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ExportAccountRequest {
+    pub login_token: String,
+}
+
+/// Body of `POST /api/v2/account/deletion`, the password is checked as in `DeleteAccountRequest`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PasswordRequest {
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub plain_password: Option<String>,
+}
+
+/// Languages of the frontend, users can prefer one of them
+pub const LANGUAGES: &[&str] = &["zh-CN", "en-US"];
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UpdateProfileRequest {
+    pub login_token: String,
+    /// name - the new user name, which must not be taken by another user
+    pub name: Option<String>,
+    /// language - one of `LANGUAGES`, empty to clear the preference
+    pub language: Option<String>,
+    /// timezone - IANA name such as "Asia/Shanghai", empty for UTC
+    pub timezone: Option<String>,
+}
+
+/// Body of `PATCH /api/v2/account`, fields left `None` are not changed
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProfilePatch {
+    pub name: Option<String>,
+    pub language: Option<String>,
+    pub timezone: Option<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ChangeMailRequest {
+    pub login_token: String,
+    /// mail - the new mail address, a verification link is sent to it
+    pub mail: String,
+    /// password - checked as in `DeleteAccountRequest`
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub plain_password: Option<String>,
+}
+
+/// Body of `POST /api/v2/account/mail`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NewMailRequest {
+    pub mail: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub plain_password: Option<String>,
+}
+
+/// Query of `GET /sso_callback`, where the OpenID Connect provider redirects the browser to
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SsoCallbackQuery {
+    pub state: String,
+    /// code - authorization code, `None` if the provider refused the login
+    pub code: Option<String>,
+    /// error - error code of the provider, e.g. "access_denied"
+    pub error: Option<String>,
+}
+
+/// Also the body of `POST /api/v2/sso/sessions`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SsoLoginRequest {
+    /// state - the state of the login, given back by the provider
+    pub state: String,
+    /// code - the authorization code given by the provider
+    pub code: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchOrgListRequest {
+    pub login_token: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SwitchOrgRequest {
+    pub login_token: String,
+    /// org - id of the organization to work in, the user has to be a member of it
+    pub org: String,
+}
+
+/// Body of `PUT /api/v2/org`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct OrgSwitch {
+    pub org: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CreateOrgRequest {
+    pub login_token: String,
+    /// id - lowercase letters, digits and dashes, used in MQTT topics
+    pub id: String,
+    pub name: String,
+}
+
+/// Body of `POST /api/v2/admin/orgs`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NewOrgRequest {
+    pub id: String,
+    pub name: String,
+}
+
+/// Body of `/admin/add_org_member` and `/admin/remove_org_member`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct OrgMemberRequest {
+    pub login_token: String,
+    pub org: String,
+    pub mail: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProvisionDeviceRequest {
+    pub login_token: String,
+    /// id - device id, a device not claimed yet gets a new claim code
+    pub id: String,
+    /// name - name of a new device, the id if empty
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Body of `POST /api/v2/admin/devices`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProvisionRequest {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Where a command sent to a device is: `pending` until it is published to the device, `sent`
+/// until the device replies, then `acked` or `failed` by the reply, or `timeout` if no reply comes
+/// in time. Publishing may also fail, which leaves it `failed`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    #[default]
+    Pending,
+    Sent,
+    Acked,
+    Failed,
+    Timeout,
+}
+
+impl CommandStatus {
+    pub const ALL: &'static [CommandStatus] = &[
+        CommandStatus::Pending,
+        CommandStatus::Sent,
+        CommandStatus::Acked,
+        CommandStatus::Failed,
+        CommandStatus::Timeout,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandStatus::Pending => "pending",
+            CommandStatus::Sent => "sent",
+            CommandStatus::Acked => "acked",
+            CommandStatus::Failed => "failed",
+            CommandStatus::Timeout => "timeout",
+        }
+    }
+
+    /// The status doesn't change any more
+    pub fn is_final(&self) -> bool {
+        !matches!(self, CommandStatus::Pending | CommandStatus::Sent)
+    }
+}
+
+impl std::str::FromStr for CommandStatus {
+    type Err = crate::error::ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|status| status.as_str() == s)
+            .copied()
+            .ok_or(crate::error::ApiError::InvalidRequest)
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SendCommandRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+    /// name - what the device is asked to do, e.g. "reboot" or "set_interval"
+    pub name: String,
+    /// params - passed on to the device as they are, usually a JSON document
+    #[serde(default)]
+    pub params: String,
+}
+
+/// Body of `POST /api/v2/devices/{id}/commands`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NewCommandRequest {
+    pub name: String,
+    #[serde(default)]
+    pub params: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchCommandListRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+    pub first_index: usize,
+    pub limit: usize,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FetchDeviceTwinRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UpdateDeviceTwinRequest {
+    pub login_token: String,
+    /// id - device id
+    pub id: String,
+    /// desired - JSON object of the configuration the device should have
+    pub desired: String,
+    /// version - `desired_version` of the twin the change is made to, it is rejected with
+    /// `version_conflict` if someone else has changed it since
+    pub version: i64,
+}
+
+/// Body of `PUT /api/v2/devices/{id}/twin/desired`
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DesiredRequest {
+    pub desired: String,
+    pub version: i64,
+}
@@ -1,54 +1,93 @@
+use crate::{
+    error::ApiError,
+    request::{ApiKeyScope, AuditAction, CommandStatus, Role},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
 pub struct SimpleResponse {
     pub success: bool,
     pub err: String,
+    pub code: Option<ApiError>,
+    /// retry_after - seconds to wait before retrying, set with `TooManyAttempts`
+    pub retry_after: Option<u64>,
 }
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
 pub struct LoginResponse {
     pub success: bool,
     pub err: String,
+    pub code: Option<ApiError>,
+    /// retry_after - seconds to wait before retrying, set with `TooManyAttempts`
+    pub retry_after: Option<u64>,
     pub login_token: String,
     pub mail: String,
     pub name: String,
+    /// expires_at - milliseconds since epoch, when the session expires if no request is made.
+    /// Refresh the session before it to get a new login token.
+    pub expires_at: i64,
+    pub role: Role,
+    /// language - preferred language of the user, empty if there is none
+    pub language: String,
+    /// timezone - preferred timezone of the user as an IANA name, empty for UTC
+    pub timezone: String,
 }
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
 pub struct FetchDeviceResponse {
     pub success: bool,
     pub err: String,
+    pub code: Option<ApiError>,
     pub id: String,
     pub name: String,
     pub info: String,
 }
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct DeviceInfo {
+    /// org - the organization of the device, ids are unique within it
+    #[serde(default)]
+    pub org: String,
     pub id: String,
     pub name: String,
     pub message_count: u32,
     pub alert_message_count: u32,
+    /// owner - mail of the user who claimed the device, empty if it isn't claimed
+    #[serde(default)]
+    pub owner: String,
 }
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
 pub struct FetchDeviceListResponse {
     pub success: bool,
     pub err: String,
+    pub code: Option<ApiError>,
     pub devices: Vec<DeviceInfo>,
 }
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
 pub struct FetchDeviceProfileResponse {
     pub success: bool,
     pub err: String,
+    pub code: Option<ApiError>,
     pub name: String,
     pub message_count: u32,
     pub alert_message_count: u32,
 }
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct MessageInfo {
     pub id: String,
     pub info: String,
@@ -60,21 +99,157 @@ pub struct MessageInfo {
 }
 
 #[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
 pub struct FetchMessageListResponse {
     pub success: bool,
     pub err: String,
+    pub code: Option<ApiError>,
     pub count: u32,
     pub messages: Vec<MessageInfo>,
 }
 
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImportRowError {
+    /// row - 1-based line number in the imported file
+    pub row: usize,
+    pub err: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct ImportMessagesResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    pub imported: u32,
+    pub duplicated: u32,
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub scope: ApiKeyScope,
+    /// created_at - milliseconds since epoch, so are the other times
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    /// last_used_at - `None` if the key has never been used
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchApiKeyListResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    pub keys: Vec<ApiKeyInfo>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct CreateApiKeyResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// key - the secret, which is only returned here and can't be fetched again
+    pub key: String,
+    pub info: ApiKeyInfo,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchTotpStatusResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// enabled - two-factor authentication is enabled, i.e. enrolled and confirmed
+    pub enabled: bool,
+    pub recovery_codes_left: u32,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct EnrollTotpResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// secret - base32 encoded, for authenticator apps that can't scan QR codes
+    pub secret: String,
+    /// uri - `otpauth://` provisioning URI, shown as a QR code
+    pub uri: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct ConfirmTotpResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// recovery_codes - each can be used once instead of a code, only returned here
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UserInfo {
+    pub mail: String,
+    pub name: String,
+    pub role: Role,
+    /// verified - the mail address has been verified
+    pub verified: bool,
+    pub disabled: bool,
+    /// device_count - number of devices followed
+    pub device_count: u32,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchUserListResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// count - number of users matching the query
+    pub count: u32,
+    pub users: Vec<UserInfo>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchAllDevicesResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// count - number of devices of every user
+    pub count: u32,
+    pub devices: Vec<DeviceInfo>,
+}
+
+/// Every response can be read from the body of an error response, the fields which are not in
+/// it are left default
 pub trait ErrorResponse {
     fn err<S: ToString>(info: S) -> Self;
+
+    fn api_err(err: ApiError) -> Self;
 }
 
 macro_rules! error_response_impl {
     ( $( $type:ty ),+ $(,)? ) => {
         $(
             impl ErrorResponse for $type {
+                #[allow(clippy::needless_update)]
                 fn err<S: ToString>(info: S) -> Self {
                     Self {
                         success: false,
@@ -82,11 +257,231 @@ macro_rules! error_response_impl {
                         ..Default::default()
                     }
                 }
+
+                #[allow(clippy::needless_update)]
+                fn api_err(err: ApiError) -> Self {
+                    Self {
+                        success: false,
+                        err: err.message_id().to_string(),
+                        code: Some(err),
+                        ..Default::default()
+                    }
+                }
             }
         )*
     };
 }
 
+/// Profile of the user, also `profile.json` of the data export
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ProfileInfo {
+    pub mail: String,
+    pub name: String,
+    pub role: Role,
+    /// verified - the mail address has been verified
+    pub verified: bool,
+    /// deletion_due_at - milliseconds since epoch, when the account will be deleted unless the
+    /// deletion is cancelled, `None` if it isn't going to be
+    pub deletion_due_at: Option<i64>,
+    /// pending_mail - new mail address waiting for its verification link to be opened, the mail
+    /// changes to it then
+    pub pending_mail: Option<String>,
+    pub language: String,
+    pub timezone: String,
+    /// sso - the account logs in by single sign-on, so it confirms changes of the account by a
+    /// recent login instead of a password
+    #[serde(default)]
+    pub sso: bool,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchProfileResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    pub profile: ProfileInfo,
+}
+
+/// A field changed by an audited action
+#[derive(Clone, Default, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AuditChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AuditEntryInfo {
+    /// actor - mail of the user who did it
+    pub actor: String,
+    pub action: AuditAction,
+    /// target - mail of a user or id of a device
+    pub target: String,
+    pub changes: Vec<AuditChange>,
+    pub ip: String,
+    /// time - milliseconds since epoch
+    pub time: i64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchAuditLogResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// count - number of entries matching the filters
+    pub count: u32,
+    /// entries - the latest first
+    pub entries: Vec<AuditEntryInfo>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchSsoStatusResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// enabled - users can log in by single sign-on with an OpenID Connect provider
+    pub enabled: bool,
+    /// provider_name - name of the provider to show, empty if single sign-on isn't enabled
+    pub provider_name: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct SsoAuthorizeResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// url - authorization URL of the provider to open in the browser, which redirects back to
+    /// `/sso_callback`
+    pub url: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchLoginOptionsResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// plain_password - passwords are checked by a directory such as LDAP, so logins have to send
+    /// `plain_password` instead of the hashed `password`
+    pub plain_password: bool,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct OrgInfo {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchOrgListResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    /// org - the organization the user works in, devices and messages are those of it
+    pub org: String,
+    /// orgs - the organizations the user is a member of, or every organization for the admin
+    /// routes, ordered by id
+    pub orgs: Vec<OrgInfo>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct ProvisionDeviceResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    pub id: String,
+    /// claim_code - needed to follow the device once, which is only returned here and can't be
+    /// fetched again
+    pub claim_code: String,
+}
+
+/// A command sent to a device
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CommandInfo {
+    pub id: String,
+    /// device_id - the device the command is sent to
+    pub device_id: String,
+    pub name: String,
+    pub params: String,
+    pub status: CommandStatus,
+    /// issued_by - mail of the user who sent it
+    pub issued_by: String,
+    /// created_at - milliseconds since epoch, so is `updated_at`
+    pub created_at: i64,
+    /// updated_at - when the status changed the last time
+    pub updated_at: i64,
+    /// result - what the device replied, or why publishing failed
+    pub result: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct SendCommandResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    pub command: CommandInfo,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchCommandListResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    pub count: u32,
+    /// commands - the latest first
+    pub commands: Vec<CommandInfo>,
+}
+
+/// Desired configuration and reported state of a device, both JSON objects
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DeviceTwin {
+    /// desired - the configuration the device should have, edited by users
+    pub desired: String,
+    /// desired_version - increased by every change of `desired`
+    pub desired_version: i64,
+    /// reported - the state the device reported in its messages
+    pub reported: String,
+    /// reported_version - increased by every report
+    pub reported_version: i64,
+    /// reported_at - milliseconds since epoch, 0 if the device hasn't reported anything
+    pub reported_at: i64,
+    /// delta - the part of `desired` that `reported` doesn't match yet
+    pub delta: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct FetchDeviceTwinResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: Option<ApiError>,
+    pub twin: DeviceTwin,
+}
+
 error_response_impl! {
     SimpleResponse,
     LoginResponse,
@@ -94,4 +489,22 @@ error_response_impl! {
     FetchDeviceProfileResponse,
     FetchDeviceListResponse,
     FetchMessageListResponse,
+    ImportMessagesResponse,
+    FetchApiKeyListResponse,
+    CreateApiKeyResponse,
+    FetchTotpStatusResponse,
+    EnrollTotpResponse,
+    ConfirmTotpResponse,
+    FetchUserListResponse,
+    FetchAllDevicesResponse,
+    FetchAuditLogResponse,
+    FetchProfileResponse,
+    FetchSsoStatusResponse,
+    SsoAuthorizeResponse,
+    FetchLoginOptionsResponse,
+    FetchOrgListResponse,
+    ProvisionDeviceResponse,
+    SendCommandResponse,
+    FetchCommandListResponse,
+    FetchDeviceTwinResponse,
 }
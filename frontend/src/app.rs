@@ -1,10 +1,26 @@
 use crate::fluent;
+use chrono_tz::Tz;
+use common::{
+    error::ApiError,
+    request::{FetchOrgListRequest, Role, SwitchOrgRequest},
+    response::{
+        ErrorResponse, FetchOrgListResponse, LoginResponse, OrgInfo, ProfileInfo, SimpleResponse,
+    },
+};
 use fluent_templates::{static_loader, LanguageIdentifier, Loader};
 use serde::{Deserialize, Serialize};
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 use yew::{
-    agent::Bridged, classes, format::Json, html, services::StorageService, Bridge, Component,
-    ComponentLink,
+    agent::Bridged,
+    classes,
+    format::Json,
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        timeout::TimeoutTask,
+        FetchService, StorageService, TimeoutService,
+    },
+    Bridge, Component, ComponentLink,
 };
 use yew_material::{
     list::{ListIndex, SelectedDetail},
@@ -15,9 +31,11 @@ use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
 
 use crate::{
     pages::{
-        default::DefaultComponent, device_content::DeviceContent, home::HomeComponent,
-        login::LoginComponent, logout_hint::LogoutHint, modify_device::ModifyDevice,
-        register::RegisterComponent,
+        admin::Admin, api_keys::ApiKeys, audit::Audit, default::DefaultComponent,
+        device_content::DeviceContent, home::HomeComponent, login::LoginComponent,
+        logout_hint::LogoutHint, modify_device::ModifyDevice, profile::Profile,
+        register::RegisterComponent, security::Security, sso_login::SsoLogin,
+        verify_mail::VerifyMail,
     },
     route::AppRoute,
 };
@@ -33,9 +51,12 @@ static_loader! {
 pub struct App {
     link: ComponentLink<Self>,
     lang_link: WeakComponentLink<MatMenu>,
+    org_link: WeakComponentLink<MatMenu>,
     state: State,
     storage: StorageService,
     route_agent: Box<dyn Bridge<RouteAgent>>,
+    fetch_task: Option<FetchTask>,
+    refresh_task: Option<TimeoutTask>,
 }
 
 #[derive(Default)]
@@ -43,34 +64,61 @@ struct State {
     login_token: String,
     mail: String,
     name: String,
+    /// expires_at - milliseconds since epoch, when the session expires without activity
+    expires_at: i64,
+    role: Role,
+    /// timezone - preferred timezone of the user as an IANA name, empty for UTC
+    timezone: String,
     is_logged_in: bool,
     device_id: String,
     device_name: String,
     device_info: String,
     lang_id: LanguageIdentifier,
+    /// org - id of the organization the user works in
+    org: String,
+    orgs: Vec<OrgInfo>,
 }
 
 pub enum Msg {
     Nop,
-    Login((String, String, String)),
+    Login(LoginResponse),
     Logout,
-    Register,
+    UpdateProfile(ProfileInfo),
+    Refresh,
+    RefreshResponse(LoginResponse),
     ShowLanguageList,
     SelectLanguage(i32),
     SelectDevice((String, String, String)),
+    FetchOrgs,
+    FetchOrgsResponse(FetchOrgListResponse),
+    ShowOrgList,
+    SelectOrg(i32),
+    SwitchOrgResponse(SimpleResponse),
 }
 
 const STORAGE_KEY: &str = "pepcy.device_viewer";
 const STORAGE_KEY_DEVICE: &str = "pepcy.device_viewer.device";
 const STORAGE_KEY_LANG: &str = "pepcy.device_viewer.lang";
 
-const LANG_LIST_ITEMS: [(&str, &str); 2] = [("简体中文", "zh-CN"), ("English", "en-US")];
+pub const LANG_LIST_ITEMS: [(&str, &str); 2] = [("简体中文", "zh-CN"), ("English", "en-US")];
+
+/// The session is refreshed this long before it expires, or earlier for short sessions
+const REFRESH_MARGIN_MS: i64 = 60 * 1000;
+/// Timeouts longer than about 24 days overflow in browsers
+const MAX_REFRESH_DELAY_MS: i64 = 24 * 3600 * 1000;
+const REFRESH_RETRY_MS: i64 = 60 * 1000;
 
 #[derive(Deserialize, Serialize)]
 struct StoredData {
     login_token: String,
     mail: String,
     name: String,
+    #[serde(default)]
+    expires_at: i64,
+    #[serde(default)]
+    role: Role,
+    #[serde(default)]
+    timezone: String,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -93,11 +141,17 @@ impl Component for App {
             login_token,
             mail,
             name,
+            expires_at,
+            role,
+            timezone,
         })) = storage.restore(STORAGE_KEY)
         {
             state.login_token = login_token;
             state.mail = mail;
             state.name = name;
+            state.expires_at = expires_at;
+            state.role = role;
+            state.timezone = timezone;
         }
         if let Json(Ok(StoredDeviceData {
             device_id,
@@ -118,42 +172,87 @@ impl Component for App {
         state.lang_id = lang_id;
 
         let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
-        Self {
+        let mut app = Self {
             link,
             lang_link: WeakComponentLink::default(),
+            org_link: WeakComponentLink::default(),
             state,
             storage,
             route_agent,
+            fetch_task: None,
+            refresh_task: None,
+        };
+        app.schedule_refresh();
+        if !app.state.login_token.is_empty() {
+            app.link.send_message(Msg::FetchOrgs);
         }
+        app
     }
 
     fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
         match msg {
             Msg::Nop => false,
-            Msg::Login((login_token, mail, name)) => {
-                let data = StoredData {
-                    login_token: login_token.clone(),
-                    mail: mail.clone(),
-                    name: name.clone(),
-                };
-                self.storage.store(STORAGE_KEY, Json(&data));
-
+            Msg::Login(response) => {
+                self.apply_preferences(&response.language, &response.timezone);
+                self.store_login(
+                    response.login_token,
+                    response.mail,
+                    response.name,
+                    response.expires_at,
+                    response.role,
+                );
                 self.state.is_logged_in = true;
-                self.state.login_token = login_token;
-                self.state.mail = mail;
-                self.state.name = name;
                 self.route_agent.send(ChangeRoute(AppRoute::Home.into()));
+                self.link.send_message(Msg::FetchOrgs);
                 true
             }
+            Msg::Refresh => {
+                let request = self.state.login_token.clone();
+                crate::create_fetch_task!(
+                    self,
+                    "/refresh_login",
+                    request,
+                    LoginResponse,
+                    RefreshResponse
+                );
+                false
+            }
+            Msg::RefreshResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.store_login(
+                        response.login_token,
+                        response.mail,
+                        response.name,
+                        response.expires_at,
+                        response.role,
+                    );
+                    true
+                } else {
+                    // an expired session is found by the page on its next request
+                    if response.code != Some(ApiError::LoginExpired) {
+                        self.refresh_after(REFRESH_RETRY_MS);
+                    }
+                    false
+                }
+            }
             Msg::Logout => {
+                self.refresh_task = None;
                 self.state.is_logged_in = false;
                 self.state.mail = "".to_string();
                 self.state.name = "".to_string();
+                self.state.role = Role::User;
+                self.state.timezone = "".to_string();
+                self.state.org = "".to_string();
+                self.state.orgs = vec![];
                 self.route_agent.send(ChangeRoute(AppRoute::Login.into()));
                 true
             }
-            Msg::Register => {
-                self.route_agent.send(ChangeRoute(AppRoute::Login.into()));
+            Msg::UpdateProfile(profile) => {
+                self.apply_preferences(&profile.language, &profile.timezone);
+                self.state.mail = profile.mail;
+                self.state.name = profile.name;
+                self.save_login();
                 true
             }
             Msg::ShowLanguageList => {
@@ -185,6 +284,57 @@ impl Component for App {
 
                 true
             }
+            Msg::FetchOrgs => {
+                let request = FetchOrgListRequest {
+                    login_token: self.state.login_token.clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/fetch_org_list",
+                    request,
+                    FetchOrgListResponse,
+                    FetchOrgsResponse
+                );
+                false
+            }
+            Msg::FetchOrgsResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.org = response.org;
+                    self.state.orgs = response.orgs;
+                    true
+                } else {
+                    false
+                }
+            }
+            Msg::ShowOrgList => {
+                self.org_link.show();
+                true
+            }
+            Msg::SelectOrg(index) => match self.state.orgs.get(index.max(0) as usize) {
+                Some(org) if index >= 0 && org.id != self.state.org => {
+                    let request = SwitchOrgRequest {
+                        login_token: self.state.login_token.clone(),
+                        org: org.id.clone(),
+                    };
+                    crate::create_fetch_task!(self, "/switch_org", request, SwitchOrgResponse);
+                    false
+                }
+                _ => false,
+            },
+            Msg::SwitchOrgResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    // devices and messages of every page belong to the previous organization,
+                    // so the selected device is forgotten and the pages are loaded again
+                    self.storage.remove(STORAGE_KEY_DEVICE);
+                    let location = yew::utils::window().location();
+                    if location.set_href("/").is_err() {
+                        let _ = location.reload();
+                    }
+                }
+                false
+            }
         }
     }
 
@@ -195,9 +345,11 @@ impl Component for App {
     fn view(&self) -> yew::Html {
         let login_callback = self
             .link
-            .callback(|data: (String, String, String)| Msg::Login(data));
-        let register_callback = self.link.callback(|_| Msg::Register);
+            .callback(|response: LoginResponse| Msg::Login(response));
         let logout_callback = self.link.callback(|_| Msg::Logout);
+        let update_profile_callback = self
+            .link
+            .callback(|profile: ProfileInfo| Msg::UpdateProfile(profile));
         let select_device_callback = self
             .link
             .callback(|data: (String, String, String)| Msg::SelectDevice(data));
@@ -212,9 +364,27 @@ impl Component for App {
         });
 
         let login_token = Rc::new(self.state.login_token.clone());
+        let org_click = self.link.callback(|_| Msg::ShowOrgList);
+        let org_select = self.link.callback(|e: SelectedDetail| {
+            if let ListIndex::Single(Some(ind)) = e.index {
+                Msg::SelectOrg(ind as i32)
+            } else {
+                Msg::SelectOrg(-1)
+            }
+        });
+        let org_name = self
+            .state
+            .orgs
+            .iter()
+            .find(|org| org.id == self.state.org)
+            .map(|org| org.name.clone())
+            .unwrap_or_default();
+
         let lang_id = self.state.lang_id.clone();
         let mail = Rc::new(self.state.mail.clone());
         let name = Rc::new(self.state.name.clone());
+        let is_admin = self.state.role == Role::Admin;
+        let timezone: Tz = self.state.timezone.parse().unwrap_or(Tz::UTC);
         let device_id = Rc::new(self.state.device_id.clone());
         let device_name = Rc::new(self.state.device_name.clone());
         let device_info = Rc::new(self.state.device_info.clone());
@@ -228,7 +398,46 @@ impl Component for App {
                         </div>
                     </MatTopAppBarTitle>
                     <MatTopAppBarActionItems>
-                        <div style="position:relative;">
+                        {
+                            // only users in several organizations have something to switch
+                            if self.state.orgs.len() > 1 {
+                                html! {
+                                    <div style="position:relative;display:inline-block;">
+                                        <span
+                                            class="org-switch"
+                                            title=fluent!(self.state.lang_id, "switch-org")
+                                            onclick=org_click >
+                                            <span class="org-name">{ org_name }</span>
+                                            <MatIconButton
+                                                classes=classes!("translate-button")
+                                                icon="business" />
+                                        </span>
+                                        <MatMenu
+                                            quick=true
+                                            menu_link=self.org_link.clone()
+                                            onselected=org_select
+                                            absolute=true
+                                            x=-20
+                                            y=20 >
+                                            {
+                                                for self.state.orgs
+                                                    .iter()
+                                                    .map(|org| {
+                                                        html! {
+                                                            <MatListItem>
+                                                                { org.name.clone() }
+                                                            </MatListItem>
+                                                        }
+                                                    })
+                                            }
+                                        </MatMenu>
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <div style="position:relative;display:inline-block;">
                             <span onclick=lang_click>
                                 <MatIconButton
                                     classes=classes!("translate-button")
@@ -265,9 +474,7 @@ impl Component for App {
                                 onlogin=login_callback.clone() />
                         },
                         AppRoute::Register => html! {
-                            <RegisterComponent
-                                lang_id=lang_id.clone()
-                                onregister=register_callback.clone() />
+                            <RegisterComponent lang_id=lang_id.clone() />
                         },
                         AppRoute::Home => html! {
                             <HomeComponent
@@ -275,6 +482,7 @@ impl Component for App {
                                 login_token=login_token.clone()
                                 mail=mail.clone()
                                 name=name.clone()
+                                is_admin=is_admin
                                 onlogout=logout_callback.clone()
                                 onselect=select_device_callback.clone() />
                         },
@@ -294,7 +502,54 @@ impl Component for App {
                                 mail=mail.clone()
                                 id=device_id.clone()
                                 name=device_name.clone()
-                                info=device_info.clone() />
+                                info=device_info.clone()
+                                timezone=timezone />
+                        },
+                        AppRoute::ApiKeys => html! {
+                            <ApiKeys
+                                lang_id=lang_id.clone()
+                                login_token=login_token.clone() />
+                        },
+                        AppRoute::Security => html! {
+                            <Security
+                                lang_id=lang_id.clone()
+                                login_token=login_token.clone() />
+                        },
+                        AppRoute::Profile => html! {
+                            <Profile
+                                lang_id=lang_id.clone()
+                                login_token=login_token.clone()
+                                onlogout=logout_callback.clone()
+                                onupdate=update_profile_callback.clone() />
+                        },
+                        AppRoute::Admin => html! {
+                            <Admin
+                                lang_id=lang_id.clone()
+                                login_token=login_token.clone()
+                                mail=mail.clone()
+                                onlogin=login_callback.clone() />
+                        },
+                        AppRoute::Audit => html! {
+                            <Audit
+                                lang_id=lang_id.clone()
+                                login_token=login_token.clone() />
+                        },
+                        AppRoute::VerifyMail(token) => html! {
+                            <VerifyMail lang_id=lang_id.clone() token=token />
+                        },
+                        AppRoute::SsoLogin(state, code) => html! {
+                            <SsoLogin
+                                lang_id=lang_id.clone()
+                                state=state
+                                code=code
+                                onlogin=login_callback.clone() />
+                        },
+                        AppRoute::SsoFailed => html! {
+                            <SsoLogin
+                                lang_id=lang_id.clone()
+                                state=String::new()
+                                code=String::new()
+                                onlogin=login_callback.clone() />
                         },
                         AppRoute::LogoutHint => html! {
                             <LogoutHint
@@ -307,3 +562,64 @@ impl Component for App {
         }
     }
 }
+
+impl App {
+    fn store_login(
+        &mut self,
+        login_token: String,
+        mail: String,
+        name: String,
+        expires_at: i64,
+        role: Role,
+    ) {
+        self.state.login_token = login_token;
+        self.state.mail = mail;
+        self.state.name = name;
+        self.state.expires_at = expires_at;
+        self.state.role = role;
+        self.save_login();
+        self.schedule_refresh();
+    }
+
+    fn save_login(&mut self) {
+        let data = StoredData {
+            login_token: self.state.login_token.clone(),
+            mail: self.state.mail.clone(),
+            name: self.state.name.clone(),
+            expires_at: self.state.expires_at,
+            role: self.state.role,
+            timezone: self.state.timezone.clone(),
+        };
+        self.storage.store(STORAGE_KEY, Json(&data));
+    }
+
+    /// Apply the preferences kept by the server, so that they follow the user across browsers.
+    /// Without a preferred language the one chosen in this browser is kept.
+    fn apply_preferences(&mut self, language: &str, timezone: &str) {
+        if LANG_LIST_ITEMS.iter().any(|item| item.1 == language) {
+            self.state.lang_id = language.parse().unwrap();
+            self.storage
+                .store(STORAGE_KEY_LANG, Json(&language.to_string()));
+        }
+        self.state.timezone = timezone.to_string();
+    }
+
+    /// Refresh the login token silently before the session expires
+    fn schedule_refresh(&mut self) {
+        if self.state.login_token.is_empty() {
+            self.refresh_task = None;
+            return;
+        }
+        let remaining = self.state.expires_at - js_sys::Date::now() as i64;
+        self.refresh_after(remaining - REFRESH_MARGIN_MS.min(remaining / 5));
+    }
+
+    fn refresh_after(&mut self, delay_ms: i64) {
+        let delay_ms = delay_ms.clamp(0, MAX_REFRESH_DELAY_MS);
+        let task = TimeoutService::spawn(
+            Duration::from_millis(delay_ms as u64),
+            self.link.callback(|_| Msg::Refresh),
+        );
+        self.refresh_task = Some(task);
+    }
+}
@@ -0,0 +1,686 @@
+use crate::{
+    fluent,
+    route::AppRoute,
+    utils::{card_div::CardDiv, paged_list::PagedList, qr_code::QrCodeView},
+};
+use common::{
+    error::ApiError,
+    request::{
+        AdminUserRequest, FetchAllDevicesRequest, FetchUserListRequest, ProvisionDeviceRequest,
+        Role, UpdateUserRequest,
+    },
+    response::{
+        DeviceInfo, ErrorResponse, FetchAllDevicesResponse, FetchUserListResponse, LoginResponse,
+        ProvisionDeviceResponse, SimpleResponse, UserInfo,
+    },
+};
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use std::{borrow::Cow, rc::Rc};
+use yew::{
+    agent::Bridged,
+    classes,
+    format::Json,
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        FetchService,
+    },
+    Bridge, Callback, Component, ComponentLink, InputData, Properties,
+};
+use yew_material::{MatButton, MatLinearProgress, MatTextField};
+use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
+
+static_loader! {
+    static LOCALES = {
+        locales: "./text/admin",
+        fallback_language: "zh-CN",
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+const PAGE_SIZE: usize = 10;
+
+/// Administration page: searching, disabling, deleting and impersonating users, resetting their
+/// two-factor authentication, every device and provisioning new ones
+pub struct Admin {
+    link: ComponentLink<Self>,
+    props: Props,
+    state: State,
+    route_agent: Box<dyn Bridge<RouteAgent>>,
+    fetch_task: Option<FetchTask>,
+}
+
+#[derive(Default)]
+struct State {
+    query: String,
+    /// searched_query - the query of the users shown, kept while turning pages
+    searched_query: String,
+    users: Vec<UserInfo>,
+    /// totp_reset - mail of the user whose two-factor authentication is being reset or was reset
+    /// last, cleared if the reset fails
+    totp_reset: Option<String>,
+    user_count: u32,
+    user_first_index: usize,
+    devices: Vec<DeviceInfo>,
+    device_count: u32,
+    device_first_index: usize,
+    /// devices_fetched - devices are fetched after the first page of users, as only one request
+    /// can be in flight
+    devices_fetched: bool,
+    provision_id: String,
+    provision_name: String,
+    /// provisioned - id and claim code of the device provisioned last, the code is shown only once
+    provisioned: Option<(String, String)>,
+    err: Option<String>,
+}
+
+pub enum Msg {
+    Nop,
+    ToLogin,
+    EditQuery(String),
+    Search,
+    FetchUsers,
+    FetchUsersResponse(FetchUserListResponse),
+    ChangeUserPage(usize),
+    UpdateUser(usize, Option<Role>, Option<bool>),
+    DeleteUser(usize),
+    UpdateResponse(SimpleResponse),
+    Impersonate(usize),
+    ImpersonateResponse(LoginResponse),
+    ResetTotp(usize),
+    ResetTotpResponse(SimpleResponse),
+    FetchDevices,
+    FetchDevicesResponse(FetchAllDevicesResponse),
+    ChangeDevicePage(usize),
+    EditProvisionId(String),
+    EditProvisionName(String),
+    Provision,
+    ProvisionResponse(ProvisionDeviceResponse),
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub lang_id: LanguageIdentifier,
+    pub login_token: Rc<String>,
+    pub mail: Rc<String>,
+    /// onlogin - called with the session of an impersonated user
+    pub onlogin: Callback<LoginResponse>,
+}
+
+impl Component for Admin {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
+        let mut component = Self {
+            props,
+            link,
+            state: State::default(),
+            route_agent,
+            fetch_task: None,
+        };
+        if component.props.login_token.is_empty() {
+            component.update(Msg::ToLogin);
+        } else {
+            component.update(Msg::FetchUsers);
+        }
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
+        match msg {
+            Msg::Nop => false,
+            Msg::ToLogin => {
+                self.route_agent
+                    .send(ChangeRoute(AppRoute::LogoutHint.into()));
+                true
+            }
+            Msg::EditQuery(query) => {
+                self.state.query = query;
+                false
+            }
+            Msg::Search => {
+                self.state.searched_query = self.state.query.trim().to_string();
+                self.state.user_first_index = 0;
+                self.update(Msg::FetchUsers)
+            }
+            Msg::FetchUsers => {
+                let request = FetchUserListRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    query: self.state.searched_query.clone(),
+                    first_index: self.state.user_first_index,
+                    limit: PAGE_SIZE,
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/admin/fetch_user_list",
+                    request,
+                    FetchUserListResponse,
+                    FetchUsersResponse
+                );
+                true
+            }
+            Msg::FetchUsersResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.err = None;
+                    self.state.user_count = response.count;
+                    self.state.users = response.users;
+                    if !self.state.devices_fetched {
+                        self.update(Msg::FetchDevices);
+                    }
+                } else {
+                    self.handle_error(&response.err, response.code);
+                }
+                true
+            }
+            Msg::ChangeUserPage(page_index) => {
+                self.state.user_first_index = page_index * PAGE_SIZE;
+                self.update(Msg::FetchUsers)
+            }
+            Msg::UpdateUser(index, role, disabled) => {
+                let request = UpdateUserRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    mail: self.state.users[index].mail.clone(),
+                    role,
+                    disabled,
+                };
+                crate::create_fetch_task!(self, "/admin/modify_user", request, UpdateResponse);
+                true
+            }
+            Msg::DeleteUser(index) => {
+                let request = AdminUserRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    mail: self.state.users[index].mail.clone(),
+                };
+                crate::create_fetch_task!(self, "/admin/remove_user", request, UpdateResponse);
+                true
+            }
+            Msg::UpdateResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.update(Msg::FetchUsers);
+                } else {
+                    self.handle_error(&response.err, response.code);
+                }
+                true
+            }
+            Msg::Impersonate(index) => {
+                let request = AdminUserRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    mail: self.state.users[index].mail.clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/admin/impersonate",
+                    request,
+                    LoginResponse,
+                    ImpersonateResponse
+                );
+                true
+            }
+            Msg::ImpersonateResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.props.onlogin.emit(response);
+                } else {
+                    self.handle_error(&response.err, response.code);
+                }
+                true
+            }
+            Msg::ResetTotp(index) => {
+                let request = AdminUserRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    mail: self.state.users[index].mail.clone(),
+                };
+                self.state.totp_reset = Some(request.mail.clone());
+                crate::create_fetch_task!(self, "/admin/reset_totp", request, ResetTotpResponse);
+                true
+            }
+            Msg::ResetTotpResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.err = None;
+                } else {
+                    self.state.totp_reset = None;
+                    self.handle_error(&response.err, response.code);
+                }
+                true
+            }
+            Msg::FetchDevices => {
+                let request = FetchAllDevicesRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    first_index: self.state.device_first_index,
+                    limit: PAGE_SIZE,
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/admin/fetch_device_list",
+                    request,
+                    FetchAllDevicesResponse,
+                    FetchDevicesResponse
+                );
+                true
+            }
+            Msg::FetchDevicesResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.devices_fetched = true;
+                    self.state.device_count = response.count;
+                    self.state.devices = response.devices;
+                } else {
+                    self.handle_error(&response.err, response.code);
+                }
+                true
+            }
+            Msg::ChangeDevicePage(page_index) => {
+                self.state.device_first_index = page_index * PAGE_SIZE;
+                self.update(Msg::FetchDevices)
+            }
+            Msg::EditProvisionId(id) => {
+                self.state.provision_id = id;
+                false
+            }
+            Msg::EditProvisionName(name) => {
+                self.state.provision_name = name;
+                false
+            }
+            Msg::Provision => {
+                if self.state.provision_id.trim().is_empty() {
+                    false
+                } else {
+                    self.state.provisioned = None;
+                    let request = ProvisionDeviceRequest {
+                        login_token: (*self.props.login_token).clone(),
+                        id: self.state.provision_id.trim().to_string(),
+                        name: self.state.provision_name.trim().to_string(),
+                    };
+                    crate::create_fetch_task!(
+                        self,
+                        "/admin/provision_device",
+                        request,
+                        ProvisionDeviceResponse,
+                        ProvisionResponse
+                    );
+                    true
+                }
+            }
+            Msg::ProvisionResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.err = None;
+                    self.state.provision_id.clear();
+                    self.state.provision_name.clear();
+                    self.state.provisioned = Some((response.id, response.claim_code));
+                    self.update(Msg::FetchDevices);
+                } else {
+                    self.handle_error(&response.err, response.code);
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> yew::Html {
+        let query_oninput = self.link.callback(|e: InputData| Msg::EditQuery(e.value));
+        let search_click = self.link.callback(|_| Msg::Search);
+        let provision_id_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditProvisionId(e.value));
+        let provision_name_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditProvisionName(e.value));
+        let provision_click = self.link.callback(|_| Msg::Provision);
+
+        html! {
+            <div class="container">
+                <div class="header">
+                    <h2>{ fluent!(self.props.lang_id, "header") }</h2>
+                </div>
+                <div class="form">
+                    <div class="form-item">
+                        <MatTextField
+                            classes=classes!("form-input")
+                            outlined=true
+                            label=fluent!(self.props.lang_id, "query-label")
+                            helper=fluent!(self.props.lang_id, "query-hint")
+                            helper_persistent=true
+                            value=self.state.query.clone()
+                            oninput=query_oninput />
+                    </div>
+                    {
+                        if let Some(err) = &self.state.err {
+                            html! {
+                                <div class="error-info">
+                                    <p>{ fluent!(self.props.lang_id, "error-label",
+                                        { "details" => err.as_str() } ) }</p>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class="form-item">
+                        <span
+                            onclick=search_click
+                            class="form-row-item"
+                            disabled=self.need_to_disable() >
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-search")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </span>
+                        <RouterAnchor<AppRoute>
+                            route={ AppRoute::Audit }
+                            classes="form-row-item">
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-audit")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </RouterAnchor<AppRoute>>
+                        <RouterAnchor<AppRoute>
+                            route={ AppRoute::Home }
+                            classes="form-row-item">
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-home")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </RouterAnchor<AppRoute>>
+                    </div>
+                </div>
+                { self.fetching_progress() }
+                <h3>{ fluent!(self.props.lang_id, "users-title", {
+                    "count" => self.state.user_count,
+                }) }</h3>
+                { self.totp_reset_html() }
+                { self.users_html() }
+                <h3>{ fluent!(self.props.lang_id, "devices-title", {
+                    "count" => self.state.device_count,
+                }) }</h3>
+                <div class="form-item">
+                    <MatTextField
+                        classes=classes!("form-row-item")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "provision-id-label")
+                        value=self.state.provision_id.clone()
+                        oninput=provision_id_oninput />
+                    <MatTextField
+                        classes=classes!("form-row-item")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "provision-name-label")
+                        helper=fluent!(self.props.lang_id, "provision-name-hint")
+                        value=self.state.provision_name.clone()
+                        oninput=provision_name_oninput />
+                    <span
+                        onclick=provision_click
+                        class="form-row-item"
+                        disabled=self.need_to_disable() >
+                        <MatButton
+                            classes=classes!("form-button")
+                            label=fluent!(self.props.lang_id, "button-provision")
+                            disabled=self.need_to_disable()
+                            raised=true />
+                    </span>
+                </div>
+                { self.provisioned_html() }
+                { self.devices_html() }
+            </div>
+        }
+    }
+}
+
+impl Admin {
+    fn need_to_disable(&self) -> bool {
+        self.fetch_task.is_some()
+    }
+
+    fn handle_error(&mut self, err: &str, code: Option<ApiError>) {
+        if code == Some(ApiError::LoginExpired) {
+            self.update(Msg::ToLogin);
+        } else {
+            self.state.err = Some(fluent!(self.props.lang_id, err));
+        }
+    }
+
+    fn fetching_progress(&self) -> yew::Html {
+        if self.fetch_task.is_some() {
+            html! {
+                <div class="fetching-progress">
+                    <MatLinearProgress indeterminate=true />
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
+    fn users_html(&self) -> yew::Html {
+        if self.state.users.is_empty() {
+            return html! {
+                <p class="no-data">{ fluent!(self.props.lang_id, "no-users") }</p>
+            };
+        }
+        let on_page_changed = self
+            .link
+            .callback(|data: (usize, usize)| Msg::ChangeUserPage(data.0));
+
+        html! {
+            <PagedList
+                lang_id=self.props.lang_id.clone()
+                page_size=PAGE_SIZE
+                items_count=self.state.user_count as usize
+                disabled=self.need_to_disable()
+                on_page_changed=on_page_changed >
+                <div class="device-list">
+                    {
+                        for self
+                            .state
+                            .users
+                            .iter()
+                            .enumerate()
+                            .map(|(ind, user)| self.user_html(user, ind))
+                    }
+                </div>
+            </PagedList>
+        }
+    }
+
+    fn user_html(&self, user: &UserInfo, index: usize) -> yew::Html {
+        let mut status = vec![fluent!(self.props.lang_id, role_message_id(user.role))];
+        if !user.verified {
+            status.push(fluent!(self.props.lang_id, "user-unverified"));
+        }
+        if user.disabled {
+            status.push(fluent!(self.props.lang_id, "user-disabled"));
+        }
+
+        html! {
+            <CardDiv classes=classes!("device-list-item")>
+                <p class="device-name">{ &user.name }</p>
+                <p class="device-id">{ &user.mail }</p>
+                <p>{ status.join(" · ") }</p>
+                <p class="device-stat">
+                    { fluent!(self.props.lang_id, "user-devices", {
+                        "count" => user.device_count,
+                    }) }
+                </p>
+                {
+                    // administrators can't change themselves
+                    if user.mail == *self.props.mail {
+                        html! {}
+                    } else {
+                        self.user_buttons(user, index)
+                    }
+                }
+            </CardDiv>
+        }
+    }
+
+    fn user_buttons(&self, user: &UserInfo, index: usize) -> yew::Html {
+        let disabled = !user.disabled;
+        let disable_click = self
+            .link
+            .callback(move |_| Msg::UpdateUser(index, None, Some(disabled)));
+        let role = match user.role {
+            Role::User => Role::Admin,
+            Role::Admin => Role::User,
+        };
+        let role_click = self
+            .link
+            .callback(move |_| Msg::UpdateUser(index, Some(role), None));
+        let impersonate_click = self.link.callback(move |_| Msg::Impersonate(index));
+        let reset_totp_click = self.link.callback(move |_| Msg::ResetTotp(index));
+        let delete_click = self.link.callback(move |_| Msg::DeleteUser(index));
+        let (disable_label, disable_icon) = if user.disabled {
+            ("button-enable", "lock_open")
+        } else {
+            ("button-disable", "block")
+        };
+        let role_label = match user.role {
+            Role::User => "button-make-admin",
+            Role::Admin => "button-make-user",
+        };
+
+        html! {
+            <div class="device-buttons">
+                <span onclick=disable_click disabled=self.need_to_disable()>
+                    <MatButton
+                        label=fluent!(self.props.lang_id, disable_label)
+                        icon=Cow::from(disable_icon)
+                        disabled=self.need_to_disable() />
+                </span>
+                <span onclick=role_click disabled=self.need_to_disable()>
+                    <MatButton
+                        label=fluent!(self.props.lang_id, role_label)
+                        icon=Cow::from("admin_panel_settings")
+                        disabled=self.need_to_disable() />
+                </span>
+                {
+                    if user.role == Role::User && !user.disabled {
+                        html! {
+                            <span onclick=impersonate_click disabled=self.need_to_disable()>
+                                <MatButton
+                                    label=fluent!(self.props.lang_id, "button-impersonate")
+                                    icon=Cow::from("support_agent")
+                                    disabled=self.need_to_disable() />
+                            </span>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    // other administrators are reset by the command line
+                    if user.role == Role::User {
+                        html! {
+                            <span onclick=reset_totp_click disabled=self.need_to_disable()>
+                                <MatButton
+                                    label=fluent!(self.props.lang_id, "button-reset-totp")
+                                    icon=Cow::from("phonelink_erase")
+                                    disabled=self.need_to_disable() />
+                            </span>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                <span onclick=delete_click disabled=self.need_to_disable()>
+                    <MatButton
+                        label=fluent!(self.props.lang_id, "button-delete")
+                        icon=Cow::from("delete")
+                        disabled=self.need_to_disable() />
+                </span>
+            </div>
+        }
+    }
+
+    fn totp_reset_html(&self) -> yew::Html {
+        match &self.state.totp_reset {
+            Some(mail) if !self.need_to_disable() => html! {
+                <CardDiv classes=classes!("hint-info")>
+                    <p>{ fluent!(self.props.lang_id, "totp-reset-hint", {
+                        "mail" => mail.as_str(),
+                    }) }</p>
+                </CardDiv>
+            },
+            _ => html! {},
+        }
+    }
+
+    fn provisioned_html(&self) -> yew::Html {
+        let (id, claim_code) = match &self.state.provisioned {
+            Some(provisioned) => provisioned,
+            None => return html! {},
+        };
+
+        html! {
+            <CardDiv classes=classes!("hint-info")>
+                <p>{ fluent!(self.props.lang_id, "provisioned-hint", { "id" => id.as_str() }) }</p>
+                <p><code>{ claim_code }</code></p>
+                <QrCodeView data=claim_code.clone() />
+            </CardDiv>
+        }
+    }
+
+    fn devices_html(&self) -> yew::Html {
+        if self.state.devices.is_empty() {
+            return html! {
+                <p class="no-data">{ fluent!(self.props.lang_id, "no-devices") }</p>
+            };
+        }
+        let on_page_changed = self
+            .link
+            .callback(|data: (usize, usize)| Msg::ChangeDevicePage(data.0));
+
+        html! {
+            <PagedList
+                lang_id=self.props.lang_id.clone()
+                page_size=PAGE_SIZE
+                items_count=self.state.device_count as usize
+                disabled=self.need_to_disable()
+                on_page_changed=on_page_changed >
+                <div class="device-list">
+                    {
+                        for self.state.devices.iter().map(|dev| html! {
+                            <CardDiv classes=classes!("device-list-item")>
+                                <p class="device-name">{ &dev.name }</p>
+                                <p class="device-id">{ &dev.id }</p>
+                                <p>{ self.owner_text(dev) }</p>
+                                <p class="device-stat">
+                                    { fluent!(self.props.lang_id, "device-stat", {
+                                        "total" => dev.message_count,
+                                        "alert" => dev.alert_message_count,
+                                    }) }
+                                </p>
+                            </CardDiv>
+                        })
+                    }
+                </div>
+            </PagedList>
+        }
+    }
+
+    fn owner_text(&self, dev: &DeviceInfo) -> String {
+        if dev.owner.is_empty() {
+            fluent!(self.props.lang_id, "device-unclaimed")
+        } else {
+            fluent!(self.props.lang_id, "device-owner", { "owner" => dev.owner.as_str() })
+        }
+    }
+}
+
+fn role_message_id(role: Role) -> &'static str {
+    match role {
+        Role::User => "role-user",
+        Role::Admin => "role-admin",
+    }
+}
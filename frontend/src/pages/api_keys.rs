@@ -0,0 +1,400 @@
+use crate::{fluent, route::AppRoute, utils::card_div::CardDiv};
+use chrono::{NaiveDate, TimeZone, Utc};
+use common::{
+    error::ApiError,
+    request::{ApiKeyScope, CreateApiKeyRequest, FetchApiKeyListRequest, RevokeApiKeyRequest},
+    response::{
+        ApiKeyInfo, CreateApiKeyResponse, ErrorResponse, FetchApiKeyListResponse, SimpleResponse,
+    },
+};
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use std::{borrow::Cow, rc::Rc};
+use yew::{
+    agent::Bridged,
+    classes,
+    format::Json,
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        FetchService,
+    },
+    Bridge, ChangeData, Component, ComponentLink, InputData, Properties,
+};
+use yew_material::{MatButton, MatLinearProgress, MatTextField};
+use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
+
+static_loader! {
+    static LOCALES = {
+        locales: "./text/api_keys",
+        fallback_language: "zh-CN",
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+pub struct ApiKeys {
+    link: ComponentLink<Self>,
+    props: Props,
+    state: State,
+    route_agent: Box<dyn Bridge<RouteAgent>>,
+    fetch_task: Option<FetchTask>,
+}
+
+#[derive(Default)]
+struct State {
+    name: String,
+    scope: ApiKeyScope,
+    /// expires_on - "YYYY-MM-DD" from the date input, empty for keys never expire
+    expires_on: String,
+    keys: Vec<ApiKeyInfo>,
+    /// new_key - the key just created, which is only shown once
+    new_key: Option<String>,
+    err: Option<String>,
+}
+
+pub enum Msg {
+    Nop,
+    ToLogin,
+    EditName(String),
+    SelectScope(ApiKeyScope),
+    EditExpiresOn(String),
+    Create,
+    CreateResponse(CreateApiKeyResponse),
+    Fetch,
+    FetchResponse(FetchApiKeyListResponse),
+    Revoke(usize),
+    RevokeResponse(SimpleResponse),
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub lang_id: LanguageIdentifier,
+    pub login_token: Rc<String>,
+}
+
+impl Component for ApiKeys {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
+        let mut component = Self {
+            props,
+            link,
+            state: State::default(),
+            route_agent,
+            fetch_task: None,
+        };
+        if component.props.login_token.is_empty() {
+            component.update(Msg::ToLogin);
+        } else {
+            component.update(Msg::Fetch);
+        }
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
+        match msg {
+            Msg::Nop => false,
+            Msg::ToLogin => {
+                self.route_agent
+                    .send(ChangeRoute(AppRoute::LogoutHint.into()));
+                true
+            }
+            Msg::EditName(name) => {
+                self.state.name = name;
+                false
+            }
+            Msg::SelectScope(scope) => {
+                self.state.scope = scope;
+                false
+            }
+            Msg::EditExpiresOn(expires_on) => {
+                self.state.expires_on = expires_on;
+                false
+            }
+            Msg::Create => {
+                self.state.new_key = None;
+                if self.state.name.trim().is_empty() {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-name"));
+                    return true;
+                }
+                let expires_at = if self.state.expires_on.is_empty() {
+                    None
+                } else if let Ok(date) =
+                    NaiveDate::parse_from_str(&self.state.expires_on, "%Y-%m-%d")
+                {
+                    Some(date.and_hms(0, 0, 0).timestamp_millis())
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-expires"));
+                    return true;
+                };
+                self.state.err = None;
+                let request = CreateApiKeyRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    name: self.state.name.clone(),
+                    scope: self.state.scope,
+                    expires_at,
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/create_api_key",
+                    request,
+                    CreateApiKeyResponse,
+                    CreateResponse
+                );
+                true
+            }
+            Msg::CreateResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.new_key = Some(response.key);
+                    self.state.keys.push(response.info);
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+            Msg::Fetch => {
+                let request = FetchApiKeyListRequest {
+                    login_token: (*self.props.login_token).clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/fetch_api_key_list",
+                    request,
+                    FetchApiKeyListResponse,
+                    FetchResponse
+                );
+                true
+            }
+            Msg::FetchResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.err = None;
+                    self.state.keys = response.keys;
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+            Msg::Revoke(index) => {
+                let request = RevokeApiKeyRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    id: self.state.keys[index].id.clone(),
+                };
+                crate::create_fetch_task!(self, "/revoke_api_key", request, RevokeResponse);
+                true
+            }
+            Msg::RevokeResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.update(Msg::Fetch);
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> yew::Html {
+        let name_oninput = self.link.callback(|e: InputData| Msg::EditName(e.value));
+        let scope_onchange = self.link.callback(|e: ChangeData| match e {
+            ChangeData::Select(select) => match select.value().parse() {
+                Ok(scope) => Msg::SelectScope(scope),
+                Err(_) => Msg::Nop,
+            },
+            _ => Msg::Nop,
+        });
+        let expires_onchange = self.link.callback(|e: ChangeData| match e {
+            ChangeData::Value(value) => Msg::EditExpiresOn(value),
+            _ => Msg::Nop,
+        });
+        let create_click = self.link.callback(|_| Msg::Create);
+
+        html! {
+            <div class="container">
+                <div class="header">
+                    <h2>{ fluent!(self.props.lang_id, "header") }</h2>
+                </div>
+                <div class="form">
+                    <div class="form-item">
+                        <MatTextField
+                            classes=classes!("form-input")
+                            outlined=true
+                            label=fluent!(self.props.lang_id, "name-label")
+                            helper=fluent!(self.props.lang_id, "name-hint")
+                            helper_persistent=true
+                            max_length=64
+                            value=self.state.name.clone()
+                            oninput=name_oninput />
+                    </div>
+                    <div class="form-item">
+                        <label class="form-row-item">
+                            { fluent!(self.props.lang_id, "scope-label") }
+                        </label>
+                        <select class="form-row-item" onchange=scope_onchange>
+                            {
+                                for ApiKeyScope::ALL.iter().map(|scope| html! {
+                                    <option
+                                        value=scope.as_str()
+                                        selected=*scope == self.state.scope>
+                                        { fluent!(self.props.lang_id, scope_message_id(*scope)) }
+                                    </option>
+                                })
+                            }
+                        </select>
+                    </div>
+                    <div class="form-item">
+                        <label class="form-row-item">
+                            { fluent!(self.props.lang_id, "expires-label") }
+                        </label>
+                        <input
+                            class="form-row-item"
+                            type="date"
+                            value=self.state.expires_on.clone()
+                            onchange=expires_onchange />
+                    </div>
+                    {
+                        if let Some(key) = &self.state.new_key {
+                            html! {
+                                <div class="hint-info">
+                                    <p>{ fluent!(self.props.lang_id, "new-key-hint") }</p>
+                                    <p><code>{ key }</code></p>
+                                </div>
+                            }
+                        } else if let Some(err) = &self.state.err {
+                            html! {
+                                <div class="error-info">
+                                    <p>{ fluent!(self.props.lang_id, "error-label",
+                                        { "details" => err.as_str() } ) }</p>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class="form-item">
+                        <span
+                            onclick=create_click
+                            class="form-row-item"
+                            disabled=self.need_to_disable() >
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-create")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </span>
+                        <RouterAnchor<AppRoute>
+                            route={ AppRoute::Home }
+                            classes="form-row-item">
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-home")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </RouterAnchor<AppRoute>>
+                    </div>
+                </div>
+                { self.fetching_progress() }
+                <div class="device-list">
+                    { self.keys_html() }
+                </div>
+            </div>
+        }
+    }
+}
+
+impl ApiKeys {
+    fn need_to_disable(&self) -> bool {
+        self.fetch_task.is_some()
+    }
+
+    fn fetching_progress(&self) -> yew::Html {
+        if self.fetch_task.is_some() {
+            html! {
+                <div class="fetching-progress">
+                    <MatLinearProgress indeterminate=true />
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
+    fn keys_html(&self) -> yew::Html {
+        if self.state.keys.is_empty() {
+            html! {
+                <p class="no-data">{ fluent!(self.props.lang_id, "no-keys") }</p>
+            }
+        } else {
+            html! {
+                for self
+                    .state
+                    .keys
+                    .iter()
+                    .enumerate()
+                    .map(|(ind, key)| self.key_html(key, ind))
+            }
+        }
+    }
+
+    fn key_html(&self, key: &ApiKeyInfo, index: usize) -> yew::Html {
+        let revoke_click = self.link.callback(move |_| Msg::Revoke(index));
+        let expires = match key.expires_at {
+            Some(time) => format_time(time),
+            None => fluent!(self.props.lang_id, "never"),
+        };
+        let last_used = match key.last_used_at {
+            Some(time) => format_time(time),
+            None => fluent!(self.props.lang_id, "never"),
+        };
+
+        html! {
+            <CardDiv classes=classes!("device-list-item")>
+                <p class="device-name">{ &key.name }</p>
+                <p class="device-id">
+                    { fluent!(self.props.lang_id, scope_message_id(key.scope)) }
+                </p>
+                <p>{ fluent!(self.props.lang_id, "key-created", {
+                    "time" => format_time(key.created_at),
+                }) }</p>
+                <p>{ fluent!(self.props.lang_id, "key-expires", { "time" => expires }) }</p>
+                <p>{ fluent!(self.props.lang_id, "key-last-used", { "time" => last_used }) }</p>
+                <div class="device-buttons">
+                    <span onclick=revoke_click disabled=self.need_to_disable()>
+                        <MatButton
+                            label=fluent!(self.props.lang_id, "button-revoke")
+                            icon=Cow::from("delete")
+                            disabled=self.need_to_disable() />
+                    </span>
+                </div>
+            </CardDiv>
+        }
+    }
+}
+
+fn scope_message_id(scope: ApiKeyScope) -> &'static str {
+    match scope {
+        ApiKeyScope::ReadOnly => "scope-read-only",
+        ApiKeyScope::DeviceWrite => "scope-device-write",
+        ApiKeyScope::Admin => "scope-admin",
+    }
+}
+
+fn format_time(timestamp: i64) -> String {
+    Utc.timestamp_millis(timestamp)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
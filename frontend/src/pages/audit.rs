@@ -0,0 +1,415 @@
+use crate::{
+    fluent,
+    route::AppRoute,
+    utils::{card_div::CardDiv, paged_list::PagedList},
+};
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use common::{
+    error::ApiError,
+    request::{AuditAction, FetchAuditLogRequest},
+    response::{AuditEntryInfo, ErrorResponse, FetchAuditLogResponse},
+};
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use std::rc::Rc;
+use yew::{
+    agent::Bridged,
+    classes,
+    format::Json,
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        FetchService,
+    },
+    Bridge, ChangeData, Component, ComponentLink, InputData, Properties,
+};
+use yew_material::{MatButton, MatLinearProgress, MatTextField};
+use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
+
+static_loader! {
+    static LOCALES = {
+        locales: "./text/audit",
+        fallback_language: "zh-CN",
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+const PAGE_SIZE: usize = 20;
+
+/// Audit log for administrators, filtered by actor, action, target and date
+pub struct Audit {
+    link: ComponentLink<Self>,
+    props: Props,
+    state: State,
+    route_agent: Box<dyn Bridge<RouteAgent>>,
+    fetch_task: Option<FetchTask>,
+}
+
+#[derive(Default)]
+struct State {
+    actor: String,
+    action: Option<AuditAction>,
+    target: String,
+    /// from_date - "YYYY-MM-DD" from the date input, so is `to_date`, empty for no limit
+    from_date: String,
+    to_date: String,
+    first_index: usize,
+    count: u32,
+    entries: Vec<AuditEntryInfo>,
+    err: Option<String>,
+}
+
+pub enum Msg {
+    Nop,
+    ToLogin,
+    EditActor(String),
+    SelectAction(Option<AuditAction>),
+    EditTarget(String),
+    EditFromDate(String),
+    EditToDate(String),
+    Search,
+    Fetch,
+    FetchResponse(FetchAuditLogResponse),
+    ChangePage(usize),
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub lang_id: LanguageIdentifier,
+    pub login_token: Rc<String>,
+}
+
+impl Component for Audit {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
+        let mut component = Self {
+            props,
+            link,
+            state: State::default(),
+            route_agent,
+            fetch_task: None,
+        };
+        if component.props.login_token.is_empty() {
+            component.update(Msg::ToLogin);
+        } else {
+            component.update(Msg::Fetch);
+        }
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
+        match msg {
+            Msg::Nop => false,
+            Msg::ToLogin => {
+                self.route_agent
+                    .send(ChangeRoute(AppRoute::LogoutHint.into()));
+                true
+            }
+            Msg::EditActor(actor) => {
+                self.state.actor = actor;
+                false
+            }
+            Msg::SelectAction(action) => {
+                self.state.action = action;
+                false
+            }
+            Msg::EditTarget(target) => {
+                self.state.target = target;
+                false
+            }
+            Msg::EditFromDate(date) => {
+                self.state.from_date = date;
+                false
+            }
+            Msg::EditToDate(date) => {
+                self.state.to_date = date;
+                false
+            }
+            Msg::Search => {
+                self.state.first_index = 0;
+                self.update(Msg::Fetch)
+            }
+            Msg::Fetch => {
+                let (from, to) = match (
+                    parse_date(&self.state.from_date),
+                    parse_date(&self.state.to_date),
+                ) {
+                    (Ok(from), Ok(to)) => (from, to),
+                    _ => {
+                        self.state.err = Some(fluent!(self.props.lang_id, "error-date"));
+                        return true;
+                    }
+                };
+                let request = FetchAuditLogRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    actor: self.state.actor.trim().to_string(),
+                    action: self.state.action,
+                    target: self.state.target.trim().to_string(),
+                    from,
+                    // the whole day of `to_date` is included
+                    to: to.map(|to| to + Duration::days(1).num_milliseconds() - 1),
+                    first_index: self.state.first_index,
+                    limit: PAGE_SIZE,
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/admin/fetch_audit_log",
+                    request,
+                    FetchAuditLogResponse,
+                    FetchResponse
+                );
+                true
+            }
+            Msg::FetchResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.err = None;
+                    self.state.count = response.count;
+                    self.state.entries = response.entries;
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+            Msg::ChangePage(page_index) => {
+                self.state.first_index = page_index * PAGE_SIZE;
+                self.update(Msg::Fetch)
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> yew::Html {
+        let actor_oninput = self.link.callback(|e: InputData| Msg::EditActor(e.value));
+        let target_oninput = self.link.callback(|e: InputData| Msg::EditTarget(e.value));
+        let action_onchange = self.link.callback(|e: ChangeData| match e {
+            ChangeData::Select(select) => Msg::SelectAction(select.value().parse().ok()),
+            _ => Msg::Nop,
+        });
+        let from_onchange = self.link.callback(|e: ChangeData| match e {
+            ChangeData::Value(value) => Msg::EditFromDate(value),
+            _ => Msg::Nop,
+        });
+        let to_onchange = self.link.callback(|e: ChangeData| match e {
+            ChangeData::Value(value) => Msg::EditToDate(value),
+            _ => Msg::Nop,
+        });
+        let search_click = self.link.callback(|_| Msg::Search);
+
+        html! {
+            <div class="container">
+                <div class="header">
+                    <h2>{ fluent!(self.props.lang_id, "header") }</h2>
+                </div>
+                <div class="form">
+                    <div class="form-item">
+                        <MatTextField
+                            classes=classes!("form-input")
+                            outlined=true
+                            label=fluent!(self.props.lang_id, "actor-label")
+                            helper=fluent!(self.props.lang_id, "actor-hint")
+                            helper_persistent=true
+                            value=self.state.actor.clone()
+                            oninput=actor_oninput />
+                    </div>
+                    <div class="form-item">
+                        <MatTextField
+                            classes=classes!("form-input")
+                            outlined=true
+                            label=fluent!(self.props.lang_id, "target-label")
+                            helper=fluent!(self.props.lang_id, "target-hint")
+                            helper_persistent=true
+                            value=self.state.target.clone()
+                            oninput=target_oninput />
+                    </div>
+                    <div class="form-item">
+                        <label class="form-row-item">
+                            { fluent!(self.props.lang_id, "action-label") }
+                        </label>
+                        <select class="form-row-item" onchange=action_onchange>
+                            <option value="" selected=self.state.action.is_none()>
+                                { fluent!(self.props.lang_id, "action-all") }
+                            </option>
+                            {
+                                for AuditAction::ALL.iter().map(|action| html! {
+                                    <option
+                                        value=action.as_str()
+                                        selected=Some(*action) == self.state.action>
+                                        { fluent!(self.props.lang_id, action_message_id(*action)) }
+                                    </option>
+                                })
+                            }
+                        </select>
+                    </div>
+                    <div class="form-item">
+                        <label class="form-row-item">
+                            { fluent!(self.props.lang_id, "from-label") }
+                        </label>
+                        <input
+                            class="form-row-item"
+                            type="date"
+                            value=self.state.from_date.clone()
+                            onchange=from_onchange />
+                        <label class="form-row-item">
+                            { fluent!(self.props.lang_id, "to-label") }
+                        </label>
+                        <input
+                            class="form-row-item"
+                            type="date"
+                            value=self.state.to_date.clone()
+                            onchange=to_onchange />
+                    </div>
+                    {
+                        if let Some(err) = &self.state.err {
+                            html! {
+                                <div class="error-info">
+                                    <p>{ fluent!(self.props.lang_id, "error-label",
+                                        { "details" => err.as_str() } ) }</p>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class="form-item">
+                        <span
+                            onclick=search_click
+                            class="form-row-item"
+                            disabled=self.need_to_disable() >
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-search")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </span>
+                        <RouterAnchor<AppRoute>
+                            route={ AppRoute::Admin }
+                            classes="form-row-item">
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-admin")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </RouterAnchor<AppRoute>>
+                    </div>
+                </div>
+                { self.fetching_progress() }
+                { self.entries_html() }
+            </div>
+        }
+    }
+}
+
+impl Audit {
+    fn need_to_disable(&self) -> bool {
+        self.fetch_task.is_some()
+    }
+
+    fn fetching_progress(&self) -> yew::Html {
+        if self.fetch_task.is_some() {
+            html! {
+                <div class="fetching-progress">
+                    <MatLinearProgress indeterminate=true />
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
+    fn entries_html(&self) -> yew::Html {
+        if self.state.entries.is_empty() {
+            return html! {
+                <p class="no-data">{ fluent!(self.props.lang_id, "no-entries") }</p>
+            };
+        }
+        let on_page_changed = self
+            .link
+            .callback(|data: (usize, usize)| Msg::ChangePage(data.0));
+
+        html! {
+            <PagedList
+                lang_id=self.props.lang_id.clone()
+                page_size=PAGE_SIZE
+                items_count=self.state.count as usize
+                disabled=self.need_to_disable()
+                on_page_changed=on_page_changed >
+                <div class="device-list">
+                    { for self.state.entries.iter().map(|entry| self.entry_html(entry)) }
+                </div>
+            </PagedList>
+        }
+    }
+
+    fn entry_html(&self, entry: &AuditEntryInfo) -> yew::Html {
+        html! {
+            <CardDiv classes=classes!("device-list-item")>
+                <p class="device-name">
+                    { fluent!(self.props.lang_id, action_message_id(entry.action)) }
+                </p>
+                <p class="device-id">{ &entry.target }</p>
+                <p>{ fluent!(self.props.lang_id, "entry-actor", {
+                    "actor" => entry.actor.as_str(),
+                    "ip" => entry.ip.as_str(),
+                }) }</p>
+                <p>{ format_time(entry.time) }</p>
+                {
+                    for entry.changes.iter().map(|change| html! {
+                        <p>{ fluent!(self.props.lang_id, "entry-change", {
+                            "field" => change.field.as_str(),
+                            "before" => change.before.as_str(),
+                            "after" => change.after.as_str(),
+                        }) }</p>
+                    })
+                }
+            </CardDiv>
+        }
+    }
+}
+
+fn action_message_id(action: AuditAction) -> &'static str {
+    match action {
+        AuditAction::Login => "action-login",
+        AuditAction::LoginFailed => "action-login-failed",
+        AuditAction::Register => "action-register",
+        AuditAction::CreateDevice => "action-create-device",
+        AuditAction::ModifyDevice => "action-modify-device",
+        AuditAction::RemoveDevice => "action-remove-device",
+        AuditAction::UpdateUser => "action-update-user",
+        AuditAction::DeleteUser => "action-delete-user",
+        AuditAction::Impersonate => "action-impersonate",
+        AuditAction::RequestDeletion => "action-request-deletion",
+        AuditAction::CancelDeletion => "action-cancel-deletion",
+        AuditAction::DeleteAccount => "action-delete-account",
+        AuditAction::CreateOrg => "action-create-org",
+        AuditAction::AddOrgMember => "action-add-org-member",
+        AuditAction::RemoveOrgMember => "action-remove-org-member",
+        AuditAction::ProvisionDevice => "action-provision-device",
+        AuditAction::SendCommand => "action-send-command",
+        AuditAction::UpdateDesired => "action-update-desired",
+        AuditAction::ResetTotp => "action-reset-totp",
+    }
+}
+
+/// Milliseconds since epoch of the start of a "YYYY-MM-DD" day, `None` for an empty string
+fn parse_date(date: &str) -> Result<Option<i64>, chrono::ParseError> {
+    if date.is_empty() {
+        return Ok(None);
+    }
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+    Ok(Some(date.and_hms(0, 0, 0).timestamp_millis()))
+}
+
+fn format_time(timestamp: i64) -> String {
+    Utc.timestamp_millis(timestamp)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
@@ -8,10 +8,19 @@ use crate::{
         paged_list::PagedList,
     },
 };
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
 use common::{
-    request::{FetchDeviceProfileRequest, FetchMessageListRequest},
-    response::{ErrorResponse, FetchDeviceProfileResponse, FetchMessageListResponse, MessageInfo},
+    error::ApiError,
+    request::{
+        FetchCommandListRequest, FetchDeviceProfileRequest, FetchDeviceTwinRequest,
+        FetchMessageListRequest, SendCommandRequest, UpdateDeviceTwinRequest,
+    },
+    response::{
+        CommandInfo, DeviceTwin, ErrorResponse, FetchCommandListResponse,
+        FetchDeviceProfileResponse, FetchDeviceTwinResponse, FetchMessageListResponse, MessageInfo,
+        SendCommandResponse,
+    },
 };
 use fluent_templates::{static_loader, LanguageIdentifier, Loader};
 use std::{rc::Rc, time::Duration};
@@ -27,7 +36,7 @@ use yew::{
     },
     Bridge, Component, ComponentLink, InputData, Properties,
 };
-use yew_material::{MatButton, MatLinearProgress};
+use yew_material::{MatButton, MatLinearProgress, MatTextField};
 use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
 
 static_loader! {
@@ -38,6 +47,9 @@ static_loader! {
     };
 }
 
+/// How many of the latest commands are shown
+const COMMAND_LIMIT: usize = 10;
+
 pub struct DeviceContent {
     link: ComponentLink<Self>,
     props: Props,
@@ -57,6 +69,12 @@ struct State {
     limit: usize,
     searched_message_count: u32,
     messages: Vec<MessageInfo>,
+    command_name: String,
+    command_params: String,
+    commands: Vec<CommandInfo>,
+    /// desired state being edited, a JSON object
+    desired: String,
+    twin: DeviceTwin,
     err: Option<String>,
 }
 
@@ -72,6 +90,17 @@ pub enum Msg {
     FetchResponse(FetchMessageListResponse),
     Search,
     ChangePage(usize, usize),
+    EditCommandName(String),
+    EditCommandParams(String),
+    SendCommand,
+    SendCommandResponse(SendCommandResponse),
+    FetchCommands,
+    FetchCommandsResponse(FetchCommandListResponse),
+    FetchTwin,
+    FetchTwinResponse(FetchDeviceTwinResponse),
+    EditDesired(String),
+    SaveDesired,
+    SaveDesiredResponse(FetchDeviceTwinResponse),
 }
 
 #[derive(Properties, Clone, PartialEq)]
@@ -82,6 +111,8 @@ pub struct Props {
     pub id: Rc<String>,
     pub name: Rc<String>,
     pub info: Rc<String>,
+    /// timezone - times of messages are shown and entered in it
+    pub timezone: Tz,
 }
 
 impl Component for DeviceContent {
@@ -157,7 +188,7 @@ impl Component for DeviceContent {
                 if response.success {
                     self.state.message_count = response.message_count;
                     self.state.alert_message_count = response.alert_message_count;
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
                     return self.update(Msg::ToLogin);
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
@@ -166,20 +197,12 @@ impl Component for DeviceContent {
             }
             Msg::Fetch => {
                 self.state.err = None;
-                let start_timestamp = if let Ok(datetime) =
-                    NaiveDateTime::parse_from_str(&self.state.start_timestamp_str, "%Y-%m-%dT%H:%M")
-                {
-                    datetime.timestamp() * 1000
-                } else {
-                    0
-                };
-                let end_timestamp = if let Ok(datetime) =
-                    NaiveDateTime::parse_from_str(&self.state.end_timestamp_str, "%Y-%m-%dT%H:%M")
-                {
-                    datetime.timestamp() * 1000
-                } else {
-                    std::i64::MAX
-                };
+                let start_timestamp = self
+                    .parse_time(&self.state.start_timestamp_str)
+                    .unwrap_or(0);
+                let end_timestamp = self
+                    .parse_time(&self.state.end_timestamp_str)
+                    .unwrap_or(std::i64::MAX);
                 let request = FetchMessageListRequest {
                     login_token: (*self.props.login_token).clone(),
                     id: (*self.props.id).clone(),
@@ -202,12 +225,12 @@ impl Component for DeviceContent {
                 if response.success {
                     self.state.messages = response.messages;
                     self.state.searched_message_count = response.count;
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
                     return self.update(Msg::ToLogin);
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
                 }
-                true
+                self.update(Msg::FetchCommands)
             }
             Msg::Search => {
                 self.state.first_index = 0;
@@ -218,6 +241,138 @@ impl Component for DeviceContent {
                 self.state.limit = limit;
                 self.update(Msg::Fetch)
             }
+            Msg::EditCommandName(name) => {
+                self.state.command_name = name;
+                false
+            }
+            Msg::EditCommandParams(params) => {
+                self.state.command_params = params;
+                false
+            }
+            Msg::SendCommand => {
+                if self.state.command_name.trim().is_empty() {
+                    false
+                } else {
+                    self.state.err = None;
+                    let request = SendCommandRequest {
+                        login_token: (*self.props.login_token).clone(),
+                        id: (*self.props.id).clone(),
+                        name: self.state.command_name.trim().to_string(),
+                        params: self.state.command_params.clone(),
+                    };
+                    crate::create_fetch_task!(
+                        self,
+                        "/send_command",
+                        request,
+                        SendCommandResponse,
+                        SendCommandResponse,
+                    );
+                    true
+                }
+            }
+            Msg::SendCommandResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.command_name.clear();
+                    self.state.command_params.clear();
+                    return self.update(Msg::FetchCommands);
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    return self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+            Msg::FetchCommands => {
+                let request = FetchCommandListRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    id: (*self.props.id).clone(),
+                    first_index: 0,
+                    limit: COMMAND_LIMIT,
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/fetch_command_list",
+                    request,
+                    FetchCommandListResponse,
+                    FetchCommandsResponse,
+                );
+                true
+            }
+            Msg::FetchCommandsResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.commands = response.commands;
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    return self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                self.update(Msg::FetchTwin)
+            }
+            Msg::FetchTwin => {
+                let request = FetchDeviceTwinRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    id: (*self.props.id).clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/fetch_device_twin",
+                    request,
+                    FetchDeviceTwinResponse,
+                    FetchTwinResponse,
+                );
+                true
+            }
+            Msg::FetchTwinResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.desired = response.twin.desired.clone();
+                    self.state.twin = response.twin;
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    return self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+            Msg::EditDesired(desired) => {
+                self.state.desired = desired;
+                false
+            }
+            Msg::SaveDesired => {
+                self.state.err = None;
+                let request = UpdateDeviceTwinRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    id: (*self.props.id).clone(),
+                    desired: self.state.desired.clone(),
+                    version: self.state.twin.desired_version,
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/update_device_twin",
+                    request,
+                    FetchDeviceTwinResponse,
+                    SaveDesiredResponse,
+                );
+                true
+            }
+            Msg::SaveDesiredResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.desired = response.twin.desired.clone();
+                    self.state.twin = response.twin;
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    return self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                    // someone else changed it meanwhile, show the latest twin
+                    if response.code == Some(ApiError::VersionConflict) {
+                        return self.update(Msg::FetchTwin);
+                    }
+                }
+                true
+            }
         }
     }
 
@@ -308,6 +463,8 @@ impl Component for DeviceContent {
                     </span>
                 </div>
                 { self.fetching_progress() }
+                { self.twin_html() }
+                { self.commands_html() }
                 { self.content_html() }
             </div>
         }
@@ -319,6 +476,17 @@ impl DeviceContent {
         self.fetch_task.is_some()
     }
 
+    /// Milliseconds since epoch of a "YYYY-MM-DDTHH:MM" time of the datetime input, which is in
+    /// the timezone of the user
+    fn parse_time(&self, time: &str) -> Option<i64> {
+        let datetime = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M").ok()?;
+        self.props
+            .timezone
+            .from_local_datetime(&datetime)
+            .earliest()
+            .map(|datetime| datetime.timestamp_millis())
+    }
+
     fn fetching_progress(&self) -> yew::Html {
         if self.fetch_task.is_some() {
             html! {
@@ -371,6 +539,165 @@ impl DeviceContent {
         }
     }
 
+    fn twin_html(&self) -> yew::Html {
+        let desired_oninput = self.link.callback(|e: InputData| Msg::EditDesired(e.value));
+        let save_click = self.link.callback(|_| Msg::SaveDesired);
+        let twin = &self.state.twin;
+
+        html! {
+            <>
+                <h3>{ fluent!(self.props.lang_id, "twin-title") }</h3>
+                <div class="form-item">
+                    <MatTextField
+                        classes=classes!("form-row-item")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "desired-label",
+                            { "version" => twin.desired_version })
+                        helper=fluent!(self.props.lang_id, "desired-hint")
+                        value=self.state.desired.clone()
+                        oninput=desired_oninput />
+                    <span
+                        class="form-row-item"
+                        onclick=save_click
+                        disabled=self.need_to_disable() >
+                        <MatButton
+                            classes=classes!("form-button")
+                            label=fluent!(self.props.lang_id, "button-save-desired")
+                            raised=true
+                            disabled=self.need_to_disable() />
+                    </span>
+                </div>
+                <CardDiv>
+                    <p>{ fluent!(self.props.lang_id, "twin-reported", {
+                        "reported" => twin.reported.as_str(),
+                        "version" => twin.reported_version,
+                    }) }</p>
+                    {
+                        if twin.reported_at == 0 {
+                            html! {
+                                <p>{ fluent!(self.props.lang_id, "twin-never-reported") }</p>
+                            }
+                        } else {
+                            let reported_at =
+                                self.props.timezone.timestamp(twin.reported_at / 1000, 0);
+                            html! {
+                                <p>{ fluent!(self.props.lang_id, "twin-reported-at",
+                                    { "time" => reported_at.to_string() }) }</p>
+                            }
+                        }
+                    }
+                    <p>{ fluent!(self.props.lang_id, "twin-delta",
+                        { "delta" => twin.delta.as_str() }) }</p>
+                </CardDiv>
+            </>
+        }
+    }
+
+    fn commands_html(&self) -> yew::Html {
+        let name_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditCommandName(e.value));
+        let params_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditCommandParams(e.value));
+        let send_click = self.link.callback(|_| Msg::SendCommand);
+        let refresh_click = self.link.callback(|_| Msg::FetchCommands);
+
+        html! {
+            <>
+                <h3>{ fluent!(self.props.lang_id, "commands-title") }</h3>
+                <div class="form-item">
+                    <MatTextField
+                        classes=classes!("form-row-item")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "command-name-label")
+                        helper=fluent!(self.props.lang_id, "command-name-hint")
+                        value=self.state.command_name.clone()
+                        oninput=name_oninput />
+                    <MatTextField
+                        classes=classes!("form-row-item")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "command-params-label")
+                        helper=fluent!(self.props.lang_id, "command-params-hint")
+                        value=self.state.command_params.clone()
+                        oninput=params_oninput />
+                    <span
+                        class="form-row-item"
+                        onclick=send_click
+                        disabled=self.need_to_disable() >
+                        <MatButton
+                            classes=classes!("form-button")
+                            label=fluent!(self.props.lang_id, "button-send-command")
+                            raised=true
+                            disabled=self.need_to_disable() />
+                    </span>
+                    <span
+                        class="form-row-item"
+                        onclick=refresh_click
+                        disabled=self.need_to_disable() >
+                        <MatButton
+                            classes=classes!("form-button")
+                            label=fluent!(self.props.lang_id, "button-refresh-commands")
+                            raised=true
+                            disabled=self.need_to_disable() />
+                    </span>
+                </div>
+                {
+                    if self.state.commands.is_empty() {
+                        html! {
+                            <p class="no-data">{ fluent!(self.props.lang_id, "no-command") }</p>
+                        }
+                    } else {
+                        html! {
+                            for self
+                                .state
+                                .commands
+                                .iter()
+                                .map(|command| self.command_html(command))
+                        }
+                    }
+                }
+            </>
+        }
+    }
+
+    fn command_html(&self, command: &CommandInfo) -> yew::Html {
+        let created_at = self.props.timezone.timestamp(command.created_at / 1000, 0);
+        let status = format!("command-status-{}", command.status.as_str());
+        html! {
+            <CardDiv>
+                <p>{ fluent!(self.props.lang_id, "command-name", {
+                    "name" => command.name.as_str(),
+                    "status" => fluent!(self.props.lang_id, &status),
+                }) }</p>
+                {
+                    if command.params.is_empty() {
+                        html! {}
+                    } else {
+                        html! {
+                            <p>{ fluent!(self.props.lang_id, "command-params",
+                                { "params" => command.params.as_str() }) }</p>
+                        }
+                    }
+                }
+                <p>{ fluent!(self.props.lang_id, "command-time", {
+                    "time" => created_at.to_string(),
+                    "mail" => command.issued_by.as_str(),
+                }) }</p>
+                {
+                    if command.result.is_empty() {
+                        html! {}
+                    } else {
+                        html! {
+                            <p>{ fluent!(self.props.lang_id, "command-result",
+                                { "result" => command.result.as_str() }) }</p>
+                        }
+                    }
+                }
+            </CardDiv>
+        }
+    }
+
     fn messages_html(&self) -> yew::Html {
         html! {
             for self
@@ -382,7 +709,7 @@ impl DeviceContent {
     }
 
     fn message_html(&self, msg: &MessageInfo) -> yew::Html {
-        let time = Utc.timestamp(msg.timestamp / 1000, 0);
+        let time = self.props.timezone.timestamp(msg.timestamp / 1000, 0);
         html! {
             <CardDiv>
                 {
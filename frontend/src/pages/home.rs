@@ -1,7 +1,9 @@
 use crate::{fluent, route::AppRoute, utils::card_div::CardDiv};
 use common::{
+    error::ApiError,
     request::{
         CreateDeviceRequest, FetchDeviceListRequest, FetchDeviceRequest, RemoveDeviceRequest,
+        ResendVerificationRequest,
     },
     response::{
         DeviceInfo, ErrorResponse, FetchDeviceListResponse, FetchDeviceResponse, SimpleResponse,
@@ -21,7 +23,10 @@ use yew::{
     Bridge, Callback, Component, ComponentLink, InputData, Properties,
 };
 use yew_material::{MatButton, MatLinearProgress, MatTextField};
-use yew_router::{agent::RouteRequest::ChangeRoute, prelude::RouteAgent};
+use yew_router::{
+    agent::RouteRequest::ChangeRoute,
+    prelude::{RouteAgent, RouterAnchor},
+};
 
 static_loader! {
     static LOCALES = {
@@ -42,7 +47,11 @@ pub struct HomeComponent {
 #[derive(Default)]
 struct State {
     create_id: String,
+    create_code: String,
     devices: Vec<DeviceInfo>,
+    /// unverified - following a device failed because the mail address isn't verified
+    unverified: bool,
+    resent: bool,
     err: Option<String>,
 }
 
@@ -52,6 +61,7 @@ pub enum Msg {
     LogoutRespone(SimpleResponse),
     ToLogin,
     EditCreateId(String),
+    EditCreateCode(String),
     CreateDevice,
     CreateDeviceResponse(SimpleResponse),
     Fetch,
@@ -62,6 +72,8 @@ pub enum Msg {
     ModifyResponse(FetchDeviceResponse),
     Details(usize),
     DetialsResponse(FetchDeviceResponse),
+    ResendVerification,
+    ResendVerificationResponse(SimpleResponse),
 }
 
 #[derive(Properties, Clone, PartialEq)]
@@ -70,6 +82,9 @@ pub struct Props {
     pub login_token: Rc<String>,
     pub mail: Rc<String>,
     pub name: Rc<String>,
+    /// is_admin - shows the button to the administration page
+    #[prop_or_default]
+    pub is_admin: bool,
     pub onlogout: Callback<()>,
     pub onselect: Callback<(String, String, String)>,
 }
@@ -117,6 +132,10 @@ impl Component for HomeComponent {
                 self.state.create_id = create_id;
                 false
             }
+            Msg::EditCreateCode(create_code) => {
+                self.state.create_code = create_code;
+                false
+            }
             Msg::CreateDevice => {
                 if self.state.create_id.trim().is_empty() {
                     false
@@ -124,8 +143,8 @@ impl Component for HomeComponent {
                     self.state.err = None;
                     let request = CreateDeviceRequest {
                         login_token: (*self.props.login_token).clone(),
-                        mail: (*self.props.mail).clone(),
                         id: self.state.create_id.trim().to_string(),
+                        claim_code: self.state.create_code.trim().to_string(),
                     };
                     crate::create_fetch_task!(
                         self,
@@ -140,9 +159,14 @@ impl Component for HomeComponent {
                 self.fetch_task = None;
                 if response.success {
                     self.state.err = None;
+                    // claim codes work only once
+                    self.state.create_code.clear();
                     self.update(Msg::Fetch)
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
                     self.update(Msg::ToLogin)
+                } else if response.code == Some(ApiError::MailNotVerified) {
+                    self.state.unverified = true;
+                    true
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
                     true
@@ -155,7 +179,6 @@ impl Component for HomeComponent {
                     self.state.err = None;
                     let request = RemoveDeviceRequest {
                         login_token: (*self.props.login_token).clone(),
-                        mail: (*self.props.mail).clone(),
                         id: self.state.devices[index].id.clone(),
                     };
                     crate::create_fetch_task!(self, "remove_device", request, RemoveDeviceResponse);
@@ -167,7 +190,7 @@ impl Component for HomeComponent {
                 if response.success {
                     self.state.err = None;
                     self.update(Msg::Fetch)
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
                     self.update(Msg::ToLogin)
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
@@ -178,7 +201,6 @@ impl Component for HomeComponent {
                 self.state.err = None;
                 let request = FetchDeviceListRequest {
                     login_token: (*self.props.login_token).clone(),
-                    mail: (*self.props.mail).clone(),
                 };
                 crate::create_fetch_task!(
                     self,
@@ -194,7 +216,7 @@ impl Component for HomeComponent {
                 if response.success {
                     self.state.err = None;
                     self.state.devices = response.devices;
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
                     self.update(Msg::ToLogin);
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
@@ -228,7 +250,7 @@ impl Component for HomeComponent {
                         .emit((response.id, response.name, response.info));
                     self.route_agent
                         .send(ChangeRoute(AppRoute::ModifyDevice.into()));
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
                     self.update(Msg::ToLogin);
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
@@ -262,7 +284,31 @@ impl Component for HomeComponent {
                         .emit((response.id, response.name, response.info));
                     self.route_agent
                         .send(ChangeRoute(AppRoute::DeviceContent.into()));
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
+                    self.update(Msg::ToLogin);
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+            Msg::ResendVerification => {
+                self.state.err = None;
+                let request = ResendVerificationRequest {
+                    login_token: (*self.props.login_token).clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/resend_verification",
+                    request,
+                    ResendVerificationResponse
+                );
+                true
+            }
+            Msg::ResendVerificationResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.resent = true;
+                } else if response.code == Some(ApiError::LoginExpired) {
                     self.update(Msg::ToLogin);
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
@@ -285,6 +331,9 @@ impl Component for HomeComponent {
         let create_oninput = self
             .link
             .callback(|e: InputData| Msg::EditCreateId(e.value));
+        let create_code_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditCreateCode(e.value));
         let create_click = self.link.callback(|_| Msg::CreateDevice);
         let fetch_click = self.link.callback(|_| Msg::Fetch);
         let logout_click = self.link.callback(|_| Msg::Logout);
@@ -312,6 +361,7 @@ impl Component for HomeComponent {
                         html! {}
                     }
                 }
+                { self.verification_html() }
                 <div class="form-item">
                     <MatTextField
                         classes=classes!("form-row-item")
@@ -320,6 +370,13 @@ impl Component for HomeComponent {
                         helper=fluent!(self.props.lang_id, "id-hint")
                         value=self.state.create_id.clone()
                         oninput=create_oninput />
+                    <MatTextField
+                        classes=classes!("form-row-item")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "claim-code-label")
+                        helper=fluent!(self.props.lang_id, "claim-code-hint")
+                        value=self.state.create_code.clone()
+                        oninput=create_code_oninput />
                     <span
                         class="form-row-item"
                         onclick=create_click
@@ -340,6 +397,50 @@ impl Component for HomeComponent {
                             raised=true
                             disabled=self.need_to_disable() />
                     </span>
+                    <RouterAnchor<AppRoute>
+                        route={ AppRoute::ApiKeys }
+                        classes="form-row-item">
+                        <MatButton
+                            classes=classes!("form-button")
+                            label=fluent!(self.props.lang_id, "button-api-keys")
+                            raised=true
+                            disabled=self.need_to_disable() />
+                    </RouterAnchor<AppRoute>>
+                    <RouterAnchor<AppRoute>
+                        route={ AppRoute::Security }
+                        classes="form-row-item">
+                        <MatButton
+                            classes=classes!("form-button")
+                            label=fluent!(self.props.lang_id, "button-security")
+                            raised=true
+                            disabled=self.need_to_disable() />
+                    </RouterAnchor<AppRoute>>
+                    <RouterAnchor<AppRoute>
+                        route={ AppRoute::Profile }
+                        classes="form-row-item">
+                        <MatButton
+                            classes=classes!("form-button")
+                            label=fluent!(self.props.lang_id, "button-profile")
+                            raised=true
+                            disabled=self.need_to_disable() />
+                    </RouterAnchor<AppRoute>>
+                    {
+                        if self.props.is_admin {
+                            html! {
+                                <RouterAnchor<AppRoute>
+                                    route={ AppRoute::Admin }
+                                    classes="form-row-item">
+                                    <MatButton
+                                        classes=classes!("form-button")
+                                        label=fluent!(self.props.lang_id, "button-admin")
+                                        raised=true
+                                        disabled=self.need_to_disable() />
+                                </RouterAnchor<AppRoute>>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
                     <span
                         class="form-row-item"
                         onclick=logout_click
@@ -365,6 +466,35 @@ impl HomeComponent {
         self.fetch_task.is_some()
     }
 
+    fn verification_html(&self) -> yew::Html {
+        if !self.state.unverified {
+            return html! {};
+        }
+        let resend_click = self.link.callback(|_| Msg::ResendVerification);
+        html! {
+            <CardDiv classes=classes!("hint-info")>
+                <p>{ fluent!(self.props.lang_id, "verify-hint", {
+                    "email" => self.props.mail.as_str(),
+                }) }</p>
+                {
+                    if self.state.resent {
+                        html! { <p>{ fluent!(self.props.lang_id, "verify-resent") }</p> }
+                    } else {
+                        html! {
+                            <span onclick=resend_click disabled=self.need_to_disable()>
+                                <MatButton
+                                    classes=classes!("form-button")
+                                    label=fluent!(self.props.lang_id, "button-resend")
+                                    raised=true
+                                    disabled=self.need_to_disable() />
+                            </span>
+                        }
+                    }
+                }
+            </CardDiv>
+        }
+    }
+
     fn fetching_progress(&self) -> yew::Html {
         if self.fetch_task.is_some() {
             html! {
@@ -1,7 +1,11 @@
 use crate::{fluent, route::AppRoute};
 use common::{
+    error::ApiError,
     request::LoginRequest,
-    response::{ErrorResponse, LoginResponse},
+    response::{
+        ErrorResponse, FetchLoginOptionsResponse, FetchSsoStatusResponse, LoginResponse,
+        SsoAuthorizeResponse,
+    },
 };
 use fluent_templates::{static_loader, LanguageIdentifier, Loader};
 use sha2::{Digest, Sha256};
@@ -16,7 +20,9 @@ use yew::{
     },
     Bridge, Callback, Component, ComponentLink, InputData, Properties,
 };
-use yew_material::{text_inputs::TextFieldType, MatButton, MatTextField};
+use yew_material::{
+    text_inputs::TextFieldType, MatButton, MatCheckbox, MatFormfield, MatTextField,
+};
 use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
 
 static_loader! {
@@ -39,6 +45,14 @@ pub struct LoginComponent {
 struct State {
     mail: String,
     password: String,
+    remember: bool,
+    /// totp_required - two-factor authentication is enabled, so the code is needed too
+    totp_required: bool,
+    totp_code: String,
+    /// plain_password - the server checks passwords by a directory, which needs them as entered
+    plain_password: bool,
+    /// sso_provider - name of the single sign-on provider, `None` if it isn't enabled
+    sso_provider: Option<String>,
     err: Option<String>,
 }
 
@@ -46,14 +60,21 @@ pub enum Msg {
     Nop,
     EditMail(String),
     EditPassword(String),
+    ToggleRemember(bool),
+    EditTotpCode(String),
     Login,
     LoginResponse(LoginResponse),
+    FetchLoginOptionsResponse(FetchLoginOptionsResponse),
+    FetchSsoStatusResponse(FetchSsoStatusResponse),
+    SsoLogin,
+    SsoAuthorizeResponse(SsoAuthorizeResponse),
 }
 
 #[derive(Properties, Clone)]
 pub struct Props {
     pub lang_id: LanguageIdentifier,
-    pub onlogin: Callback<(String, String, String)>,
+    /// onlogin - the new session together with the preferences of the user
+    pub onlogin: Callback<LoginResponse>,
 }
 
 impl Component for LoginComponent {
@@ -62,13 +83,21 @@ impl Component for LoginComponent {
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
         let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
-        Self {
+        let mut component = Self {
             link,
             props,
             state: State::default(),
             route_agent,
             fetch_task: None,
-        }
+        };
+        crate::create_fetch_task!(
+            component,
+            "/fetch_login_options",
+            (),
+            FetchLoginOptionsResponse,
+            FetchLoginOptionsResponse
+        );
+        component
     }
 
     fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
@@ -82,18 +111,40 @@ impl Component for LoginComponent {
                 self.state.password = password;
                 false
             }
+            Msg::ToggleRemember(remember) => {
+                self.state.remember = remember;
+                false
+            }
+            Msg::EditTotpCode(code) => {
+                self.state.totp_code = code;
+                false
+            }
             Msg::Login => {
                 self.state.err = None;
                 if self.state.mail.is_empty() {
                     self.state.err = Some(fluent!(self.props.lang_id, "error-email-empty"));
                 } else if self.state.password.is_empty() {
                     self.state.err = Some(fluent!(self.props.lang_id, "error-password-empty"));
+                } else if self.state.totp_required && self.state.totp_code.trim().is_empty() {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-totp-code-empty"));
                 } else {
-                    let hashed_password =
-                        format!("{:x}", Sha256::digest(self.state.password.as_bytes()));
+                    let (password, plain_password) = if self.state.plain_password {
+                        (String::new(), Some(self.state.password.clone()))
+                    } else {
+                        let hashed_password =
+                            format!("{:x}", Sha256::digest(self.state.password.as_bytes()));
+                        (hashed_password, None)
+                    };
                     let request = LoginRequest {
                         mail: self.state.mail.clone(),
-                        password: hashed_password,
+                        password,
+                        plain_password,
+                        remember: self.state.remember,
+                        totp_code: if self.state.totp_required {
+                            Some(self.state.totp_code.trim().to_string())
+                        } else {
+                            None
+                        },
                     };
                     crate::create_fetch_task!(
                         self,
@@ -109,14 +160,66 @@ impl Component for LoginComponent {
                 self.fetch_task = None;
                 if response.success {
                     self.route_agent.send(ChangeRoute(AppRoute::Home.into()));
-                    self.props
-                        .onlogin
-                        .emit((response.login_token, response.mail, response.name));
+                    self.props.onlogin.emit(response);
+                } else if response.code == Some(ApiError::TotpRequired) {
+                    self.state.totp_required = true;
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-totp-required"));
+                } else if response.code == Some(ApiError::TooManyAttempts) {
+                    let minutes = (response.retry_after.unwrap_or(60) + 59) / 60;
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-too-many-attempts", {
+                        "minutes" => minutes.max(1),
+                    }));
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
                 }
                 true
             }
+            Msg::FetchLoginOptionsResponse(response) => {
+                self.state.plain_password = response.plain_password;
+                crate::create_fetch_task!(
+                    self,
+                    "/fetch_sso_status",
+                    (),
+                    FetchSsoStatusResponse,
+                    FetchSsoStatusResponse
+                );
+                false
+            }
+            Msg::FetchSsoStatusResponse(response) => {
+                self.fetch_task = None;
+                if response.enabled {
+                    self.state.sso_provider = Some(response.provider_name);
+                }
+                true
+            }
+            Msg::SsoLogin => {
+                self.state.err = None;
+                crate::create_fetch_task!(
+                    self,
+                    "/sso_authorize",
+                    (),
+                    SsoAuthorizeResponse,
+                    SsoAuthorizeResponse
+                );
+                true
+            }
+            Msg::SsoAuthorizeResponse(response) => {
+                if response.success {
+                    // the fetch task is kept, so the page stays disabled until the provider shows
+                    if yew::utils::window()
+                        .location()
+                        .set_href(&response.url)
+                        .is_err()
+                    {
+                        self.fetch_task = None;
+                        self.state.err = Some(fluent!(self.props.lang_id, "error-unknown"));
+                    }
+                } else {
+                    self.fetch_task = None;
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
         }
     }
 
@@ -130,7 +233,12 @@ impl Component for LoginComponent {
         let password_oninput = self
             .link
             .callback(|e: InputData| Msg::EditPassword(e.value));
+        let remember_onchange = self.link.callback(Msg::ToggleRemember);
+        let totp_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditTotpCode(e.value));
         let login_click = self.link.callback(|_| Msg::Login);
+        let sso_click = self.link.callback(|_| Msg::SsoLogin);
         html! {
             <div class="container">
                 <div class="form">
@@ -158,6 +266,31 @@ impl Component for LoginComponent {
                             value=self.state.password.clone()
                             oninput=password_oninput />
                     </div>
+                    {
+                        if self.state.totp_required {
+                            html! {
+                                <div class="form-item">
+                                    <MatTextField
+                                        classes=classes!("form-input")
+                                        outlined=true
+                                        label=fluent!(self.props.lang_id, "totp-label")
+                                        helper=fluent!(self.props.lang_id, "totp-hint")
+                                        helper_persistent=true
+                                        value=self.state.totp_code.clone()
+                                        oninput=totp_oninput />
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class="form-item">
+                        <MatFormfield label=fluent!(self.props.lang_id, "remember-label")>
+                            <MatCheckbox
+                                checked=self.state.remember
+                                onchange=remember_onchange />
+                        </MatFormfield>
+                    </div>
                     {
                         if let Some(err) = &self.state.err {
                             html! {
@@ -191,6 +324,27 @@ impl Component for LoginComponent {
                                 raised=true />
                         </RouterAnchor<AppRoute>>
                     </div>
+                    {
+                        if let Some(provider) = &self.state.sso_provider {
+                            html! {
+                                <div class="form-item">
+                                    <span
+                                        onclick=sso_click
+                                        class="form-row-item"
+                                        disabled=self.need_to_disable() >
+                                        <MatButton
+                                            classes=classes!("form-button")
+                                            label=fluent!(self.props.lang_id, "btn-sso",
+                                                { "provider" => provider.as_str() })
+                                            disabled=self.need_to_disable()
+                                            outlined=true />
+                                    </span>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
                 </div>
             </div>
         }
@@ -1,7 +1,14 @@
+pub mod admin;
+pub mod api_keys;
+pub mod audit;
 pub mod default;
 pub mod device_content;
 pub mod home;
 pub mod login;
 pub mod logout_hint;
 pub mod modify_device;
+pub mod profile;
 pub mod register;
+pub mod security;
+pub mod sso_login;
+pub mod verify_mail;
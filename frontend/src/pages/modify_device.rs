@@ -1,5 +1,6 @@
 use crate::{fluent, route::AppRoute};
 use common::{
+    error::ApiError,
     request::ModifyDeviceRequest,
     response::{ErrorResponse, SimpleResponse},
 };
@@ -131,7 +132,7 @@ impl Component for ModifyDevice {
                 self.fetch_task = None;
                 if response.success {
                     self.state.success_hint = Some(fluent!(self.props.lang_id, "success-info"));
-                } else if response.err == "Login has expired" {
+                } else if response.code == Some(ApiError::LoginExpired) {
                     self.update(Msg::ToLogin);
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
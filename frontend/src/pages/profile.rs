@@ -0,0 +1,646 @@
+use crate::{
+    app::LANG_LIST_ITEMS,
+    fluent,
+    pages::register::{MAIL_RE, NAME_RE},
+    route::AppRoute,
+    utils::{card_div::CardDiv, download},
+};
+use chrono::{TimeZone, Utc};
+use chrono_tz::TZ_VARIANTS;
+use common::{
+    error::ApiError,
+    request::{
+        CancelAccountDeletionRequest, ChangeMailRequest, DeleteAccountRequest,
+        ExportAccountRequest, FetchProfileRequest, Role, UpdateProfileRequest,
+    },
+    response::{
+        ErrorResponse, FetchLoginOptionsResponse, FetchProfileResponse, ProfileInfo, SimpleResponse,
+    },
+};
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use sha2::{Digest, Sha256};
+use std::rc::Rc;
+use yew::{
+    agent::Bridged,
+    classes,
+    format::{Binary, Json},
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        FetchService,
+    },
+    Bridge, Callback, ChangeData, Component, ComponentLink, InputData, Properties,
+};
+use yew_material::{text_inputs::TextFieldType, MatButton, MatLinearProgress, MatTextField};
+use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
+
+static_loader! {
+    static LOCALES = {
+        locales: "./text/profile",
+        fallback_language: "zh-CN",
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+const EXPORT_FILE_NAME: &str = "bs-app-export.zip";
+const EXPORT_CONTENT_TYPE: &str = "application/zip";
+
+/// Profile of the user, where the name, the preferences and the e-mail address can be changed,
+/// the personal data exported and the account deleted
+pub struct Profile {
+    link: ComponentLink<Self>,
+    props: Props,
+    state: State,
+    route_agent: Box<dyn Bridge<RouteAgent>>,
+    fetch_task: Option<FetchTask>,
+}
+
+#[derive(Default)]
+struct State {
+    profile: Option<ProfileInfo>,
+    name: String,
+    language: String,
+    timezone: String,
+    new_mail: String,
+    mail_password: String,
+    password: String,
+    /// plain_password - the server checks passwords by a directory, which needs them as entered
+    plain_password: bool,
+    /// deletion_requested - the deletion has just been requested and every session has ended
+    deletion_requested: bool,
+    err: Option<String>,
+}
+
+pub enum Msg {
+    Nop,
+    ToLogin,
+    Logout,
+    EditPassword(String),
+    EditName(String),
+    SelectLanguage(String),
+    SelectTimezone(String),
+    EditNewMail(String),
+    EditMailPassword(String),
+    FetchLoginOptionsResponse(FetchLoginOptionsResponse),
+    Fetch,
+    FetchResponse(FetchProfileResponse),
+    Save,
+    SaveResponse(SimpleResponse),
+    ChangeMail,
+    ChangeMailResponse(SimpleResponse),
+    Export,
+    ExportResponse(Result<Vec<u8>, SimpleResponse>),
+    Delete,
+    DeleteResponse(SimpleResponse),
+    Cancel,
+    CancelResponse(SimpleResponse),
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub lang_id: LanguageIdentifier,
+    pub login_token: Rc<String>,
+    pub onlogout: Callback<()>,
+    /// onupdate - called with the profile whenever it is fetched, so that the app follows changes
+    pub onupdate: Callback<ProfileInfo>,
+}
+
+impl Component for Profile {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
+        let mut component = Self {
+            props,
+            link,
+            state: State::default(),
+            route_agent,
+            fetch_task: None,
+        };
+        if component.props.login_token.is_empty() {
+            component.update(Msg::ToLogin);
+        } else {
+            crate::create_fetch_task!(
+                component,
+                "/fetch_login_options",
+                (),
+                FetchLoginOptionsResponse,
+                FetchLoginOptionsResponse
+            );
+        }
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
+        match msg {
+            Msg::Nop => false,
+            Msg::ToLogin => {
+                self.route_agent
+                    .send(ChangeRoute(AppRoute::LogoutHint.into()));
+                true
+            }
+            Msg::Logout => {
+                self.props.onlogout.emit(());
+                false
+            }
+            Msg::EditPassword(password) => {
+                self.state.password = password;
+                false
+            }
+            Msg::EditName(name) => {
+                self.state.name = name;
+                false
+            }
+            Msg::SelectLanguage(language) => {
+                self.state.language = language;
+                false
+            }
+            Msg::SelectTimezone(timezone) => {
+                self.state.timezone = timezone;
+                false
+            }
+            Msg::EditNewMail(mail) => {
+                self.state.new_mail = mail;
+                false
+            }
+            Msg::EditMailPassword(password) => {
+                self.state.mail_password = password;
+                false
+            }
+            Msg::FetchLoginOptionsResponse(response) => {
+                self.state.plain_password = response.plain_password;
+                self.update(Msg::Fetch)
+            }
+            Msg::Fetch => {
+                let request = FetchProfileRequest {
+                    login_token: (*self.props.login_token).clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/fetch_profile",
+                    request,
+                    FetchProfileResponse,
+                    FetchResponse
+                );
+                true
+            }
+            Msg::FetchResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.name = response.profile.name.clone();
+                    self.state.language = response.profile.language.clone();
+                    self.state.timezone = response.profile.timezone.clone();
+                    self.props.onupdate.emit(response.profile.clone());
+                    self.state.profile = Some(response.profile);
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+            Msg::Save => {
+                if !NAME_RE.is_match(&self.state.name) {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-username"));
+                    return true;
+                }
+                self.state.err = None;
+                let request = UpdateProfileRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    name: Some(self.state.name.clone()),
+                    language: Some(self.state.language.clone()),
+                    timezone: Some(self.state.timezone.clone()),
+                };
+                crate::create_fetch_task!(self, "/update_profile", request, SaveResponse);
+                true
+            }
+            Msg::SaveResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.update(Msg::Fetch);
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+            Msg::ChangeMail => {
+                if !MAIL_RE.is_match(&self.state.new_mail) {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-email"));
+                    return true;
+                }
+                if self.state.mail_password.is_empty() && !self.sso() {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-password-empty"));
+                    return true;
+                }
+                self.state.err = None;
+                let (password, plain_password) = self.password_fields(&self.state.mail_password);
+                let request = ChangeMailRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    mail: self.state.new_mail.clone(),
+                    password,
+                    plain_password,
+                };
+                crate::create_fetch_task!(self, "/change_mail", request, ChangeMailResponse);
+                true
+            }
+            Msg::ChangeMailResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.new_mail.clear();
+                    self.state.mail_password.clear();
+                    self.update(Msg::Fetch);
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+            Msg::Export => {
+                self.state.err = None;
+                let request = ExportAccountRequest {
+                    login_token: (*self.props.login_token).clone(),
+                };
+                let body = serde_json::to_value(&request).unwrap();
+                let request = Request::post("/export_account")
+                    .header("Content-Type", "application/json")
+                    .body(Json(&body))
+                    .expect("Failed to construct fetch task");
+                let callback = self.link.callback(|response: Response<Binary>| {
+                    let success = response.status().is_success();
+                    match response.into_body() {
+                        Ok(data) if success => Msg::ExportResponse(Ok(data)),
+                        Ok(data) => Msg::ExportResponse(Err(serde_json::from_slice(&data)
+                            .unwrap_or_else(|_| SimpleResponse::err("error-unknown")))),
+                        Err(_) => Msg::ExportResponse(Err(SimpleResponse::err("error-net"))),
+                    }
+                });
+                let task =
+                    FetchService::fetch_binary(request, callback).expect("Failed to start request");
+                self.fetch_task = Some(task);
+                true
+            }
+            Msg::ExportResponse(response) => {
+                self.fetch_task = None;
+                match response {
+                    Ok(data) => {
+                        if download::save_file(&data, EXPORT_FILE_NAME, EXPORT_CONTENT_TYPE)
+                            .is_err()
+                        {
+                            self.state.err = Some(fluent!(self.props.lang_id, "error-unknown"));
+                        }
+                    }
+                    Err(response) => self.handle_err(&response.err, response.code),
+                }
+                true
+            }
+            Msg::Delete => {
+                if self.state.password.is_empty() && !self.sso() {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-password-empty"));
+                    return true;
+                }
+                self.state.err = None;
+                let (password, plain_password) = self.password_fields(&self.state.password);
+                let request = DeleteAccountRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    password,
+                    plain_password,
+                };
+                crate::create_fetch_task!(self, "/delete_account", request, DeleteResponse);
+                true
+            }
+            Msg::DeleteResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.password.clear();
+                    self.state.deletion_requested = true;
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+            Msg::Cancel => {
+                self.state.err = None;
+                let request = CancelAccountDeletionRequest {
+                    login_token: (*self.props.login_token).clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/cancel_account_deletion",
+                    request,
+                    CancelResponse
+                );
+                true
+            }
+            Msg::CancelResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.update(Msg::Fetch);
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> yew::Html {
+        html! {
+            <div class="container">
+                <div class="header">
+                    <h2>{ fluent!(self.props.lang_id, "header") }</h2>
+                </div>
+                <div class="form">
+                    { self.content_html() }
+                    {
+                        if let Some(err) = &self.state.err {
+                            html! {
+                                <div class="error-info">
+                                    <p>{ fluent!(self.props.lang_id, "error-label",
+                                        { "details" => err.as_str() } ) }</p>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    {
+                        if self.state.deletion_requested {
+                            html! {}
+                        } else {
+                            html! {
+                                <div class="form-item">
+                                    <RouterAnchor<AppRoute>
+                                        route={ AppRoute::Home }
+                                        classes="form-row-item">
+                                        <MatButton
+                                            classes=classes!("form-button")
+                                            label=fluent!(self.props.lang_id, "button-home")
+                                            disabled=self.need_to_disable()
+                                            raised=true />
+                                    </RouterAnchor<AppRoute>>
+                                </div>
+                            }
+                        }
+                    }
+                </div>
+                { self.fetching_progress() }
+            </div>
+        }
+    }
+}
+
+impl Profile {
+    fn need_to_disable(&self) -> bool {
+        self.fetch_task.is_some()
+    }
+
+    /// The account logs in by single sign-on, it may leave passwords empty after a recent login
+    fn sso(&self) -> bool {
+        self.state
+            .profile
+            .as_ref()
+            .map_or(false, |profile| profile.sso)
+    }
+
+    /// `password` and `plain_password` of a request, the password is hashed as for logging in
+    /// unless the server needs it as entered
+    fn password_fields(&self, password: &str) -> (String, Option<String>) {
+        if password.is_empty() {
+            (String::new(), None)
+        } else if self.state.plain_password {
+            (String::new(), Some(password.to_string()))
+        } else {
+            (format!("{:x}", Sha256::digest(password.as_bytes())), None)
+        }
+    }
+
+    fn handle_err(&mut self, err: &str, code: Option<ApiError>) {
+        if code == Some(ApiError::LoginExpired) {
+            self.update(Msg::ToLogin);
+        } else {
+            self.state.err = Some(fluent!(self.props.lang_id, err));
+        }
+    }
+
+    fn fetching_progress(&self) -> yew::Html {
+        if self.fetch_task.is_some() {
+            html! {
+                <div class="fetching-progress">
+                    <MatLinearProgress indeterminate=true />
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
+    fn button(&self, label_id: &str, msg: fn() -> Msg) -> yew::Html {
+        let onclick = self.link.callback(move |_| msg());
+        html! {
+            <div class="form-item">
+                <span onclick=onclick class="form-row-item" disabled=self.need_to_disable()>
+                    <MatButton
+                        classes=classes!("form-button")
+                        label=fluent!(self.props.lang_id, label_id)
+                        disabled=self.need_to_disable()
+                        raised=true />
+                </span>
+            </div>
+        }
+    }
+
+    fn content_html(&self) -> yew::Html {
+        if self.state.deletion_requested {
+            return html! {
+                <>
+                    <CardDiv classes=classes!("hint-info")>
+                        <p>{ fluent!(self.props.lang_id, "deletion-requested-hint") }</p>
+                    </CardDiv>
+                    { self.button("button-login", || Msg::Logout) }
+                </>
+            };
+        }
+        let profile = match &self.state.profile {
+            Some(profile) => profile,
+            None => return html! {},
+        };
+        html! {
+            <>
+                <div class="form-item">
+                    <p>{ fluent!(self.props.lang_id, "mail-label",
+                        { "mail" => profile.mail.as_str() }) }</p>
+                    <p>{ fluent!(self.props.lang_id, "role-label",
+                        { "role" => fluent!(self.props.lang_id, role_message_id(profile.role)) }) }</p>
+                </div>
+                { self.preferences_html() }
+                { self.mail_html(profile) }
+                <div class="form-item">
+                    <p>{ fluent!(self.props.lang_id, "export-hint") }</p>
+                </div>
+                { self.button("button-export", || Msg::Export) }
+                { self.deletion_html(profile) }
+            </>
+        }
+    }
+
+    fn preferences_html(&self) -> yew::Html {
+        let name_oninput = self.link.callback(|e: InputData| Msg::EditName(e.value));
+        let language_onchange = self.link.callback(|e: ChangeData| match e {
+            ChangeData::Select(select) => Msg::SelectLanguage(select.value()),
+            _ => Msg::Nop,
+        });
+        let timezone_onchange = self.link.callback(|e: ChangeData| match e {
+            ChangeData::Select(select) => Msg::SelectTimezone(select.value()),
+            _ => Msg::Nop,
+        });
+        html! {
+            <>
+                <div class="form-item">
+                    <MatTextField
+                        classes=classes!("form-input")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "name-label")
+                        helper=fluent!(self.props.lang_id, "name-hint")
+                        helper_persistent=true
+                        value=self.state.name.clone()
+                        oninput=name_oninput />
+                </div>
+                <div class="form-item">
+                    <label class="form-row-item">
+                        { fluent!(self.props.lang_id, "language-label") }
+                    </label>
+                    <select class="form-row-item" onchange=language_onchange>
+                        <option value="" selected=self.state.language.is_empty()>
+                            { fluent!(self.props.lang_id, "language-browser") }
+                        </option>
+                        {
+                            for LANG_LIST_ITEMS.iter().map(|item| html! {
+                                <option value=item.1 selected=item.1 == self.state.language>
+                                    { item.0 }
+                                </option>
+                            })
+                        }
+                    </select>
+                </div>
+                <div class="form-item">
+                    <label class="form-row-item">
+                        { fluent!(self.props.lang_id, "timezone-label") }
+                    </label>
+                    <select class="form-row-item" onchange=timezone_onchange>
+                        <option value="" selected=self.state.timezone.is_empty()>
+                            { "UTC" }
+                        </option>
+                        {
+                            for TZ_VARIANTS.iter().map(|tz| html! {
+                                <option value=tz.name() selected=tz.name() == self.state.timezone>
+                                    { tz.name() }
+                                </option>
+                            })
+                        }
+                    </select>
+                </div>
+                { self.button("button-save", || Msg::Save) }
+            </>
+        }
+    }
+
+    fn mail_html(&self, profile: &ProfileInfo) -> yew::Html {
+        let mail_oninput = self.link.callback(|e: InputData| Msg::EditNewMail(e.value));
+        let password_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditMailPassword(e.value));
+        html! {
+            <>
+                {
+                    if let Some(pending_mail) = &profile.pending_mail {
+                        html! {
+                            <CardDiv classes=classes!("hint-info")>
+                                <p>{ fluent!(self.props.lang_id, "pending-mail-hint",
+                                    { "mail" => pending_mail.as_str() }) }</p>
+                            </CardDiv>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                <div class="form-item">
+                    <p>{ fluent!(self.props.lang_id, "change-mail-hint") }</p>
+                </div>
+                <div class="form-item">
+                    <MatTextField
+                        classes=classes!("form-input")
+                        outlined=true
+                        label=fluent!(self.props.lang_id, "new-mail-label")
+                        value=self.state.new_mail.clone()
+                        oninput=mail_oninput />
+                </div>
+                <div class="form-item">
+                    <MatTextField
+                        classes=classes!("form-input")
+                        outlined=true
+                        field_type=TextFieldType::Password
+                        label=fluent!(self.props.lang_id, "password-label")
+                        helper=fluent!(self.props.lang_id,
+                            if profile.sso { "sso-mail-password-hint" } else { "mail-password-hint" })
+                        helper_persistent=true
+                        value=self.state.mail_password.clone()
+                        oninput=password_oninput />
+                </div>
+                { self.button("button-change-mail", || Msg::ChangeMail) }
+            </>
+        }
+    }
+
+    fn deletion_html(&self, profile: &ProfileInfo) -> yew::Html {
+        if let Some(deletion_due_at) = profile.deletion_due_at {
+            return html! {
+                <>
+                    <CardDiv classes=classes!("hint-info")>
+                        <p>{ fluent!(self.props.lang_id, "deletion-due-hint",
+                            { "time" => format_time(deletion_due_at) }) }</p>
+                    </CardDiv>
+                    { self.button("button-cancel-deletion", || Msg::Cancel) }
+                </>
+            };
+        }
+        let password_oninput = self
+            .link
+            .callback(|e: InputData| Msg::EditPassword(e.value));
+        html! {
+            <>
+                <div class="form-item">
+                    <p>{ fluent!(self.props.lang_id, "delete-hint") }</p>
+                </div>
+                <div class="form-item">
+                    <MatTextField
+                        classes=classes!("form-input")
+                        outlined=true
+                        field_type=TextFieldType::Password
+                        label=fluent!(self.props.lang_id, "password-label")
+                        helper=fluent!(self.props.lang_id,
+                            if profile.sso { "sso-password-hint" } else { "password-hint" })
+                        helper_persistent=true
+                        value=self.state.password.clone()
+                        oninput=password_oninput />
+                </div>
+                { self.button("button-delete", || Msg::Delete) }
+            </>
+        }
+    }
+}
+
+fn role_message_id(role: Role) -> &'static str {
+    match role {
+        Role::User => "role-user",
+        Role::Admin => "role-admin",
+    }
+}
+
+fn format_time(timestamp: i64) -> String {
+    Utc.timestamp_millis(timestamp)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
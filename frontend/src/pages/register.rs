@@ -1,4 +1,4 @@
-use crate::{fluent, route::AppRoute};
+use crate::{fluent, route::AppRoute, utils::card_div::CardDiv};
 use common::{
     request::RegisterRequest,
     response::{ErrorResponse, SimpleResponse},
@@ -8,7 +8,6 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use sha2::{Digest, Sha256};
 use yew::{
-    agent::Bridged,
     classes,
     format::Json,
     html,
@@ -17,13 +16,13 @@ use yew::{
         FetchService,
     },
     web_sys::HtmlInputElement,
-    Bridge, Callback, Component, ComponentLink, InputData, NodeRef, Properties,
+    Component, ComponentLink, InputData, NodeRef, Properties,
 };
 use yew_material::{
     text_inputs::{TextFieldType, ValidityState},
     MatButton, MatTextField,
 };
-use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
+use yew_router::prelude::*;
 
 static_loader! {
     static LOCALES = {
@@ -37,7 +36,6 @@ pub struct RegisterComponent {
     link: ComponentLink<Self>,
     props: Props,
     state: State,
-    route_agent: Box<dyn Bridge<RouteAgent>>,
     fetch_task: Option<FetchTask>,
     password_ref: NodeRef,
 }
@@ -48,11 +46,12 @@ struct State {
     name: String,
     password: String,
     password_twice: String,
+    /// registered - the verification mail has been sent, the user logs in after verifying
+    registered: bool,
     err: Option<String>,
 }
 
 pub enum Msg {
-    Nop,
     EditMail(String),
     EditName(String),
     EditPassword(String),
@@ -64,13 +63,12 @@ pub enum Msg {
 #[derive(Properties, Clone)]
 pub struct Props {
     pub lang_id: LanguageIdentifier,
-    pub onregister: Callback<()>,
 }
 
 lazy_static! {
-    static ref MAIL_RE: Regex =
+    pub(crate) static ref MAIL_RE: Regex =
         Regex::new(r"^[0-9a-zA-Z._+-]+@[0-9a-zA-Z-]+\.[0-9a-zA-Z-.]+$").unwrap();
-    static ref NAME_RE: Regex = Regex::new(r"^[0-9a-zA-Z_]{4, 32}$").unwrap();
+    pub(crate) static ref NAME_RE: Regex = Regex::new(r"^[0-9a-zA-Z_]{4, 32}$").unwrap();
     static ref PASSWORD_RE: Regex = Regex::new(r"^[0-9a-zA-Z_]{6, 32}$").unwrap();
 }
 
@@ -79,12 +77,10 @@ impl Component for RegisterComponent {
     type Properties = Props;
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
-        let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
         Self {
             link,
             props,
             state: State::default(),
-            route_agent,
             fetch_task: None,
             password_ref: NodeRef::default(),
         }
@@ -92,7 +88,6 @@ impl Component for RegisterComponent {
 
     fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
         match msg {
-            Msg::Nop => false,
             Msg::EditMail(mail) => {
                 self.state.mail = mail;
                 false
@@ -110,6 +105,9 @@ impl Component for RegisterComponent {
                 false
             }
             Msg::Register => {
+                if self.state.registered {
+                    return false;
+                }
                 self.state.err = None;
                 if !MAIL_RE.is_match(&self.state.mail) {
                     self.state.err = Some(fluent!(self.props.lang_id, "error-email"));
@@ -134,8 +132,7 @@ impl Component for RegisterComponent {
             Msg::RegisterResponse(response) => {
                 self.fetch_task = None;
                 if response.success {
-                    self.route_agent.send(ChangeRoute(AppRoute::Login.into()));
-                    self.props.onregister.emit(());
+                    self.state.registered = true;
                 } else {
                     self.state.err = Some(fluent!(self.props.lang_id, &response.err));
                 }
@@ -263,6 +260,18 @@ impl Component for RegisterComponent {
                             value=self.state.password_twice.clone()
                             oninput=password2_oninput />
                     </div>
+                    {
+                        if self.state.registered {
+                            html! {
+                                <CardDiv classes=classes!("hint-info")>
+                                    <p>{ fluent!(self.props.lang_id, "registered-hint",
+                                        { "email" => self.state.mail.as_str() }) }</p>
+                                </CardDiv>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
                     {
                         if let Some(err) = &self.state.err {
                             html! {
@@ -279,11 +288,11 @@ impl Component for RegisterComponent {
                         <span
                             onclick=register_click
                             class="form-row-item"
-                            disabled=self.need_to_disable() >
+                            disabled=self.need_to_disable() || self.state.registered >
                             <MatButton
                                 classes=classes!("form-button")
                                 label=fluent!(self.props.lang_id, "button-register")
-                                disabled=self.need_to_disable()
+                                disabled=self.need_to_disable() || self.state.registered
                                 raised=true />
                         </span>
                         <RouterAnchor<AppRoute>
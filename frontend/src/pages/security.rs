@@ -0,0 +1,373 @@
+use crate::{
+    fluent,
+    route::AppRoute,
+    utils::{card_div::CardDiv, qr_code::QrCodeView},
+};
+use common::{
+    error::ApiError,
+    request::{ConfirmTotpRequest, DisableTotpRequest, EnrollTotpRequest, FetchTotpStatusRequest},
+    response::{
+        ConfirmTotpResponse, EnrollTotpResponse, ErrorResponse, FetchTotpStatusResponse,
+        SimpleResponse,
+    },
+};
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use std::rc::Rc;
+use yew::{
+    agent::Bridged,
+    classes,
+    format::Json,
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        FetchService,
+    },
+    Bridge, Component, ComponentLink, InputData, Properties,
+};
+use yew_material::{MatButton, MatLinearProgress, MatTextField};
+use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
+
+static_loader! {
+    static LOCALES = {
+        locales: "./text/security",
+        fallback_language: "zh-CN",
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+pub struct Security {
+    link: ComponentLink<Self>,
+    props: Props,
+    state: State,
+    route_agent: Box<dyn Bridge<RouteAgent>>,
+    fetch_task: Option<FetchTask>,
+}
+
+#[derive(Default)]
+struct State {
+    enabled: bool,
+    recovery_codes_left: u32,
+    /// enrollment - (secret, provisioning URI) waiting to be confirmed
+    enrollment: Option<(String, String)>,
+    code: String,
+    /// recovery_codes - the codes just generated, which are only shown once
+    recovery_codes: Vec<String>,
+    err: Option<String>,
+}
+
+pub enum Msg {
+    Nop,
+    ToLogin,
+    EditCode(String),
+    Fetch,
+    FetchResponse(FetchTotpStatusResponse),
+    Enroll,
+    EnrollResponse(EnrollTotpResponse),
+    Confirm,
+    ConfirmResponse(ConfirmTotpResponse),
+    Disable,
+    DisableResponse(SimpleResponse),
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub lang_id: LanguageIdentifier,
+    pub login_token: Rc<String>,
+}
+
+impl Component for Security {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
+        let mut component = Self {
+            props,
+            link,
+            state: State::default(),
+            route_agent,
+            fetch_task: None,
+        };
+        if component.props.login_token.is_empty() {
+            component.update(Msg::ToLogin);
+        } else {
+            component.update(Msg::Fetch);
+        }
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
+        match msg {
+            Msg::Nop => false,
+            Msg::ToLogin => {
+                self.route_agent
+                    .send(ChangeRoute(AppRoute::LogoutHint.into()));
+                true
+            }
+            Msg::EditCode(code) => {
+                self.state.code = code;
+                false
+            }
+            Msg::Fetch => {
+                let request = FetchTotpStatusRequest {
+                    login_token: (*self.props.login_token).clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/fetch_totp_status",
+                    request,
+                    FetchTotpStatusResponse,
+                    FetchResponse
+                );
+                true
+            }
+            Msg::FetchResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.enabled = response.enabled;
+                    self.state.recovery_codes_left = response.recovery_codes_left;
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+            Msg::Enroll => {
+                self.state.err = None;
+                self.state.recovery_codes.clear();
+                let request = EnrollTotpRequest {
+                    login_token: (*self.props.login_token).clone(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/enroll_totp",
+                    request,
+                    EnrollTotpResponse,
+                    EnrollResponse
+                );
+                true
+            }
+            Msg::EnrollResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.code.clear();
+                    self.state.enrollment = Some((response.secret, response.uri));
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+            Msg::Confirm => {
+                if self.state.code.trim().is_empty() {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-code-empty"));
+                    return true;
+                }
+                self.state.err = None;
+                let request = ConfirmTotpRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    code: self.state.code.trim().to_string(),
+                };
+                crate::create_fetch_task!(
+                    self,
+                    "/confirm_totp",
+                    request,
+                    ConfirmTotpResponse,
+                    ConfirmResponse
+                );
+                true
+            }
+            Msg::ConfirmResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.code.clear();
+                    self.state.enrollment = None;
+                    self.state.recovery_codes = response.recovery_codes;
+                    self.update(Msg::Fetch);
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+            Msg::Disable => {
+                if self.state.code.trim().is_empty() {
+                    self.state.err = Some(fluent!(self.props.lang_id, "error-code-empty"));
+                    return true;
+                }
+                self.state.err = None;
+                let request = DisableTotpRequest {
+                    login_token: (*self.props.login_token).clone(),
+                    code: self.state.code.trim().to_string(),
+                };
+                crate::create_fetch_task!(self, "/disable_totp", request, DisableResponse);
+                true
+            }
+            Msg::DisableResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.code.clear();
+                    self.state.recovery_codes.clear();
+                    self.update(Msg::Fetch);
+                } else {
+                    self.handle_err(&response.err, response.code);
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> yew::Html {
+        html! {
+            <div class="container">
+                <div class="header">
+                    <h2>{ fluent!(self.props.lang_id, "header") }</h2>
+                </div>
+                <div class="form">
+                    <div class="form-item">
+                        <p>{
+                            if self.state.enabled {
+                                fluent!(self.props.lang_id, "status-enabled", {
+                                    "count" => self.state.recovery_codes_left,
+                                })
+                            } else {
+                                fluent!(self.props.lang_id, "status-disabled")
+                            }
+                        }</p>
+                    </div>
+                    { self.form_html() }
+                    { self.recovery_codes_html() }
+                    {
+                        if let Some(err) = &self.state.err {
+                            html! {
+                                <div class="error-info">
+                                    <p>{ fluent!(self.props.lang_id, "error-label",
+                                        { "details" => err.as_str() } ) }</p>
+                                </div>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class="form-item">
+                        <RouterAnchor<AppRoute>
+                            route={ AppRoute::Home }
+                            classes="form-row-item">
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-home")
+                                disabled=self.need_to_disable()
+                                raised=true />
+                        </RouterAnchor<AppRoute>>
+                    </div>
+                </div>
+                { self.fetching_progress() }
+            </div>
+        }
+    }
+}
+
+impl Security {
+    fn need_to_disable(&self) -> bool {
+        self.fetch_task.is_some()
+    }
+
+    fn handle_err(&mut self, err: &str, code: Option<ApiError>) {
+        if code == Some(ApiError::LoginExpired) {
+            self.update(Msg::ToLogin);
+        } else {
+            self.state.err = Some(fluent!(self.props.lang_id, err));
+        }
+    }
+
+    fn fetching_progress(&self) -> yew::Html {
+        if self.fetch_task.is_some() {
+            html! {
+                <div class="fetching-progress">
+                    <MatLinearProgress indeterminate=true />
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
+    fn code_field(&self, hint_id: &str) -> yew::Html {
+        let code_oninput = self.link.callback(|e: InputData| Msg::EditCode(e.value));
+        html! {
+            <div class="form-item">
+                <MatTextField
+                    classes=classes!("form-input")
+                    outlined=true
+                    label=fluent!(self.props.lang_id, "code-label")
+                    helper=fluent!(self.props.lang_id, hint_id)
+                    helper_persistent=true
+                    value=self.state.code.clone()
+                    oninput=code_oninput />
+            </div>
+        }
+    }
+
+    fn button(&self, label_id: &str, msg: fn() -> Msg) -> yew::Html {
+        let onclick = self.link.callback(move |_| msg());
+        html! {
+            <div class="form-item">
+                <span onclick=onclick class="form-row-item" disabled=self.need_to_disable()>
+                    <MatButton
+                        classes=classes!("form-button")
+                        label=fluent!(self.props.lang_id, label_id)
+                        disabled=self.need_to_disable()
+                        raised=true />
+                </span>
+            </div>
+        }
+    }
+
+    fn form_html(&self) -> yew::Html {
+        if self.state.enabled {
+            html! {
+                <>
+                    { self.code_field("code-or-recovery-hint") }
+                    { self.button("button-disable", || Msg::Disable) }
+                </>
+            }
+        } else if let Some((secret, uri)) = &self.state.enrollment {
+            html! {
+                <>
+                    <div class="form-item">
+                        <p>{ fluent!(self.props.lang_id, "scan-hint") }</p>
+                    </div>
+                    <div class="form-item">
+                        <QrCodeView data=uri.clone() />
+                    </div>
+                    <div class="form-item">
+                        <p>{ fluent!(self.props.lang_id, "secret-label") }</p>
+                        <p><code>{ secret }</code></p>
+                    </div>
+                    { self.code_field("code-hint") }
+                    { self.button("button-confirm", || Msg::Confirm) }
+                </>
+            }
+        } else {
+            self.button("button-enable", || Msg::Enroll)
+        }
+    }
+
+    fn recovery_codes_html(&self) -> yew::Html {
+        if self.state.recovery_codes.is_empty() {
+            return html! {};
+        }
+        html! {
+            <CardDiv classes=classes!("hint-info")>
+                <p>{ fluent!(self.props.lang_id, "recovery-codes-hint") }</p>
+                {
+                    for self.state.recovery_codes.iter().map(|code| html! {
+                        <p><code>{ code }</code></p>
+                    })
+                }
+            </CardDiv>
+        }
+    }
+}
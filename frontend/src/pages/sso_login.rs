@@ -0,0 +1,170 @@
+use crate::{fluent, route::AppRoute};
+use common::{
+    request::SsoLoginRequest,
+    response::{ErrorResponse, LoginResponse},
+};
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use yew::{
+    agent::Bridged,
+    classes,
+    format::Json,
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        FetchService,
+    },
+    Bridge, Callback, Component, ComponentLink, Properties,
+};
+use yew_material::{MatButton, MatLinearProgress};
+use yew_router::{agent::RouteRequest::ChangeRoute, prelude::*};
+
+static_loader! {
+    static LOCALES = {
+        locales: "./text/sso_login",
+        fallback_language: "zh-CN",
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+/// Redirected to by `/sso_callback` after the user has signed in at the provider, the login is
+/// finished as soon as the page is shown. An empty state means the provider refused the login.
+pub struct SsoLogin {
+    link: ComponentLink<Self>,
+    props: Props,
+    err: Option<String>,
+    route_agent: Box<dyn Bridge<RouteAgent>>,
+    fetch_task: Option<FetchTask>,
+}
+
+pub enum Msg {
+    Nop,
+    Login,
+    LoginResponse(LoginResponse),
+}
+
+#[derive(Properties, Clone)]
+pub struct Props {
+    pub lang_id: LanguageIdentifier,
+    pub state: String,
+    pub code: String,
+    pub onlogin: Callback<LoginResponse>,
+}
+
+impl Component for SsoLogin {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let route_agent = RouteAgent::bridge(link.callback(|_| Msg::Nop));
+        let mut component = Self {
+            link,
+            props,
+            err: None,
+            route_agent,
+            fetch_task: None,
+        };
+        component.update(Msg::Login);
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
+        match msg {
+            Msg::Nop => false,
+            Msg::Login => {
+                self.err = None;
+                if self.props.state.is_empty() {
+                    self.err = Some(fluent!(self.props.lang_id, "error-sso-failed"));
+                } else {
+                    let request = SsoLoginRequest {
+                        state: decode(&self.props.state),
+                        code: decode(&self.props.code),
+                    };
+                    crate::create_fetch_task!(
+                        self,
+                        "/sso_login",
+                        request,
+                        LoginResponse,
+                        LoginResponse
+                    );
+                }
+                true
+            }
+            Msg::LoginResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.route_agent.send(ChangeRoute(AppRoute::Home.into()));
+                    self.props.onlogin.emit(response);
+                } else {
+                    self.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        let changed = self.props.state != props.state || self.props.code != props.code;
+        self.props = props;
+        if changed {
+            self.update(Msg::Login);
+        }
+        true
+    }
+
+    fn view(&self) -> yew::Html {
+        html! {
+            <div class="container">
+                <div class="header">
+                    <h2>{ fluent!(self.props.lang_id, "header") }</h2>
+                </div>
+                <div class="form">
+                    {
+                        if let Some(err) = &self.err {
+                            html! {
+                                <div class="error-info">
+                                    <p>{ fluent!(self.props.lang_id, "error-label",
+                                        { "details" => err.as_str() }) }</p>
+                                </div>
+                            }
+                        } else {
+                            html! {
+                                <div class="form-item">
+                                    <p>{ fluent!(self.props.lang_id, "logging-in") }</p>
+                                </div>
+                            }
+                        }
+                    }
+                    <div class="form-item">
+                        <RouterAnchor<AppRoute>
+                            route={ AppRoute::Login }
+                            classes="form-row-item">
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-login")
+                                disabled=self.fetch_task.is_some()
+                                raised=true />
+                        </RouterAnchor<AppRoute>>
+                    </div>
+                </div>
+                {
+                    if self.fetch_task.is_some() {
+                        html! {
+                            <div class="fetching-progress">
+                                <MatLinearProgress indeterminate=true />
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </div>
+        }
+    }
+}
+
+/// The route keeps the parameters percent-encoded as `/sso_callback` has put them
+fn decode(param: &str) -> String {
+    js_sys::decode_uri_component(param)
+        .map(String::from)
+        .unwrap_or_else(|_| param.to_string())
+}
@@ -0,0 +1,156 @@
+use crate::{fluent, route::AppRoute};
+use common::{
+    request::VerifyMailRequest,
+    response::{ErrorResponse, SimpleResponse},
+};
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use yew::{
+    classes,
+    format::Json,
+    html,
+    services::{
+        fetch::{FetchTask, Request, Response},
+        FetchService,
+    },
+    Component, ComponentLink, Properties,
+};
+use yew_material::{MatButton, MatLinearProgress};
+use yew_router::prelude::*;
+
+static_loader! {
+    static LOCALES = {
+        locales: "./text/verify_mail",
+        fallback_language: "zh-CN",
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+/// Opened by the link in a verification mail, the token is posted as soon as the page is shown
+pub struct VerifyMail {
+    link: ComponentLink<Self>,
+    props: Props,
+    state: State,
+    fetch_task: Option<FetchTask>,
+}
+
+#[derive(Default)]
+struct State {
+    verified: bool,
+    err: Option<String>,
+}
+
+pub enum Msg {
+    Verify,
+    VerifyResponse(SimpleResponse),
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub lang_id: LanguageIdentifier,
+    pub token: String,
+}
+
+impl Component for VerifyMail {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = Self {
+            link,
+            props,
+            state: State::default(),
+            fetch_task: None,
+        };
+        component.update(Msg::Verify);
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> yew::ShouldRender {
+        match msg {
+            Msg::Verify => {
+                self.state.err = None;
+                let request = VerifyMailRequest {
+                    token: self.props.token.clone(),
+                };
+                crate::create_fetch_task!(self, "/verify_mail", request, VerifyResponse);
+                true
+            }
+            Msg::VerifyResponse(response) => {
+                self.fetch_task = None;
+                if response.success {
+                    self.state.verified = true;
+                } else {
+                    self.state.err = Some(fluent!(self.props.lang_id, &response.err));
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        if self.props.token != props.token {
+            self.props = props;
+            self.state = State::default();
+            self.update(Msg::Verify);
+        } else {
+            self.props = props;
+        }
+        true
+    }
+
+    fn view(&self) -> yew::Html {
+        html! {
+            <div class="container">
+                <div class="header">
+                    <h2>{ fluent!(self.props.lang_id, "header") }</h2>
+                </div>
+                <div class="form">
+                    {
+                        if let Some(err) = &self.state.err {
+                            html! {
+                                <div class="error-info">
+                                    <p>{ fluent!(self.props.lang_id, "error-label",
+                                        { "details" => err.as_str() }) }</p>
+                                </div>
+                            }
+                        } else if self.state.verified {
+                            html! {
+                                <div class="form-item">
+                                    <p>{ fluent!(self.props.lang_id, "verified") }</p>
+                                </div>
+                            }
+                        } else {
+                            html! {
+                                <div class="form-item">
+                                    <p>{ fluent!(self.props.lang_id, "verifying") }</p>
+                                </div>
+                            }
+                        }
+                    }
+                    <div class="form-item">
+                        <RouterAnchor<AppRoute>
+                            route={ AppRoute::Login }
+                            classes="form-row-item">
+                            <MatButton
+                                classes=classes!("form-button")
+                                label=fluent!(self.props.lang_id, "button-login")
+                                disabled=self.fetch_task.is_some()
+                                raised=true />
+                        </RouterAnchor<AppRoute>>
+                    </div>
+                </div>
+                {
+                    if self.fetch_task.is_some() {
+                        html! {
+                            <div class="fetching-progress">
+                                <MatLinearProgress indeterminate=true />
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </div>
+        }
+    }
+}
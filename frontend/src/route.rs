@@ -12,6 +12,22 @@ pub enum AppRoute {
     ModifyDevice,
     #[to = "/#device_content"]
     DeviceContent,
+    #[to = "/#api_keys"]
+    ApiKeys,
+    #[to = "/#security"]
+    Security,
+    #[to = "/#profile"]
+    Profile,
+    #[to = "/#admin"]
+    Admin,
+    #[to = "/#audit"]
+    Audit,
+    #[to = "/#verify_mail/{}"]
+    VerifyMail(String),
+    #[to = "/#sso_login/{}/{}"]
+    SsoLogin(String, String),
+    #[to = "/#sso_failed"]
+    SsoFailed,
     #[to = "/#go_to_login"]
     LogoutHint,
     #[to = "/"]
@@ -0,0 +1,22 @@
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+/// Let the browser save `data` as a file, as if it were downloaded from a link
+pub fn save_file(data: &[u8], file_name: &str, content_type: &str) -> Result<(), JsValue> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let mut options = BlobPropertyBag::new();
+    options.type_(content_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let anchor: HtmlAnchorElement = web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .create_element("a")?
+        .dyn_into()?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+    Url::revoke_object_url(&url)
+}
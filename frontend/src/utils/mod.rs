@@ -1,7 +1,9 @@
 pub mod card_div;
+pub mod download;
 pub mod line_chart;
 pub mod map;
 pub mod paged_list;
+pub mod qr_code;
 
 #[macro_export]
 macro_rules! create_fetch_task {
@@ -0,0 +1,62 @@
+use qrcode::{render::svg, QrCode};
+use web_sys::Element;
+use yew::{virtual_dom::VNode, Component, Properties};
+
+/// A QR code rendered as an inline SVG
+pub struct QrCodeView {
+    element: Element,
+    props: QrCodeProps,
+}
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct QrCodeProps {
+    pub data: String,
+    /// size - minimum width and height in pixels
+    #[prop_or(200)]
+    pub size: u32,
+}
+
+impl Component for QrCodeView {
+    type Message = ();
+    type Properties = QrCodeProps;
+
+    fn create(props: Self::Properties, _link: yew::ComponentLink<Self>) -> Self {
+        Self {
+            element: web_sys::window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .create_element("div")
+                .unwrap(),
+            props,
+        }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> yew::ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> yew::ShouldRender {
+        if self.props != props {
+            self.props = props;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn view(&self) -> yew::Html {
+        let svg = match QrCode::new(self.props.data.as_bytes()) {
+            Ok(code) => code
+                .render::<svg::Color>()
+                .min_dimensions(self.props.size, self.props.size)
+                .build(),
+            // too long to be encoded
+            Err(_) => String::new(),
+        };
+        self.element.set_inner_html(&svg);
+        self.element.set_class_name("qr-code");
+
+        VNode::VRef(self.element.clone().into())
+    }
+}
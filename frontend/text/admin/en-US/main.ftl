@@ -0,0 +1,48 @@
+header = Administration
+query-label = Search Users
+query-hint = Part of the e-mail address or the name, empty for every user
+button-search = Search
+button-audit = Audit Log
+button-home = Go Back to Home
+users-title = Users ({ $count })
+devices-title = Devices ({ $count })
+no-users = No users found
+no-devices = No devices yet
+provision-id-label = Device ID
+provision-name-label = Device Name
+provision-name-hint = Optional, the ID if empty
+button-provision = Provision Device
+provisioned-hint = Device { $id } is ready to be claimed. Print the claim code or the QR code on it, it is shown only this once:
+totp-reset-hint = Two-factor authentication of { $mail } is disabled, the user can log in with the password alone and enable it again
+device-owner = Claimed by { $owner }
+device-unclaimed = Not claimed
+role-user = User
+role-admin = Administrator
+user-unverified = E-mail not verified
+user-disabled = Disabled
+user-devices = Follows { $count ->
+        [one] 1 device
+        *[other] { $count } devices
+    }
+device-stat = { $total ->
+        [one] 1 message
+        *[other] { $total } messages
+    }, { $alert ->
+        [one] 1 is
+        *[other] { $alert } are
+    } alert
+button-disable = Disable
+button-enable = Enable
+button-make-admin = Make Administrator
+button-make-user = Make User
+button-impersonate = Log in as User
+button-reset-totp = Reset Two-Factor Authentication
+button-delete = Delete
+error-label = Error: { $details }
+error-forbidden = Only administrators can open this page
+error-invalid-request = Administrators can't change themselves
+error-no-user = User doesn't exist
+error-device-claimed = The device has already been claimed
+error-account-disabled = The account is disabled
+error-net = Net error
+error-unknown = Unknown error
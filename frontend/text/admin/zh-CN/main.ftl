@@ -0,0 +1,39 @@
+header = 管理
+query-label = 搜索用户
+query-hint = 邮箱或用户名的一部分，留空显示全部用户
+button-search = 搜索
+button-audit = 审计日志
+button-home = 返回主页
+users-title = 用户（{ $count }）
+devices-title = 设备（{ $count }）
+no-users = 没有找到用户
+no-devices = 暂无设备
+provision-id-label = 设备 ID
+provision-name-label = 设备名称
+provision-name-hint = 可选，留空时使用设备 ID
+button-provision = 登记设备
+provisioned-hint = 设备 { $id } 已可被认领。请将认领码或二维码印在设备上，它们只显示这一次：
+totp-reset-hint = 已关闭 { $mail } 的两步验证，该用户可以仅凭密码登录并重新启用
+device-owner = 已被 { $owner } 认领
+device-unclaimed = 未被认领
+role-user = 普通用户
+role-admin = 管理员
+user-unverified = 邮箱未验证
+user-disabled = 已禁用
+user-devices = 关注了 { $count } 个设备
+device-stat = { $total } 条信息，{ $alert } 条存在警告
+button-disable = 禁用
+button-enable = 启用
+button-make-admin = 设为管理员
+button-make-user = 设为普通用户
+button-impersonate = 以该用户登录
+button-reset-totp = 重置两步验证
+button-delete = 删除
+error-label = 错误：{ $details }
+error-forbidden = 只有管理员可以打开此页面
+error-invalid-request = 管理员不能修改自己
+error-no-user = 该用户不存在
+error-device-claimed = 该设备已被认领
+error-account-disabled = 该账户已被禁用
+error-net = 网络错误
+error-unknown = 未知错误
@@ -0,0 +1,25 @@
+header = API Keys
+name-label = Key Name
+name-hint = What the key is used for, e.g. the name of a script (at most 64 characters)
+scope-label = Scope
+scope-read-only = Read only
+scope-device-write = Read and modify devices
+scope-admin = Full access, including API keys
+expires-label = Expires on (leave empty for never)
+new-key-hint = Copy the key now, it won't be shown again:
+no-keys = No API keys yet
+never = Never
+key-created = Created at { $time }
+key-expires = Expires at { $time }
+key-last-used = Last used at { $time }
+button-create = Create Key
+button-revoke = Revoke
+button-home = Go Back to Home
+error-label = Error: { $details }
+error-name = Key name can't be empty
+error-expires = Invalid expiry date
+error-invalid-request = Invalid name or expiry date
+error-forbidden = Permission denied
+error-no-api-key = API key doesn't exist
+error-net = Net error
+error-unknown = Unknown error
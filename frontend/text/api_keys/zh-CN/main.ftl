@@ -0,0 +1,25 @@
+header = API 密钥
+name-label = 密钥名称
+name-hint = 密钥的用途，例如脚本名称（至多 64 个字符）
+scope-label = 权限范围
+scope-read-only = 只读
+scope-device-write = 读取并修改设备
+scope-admin = 完全访问，包括管理 API 密钥
+expires-label = 过期日期（留空则永不过期）
+new-key-hint = 请立即复制该密钥，之后将无法再次查看：
+no-keys = 还没有 API 密钥
+never = 从不
+key-created = 创建于 { $time }
+key-expires = 过期于 { $time }
+key-last-used = 最近使用于 { $time }
+button-create = 创建密钥
+button-revoke = 撤销
+button-home = 返回主页
+error-label = 错误：{ $details }
+error-name = 密钥名称不能为空
+error-expires = 过期日期无效
+error-invalid-request = 名称或过期日期无效
+error-forbidden = 没有权限
+error-no-api-key = 该 API 密钥不存在
+error-net = 网络错误
+error-unknown = 未知错误
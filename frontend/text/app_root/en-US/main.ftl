@@ -1 +1,2 @@
-header = Device Viewer
\ No newline at end of file
+header = Device Viewer
+switch-org = Switch organization
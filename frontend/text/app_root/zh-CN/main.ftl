@@ -1 +1,2 @@
 header = 设备浏览
+switch-org = 切换组织
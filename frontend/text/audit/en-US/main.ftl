@@ -0,0 +1,38 @@
+header = Audit Log
+actor-label = Actor
+actor-hint = E-mail address of the user who did it, empty for anyone
+target-label = Target
+target-hint = E-mail address of a user or ID of a device, empty for any
+action-label = Action
+action-all = All
+action-login = Login
+action-login-failed = Failed login
+action-register = Registration
+action-create-device = Device followed
+action-modify-device = Device modified
+action-remove-device = Device unfollowed
+action-update-user = User updated
+action-delete-user = User deleted
+action-impersonate = Logged in as user
+action-request-deletion = Account deletion requested
+action-cancel-deletion = Account deletion canceled
+action-delete-account = Account deleted
+action-create-org = Organization created
+action-add-org-member = Organization member added
+action-remove-org-member = Organization member removed
+action-provision-device = Device provisioned
+action-send-command = Command sent to device
+action-update-desired = Desired device state changed
+action-reset-totp = Two-factor authentication reset
+from-label = From
+to-label = To
+button-search = Search
+button-admin = Go Back to Administration
+no-entries = No entries found
+entry-actor = By { $actor } from { $ip }
+entry-change = { $field }: "{ $before }" → "{ $after }"
+error-label = Error: { $details }
+error-date = Invalid date
+error-forbidden = Only administrators can open this page
+error-net = Net error
+error-unknown = Unknown error
@@ -0,0 +1,38 @@
+header = 审计日志
+actor-label = 操作者
+actor-hint = 执行操作的用户的邮箱，留空表示任何人
+target-label = 对象
+target-hint = 用户的邮箱或设备 ID，留空表示全部
+action-label = 操作
+action-all = 全部
+action-login = 登录
+action-login-failed = 登录失败
+action-register = 注册
+action-create-device = 添加设备
+action-modify-device = 修改设备
+action-remove-device = 删除设备
+action-update-user = 修改用户
+action-delete-user = 删除用户
+action-impersonate = 以用户身份登录
+action-request-deletion = 申请删除账号
+action-cancel-deletion = 取消删除账号
+action-delete-account = 删除账号
+action-create-org = 创建组织
+action-add-org-member = 添加组织成员
+action-remove-org-member = 移除组织成员
+action-provision-device = 登记设备
+action-send-command = 向设备发送命令
+action-update-desired = 修改设备期望状态
+action-reset-totp = 重置两步验证
+from-label = 开始日期
+to-label = 结束日期
+button-search = 搜索
+button-admin = 返回管理页面
+no-entries = 没有找到记录
+entry-actor = 由 { $actor } 从 { $ip } 执行
+entry-change = { $field }：“{ $before }” → “{ $after }”
+error-label = 错误：{ $details }
+error-date = 日期无效
+error-forbidden = 只有管理员可以打开此页面
+error-net = 网络错误
+error-unknown = 未知错误
@@ -20,7 +20,35 @@ msg-title = Detailed Data
 msg-value = Value: { $value }
 msg-position = Position: ({ $lng }, { $lat })
 msg-time = Time: { $time }
+twin-title = Device Twin
+desired-label = Desired state (version { $version })
+desired-hint = A JSON object the device is asked to apply, e.g. {"{"} "interval": 5 {"}"}
+button-save-desired = Save
+twin-reported = Reported state (version { $version }): { $reported }
+twin-reported-at = Last reported at { $time }
+twin-never-reported = The device hasn't reported its state yet
+twin-delta = Not applied yet: { $delta }
+commands-title = Commands
+command-name-label = Command
+command-name-hint = e.g. reboot
+command-params-label = Parameters
+command-params-hint = Passed on to the device as they are, e.g. {"{"} "delay": 5 {"}"}
+button-send-command = Send
+button-refresh-commands = Refresh
+no-command = No command is sent to this device
+command-name = { $name } - { $status }
+command-params = Parameters: { $params }
+command-time = Sent at { $time } by { $mail }
+command-result = Result: { $result }
+command-status-pending = Pending
+command-status-sent = Waiting for reply
+command-status-acked = Done
+command-status-failed = Failed
+command-status-timeout = Timed out
 error-label = Failed to fecth data: { $details }
 error-net = Net error
 error-unknown = Unknown error
-error-no-device = Device doesn't exist
\ No newline at end of file
+error-no-device = Device doesn't exist
+error-forbidden = You don't follow this device
+error-invalid-request = Invalid command or desired state
+error-version-conflict = The desired state was changed by someone else, please try again
\ No newline at end of file
@@ -14,7 +14,35 @@ msg-title = 详细数据
 msg-value = 值：{ $value }
 msg-position = 地点：({ $lng }, { $lat })
 msg-time = 时间：{ $time }
+twin-title = 设备孪生
+desired-label = 期望状态（版本 { $version }）
+desired-hint = 要求设备应用的 JSON 对象，例如 {"{"} "interval": 5 {"}"}
+button-save-desired = 保存
+twin-reported = 上报状态（版本 { $version }）：{ $reported }
+twin-reported-at = 最近上报于 { $time }
+twin-never-reported = 设备尚未上报状态
+twin-delta = 尚未应用：{ $delta }
+commands-title = 命令
+command-name-label = 命令
+command-name-hint = 例如 reboot
+command-params-label = 参数
+command-params-hint = 原样传给设备，例如 {"{"} "delay": 5 {"}"}
+button-send-command = 发送
+button-refresh-commands = 刷新
+no-command = 尚未向该设备发送命令
+command-name = { $name } - { $status }
+command-params = 参数：{ $params }
+command-time = { $mail } 发送于 { $time }
+command-result = 结果：{ $result }
+command-status-pending = 等待发送
+command-status-sent = 等待回复
+command-status-acked = 已完成
+command-status-failed = 失败
+command-status-timeout = 超时
 error-label = 获取数据失败：{ $details }
 error-net = 网络错误
 error-unknown = 未知错误
-error-no-device = 该设备不存在
\ No newline at end of file
+error-no-device = 该设备不存在
+error-forbidden = 你没有关注该设备
+error-invalid-request = 命令或期望状态无效
+error-version-conflict = 期望状态已被他人修改，请重试
\ No newline at end of file
@@ -2,8 +2,15 @@ header = Home
 welcome = Welcome, { $username }({ $email })!
 id-label = Device ID
 id-hint = Device ID to be added
+claim-code-label = Claim Code
+claim-code-hint = Printed on the device, not needed for devices you own
 button-add = Add Device
 button-fetch = Refresh Devices
+button-api-keys = API Keys
+button-security = Two-Factor Authentication
+button-profile = Profile
+button-admin = Administration
+button-resend = Resend Verification Mail
 button-logout = Logout
 button-edit = Edit
 button-details = Details
@@ -19,4 +26,9 @@ error-label = Error: { $details }
 error-net = Net error
 error-unknown = Unknown error
 error-no-device = Device doesn't exist
-error-no-user = User doesn't exist
\ No newline at end of file
+error-no-user = User doesn't exist
+error-invalid-claim-code = Wrong claim code, or the device hasn't been provisioned
+error-device-claimed = The device has been claimed by someone else
+error-too-many-attempts = A mail was sent just now, please try again later
+verify-hint = Please verify your e-mail address { $email } by the link in the verification mail before adding devices.
+verify-resent = A new verification mail has been sent.
\ No newline at end of file
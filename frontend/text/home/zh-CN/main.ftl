@@ -2,8 +2,15 @@ header = 主页
 welcome = { $username }（{ $email }），您好！
 id-label = 设备 ID
 id-hint = 要添加的设备 ID
+claim-code-label = 认领码
+claim-code-hint = 印在设备上，添加自己的设备时无需填写
 button-add = 添加设备
 button-fetch = 刷新设备
+button-api-keys = API 密钥
+button-security = 两步验证
+button-profile = 个人资料
+button-admin = 管理
+button-resend = 重新发送验证邮件
 button-logout = 登出
 button-edit = 编辑
 button-details = 详情
@@ -13,4 +20,9 @@ error-label = 错误：{ $details }
 error-net = 网络错误
 error-unknown = 未知错误
 error-no-device = 该设备不存在
-error-no-user = 该用户不存在
\ No newline at end of file
+error-no-user = 该用户不存在
+error-invalid-claim-code = 认领码错误，或该设备尚未登记
+error-device-claimed = 该设备已被他人认领
+error-too-many-attempts = 刚刚已发送过邮件，请稍后再试
+verify-hint = 添加设备前，请点击验证邮件中的链接验证您的邮箱 { $email }。
+verify-resent = 已重新发送验证邮件。
\ No newline at end of file
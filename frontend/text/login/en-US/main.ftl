@@ -3,12 +3,27 @@ email-label = E-mail
 email-hint = E-mail address
 password-label = Password
 password-hint = Password
+remember-label = Remember me
+totp-label = Authentication Code
+totp-hint = Code of the authenticator app or a recovery code
 error-label = Failed to login: { $details }
 error-email-empty = E-mail address must not be empty
 error-password-empty = Password must not be empty
 error-no-user = No such user
 error-wrong-password = Password is wrong
+error-account-disabled = The account is disabled, please contact the administrator
+error-totp-required = Two-factor authentication is enabled, please enter the authentication code
+error-totp-code-empty = Authentication code must not be empty
+error-wrong-totp-code = The authentication code is wrong or has been used
+error-too-many-attempts = Too many failed attempts, please try again in { $minutes } { $minutes ->
+    [one] minute
+   *[other] minutes
+}
+error-sso-failed = The single sign-on has failed, please try again
+error-mail-not-verified = The provider hasn't verified your e-mail address
+error-invalid-request = Single sign-on is not enabled
 error-net = Net error
 error-unknown = Unknown error
 btn-login = Login
 btn-register = Register
+btn-sso = Sign in with { $provider }
@@ -3,12 +3,24 @@ email-label = 邮箱
 email-hint = 账号邮箱地址
 password-label = 密码
 password-hint = 密码
+remember-label = 记住我
+totp-label = 验证码
+totp-hint = 身份验证器应用中的验证码或恢复码
 error-label = 登陆失败: { $details }
 error-email-empty = 邮箱不能为空
 error-password-empty = 密码不能为空
 error-no-user = 用户不存在
 error-wrong-password = 密码错误
+error-account-disabled = 该账户已被禁用，请联系管理员
+error-totp-required = 已启用两步验证，请输入验证码
+error-totp-code-empty = 验证码不能为空
+error-wrong-totp-code = 验证码错误或已被使用
+error-too-many-attempts = 失败次数过多，请在 { $minutes } 分钟后重试
+error-sso-failed = 单点登录失败，请重试
+error-mail-not-verified = 身份提供方尚未验证您的邮箱
+error-invalid-request = 未启用单点登录
 error-net = 网络错误
 error-unknown = 未知错误
 btn-login = 登录
-btn-register = 注册
\ No newline at end of file
+btn-register = 注册
+btn-sso = 使用 { $provider } 登录
\ No newline at end of file
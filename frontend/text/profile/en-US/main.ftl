@@ -0,0 +1,42 @@
+header = Profile
+mail-label = E-mail: { $mail }
+role-label = Role: { $role }
+role-user = User
+role-admin = Administrator
+name-label = Username
+name-hint = 4 to 32 letters, digits or underscores
+language-label = Language
+language-browser = Chosen in this browser
+timezone-label = Timezone of message times
+button-save = Save Profile
+change-mail-hint = A new e-mail address takes effect once the link sent to it is opened. Until then the current address keeps working.
+new-mail-label = New e-mail address
+mail-password-hint = Enter your password to confirm the change
+sso-mail-password-hint = Enter your password to confirm the change, or leave it empty within 5 minutes of logging in by single sign-on
+button-change-mail = Change E-mail
+pending-mail-hint = A verification link has been sent to { $mail }, open it to finish the change
+export-hint = Download a zip of your personal data: the profile, the followed devices and the audit log of your account.
+button-export = Export Personal Data
+delete-hint = Deleting the account ends every login and stops the API keys at once. The account is deleted for good after a grace period, logging in before then can still cancel it.
+password-label = Password
+password-hint = Enter your password to confirm the deletion
+sso-password-hint = Enter your password to confirm the deletion, or leave it empty within 5 minutes of logging in by single sign-on
+button-delete = Delete Account
+deletion-due-hint = The account will be deleted at { $time } (UTC)
+button-cancel-deletion = Cancel Deletion
+deletion-requested-hint = The account will be deleted after the grace period. To keep it, log in again before then and cancel the deletion on this page.
+button-login = Go to Login
+button-home = Go Back to Home
+error-label = Error: { $details }
+error-password-empty = Password can't be empty
+error-wrong-password = Wrong password
+error-username = Invalid username
+error-email = Invalid e-mail address
+error-dup-username = The username has been used
+error-dup-email = This e-mail address has been registered
+error-too-many-attempts = Too many attempts, please try again later
+error-invalid-request = The request is invalid or outdated, please refresh
+error-no-user = User not found
+error-forbidden = Permission denied
+error-net = Net error
+error-unknown = Unknown error
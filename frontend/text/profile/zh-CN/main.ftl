@@ -0,0 +1,42 @@
+header = 个人资料
+mail-label = 邮箱：{ $mail }
+role-label = 角色：{ $role }
+role-user = 普通用户
+role-admin = 管理员
+name-label = 用户名
+name-hint = 4 到 32 位字母、数字或下划线
+language-label = 语言
+language-browser = 使用本浏览器的选择
+timezone-label = 消息时间的时区
+button-save = 保存资料
+change-mail-hint = 新邮箱在打开发送到该邮箱的链接后生效，在此之前仍使用当前邮箱。
+new-mail-label = 新邮箱
+mail-password-hint = 输入密码以确认修改
+sso-mail-password-hint = 输入密码以确认修改，通过单点登录登录后 5 分钟内也可以留空
+button-change-mail = 修改邮箱
+pending-mail-hint = 验证链接已发送至 { $mail }，打开链接以完成修改
+export-hint = 下载个人数据的压缩包，包括个人资料、关注的设备和账号的审计日志。
+button-export = 导出个人数据
+delete-hint = 删除账号会立即结束所有登录并停用 API 密钥。账号会在宽限期结束后被永久删除，在此之前登录仍可取消删除。
+password-label = 密码
+password-hint = 输入密码以确认删除
+sso-password-hint = 输入密码以确认删除，通过单点登录登录后 5 分钟内也可以留空
+button-delete = 删除账号
+deletion-due-hint = 账号将于 { $time }（UTC）被删除
+button-cancel-deletion = 取消删除
+deletion-requested-hint = 账号将在宽限期结束后被删除。如需保留，请在此之前重新登录并在此页面取消删除。
+button-login = 前往登录
+button-home = 返回主页
+error-label = 错误：{ $details }
+error-password-empty = 密码不能为空
+error-wrong-password = 密码错误
+error-username = 用户名无效
+error-email = 邮箱地址无效
+error-dup-username = 用户名已被使用
+error-dup-email = 该邮箱已被注册
+error-too-many-attempts = 尝试次数过多，请稍后再试
+error-invalid-request = 请求无效或已过期，请刷新
+error-no-user = 用户不存在
+error-forbidden = 没有权限
+error-net = 网络错误
+error-unknown = 未知错误
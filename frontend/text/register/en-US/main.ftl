@@ -11,11 +11,13 @@ password-inv = Invalid password (6-32 characters, allowed characters: a-zA-Z0-9_
 password2-label = Password (twice)
 password2-hint = Password (must be the same as above)
 password2-inv = Password is different from above
+registered-hint = A verification mail has been sent to { $email }, please follow the link in it to verify your e-mail address, then log in.
 error-label = Failed to register: { $details }
 error-net = Net error
 error-unknown = Unknown error
 error-dup-email = This e-mail address has been registered
 error-dup-username = The username has been used
+error-too-many-attempts = Too many failed attempts, please try again later
 error-email = Invalid e-mail address
 error-username = Invalid username
 error-password = Invalid passwor
@@ -11,11 +11,13 @@ password-inv = 密码不符合要求（6-32 个字符，且仅允许以下字符
 password2-label = 重复输入密码
 password2-hint = 重复输入密码（必须和上一栏相同）
 password2-inv = 与上一栏密码不同
+registered-hint = 验证邮件已发送至 { $email }，请点击邮件中的链接验证邮箱后登录。
 error-label = 注册失败：{ $details }
 error-net = 网络错误
 error-unknown = 未知错误
 error-dup-email = 该邮箱地址已被注册
 error-dup-username = 该用户名已被使用
+error-too-many-attempts = 失败次数过多，请稍后重试
 error-email = 邮箱地址格式有误
 error-username = 用户名不合要求
 error-password = 密码不合要求
@@ -0,0 +1,23 @@
+header = Two-Factor Authentication
+status-enabled = Two-factor authentication is enabled, { $count ->
+        [one] 1 recovery code is
+        *[other] { $count } recovery codes are
+    } left
+status-disabled = Two-factor authentication is disabled. Once enabled, logging in also needs a code of an authenticator app.
+scan-hint = Scan the QR code with your authenticator app, or enter the secret below by hand, then enter the code it shows to confirm.
+secret-label = Secret:
+code-label = Code
+code-hint = 6-digit code of the authenticator app
+code-or-recovery-hint = Code of the authenticator app or a recovery code
+recovery-codes-hint = Save these recovery codes somewhere safe. Each of them can be used once to log in when the authenticator app is not available, and they won't be shown again:
+button-enable = Enable
+button-confirm = Confirm
+button-disable = Disable
+button-home = Go Back to Home
+error-label = Error: { $details }
+error-code-empty = Code can't be empty
+error-wrong-totp-code = The code is wrong or has been used
+error-invalid-request = Two-factor authentication has been enabled or disabled already, please refresh
+error-forbidden = Permission denied
+error-net = Net error
+error-unknown = Unknown error
@@ -0,0 +1,20 @@
+header = 两步验证
+status-enabled = 两步验证已启用，剩余 { $count } 个恢复码
+status-disabled = 两步验证未启用。启用后，登录时还需要输入身份验证器应用中的验证码。
+scan-hint = 请用身份验证器应用扫描二维码，或手动输入下方的密钥，然后输入应用显示的验证码以确认。
+secret-label = 密钥：
+code-label = 验证码
+code-hint = 身份验证器应用中的 6 位验证码
+code-or-recovery-hint = 身份验证器应用中的验证码或恢复码
+recovery-codes-hint = 请妥善保存这些恢复码。无法使用身份验证器应用时，每个恢复码可用于登录一次，之后将无法再次查看：
+button-enable = 启用
+button-confirm = 确认
+button-disable = 停用
+button-home = 返回主页
+error-label = 错误：{ $details }
+error-code-empty = 验证码不能为空
+error-wrong-totp-code = 验证码错误或已被使用
+error-invalid-request = 两步验证已被启用或停用，请刷新页面
+error-forbidden = 没有权限
+error-net = 网络错误
+error-unknown = 未知错误
@@ -0,0 +1,11 @@
+header = Single Sign-On
+logging-in = Logging in...
+error-label = Failed to login: { $details }
+error-net = Net error
+error-unknown = Unknown error
+error-sso-failed = The login has failed, expired or been used, please try again
+error-mail-not-verified = The provider hasn't verified your e-mail address
+error-account-disabled = The account is disabled, please contact the administrator
+error-too-many-attempts = Too many failed attempts, please try again later
+error-invalid-request = Single sign-on is not enabled
+button-login = Back to Login
@@ -0,0 +1,11 @@
+header = 单点登录
+logging-in = 正在登录……
+error-label = 登录失败：{ $details }
+error-net = 网络错误
+error-unknown = 未知错误
+error-sso-failed = 登录失败、已过期或已使用，请重试
+error-mail-not-verified = 身份提供方尚未验证您的邮箱
+error-account-disabled = 账户已被禁用，请联系管理员
+error-too-many-attempts = 失败次数过多，请稍后再试
+error-invalid-request = 未启用单点登录
+button-login = 返回登录
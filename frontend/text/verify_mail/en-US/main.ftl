@@ -0,0 +1,8 @@
+header = Verify E-Mail
+verifying = Verifying your e-mail address...
+verified = Your e-mail address has been verified.
+error-label = Failed to verify: { $details }
+error-net = Net error
+error-unknown = Unknown error
+error-invalid-verification = The link is invalid, used or expired, please log in and send the verification mail again
+button-login = Login
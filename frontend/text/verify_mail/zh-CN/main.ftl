@@ -0,0 +1,8 @@
+header = 验证邮箱
+verifying = 正在验证您的邮箱……
+verified = 您的邮箱已验证。
+error-label = 验证失败：{ $details }
+error-net = 网络错误
+error-unknown = 未知错误
+error-invalid-verification = 链接无效、已使用或已过期，请登录后重新发送验证邮件
+button-login = 登录